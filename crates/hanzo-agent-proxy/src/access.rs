@@ -0,0 +1,276 @@
+//! Request ids, access logs and request metrics
+//!
+//! Every response carries an `x-request-id`, the client's own if it sent a
+//! usable one. Handlers note the key, model and backend of a request in its
+//! [`Access`] extension; once the response body has been sent (or the client
+//! went away) the request is counted in metrics and, with `--access-log`,
+//! written as one JSON line.
+
+use crate::metrics::{Observation, METRICS};
+use crate::{stream, AppState};
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{warn, Instrument};
+
+/// Header carrying the request id
+pub(crate) const REQUEST_ID: &str = "x-request-id";
+
+/// Largest error body read for its error code
+const ERROR_BODY_LIMIT: usize = 64 * 1024;
+
+/// Routes counted under their own path; anything else is `other`
+const ENDPOINTS: &[&str] = &[
+    "/",
+    "/health",
+    "/metrics",
+    "/v1/models",
+    "/v1/usage",
+    "/v1/chat/completions",
+    "/v1/messages",
+];
+
+#[derive(Default)]
+struct Details {
+    key: Option<String>,
+    model: Option<String>,
+    /// Model as labelled in metrics
+    model_label: Option<String>,
+    backend: Option<String>,
+}
+
+/// What handling a request learned about it, for logs and metrics
+#[derive(Clone, Default)]
+pub(crate) struct Access(Arc<Mutex<Details>>);
+
+impl Access {
+    pub fn key(&self, key: &str) {
+        self.0.lock().unwrap().key = Some(key.to_string());
+    }
+
+    /// The requested model, and the bounded label it is counted under
+    pub fn model(&self, model: &str, label: &str) {
+        let mut details = self.0.lock().unwrap();
+        details.model = Some(model.to_string());
+        details.model_label = Some(label.to_string());
+    }
+
+    /// The backend that served the request; the last one tried on failover
+    pub fn backend(&self, backend: &str) {
+        self.0.lock().unwrap().backend = Some(backend.to_string());
+    }
+}
+
+/// JSON lines access log
+pub(crate) struct AccessLog {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    /// Log to a file, appending, or to stdout for `-`
+    pub fn open(path: &Path) -> Result<Self> {
+        let out: Box<dyn Write + Send> = if path == Path::new("-") {
+            Box::new(std::io::stdout())
+        } else {
+            Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("opening {}", path.display()))?,
+            )
+        };
+        Ok(Self {
+            out: Mutex::new(out),
+        })
+    }
+
+    fn write(&self, entry: &Entry) {
+        let line = serde_json::to_string(entry).unwrap_or_default();
+        let mut out = self.out.lock().unwrap();
+        if let Err(e) = writeln!(out, "{}", line).and_then(|_| out.flush()) {
+            warn!(error = %e, "Failed to write access log");
+        }
+    }
+}
+
+/// One access log line
+#[derive(Serialize)]
+struct Entry {
+    timestamp: String,
+    request_id: String,
+    method: String,
+    path: String,
+    status: u16,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backend: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Assign a request id, then log and count the request once it is done
+pub(crate) async fn observe(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| valid_id(id))
+        .map_or_else(generate_id, str::to_string);
+    let access = Access::default();
+    request.extensions_mut().insert(access.clone());
+    let (method, path) = (
+        request.method().to_string(),
+        request.uri().path().to_string(),
+    );
+
+    let start = Instant::now();
+    let span = tracing::info_span!("request", id = %id);
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID, value);
+    }
+    let (response, error) = error_code(response).await;
+
+    let done = Done {
+        log: state.access_log.clone(),
+        access,
+        start,
+        id,
+        method,
+        path,
+        status: response.status(),
+        error,
+    };
+    stream::hold(response, done)
+}
+
+/// Logs and counts a request when dropped with its response body
+struct Done {
+    log: Option<Arc<AccessLog>>,
+    access: Access,
+    start: Instant,
+    id: String,
+    method: String,
+    path: String,
+    status: StatusCode,
+    error: Option<String>,
+}
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        let latency = self.start.elapsed();
+        let details = std::mem::take(&mut *self.access.0.lock().unwrap());
+        METRICS.observe(&Observation {
+            endpoint: endpoint(&self.path),
+            model: details.model_label.as_deref().unwrap_or(""),
+            backend: details.backend.as_deref().unwrap_or(""),
+            status: self.status,
+            code: self.error.as_deref(),
+            latency,
+        });
+
+        if let Some(ref log) = self.log {
+            log.write(&Entry {
+                timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                request_id: std::mem::take(&mut self.id),
+                method: std::mem::take(&mut self.method),
+                path: std::mem::take(&mut self.path),
+                status: self.status.as_u16(),
+                latency_ms: latency.as_millis() as u64,
+                key: details.key,
+                model: details.model,
+                backend: details.backend,
+                error: self.error.take(),
+            });
+        }
+    }
+}
+
+/// Metrics label for a request path
+fn endpoint(path: &str) -> &'static str {
+    ENDPOINTS
+        .iter()
+        .find(|e| **e == path)
+        .copied()
+        .unwrap_or("other")
+}
+
+/// Read the error code of an error response, keeping its body
+///
+/// OpenAI errors carry `error.code`, Anthropic errors `error.type`.
+async fn error_code(response: Response) -> (Response, Option<String>) {
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return (response, None);
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, ERROR_BODY_LIMIT).await {
+        Ok(bytes) => bytes,
+        Err(_) => return (Response::from_parts(parts, Body::empty()), None),
+    };
+    let code = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .ok()
+        .and_then(|body| {
+            let error = &body["error"];
+            error["code"]
+                .as_str()
+                .or(error["type"].as_str())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| status.as_u16().to_string());
+    (Response::from_parts(parts, Body::from(bytes)), Some(code))
+}
+
+/// Client ids are echoed if they are short and printable
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b))
+}
+
+fn generate_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_ids() {
+        assert!(valid_id("3f2a-41:b_c.d"));
+        assert!(!valid_id(""));
+        assert!(!valid_id("has space"));
+        assert!(!valid_id(&"x".repeat(200)));
+        assert!(valid_id(&generate_id()));
+        assert_ne!(generate_id(), generate_id());
+    }
+
+    #[test]
+    fn test_endpoint() {
+        assert_eq!(endpoint("/v1/messages"), "/v1/messages");
+        assert_eq!(endpoint("/v1/whatever/123"), "other");
+    }
+}
@@ -0,0 +1,729 @@
+//! Anthropic Messages API (`/v1/messages`)
+//!
+//! Requests are translated to the internal chat request, served by the
+//! shared pipeline ([`serve_chat`]), and the result translated back into
+//! Anthropic messages or streaming events.
+
+use crate::access::Access;
+use crate::keys::ApiKey;
+use crate::stream::{self, StreamEvent};
+use crate::usage::count_tokens;
+use crate::{
+    serve_chat, unix_now, AppState, ChatRequest, ChatResponse, FunctionCall, Message, Reply,
+    ToolCall,
+};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{sse::Event, IntoResponse, Response},
+    Extension, Json,
+};
+use futures::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+// ─────────────────────────────────────────────────────────────────────────────
+// API Types
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+pub(crate) struct MessagesRequest {
+    model: String,
+    messages: Vec<AnthropicMessage>,
+    #[serde(default)]
+    system: Option<Content>,
+    #[serde(default)]
+    tools: Vec<Tool>,
+    #[serde(default)]
+    tool_choice: Option<Value>,
+    #[serde(default)]
+    stream: bool,
+    #[serde(default)]
+    max_tokens: Option<u32>,
+    #[serde(default)]
+    temperature: Option<f64>,
+    #[serde(default)]
+    top_p: Option<f64>,
+    #[serde(default)]
+    stop_sequences: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicMessage {
+    role: String,
+    content: Content,
+}
+
+/// Message content: a plain string or a list of blocks
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Content {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+impl Content {
+    /// Concatenated text of all text blocks
+    fn text(&self) -> String {
+        match self {
+            Content::Text(text) => text.clone(),
+            Content::Blocks(blocks) => blocks
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    fn blocks(&self) -> &[ContentBlock] {
+        match self {
+            Content::Text(_) => &[],
+            Content::Blocks(blocks) => blocks,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        #[serde(default)]
+        content: Option<Content>,
+        #[serde(default)]
+        is_error: bool,
+    },
+    /// Images, documents, and thinking blocks are not forwarded
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Deserialize)]
+struct Tool {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    input_schema: Value,
+}
+
+#[derive(Serialize)]
+struct MessagesResponse {
+    id: String,
+    r#type: &'static str,
+    role: &'static str,
+    model: String,
+    content: Vec<ResponseBlock>,
+    stop_reason: &'static str,
+    stop_sequence: Option<String>,
+    usage: AnthropicUsage,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponseBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+}
+
+#[derive(Serialize)]
+struct AnthropicUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+}
+
+#[derive(Serialize)]
+pub(crate) struct AnthropicError {
+    r#type: &'static str,
+    error: AnthropicErrorDetail,
+}
+
+#[derive(Serialize)]
+struct AnthropicErrorDetail {
+    r#type: &'static str,
+    message: String,
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Translation
+// ─────────────────────────────────────────────────────────────────────────────
+
+impl MessagesRequest {
+    /// Translate to the internal (OpenAI-format) chat request
+    fn to_chat_request(&self) -> ChatRequest {
+        let mut messages = vec![];
+        if let Some(ref system) = self.system {
+            messages.push(Message {
+                role: "system".to_string(),
+                content: system.text().into(),
+                ..Default::default()
+            });
+        }
+
+        for message in &self.messages {
+            // Tool results become their own `tool` messages
+            for block in message.content.blocks() {
+                if let ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                } = block
+                {
+                    let text = content.as_ref().map(Content::text).unwrap_or_default();
+                    messages.push(Message {
+                        role: "tool".to_string(),
+                        content: if *is_error {
+                            format!("Error: {}", text)
+                        } else {
+                            text
+                        }
+                        .into(),
+                        tool_call_id: Some(tool_use_id.clone()),
+                        ..Default::default()
+                    });
+                }
+            }
+
+            let tool_calls: Vec<ToolCall> = message
+                .content
+                .blocks()
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::ToolUse { id, name, input } => Some(ToolCall {
+                        id: id.clone(),
+                        r#type: "function".to_string(),
+                        function: FunctionCall {
+                            name: name.clone(),
+                            arguments: input.to_string(),
+                        },
+                    }),
+                    _ => None,
+                })
+                .collect();
+            let content = message.content.text();
+            if content.is_empty() && tool_calls.is_empty() {
+                continue;
+            }
+            messages.push(Message {
+                role: message.role.clone(),
+                content: content.into(),
+                tool_calls,
+                ..Default::default()
+            });
+        }
+
+        let tools = self
+            .tools
+            .iter()
+            .map(|t| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": t.name,
+                        "description": t.description,
+                        "parameters": t.input_schema,
+                    }
+                })
+            })
+            .collect();
+
+        // Sampling parameters are forwarded to HTTP upstreams
+        let mut extra = serde_json::Map::new();
+        if let Some(max_tokens) = self.max_tokens {
+            extra.insert("max_tokens".to_string(), max_tokens.into());
+        }
+        if let Some(temperature) = self.temperature {
+            extra.insert("temperature".to_string(), temperature.into());
+        }
+        if let Some(top_p) = self.top_p {
+            extra.insert("top_p".to_string(), top_p.into());
+        }
+        if !self.stop_sequences.is_empty() {
+            extra.insert("stop".to_string(), json!(self.stop_sequences));
+        }
+
+        ChatRequest {
+            model: Some(self.model.clone()),
+            messages,
+            stream: self.stream,
+            tools,
+            tool_choice: self.tool_choice.as_ref().and_then(openai_tool_choice),
+            extra,
+        }
+    }
+}
+
+/// Translate an Anthropic `tool_choice` to OpenAI format
+fn openai_tool_choice(choice: &Value) -> Option<Value> {
+    match choice["type"].as_str()? {
+        "auto" => Some("auto".into()),
+        "any" => Some("required".into()),
+        "none" => Some("none".into()),
+        "tool" => Some(json!({"type": "function", "function": {"name": choice["name"]}})),
+        _ => None,
+    }
+}
+
+impl MessagesResponse {
+    /// Translate an internal chat response
+    fn from_chat(response: ChatResponse, model: &str) -> Self {
+        let mut content = vec![];
+        let mut finish_reason = "stop".to_string();
+        if let Some(choice) = response.choices.into_iter().next() {
+            finish_reason = choice.finish_reason;
+            if let Some(text) = choice.message.content.filter(|t| !t.is_empty()) {
+                content.push(ResponseBlock::Text { text });
+            }
+            for call in choice.message.tool_calls {
+                content.push(ResponseBlock::ToolUse {
+                    input: tool_input(&call.function.arguments),
+                    id: call.id,
+                    name: call.function.name,
+                });
+            }
+        }
+
+        Self {
+            id: format!("msg_{}", response.id.trim_start_matches("chatcmpl-")),
+            r#type: "message",
+            role: "assistant",
+            model: model.to_string(),
+            content,
+            stop_reason: stop_reason(&finish_reason),
+            stop_sequence: None,
+            usage: AnthropicUsage {
+                input_tokens: response.usage.prompt_tokens,
+                output_tokens: response.usage.completion_tokens,
+            },
+        }
+    }
+}
+
+/// Map an OpenAI finish reason to an Anthropic stop reason
+fn stop_reason(finish_reason: &str) -> &'static str {
+    match finish_reason {
+        "length" => "max_tokens",
+        "tool_calls" | "function_call" => "tool_use",
+        _ => "end_turn",
+    }
+}
+
+/// Parse JSON-encoded tool arguments into a `tool_use` input object
+fn tool_input(arguments: &str) -> Value {
+    match serde_json::from_str(arguments) {
+        Ok(value @ Value::Object(_)) => value,
+        _ => {
+            warn!(arguments, "Tool call arguments are not a JSON object");
+            json!({})
+        }
+    }
+}
+
+pub(crate) fn error(
+    status: StatusCode,
+    message: impl Into<String>,
+) -> (StatusCode, Json<AnthropicError>) {
+    let r#type = match status {
+        StatusCode::BAD_REQUEST => "invalid_request_error",
+        StatusCode::UNAUTHORIZED => "authentication_error",
+        StatusCode::FORBIDDEN => "permission_error",
+        StatusCode::NOT_FOUND => "not_found_error",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+        StatusCode::SERVICE_UNAVAILABLE => "overloaded_error",
+        _ => "api_error",
+    };
+    (
+        status,
+        Json(AnthropicError {
+            r#type: "error",
+            error: AnthropicErrorDetail {
+                r#type,
+                message: message.into(),
+            },
+        }),
+    )
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Streaming
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn sse(name: &str, data: Value) -> Event {
+    Event::default().event(name).data(data.to_string())
+}
+
+/// Turns stream events into Anthropic `message_*` / `content_block_*` events
+struct EventWriter {
+    id: String,
+    model: String,
+    /// Index of the next content block
+    index: usize,
+    text_open: bool,
+    output_tokens: u32,
+}
+
+impl EventWriter {
+    fn start(&self, input_tokens: u32) -> Vec<Event> {
+        vec![
+            sse(
+                "message_start",
+                json!({
+                    "type": "message_start",
+                    "message": {
+                        "id": self.id,
+                        "type": "message",
+                        "role": "assistant",
+                        "model": self.model,
+                        "content": [],
+                        "stop_reason": null,
+                        "stop_sequence": null,
+                        "usage": {"input_tokens": input_tokens, "output_tokens": 0},
+                    }
+                }),
+            ),
+            sse("ping", json!({"type": "ping"})),
+        ]
+    }
+
+    fn close_text(&mut self, events: &mut Vec<Event>) {
+        if self.text_open {
+            events.push(sse(
+                "content_block_stop",
+                json!({"type": "content_block_stop", "index": self.index}),
+            ));
+            self.text_open = false;
+            self.index += 1;
+        }
+    }
+
+    fn handle(&mut self, event: StreamEvent) -> Vec<Event> {
+        let mut events = vec![];
+        match event {
+            StreamEvent::Text(text) => {
+                if text.is_empty() {
+                    return events;
+                }
+                if !self.text_open {
+                    events.push(sse(
+                        "content_block_start",
+                        json!({
+                            "type": "content_block_start",
+                            "index": self.index,
+                            "content_block": {"type": "text", "text": ""},
+                        }),
+                    ));
+                    self.text_open = true;
+                }
+                self.output_tokens += count_tokens(&text);
+                events.push(sse(
+                    "content_block_delta",
+                    json!({
+                        "type": "content_block_delta",
+                        "index": self.index,
+                        "delta": {"type": "text_delta", "text": text},
+                    }),
+                ));
+            }
+            StreamEvent::ToolCall(call) => {
+                self.close_text(&mut events);
+                self.output_tokens += count_tokens(&call.function.arguments);
+                events.push(sse(
+                    "content_block_start",
+                    json!({
+                        "type": "content_block_start",
+                        "index": self.index,
+                        "content_block": {
+                            "type": "tool_use",
+                            "id": call.id,
+                            "name": call.function.name,
+                            "input": {},
+                        },
+                    }),
+                ));
+                events.push(sse(
+                    "content_block_delta",
+                    json!({
+                        "type": "content_block_delta",
+                        "index": self.index,
+                        "delta": {
+                            "type": "input_json_delta",
+                            "partial_json": call.function.arguments,
+                        },
+                    }),
+                ));
+                events.push(sse(
+                    "content_block_stop",
+                    json!({"type": "content_block_stop", "index": self.index}),
+                ));
+                self.index += 1;
+            }
+            StreamEvent::Finish(reason) => {
+                self.close_text(&mut events);
+                events.push(sse(
+                    "message_delta",
+                    json!({
+                        "type": "message_delta",
+                        "delta": {"stop_reason": stop_reason(&reason), "stop_sequence": null},
+                        "usage": {"output_tokens": self.output_tokens},
+                    }),
+                ));
+                events.push(sse("message_stop", json!({"type": "message_stop"})));
+            }
+            StreamEvent::Error(message) => {
+                events.push(sse(
+                    "error",
+                    json!({
+                        "type": "error",
+                        "error": {"type": "api_error", "message": message},
+                    }),
+                ));
+            }
+        }
+        events
+    }
+}
+
+/// Format stream events as Anthropic SSE
+fn anthropic_sse(rx: mpsc::Receiver<StreamEvent>, model: &str, input_tokens: u32) -> Response {
+    let mut writer = EventWriter {
+        id: format!("msg_{}", unix_now()),
+        model: model.to_string(),
+        index: 0,
+        text_open: false,
+        output_tokens: 0,
+    };
+    let start = futures::stream::iter(writer.start(input_tokens));
+    let body = stream::receiver_stream(rx)
+        .flat_map(move |event| futures::stream::iter(writer.handle(event)));
+    stream::sse_response(start.chain(body))
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Handler
+// ─────────────────────────────────────────────────────────────────────────────
+
+pub(crate) async fn messages_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Arc<ApiKey>>,
+    Extension(access): Extension<Access>,
+    headers: HeaderMap,
+    Json(request): Json<MessagesRequest>,
+) -> Result<Response, (StatusCode, Json<AnthropicError>)> {
+    let chat = request.to_chat_request();
+    let served = serve_chat(&state, &caller, &access, &headers, &chat, false)
+        .await
+        .map_err(|e| error(e.status, e.message))?;
+    let input_tokens = served.prompt_tokens;
+    Ok(served.respond(|reply| match reply {
+        Reply::Complete(response) => {
+            Json(MessagesResponse::from_chat(response, &request.model)).into_response()
+        }
+        Reply::Stream(rx) => anthropic_sse(rx, &request.model, input_tokens),
+        Reply::Proxied(_) => unreachable!("OpenAI SSE is not proxied to Anthropic clients"),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::cli_registry;
+    use crate::{Choice, ResponseMessage, Usage};
+
+    #[test]
+    fn test_request_translation() {
+        let request: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-cli",
+            "max_tokens": 1024,
+            "tool_choice": {"type": "tool", "name": "get_weather"},
+            "system": [{"type": "text", "text": "Be brief."}],
+            "tools": [{
+                "name": "get_weather",
+                "description": "Current weather",
+                "input_schema": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }],
+            "messages": [
+                {"role": "user", "content": "Weather in Paris?"},
+                {"role": "assistant", "content": [
+                    {"type": "text", "text": "Checking."},
+                    {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"city": "Paris"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "18C"},
+                    {"type": "image", "source": {"type": "base64", "data": ""}}
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let chat = request.to_chat_request();
+        let roles: Vec<&str> = chat.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["system", "user", "assistant", "tool"]);
+        assert_eq!(chat.messages[0].content.text(), "Be brief.");
+        assert_eq!(chat.messages[2].tool_calls[0].function.name, "get_weather");
+        assert_eq!(
+            chat.messages[2].tool_calls[0].function.arguments,
+            r#"{"city":"Paris"}"#
+        );
+        assert_eq!(chat.messages[3].tool_call_id.as_deref(), Some("toolu_1"));
+        assert_eq!(chat.messages[3].content.text(), "18C");
+        assert_eq!(chat.tools[0]["function"]["parameters"]["type"], "object");
+        assert_eq!(chat.extra["max_tokens"], 1024);
+        assert_eq!(chat.tool_choice.unwrap()["function"]["name"], "get_weather");
+    }
+
+    #[test]
+    fn test_response_translation() {
+        let response = ChatResponse {
+            id: "chatcmpl-1".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "upstream".to_string(),
+            choices: vec![Choice {
+                index: 0,
+                message: ResponseMessage {
+                    role: "assistant".to_string(),
+                    content: None,
+                    tool_calls: vec![ToolCall {
+                        id: "call_1".to_string(),
+                        r#type: "function".to_string(),
+                        function: FunctionCall {
+                            name: "get_weather".to_string(),
+                            arguments: r#"{"city":"Paris"}"#.to_string(),
+                        },
+                    }],
+                },
+                finish_reason: "tool_calls".to_string(),
+            }],
+            usage: Usage {
+                prompt_tokens: 10,
+                completion_tokens: 5,
+                total_tokens: 15,
+            },
+        };
+
+        let value = serde_json::to_value(MessagesResponse::from_chat(response, "hanzo")).unwrap();
+        assert_eq!(value["type"], "message");
+        assert_eq!(value["model"], "hanzo");
+        assert_eq!(value["stop_reason"], "tool_use");
+        assert_eq!(value["content"][0]["type"], "tool_use");
+        assert_eq!(value["content"][0]["input"]["city"], "Paris");
+        assert_eq!(value["usage"]["input_tokens"], 10);
+    }
+
+    #[tokio::test]
+    async fn test_stream_events() {
+        let (tx, rx) = mpsc::channel(8);
+        tx.send(StreamEvent::Text("Hello".to_string()))
+            .await
+            .unwrap();
+        tx.send(StreamEvent::ToolCall(ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: "lookup".to_string(),
+                arguments: "{}".to_string(),
+            },
+        }))
+        .await
+        .unwrap();
+        tx.send(StreamEvent::Finish("tool_calls".to_string()))
+            .await
+            .unwrap();
+        drop(tx);
+
+        let mut body = anthropic_sse(rx, "claude-cli", 3)
+            .into_body()
+            .into_data_stream();
+        let mut text = String::new();
+        while let Some(chunk) = body.next().await {
+            text.push_str(&String::from_utf8_lossy(&chunk.unwrap()));
+        }
+
+        let names: Vec<&str> = text
+            .lines()
+            .filter_map(|l| l.strip_prefix("event: "))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "message_start",
+                "ping",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert!(text.contains(r#""stop_reason":"tool_use""#));
+        assert!(text.contains(r#""index":1"#));
+    }
+
+    #[tokio::test]
+    async fn test_messages_endpoint() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let (_dir, registry) = cli_registry(
+            "cat > /dev/null\necho \"hello there\"\n",
+            "input_mode = \"stdin\"\nmodels = [\"claude-cli\"]",
+        );
+        let app = crate::router(AppState::for_tests(registry), false);
+        let send = |stream: bool| {
+            let body = json!({
+                "model": "claude-cli",
+                "max_tokens": 100,
+                "stream": stream,
+                "messages": [{"role": "user", "content": "Hi"}]
+            });
+            let request = Request::post("/v1/messages")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+
+        let response = send(false).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let message: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(message["type"], "message");
+        assert_eq!(message["model"], "claude-cli");
+        assert_eq!(message["content"][0]["text"], "hello there");
+
+        let response = send(true).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.starts_with("event: message_start\n"), "{}", text);
+        assert!(text.contains(r#""text":"hello there""#), "{}", text);
+        assert!(text.contains("event: message_stop\n"), "{}", text);
+    }
+}
@@ -0,0 +1,807 @@
+//! Backend registry loaded from TOML or YAML
+//!
+//! The built-in registry lives in `backends.toml`; `--config` replaces it
+//! with a user file that is polled and hot-reloaded when it changes.
+//! String settings of HTTP backends expand `${VAR}` and `${VAR:-default}`
+//! from the environment when a request is made.
+
+use crate::sandbox::ResourceLimits;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Built-in backend definitions
+const BUILTIN_CONFIG: &str = include_str!("../backends.toml");
+
+/// Placeholder element that expands to a backend's `model_args`
+const MODEL_ARGS: &str = "{model_args}";
+
+/// How the prompt reaches a backend
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum InputMode {
+    /// Written to the CLI's stdin
+    Stdin,
+    /// Substituted into the argument template
+    #[default]
+    Args,
+    /// Direct HTTP passthrough to a local server
+    Http,
+}
+
+/// Backend configuration for a CLI tool
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Backend {
+    pub name: String,
+    /// Executable to spawn (unused for HTTP backends)
+    pub command: String,
+    /// Argument template with `{prompt}`, `{model}` and `{model_args}`
+    pub args: Vec<String>,
+    /// Arguments that select a model, added only for forwarded models
+    pub model_args: Vec<String>,
+    /// Prefixes a model needs to be forwarded (any model if empty)
+    pub model_arg_prefixes: Vec<String>,
+    /// Model used when none is requested; never forwarded
+    pub default_model: Option<String>,
+    pub input_mode: InputMode,
+    /// Exact model aliases served by this backend
+    pub models: Vec<String>,
+    /// Model prefixes routed to this backend
+    pub model_prefixes: Vec<String>,
+    /// Environment variables removed before spawning (e.g. API keys)
+    pub env_remove: Vec<String>,
+    /// Environment variables set before spawning
+    pub env: BTreeMap<String, String>,
+    /// Working directory for the CLI
+    pub workdir: Option<PathBuf>,
+    /// OpenAI-compatible base URL of an HTTP backend (e.g. `https://host/v1`)
+    pub base_url: Option<String>,
+    /// Environment variable holding the upstream API key
+    pub api_key_env: Option<String>,
+    /// Headers added to upstream requests
+    pub headers: BTreeMap<String, String>,
+    /// Requested model to upstream model renames
+    pub model_map: BTreeMap<String, String>,
+    /// Model sent upstream for every request not renamed by `model_map`
+    pub upstream_model: Option<String>,
+    /// Arguments added to start a CLI session, with `{session}`
+    pub session_args: Vec<String>,
+    /// Arguments added to resume a CLI session, with `{session}`
+    pub resume_args: Vec<String>,
+    /// Most requests run at once (unlimited if unset)
+    pub max_concurrency: Option<usize>,
+    /// Requests that may wait for a free slot; more are refused
+    pub max_queue: usize,
+    /// Seconds before a CLI run is killed
+    pub timeout_secs: Option<u64>,
+    /// Environment variables (or `PREFIX*`) passed to the CLI; all if empty
+    pub env_allow: Vec<String>,
+    /// Run each CLI invocation in a fresh temporary directory
+    pub temp_workdir: bool,
+    /// Resource limits for CLI processes
+    pub limits: ResourceLimits,
+}
+
+impl Backend {
+    /// Render the argument template for a prompt and model
+    pub fn build_args(&self, prompt: &str, model: &str) -> Vec<String> {
+        let model = match (model, &self.default_model) {
+            ("", Some(default)) => default.as_str(),
+            _ => model,
+        };
+        let render = |arg: &String| arg.replace("{model}", model).replace("{prompt}", prompt);
+        let model_args: Vec<String> = if self.forwards_model(model) {
+            self.model_args.iter().map(render).collect()
+        } else {
+            vec![]
+        };
+
+        let mut args = vec![];
+        let mut placed = false;
+        for arg in &self.args {
+            if arg == MODEL_ARGS {
+                args.extend(model_args.iter().cloned());
+                placed = true;
+            } else {
+                args.push(render(arg));
+            }
+        }
+        if !placed {
+            args.extend(model_args);
+        }
+        args
+    }
+
+    /// Model to send to the backend for a requested model
+    pub fn upstream_model(&self, model: &str) -> String {
+        if let Some(mapped) = self.model_map.get(model) {
+            return mapped.clone();
+        }
+        match self.upstream_model {
+            Some(ref upstream) => expand_env(upstream),
+            None => model.to_string(),
+        }
+    }
+
+    /// Time limit of a CLI run
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+
+    /// Chat completions endpoint of an HTTP backend
+    pub fn endpoint(&self) -> Option<String> {
+        self.url("chat/completions")
+    }
+
+    /// Model listing endpoint of an HTTP backend, used for health probes
+    pub fn models_url(&self) -> Option<String> {
+        self.url("models")
+    }
+
+    fn url(&self, path: &str) -> Option<String> {
+        let base = expand_env(self.base_url.as_deref()?);
+        Some(format!("{}/{}", base.trim_end_matches('/'), path))
+    }
+
+    /// Upstream API key, if configured and set
+    pub fn api_key(&self) -> Option<String> {
+        std::env::var(self.api_key_env.as_deref()?)
+            .ok()
+            .filter(|k| !k.is_empty())
+    }
+
+    /// Headers with environment references expanded
+    pub fn expanded_headers(&self) -> impl Iterator<Item = (&str, String)> {
+        self.headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), expand_env(value)))
+    }
+
+    /// Whether a requested model is passed through to the CLI
+    fn forwards_model(&self, model: &str) -> bool {
+        !model.is_empty()
+            && self.default_model.as_deref() != Some(model)
+            && (self.model_arg_prefixes.is_empty()
+                || self
+                    .model_arg_prefixes
+                    .iter()
+                    .any(|p| model.starts_with(p.as_str())))
+    }
+}
+
+/// Routing rule from a model pattern to a backend
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Route {
+    /// Glob pattern (`*` and `?`) matched against the whole model name
+    #[serde(rename = "match")]
+    pub glob: Option<String>,
+    /// Regular expression matched against the model name
+    pub regex: Option<String>,
+    pub backend: String,
+    /// Model to use instead; regex routes may reference captures (`$1`)
+    pub model: Option<String>,
+    #[serde(skip)]
+    pattern: Option<Regex>,
+}
+
+impl Route {
+    fn compile(&mut self) -> Result<()> {
+        let source = match (&self.glob, &self.regex) {
+            (Some(glob), None) => glob_to_regex(glob),
+            (None, Some(regex)) => regex.clone(),
+            _ => bail!(
+                "route to '{}' needs exactly one of `match` or `regex`",
+                self.backend
+            ),
+        };
+        self.pattern = Some(
+            Regex::new(&source)
+                .with_context(|| format!("invalid route pattern for '{}'", self.backend))?,
+        );
+        Ok(())
+    }
+
+    /// The (possibly rewritten) model if this route matches
+    fn apply(&self, model: &str) -> Option<String> {
+        let captures = self.pattern.as_ref()?.captures(model)?;
+        Some(match self.model {
+            Some(ref template) => {
+                let mut rewritten = String::new();
+                captures.expand(template, &mut rewritten);
+                rewritten
+            }
+            None => model.to_string(),
+        })
+    }
+}
+
+/// Anchored regex equivalent of a glob pattern
+pub(crate) fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    pattern
+}
+
+/// Expand `${VAR}` and `${VAR:-default}` from the environment
+pub(crate) fn expand_env(value: &str) -> String {
+    static VAR: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").unwrap());
+    VAR.replace_all(value, |caps: &regex::Captures| {
+        std::env::var(&caps[1])
+            .ok()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| caps.get(2).map_or("", |d| d.as_str()).to_string())
+    })
+    .into_owned()
+}
+
+/// How a group orders its members for each request
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Strategy {
+    /// Members in listed order
+    #[default]
+    Priority,
+    /// Rotate the first member on every request
+    RoundRobin,
+    /// Fewest requests in flight first
+    LeastInFlight,
+}
+
+/// Backends serving the same models, with failover between them
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Group {
+    pub name: String,
+    pub strategy: Strategy,
+    /// Member backend names
+    pub members: Vec<String>,
+    /// Exact model aliases served by this group
+    pub models: Vec<String>,
+    /// Model prefixes routed to this group
+    pub model_prefixes: Vec<String>,
+}
+
+/// Probe and circuit breaker settings
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct HealthConfig {
+    /// Consecutive failures that open a backend's circuit
+    pub failure_threshold: u32,
+    /// Seconds an open circuit rejects requests before a trial request
+    pub cooldown_secs: u64,
+    /// Seconds between health probes (0 disables probing)
+    pub probe_interval_secs: u64,
+    pub probe_timeout_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 3,
+            cooldown_secs: 30,
+            probe_interval_secs: 15,
+            probe_timeout_secs: 5,
+        }
+    }
+}
+
+/// Backends that can serve a request, and the model to give them
+pub(crate) struct Target<'a> {
+    /// Backend or group name
+    pub name: &'a str,
+    pub strategy: Strategy,
+    pub members: Vec<&'a Backend>,
+    /// Model after route rewriting, before each backend's `upstream_model`
+    pub model: String,
+}
+
+/// Set of backends and the rules that route models to them
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Registry {
+    /// Backend or group for models that match no alias, route, or prefix
+    pub default_backend: Option<String>,
+    pub backends: Vec<Backend>,
+    pub groups: Vec<Group>,
+    /// Pattern routes, checked in order after exact aliases
+    pub routes: Vec<Route>,
+    pub health: HealthConfig,
+}
+
+impl Registry {
+    /// The built-in backends
+    pub fn builtin() -> Self {
+        Self::from_toml(BUILTIN_CONFIG).expect("built-in backends.toml is valid")
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self> {
+        toml::from_str::<Self>(s)?.validated()
+    }
+
+    pub fn from_yaml(s: &str) -> Result<Self> {
+        serde_yaml::from_str::<Self>(s)?.validated()
+    }
+
+    /// Load a registry, choosing YAML or TOML by extension
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let registry = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => Self::from_yaml(&contents),
+            _ => Self::from_toml(&contents),
+        };
+        registry.with_context(|| format!("loading {}", path.display()))
+    }
+
+    fn validated(mut self) -> Result<Self> {
+        let mut names = HashSet::new();
+        for backend in &self.backends {
+            if backend.name.is_empty() {
+                bail!("backend without a name");
+            }
+            if !names.insert(backend.name.as_str()) {
+                bail!("duplicate backend '{}'", backend.name);
+            }
+            match backend.input_mode {
+                InputMode::Http if backend.base_url.is_none() => {
+                    bail!("HTTP backend '{}' has no base_url", backend.name)
+                }
+                InputMode::Stdin | InputMode::Args if backend.command.is_empty() => {
+                    bail!("backend '{}' has no command", backend.name)
+                }
+                _ => {}
+            }
+        }
+        let backends = names.clone();
+        for group in &self.groups {
+            if group.name.is_empty() {
+                bail!("group without a name");
+            }
+            if !names.insert(group.name.as_str()) {
+                bail!("group '{}' reuses an existing name", group.name);
+            }
+            if group.members.is_empty() {
+                bail!("group '{}' has no members", group.name);
+            }
+            if let Some(member) = group
+                .members
+                .iter()
+                .find(|m| !backends.contains(m.as_str()))
+            {
+                bail!(
+                    "group '{}' member '{}' is not a backend",
+                    group.name,
+                    member
+                );
+            }
+        }
+        if let Some(ref default) = self.default_backend {
+            if !names.contains(default.as_str()) {
+                bail!("default_backend '{}' is not defined", default);
+            }
+        }
+        for route in &mut self.routes {
+            if !names.contains(route.backend.as_str()) {
+                bail!("route to undefined backend '{}'", route.backend);
+            }
+            route.compile()?;
+        }
+        Ok(self)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Backend> {
+        self.backends.iter().find(|b| b.name == name)
+    }
+
+    /// Target for a backend or group name
+    fn target(&self, name: &str, model: String) -> Option<Target<'_>> {
+        if let Some(group) = self.groups.iter().find(|g| g.name == name) {
+            return Some(Target {
+                name: &group.name,
+                strategy: group.strategy,
+                members: group.members.iter().filter_map(|m| self.get(m)).collect(),
+                model,
+            });
+        }
+        let backend = self.get(name)?;
+        Some(Target {
+            name: &backend.name,
+            strategy: Strategy::Priority,
+            members: vec![backend],
+            model,
+        })
+    }
+
+    /// Whether a model is listed by name, rather than matched by a route,
+    /// prefix or the default
+    pub fn lists(&self, model: &str) -> bool {
+        let aliased = |models: &[String]| models.iter().any(|m| m == model);
+        self.groups.iter().any(|g| aliased(&g.models))
+            || self.backends.iter().any(|b| aliased(&b.models))
+    }
+
+    /// Find the backends that serve a requested model
+    ///
+    /// Checks exact aliases (groups first), then routes, then prefixes, then
+    /// the default.
+    pub fn resolve(&self, model: &str) -> Option<Target<'_>> {
+        let aliased = |models: &[String]| models.iter().any(|m| m == model);
+        let prefixed = |prefixes: &[String]| prefixes.iter().any(|p| model.starts_with(p.as_str()));
+
+        let name = self
+            .groups
+            .iter()
+            .find(|g| aliased(&g.models))
+            .map(|g| &g.name)
+            .or_else(|| {
+                self.backends
+                    .iter()
+                    .find(|b| aliased(&b.models))
+                    .map(|b| &b.name)
+            });
+        if let Some(name) = name {
+            return self.target(name, model.to_string());
+        }
+
+        for route in &self.routes {
+            if let Some(rewritten) = route.apply(model) {
+                return self.target(&route.backend, rewritten);
+            }
+        }
+
+        let name = self
+            .groups
+            .iter()
+            .find(|g| prefixed(&g.model_prefixes))
+            .map(|g| &g.name)
+            .or_else(|| {
+                self.backends
+                    .iter()
+                    .find(|b| prefixed(&b.model_prefixes))
+                    .map(|b| &b.name)
+            })
+            .or(self.default_backend.as_ref())?;
+        self.target(name, model.to_string())
+    }
+}
+
+/// Registry shared between handlers, reloaded when its file changes
+pub(crate) struct RegistryHandle {
+    current: RwLock<Arc<Registry>>,
+    path: Option<PathBuf>,
+    stamp: RwLock<Option<(SystemTime, u64)>>,
+}
+
+impl RegistryHandle {
+    /// Handle serving the built-in registry
+    pub fn builtin() -> Self {
+        Self::new(Registry::builtin(), None)
+    }
+
+    /// Load a registry file
+    pub fn from_file(path: PathBuf) -> Result<Self> {
+        let stamp = file_stamp(&path);
+        let registry = Registry::from_file(&path)?;
+        let handle = Self::new(registry, Some(path));
+        *handle.stamp.write().unwrap() = stamp;
+        Ok(handle)
+    }
+
+    fn new(registry: Registry, path: Option<PathBuf>) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(registry)),
+            path,
+            stamp: RwLock::new(None),
+        }
+    }
+
+    /// The current registry
+    pub fn snapshot(&self) -> Arc<Registry> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Reload the file if it changed, keeping the old registry on errors
+    ///
+    /// Returns whether a new registry was installed.
+    pub fn reload_if_changed(&self) -> bool {
+        let Some(ref path) = self.path else {
+            return false;
+        };
+        let stamp = file_stamp(path);
+        if stamp.is_none() || stamp == *self.stamp.read().unwrap() {
+            return false;
+        }
+        *self.stamp.write().unwrap() = stamp;
+
+        match Registry::from_file(path) {
+            Ok(registry) => {
+                info!(
+                    path = %path.display(),
+                    backends = registry.backends.len(),
+                    "Reloaded backend registry"
+                );
+                *self.current.write().unwrap() = Arc::new(registry);
+                true
+            }
+            Err(e) => {
+                warn!(error = %format!("{:#}", e), "Keeping previous backend registry");
+                false
+            }
+        }
+    }
+
+    /// Poll the registry file for changes in the background
+    pub fn watch(self: Arc<Self>, interval: Duration) {
+        if self.path.is_none() || interval.is_zero() {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.reload_if_changed();
+            }
+        });
+    }
+}
+
+/// Modification time and size, used to detect edits
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// First backend for a model and the model it receives
+    fn resolve<'a>(registry: &'a Registry, model: &str) -> Option<(&'a Backend, String)> {
+        let target = registry.resolve(model)?;
+        let backend = target.members[0];
+        Some((backend, backend.upstream_model(&target.model)))
+    }
+
+    #[test]
+    fn test_builtin_registry() {
+        let registry = Registry::builtin();
+        assert_eq!(registry.backends.len(), 7);
+        assert_eq!(registry.default_backend.as_deref(), Some("claude"));
+        assert_eq!(registry.get("claude").unwrap().input_mode, InputMode::Stdin);
+    }
+
+    #[test]
+    fn test_yaml_registry() {
+        let registry = Registry::from_yaml(
+            r#"
+default_backend: internal
+backends:
+  - name: internal
+    command: /opt/bin/ask
+    args: ["--model={model}", "--", "{prompt}"]
+    default_model: small
+    models: [internal]
+    model_prefixes: [corp-]
+    env_remove: [SECRET_TOKEN]
+    env:
+      ASK_MODE: batch
+    workdir: /tmp
+"#,
+        )
+        .unwrap();
+
+        let (backend, _) = resolve(&registry, "corp-large").unwrap();
+        assert_eq!(backend.name, "internal");
+        assert_eq!(backend.env["ASK_MODE"], "batch");
+        assert_eq!(backend.workdir.as_deref(), Some(Path::new("/tmp")));
+        assert_eq!(
+            backend.build_args("hi", ""),
+            vec!["--model=small", "--", "hi"]
+        );
+        assert_eq!(resolve(&registry, "other").unwrap().0.name, "internal");
+    }
+
+    #[test]
+    fn test_model_args_placement() {
+        let registry = Registry::builtin();
+        let claude = registry.get("claude").unwrap();
+        assert_eq!(
+            claude.build_args("p", "claude-cli"),
+            vec!["-p", "--output-format", "text"]
+        );
+        assert_eq!(
+            claude.build_args("p", "claude-opus"),
+            vec!["-p", "--output-format", "text", "--model", "claude-opus"]
+        );
+
+        let codex = registry.get("codex").unwrap();
+        assert_eq!(
+            codex.build_args("p", "codex-mini"),
+            vec!["-q", "--full-auto", "-m", "codex-mini", "p"]
+        );
+        assert_eq!(
+            codex.build_args("p", "gpt-4o"),
+            vec!["-q", "--full-auto", "p"]
+        );
+    }
+
+    #[test]
+    fn test_prompt_is_not_templated() {
+        let registry = Registry::builtin();
+        let qwen = registry.get("qwen").unwrap();
+        assert_eq!(
+            qwen.build_args("say {model}", "qwen"),
+            vec!["-p", "say {model}"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_registries() {
+        assert!(Registry::from_toml("[[backends]]\nname = \"x\"\n").is_err());
+        assert!(Registry::from_toml("default_backend = \"missing\"\n").is_err());
+        assert!(
+            Registry::from_toml("[[backends]]\nname = \"x\"\ninput_mode = \"http\"\n").is_err()
+        );
+        assert!(Registry::from_toml(
+            "[[backends]]\nname = \"x\"\ncommand = \"x\"\n[[backends]]\nname = \"x\"\ncommand = \"y\"\n"
+        )
+        .is_err());
+        assert!(
+            Registry::from_toml("[[backends]]\nname = \"x\"\ncommand = \"x\"\ntypo = 1\n").is_err()
+        );
+    }
+
+    #[test]
+    fn test_hot_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backends.toml");
+        std::fs::write(&path, "[[backends]]\nname = \"a\"\ncommand = \"a\"\n").unwrap();
+
+        let handle = RegistryHandle::from_file(path.clone()).unwrap();
+        assert!(!handle.reload_if_changed());
+        assert!(handle.snapshot().get("a").is_some());
+
+        std::fs::write(
+            &path,
+            "[[backends]]\nname = \"b\"\ncommand = \"b\"\nmodels = [\"bee\"]\n",
+        )
+        .unwrap();
+        assert!(handle.reload_if_changed());
+        assert_eq!(resolve(&handle.snapshot(), "bee").unwrap().0.name, "b");
+
+        // A broken edit keeps the last good registry
+        std::fs::write(&path, "[[backends]]\nname = \"c\"\n").unwrap();
+        assert!(!handle.reload_if_changed());
+        assert!(handle.snapshot().get("b").is_some());
+    }
+
+    #[test]
+    fn test_http_upstreams_and_routes() {
+        std::env::set_var("PROXY_TEST_OPENAI_KEY", "sk-test");
+        let registry = Registry::from_toml(
+            r#"
+[[backends]]
+name = "openai"
+input_mode = "http"
+base_url = "${PROXY_TEST_OPENAI_BASE:-https://api.openai.com/v1}/"
+api_key_env = "PROXY_TEST_OPENAI_KEY"
+headers = { "OpenAI-Project" = "${PROXY_TEST_PROJECT:-proj_default}" }
+model_map = { "fast" = "gpt-4o-mini" }
+models = ["fast"]
+
+[[backends]]
+name = "local"
+input_mode = "http"
+base_url = "http://localhost:8000/v1"
+upstream_model = "served-model"
+
+[[routes]]
+match = "gpt-*"
+backend = "openai"
+
+[[routes]]
+regex = "^local/(.+)$"
+backend = "local"
+model = "$1"
+"#,
+        )
+        .unwrap();
+
+        let (openai, model) = resolve(&registry, "fast").unwrap();
+        assert_eq!(model, "gpt-4o-mini");
+        assert_eq!(
+            openai.endpoint().unwrap(),
+            "https://api.openai.com/v1/chat/completions"
+        );
+        assert_eq!(openai.api_key().as_deref(), Some("sk-test"));
+        let headers: Vec<_> = openai.expanded_headers().collect();
+        assert_eq!(
+            headers,
+            vec![("OpenAI-Project", "proj_default".to_string())]
+        );
+
+        let (backend, model) = resolve(&registry, "gpt-4.1").unwrap();
+        assert_eq!(
+            (backend.name.as_str(), model.as_str()),
+            ("openai", "gpt-4.1")
+        );
+        assert!(registry.resolve("xgpt-4").is_none());
+
+        // Regex captures rewrite the model, then upstream_model overrides it
+        let (backend, model) = resolve(&registry, "local/qwen").unwrap();
+        assert_eq!(
+            (backend.name.as_str(), model.as_str()),
+            ("local", "served-model")
+        );
+    }
+
+    #[test]
+    fn test_invalid_routes() {
+        let backend = "[[backends]]\nname = \"x\"\ncommand = \"x\"\n";
+        for route in [
+            "[[routes]]\nmatch = \"*\"\nbackend = \"missing\"\n",
+            "[[routes]]\nbackend = \"x\"\n",
+            "[[routes]]\nmatch = \"*\"\nregex = \".*\"\nbackend = \"x\"\n",
+            "[[routes]]\nregex = \"(\"\nbackend = \"x\"\n",
+        ] {
+            assert!(Registry::from_toml(&format!("{}{}", backend, route)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_groups() {
+        let registry = Registry::from_yaml(
+            r#"
+default_backend: local
+backends:
+  - { name: a, command: a, models: [shared] }
+  - { name: b, command: b }
+groups:
+  - name: local
+    strategy: least-in-flight
+    members: [a, b]
+    models: [shared]
+    model_prefixes: [loc-]
+health:
+  failure_threshold: 5
+"#,
+        )
+        .unwrap();
+
+        // Group aliases win over member aliases
+        let target = registry.resolve("shared").unwrap();
+        assert_eq!(target.name, "local");
+        assert_eq!(target.strategy, Strategy::LeastInFlight);
+        let members: Vec<&str> = target.members.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(members, vec!["a", "b"]);
+
+        assert_eq!(registry.resolve("loc-x").unwrap().name, "local");
+        assert_eq!(registry.resolve("anything").unwrap().name, "local");
+        assert_eq!(registry.health.failure_threshold, 5);
+        assert_eq!(registry.health.cooldown_secs, 30);
+
+        let backend = "[[backends]]\nname = \"a\"\ncommand = \"a\"\n";
+        for group in [
+            "[[groups]]\nname = \"a\"\nmembers = [\"a\"]\n",
+            "[[groups]]\nname = \"g\"\n",
+            "[[groups]]\nname = \"g\"\nmembers = [\"missing\"]\n",
+        ] {
+            assert!(Registry::from_toml(&format!("{}{}", backend, group)).is_err());
+        }
+    }
+}
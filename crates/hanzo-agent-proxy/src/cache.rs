@@ -0,0 +1,829 @@
+//! Response cache for deterministic requests
+//!
+//! Requests with `temperature: 0` are keyed on a hash of the normalized
+//! request: model, messages, tools, and sampling parameters, with tool call
+//! ids renumbered and transport fields such as `stream` dropped. Entries are
+//! kept in an in-memory LRU or as files in a directory and expire after a TTL.
+//! The directory is pruned after each write: expired files are removed and
+//! the oldest are evicted beyond the capacity.
+//! With an embeddings endpoint configured, a miss can be served from a
+//! near-duplicate request with the same model, tools, and parameters.
+
+use crate::stream::{self, StreamEvent};
+use crate::usage::{count_tokens, token_usage};
+use crate::{chat_response, unix_now, ChatRequest, ChatResponse, HTTP_CLIENT};
+use anyhow::{Context, Result};
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Request fields that don't affect the response
+const TRANSPORT_FIELDS: [&str; 4] = ["stream", "stream_options", "user", "metadata"];
+
+/// Where cached responses are kept
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum CacheBackend {
+    Memory,
+    Disk,
+}
+
+/// Embeddings endpoint for near-duplicate matching
+pub(crate) struct Embeddings {
+    /// OpenAI-compatible base URL (`.../v1`)
+    pub url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    /// Minimum cosine similarity for a hit
+    pub threshold: f32,
+}
+
+/// Cache key for one request
+#[derive(Clone, Debug)]
+pub(crate) struct CacheKey {
+    /// Hash of the whole normalized request
+    hash: String,
+    /// Hash of everything but the messages; near duplicates must share it
+    scope: String,
+    /// Conversation text that is embedded
+    text: String,
+    /// Embedding, once computed
+    vector: Option<Vec<f32>>,
+}
+
+/// How a request may use the cache, from its `Cache-Control` header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Directives {
+    pub lookup: bool,
+    pub store: bool,
+}
+
+impl Directives {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let values: Vec<String> = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|d| d.trim().to_ascii_lowercase())
+            .collect();
+        Self {
+            lookup: !values.iter().any(|d| d == "no-cache" || d == "no-store"),
+            store: !values.iter().any(|d| d == "no-store"),
+        }
+    }
+}
+
+/// Cache outcome reported in response headers
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum CacheStatus {
+    Hit {
+        /// Seconds since the response was stored
+        age: u64,
+        /// Similarity of a near-duplicate match
+        similarity: Option<f32>,
+    },
+    Miss,
+    /// Not cacheable, or the client sent `Cache-Control: no-cache`
+    Bypass,
+}
+
+impl CacheStatus {
+    /// Add `x-cache` (and for hits `age`) headers to a response
+    pub fn mark(&self, mut response: Response) -> Response {
+        let headers = response.headers_mut();
+        let status = match self {
+            CacheStatus::Hit { .. } => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Bypass => "BYPASS",
+        };
+        headers.insert("x-cache", HeaderValue::from_static(status));
+        if let CacheStatus::Hit { age, similarity } = *self {
+            headers.insert(header::AGE, age.into());
+            if let Some(similarity) = similarity {
+                if let Ok(value) = HeaderValue::from_str(&format!("{:.4}", similarity)) {
+                    headers.insert("x-cache-similarity", value);
+                }
+            }
+        }
+        response
+    }
+}
+
+/// Where a response is stored after a miss
+#[derive(Clone)]
+pub(crate) struct Slot {
+    cache: Arc<ResponseCache>,
+    key: CacheKey,
+}
+
+impl Slot {
+    pub async fn store(&self, response: &ChatResponse) {
+        self.cache.store(&self.key, response).await;
+    }
+
+    /// Store a streamed response once it finishes without error
+    pub fn capture(
+        &self,
+        model: &str,
+        prompt_tokens: u32,
+        rx: mpsc::Receiver<StreamEvent>,
+    ) -> mpsc::Receiver<StreamEvent> {
+        let (slot, model) = (self.clone(), model.to_string());
+        let mut text = String::new();
+        let mut tool_calls = vec![];
+        let mut failed = false;
+        stream::tap(rx, move |event| match event {
+            StreamEvent::Text(t) => text.push_str(t),
+            StreamEvent::ToolCall(call) => tool_calls.push(call.clone()),
+            StreamEvent::Error(_) => failed = true,
+            StreamEvent::Finish(reason) if !failed => {
+                let usage = token_usage(prompt_tokens, count_tokens(&text));
+                let calls = std::mem::take(&mut tool_calls);
+                let response =
+                    chat_response(&model, std::mem::take(&mut text), calls, reason, usage);
+                let slot = slot.clone();
+                tokio::spawn(async move { slot.store(&response).await });
+            }
+            StreamEvent::Finish(_) => {}
+        })
+    }
+}
+
+/// Result of checking the cache for a request
+#[derive(Default)]
+pub(crate) struct Lookup {
+    /// Cached response
+    pub hit: Option<ChatResponse>,
+    /// Reported outcome; `None` when caching is off
+    pub status: Option<CacheStatus>,
+    /// Where to store the response, for cacheable misses
+    pub slot: Option<Slot>,
+}
+
+impl Lookup {
+    /// Mark a response with the cache outcome, if caching is on
+    pub fn mark(&self, response: Response) -> Response {
+        match self.status {
+            Some(ref status) => status.mark(response),
+            None => response,
+        }
+    }
+}
+
+/// Check the cache for a request
+pub(crate) async fn lookup(
+    cache: Option<&Arc<ResponseCache>>,
+    request: &ChatRequest,
+    headers: &HeaderMap,
+) -> Lookup {
+    let Some(cache) = cache else {
+        return Lookup::default();
+    };
+    let directives = Directives::from_headers(headers);
+    let Some(mut key) = ResponseCache::key(request) else {
+        return Lookup {
+            status: Some(CacheStatus::Bypass),
+            ..Default::default()
+        };
+    };
+
+    let mut status = CacheStatus::Bypass;
+    if directives.lookup {
+        if let Some((response, hit)) = cache.get(&mut key).await {
+            return Lookup {
+                hit: Some(response),
+                status: Some(hit),
+                slot: None,
+            };
+        }
+        status = CacheStatus::Miss;
+    }
+    Lookup {
+        hit: None,
+        status: Some(status),
+        slot: directives.store.then(|| Slot {
+            cache: cache.clone(),
+            key,
+        }),
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    /// UNIX timestamp of the store
+    created: u64,
+    response: ChatResponse,
+}
+
+/// Least-recently-used map with a fixed capacity
+struct Lru {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, (u64, Entry)>,
+    /// Keys by last use
+    order: BTreeMap<u64, String>,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some((used, _)) = self.entries.get_mut(key) {
+            self.order.remove(used);
+            self.tick += 1;
+            *used = self.tick;
+            self.order.insert(self.tick, key.to_string());
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Entry> {
+        self.touch(key);
+        self.entries.get(key).map(|(_, entry)| entry.clone())
+    }
+
+    fn put(&mut self, key: &str, entry: Entry) {
+        self.tick += 1;
+        if let Some((used, _)) = self.entries.insert(key.to_string(), (self.tick, entry)) {
+            self.order.remove(&used);
+        }
+        self.order.insert(self.tick, key.to_string());
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((used, _)) = self.entries.remove(key) {
+            self.order.remove(&used);
+        }
+    }
+}
+
+enum Store {
+    Memory(Mutex<Lru>),
+    /// One `<hash>.json` file per entry
+    Disk(PathBuf),
+}
+
+/// A request embedding, for near-duplicate lookups
+struct Indexed {
+    scope: String,
+    vector: Vec<f32>,
+    hash: String,
+    created: u64,
+}
+
+/// Cache of chat responses
+pub(crate) struct ResponseCache {
+    store: Store,
+    /// Entries expire after this many seconds (0 keeps them)
+    ttl: u64,
+    embeddings: Option<Embeddings>,
+    index: Mutex<Vec<Indexed>>,
+    /// Largest number of embeddings kept
+    capacity: usize,
+}
+
+impl ResponseCache {
+    pub fn memory(capacity: usize, ttl: Duration) -> Self {
+        Self::new(Store::Memory(Mutex::new(Lru::new(capacity))), capacity, ttl)
+    }
+
+    pub fn disk(dir: PathBuf, capacity: usize, ttl: Duration) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("creating cache directory {}", dir.display()))?;
+        let cache = Self::new(Store::Disk(dir.clone()), capacity, ttl);
+        prune_dir(&dir, cache.capacity, cache.ttl);
+        Ok(cache)
+    }
+
+    fn new(store: Store, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            store,
+            ttl: ttl.as_secs(),
+            embeddings: None,
+            index: Mutex::new(vec![]),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Also serve near-duplicate requests, matched by embedding similarity
+    pub fn with_embeddings(mut self, embeddings: Embeddings) -> Self {
+        self.embeddings = Some(embeddings);
+        self
+    }
+
+    fn expired(&self, created: u64) -> bool {
+        self.ttl > 0 && unix_now().saturating_sub(created) >= self.ttl
+    }
+
+    /// Key for a request, or `None` if its response isn't deterministic
+    pub fn key(request: &ChatRequest) -> Option<CacheKey> {
+        let mut value = serde_json::to_value(request).ok()?;
+        let object = value.as_object_mut()?;
+        if object.get("temperature").and_then(Value::as_f64) != Some(0.0) {
+            return None;
+        }
+        if object
+            .get("n")
+            .and_then(Value::as_u64)
+            .is_some_and(|n| n > 1)
+        {
+            return None;
+        }
+        for field in TRANSPORT_FIELDS {
+            object.remove(field);
+        }
+
+        let mut messages = object.remove("messages").unwrap_or_default();
+        renumber_tool_calls(&mut messages);
+        let scope = digest(&value);
+        value["messages"] = messages;
+
+        let text = request
+            .messages
+            .iter()
+            .map(|m| format!("{}: {}", m.role, m.content.text()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Some(CacheKey {
+            hash: digest(&value),
+            scope,
+            text,
+            vector: None,
+        })
+    }
+
+    /// Cached response for a key, trying near duplicates on an exact miss
+    async fn get(&self, key: &mut CacheKey) -> Option<(ChatResponse, CacheStatus)> {
+        if let Some(entry) = self.load(&key.hash).await {
+            let age = unix_now().saturating_sub(entry.created);
+            let status = CacheStatus::Hit {
+                age,
+                similarity: None,
+            };
+            return Some((entry.response, status));
+        }
+
+        let embeddings = self.embeddings.as_ref()?;
+        let vector = match embed(embeddings, &key.text).await {
+            Ok(vector) => key.vector.insert(vector),
+            Err(e) => {
+                warn!(error = %e, "Cache embedding failed");
+                return None;
+            }
+        };
+        let (similarity, hash) = {
+            let index = self.index.lock().unwrap();
+            index
+                .iter()
+                .filter(|i| i.scope == key.scope && !self.expired(i.created))
+                .map(|i| (cosine(vector, &i.vector), i.hash.clone()))
+                .max_by(|a, b| a.0.total_cmp(&b.0))?
+        };
+        if similarity < embeddings.threshold {
+            return None;
+        }
+        debug!(similarity, "Near-duplicate cache hit");
+        let entry = self.load(&hash).await?;
+        let status = CacheStatus::Hit {
+            age: unix_now().saturating_sub(entry.created),
+            similarity: Some(similarity),
+        };
+        Some((entry.response, status))
+    }
+
+    async fn load(&self, hash: &str) -> Option<Entry> {
+        let entry = match self.store {
+            Store::Memory(ref lru) => lru.lock().unwrap().get(hash)?,
+            Store::Disk(ref dir) => {
+                let bytes = tokio::fs::read(dir.join(format!("{}.json", hash)))
+                    .await
+                    .ok()?;
+                serde_json::from_slice(&bytes).ok()?
+            }
+        };
+        if self.expired(entry.created) {
+            self.remove(hash).await;
+            return None;
+        }
+        Some(entry)
+    }
+
+    async fn remove(&self, hash: &str) {
+        match self.store {
+            Store::Memory(ref lru) => lru.lock().unwrap().remove(hash),
+            Store::Disk(ref dir) => {
+                let _ = tokio::fs::remove_file(dir.join(format!("{}.json", hash))).await;
+            }
+        }
+    }
+
+    /// Store a response under a key
+    async fn store(&self, key: &CacheKey, response: &ChatResponse) {
+        let entry = Entry {
+            created: unix_now(),
+            response: response.clone(),
+        };
+        let created = entry.created;
+        match self.store {
+            Store::Memory(ref lru) => lru.lock().unwrap().put(&key.hash, entry),
+            Store::Disk(ref dir) => {
+                if let Err(e) = write_entry(dir, &key.hash, &entry).await {
+                    warn!(error = %e, "Failed to write cache entry");
+                }
+                let (dir, capacity, ttl) = (dir.clone(), self.capacity, self.ttl);
+                let _ = tokio::task::spawn_blocking(move || prune_dir(&dir, capacity, ttl)).await;
+            }
+        }
+
+        if let Some(ref vector) = key.vector {
+            let mut index = self.index.lock().unwrap();
+            index.retain(|i| i.hash != key.hash && !self.expired(i.created));
+            if index.len() >= self.capacity {
+                index.remove(0);
+            }
+            index.push(Indexed {
+                scope: key.scope.clone(),
+                vector: vector.clone(),
+                hash: key.hash.clone(),
+                created,
+            });
+        }
+    }
+}
+
+/// Write an entry atomically
+async fn write_entry(dir: &Path, hash: &str, entry: &Entry) -> Result<()> {
+    let path = dir.join(format!("{}.json", hash));
+    let partial = dir.join(format!("{}.json.tmp", hash));
+    tokio::fs::write(&partial, serde_json::to_vec(entry)?).await?;
+    tokio::fs::rename(&partial, &path).await?;
+    Ok(())
+}
+
+/// Remove expired entries from a cache directory, then the oldest beyond `capacity`
+///
+/// Entries are aged by modification time, which is when they were written.
+fn prune_dir(dir: &Path, capacity: usize, ttl: u64) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!(error = %e, "Failed to list cache directory");
+            return;
+        }
+    };
+    let now = SystemTime::now();
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|p| Some((p.metadata().ok()?.modified().ok()?, p)))
+        .collect();
+    files.sort();
+
+    let expired = |modified: &SystemTime| {
+        ttl > 0
+            && now
+                .duration_since(*modified)
+                .is_ok_and(|age| age.as_secs() >= ttl)
+    };
+    let stale = files
+        .iter()
+        .take_while(|(modified, _)| expired(modified))
+        .count();
+    let evict = stale.max(files.len().saturating_sub(capacity));
+    for (_, path) in &files[..evict] {
+        if let Err(e) = std::fs::remove_file(path) {
+            debug!(error = %e, path = %path.display(), "Failed to remove cache entry");
+        }
+    }
+    if evict > 0 {
+        debug!(removed = evict, "Pruned cache directory");
+    }
+}
+
+/// Replace tool call ids with their order of appearance
+///
+/// Ids are generated per response, so identical conversations would
+/// otherwise never share a key.
+fn renumber_tool_calls(messages: &mut Value) {
+    let mut ids: HashMap<String, String> = HashMap::new();
+    let mut renumber = |id: &mut Value| {
+        if let Some(original) = id.as_str() {
+            let next = format!("call_{}", ids.len());
+            *id = ids
+                .entry(original.to_string())
+                .or_insert(next)
+                .clone()
+                .into();
+        }
+    };
+    for message in messages.as_array_mut().into_iter().flatten() {
+        for call in message["tool_calls"].as_array_mut().into_iter().flatten() {
+            renumber(&mut call["id"]);
+        }
+        if let Some(id) = message.get_mut("tool_call_id") {
+            renumber(id);
+        }
+    }
+}
+
+/// SHA-256 of a JSON value; object keys serialize in sorted order
+fn digest(value: &Value) -> String {
+    hex::encode(Sha256::digest(value.to_string().as_bytes()))
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// Embed a text with an OpenAI-compatible `/embeddings` endpoint
+async fn embed(embeddings: &Embeddings, text: &str) -> Result<Vec<f32>> {
+    let url = format!("{}/embeddings", embeddings.url.trim_end_matches('/'));
+    let mut request = HTTP_CLIENT
+        .post(&url)
+        .timeout(Duration::from_secs(10))
+        .json(&json!({"model": embeddings.model, "input": text}));
+    if let Some(ref key) = embeddings.api_key {
+        request = request.bearer_auth(key);
+    }
+    let body: Value = request.send().await?.error_for_status()?.json().await?;
+    serde_json::from_value(body["data"][0]["embedding"].clone())
+        .context("embeddings response has no data[0].embedding")
+}
+
+/// Events that replay a cached response as a stream
+pub(crate) fn replay(response: ChatResponse) -> mpsc::Receiver<StreamEvent> {
+    let choice = response.choices.into_iter().next();
+    let mut events = vec![];
+    let mut finish = "stop".to_string();
+    if let Some(choice) = choice {
+        if let Some(text) = choice.message.content.filter(|t| !t.is_empty()) {
+            events.push(StreamEvent::Text(text));
+        }
+        events.extend(
+            choice
+                .message
+                .tool_calls
+                .into_iter()
+                .map(StreamEvent::ToolCall),
+        );
+        finish = choice.finish_reason;
+    }
+    events.push(StreamEvent::Finish(finish));
+
+    let (tx, rx) = mpsc::channel(events.len());
+    for event in events {
+        let _ = tx.try_send(event);
+    }
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: Value) -> ChatRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn response(text: &str) -> ChatResponse {
+        chat_response("m", text.to_string(), vec![], "stop", token_usage(1, 1))
+    }
+
+    fn text(response: &ChatResponse) -> &str {
+        response.choices[0].message.content.as_deref().unwrap()
+    }
+
+    #[test]
+    fn test_key_normalization() {
+        let key = |body| ResponseCache::key(&request(body)).map(|k| k.hash);
+        let base = json!({
+            "model": "m",
+            "temperature": 0,
+            "messages": [
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_17_3", "type": "function", "function": {"name": "f", "arguments": "{}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_17_3", "content": "ok"}
+            ]
+        });
+        let mut other = base.clone();
+        other["stream"] = true.into();
+        other["user"] = "alice".into();
+        other["messages"][0]["tool_calls"][0]["id"] = "call_99_0".into();
+        other["messages"][1]["tool_call_id"] = "call_99_0".into();
+        assert!(key(base.clone()).is_some());
+        assert_eq!(key(base.clone()), key(other));
+
+        // Parameters and content change the key
+        let mut other = base.clone();
+        other["max_tokens"] = 10.into();
+        assert_ne!(key(base.clone()), key(other));
+        let mut other = base.clone();
+        other["messages"][1]["content"] = "failed".into();
+        assert_ne!(key(base.clone()), key(other));
+
+        // Only deterministic requests are cached
+        let mut other = base.clone();
+        other["temperature"] = 0.5.into();
+        assert_eq!(key(other), None);
+        let mut other = base.clone();
+        other["n"] = 2.into();
+        assert_eq!(key(other), None);
+        let mut other = base.clone();
+        other.as_object_mut().unwrap().remove("temperature");
+        assert_eq!(key(other), None);
+    }
+
+    #[tokio::test]
+    async fn test_lru_and_ttl() {
+        let cache = ResponseCache::memory(2, Duration::ZERO);
+        let mut keys: Vec<CacheKey> = ["a", "b", "c"]
+            .iter()
+            .map(|t| {
+                let body = json!({"temperature": 0, "messages": [{"role": "user", "content": t}]});
+                ResponseCache::key(&request(body)).unwrap()
+            })
+            .collect();
+        cache.store(&keys[0], &response("a")).await;
+        cache.store(&keys[1], &response("b")).await;
+        // Using "a" makes "b" the one evicted
+        assert!(cache.get(&mut keys[0]).await.is_some());
+        cache.store(&keys[2], &response("c")).await;
+        assert!(cache.get(&mut keys[1]).await.is_none());
+        let (hit, status) = cache.get(&mut keys[0]).await.unwrap();
+        assert_eq!(text(&hit), "a");
+        assert!(matches!(
+            status,
+            CacheStatus::Hit {
+                similarity: None,
+                ..
+            }
+        ));
+
+        // Entries past the TTL are dropped
+        let cache = ResponseCache::memory(2, Duration::from_secs(60));
+        let entry = Entry {
+            created: unix_now() - 61,
+            response: response("old"),
+        };
+        if let Store::Memory(ref lru) = cache.store {
+            lru.lock().unwrap().put(&keys[0].hash, entry);
+        }
+        assert!(cache.get(&mut keys[0]).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disk_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = json!({"temperature": 0, "messages": [{"role": "user", "content": "hi"}]});
+        let mut key = ResponseCache::key(&request(body)).unwrap();
+        let cache = ResponseCache::disk(dir.path().to_path_buf(), 10, Duration::ZERO).unwrap();
+        cache.store(&key, &response("saved")).await;
+
+        // A new cache over the same directory sees the entry
+        let cache = ResponseCache::disk(dir.path().to_path_buf(), 10, Duration::ZERO).unwrap();
+        let (hit, _) = cache.get(&mut key).await.unwrap();
+        assert_eq!(text(&hit), "saved");
+        assert!(dir.path().join(format!("{}.json", key.hash)).exists());
+    }
+
+    #[tokio::test]
+    async fn test_disk_pruning() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = || {
+            let mut names: Vec<String> = std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect();
+            names.sort();
+            names
+        };
+        let age = |hash: &str, secs: u64| {
+            let file = std::fs::File::options()
+                .write(true)
+                .open(dir.path().join(format!("{}.json", hash)))
+                .unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(secs))
+                .unwrap();
+        };
+        let keys: Vec<CacheKey> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|t| {
+                let body = json!({"temperature": 0, "messages": [{"role": "user", "content": t}]});
+                ResponseCache::key(&request(body)).unwrap()
+            })
+            .collect();
+        let cache =
+            ResponseCache::disk(dir.path().to_path_buf(), 2, Duration::from_secs(60)).unwrap();
+
+        // The oldest files are evicted beyond the capacity
+        cache.store(&keys[0], &response("a")).await;
+        cache.store(&keys[1], &response("b")).await;
+        age(&keys[0].hash, 20);
+        age(&keys[1].hash, 10);
+        cache.store(&keys[2], &response("c")).await;
+        let mut expected = vec![
+            format!("{}.json", keys[1].hash),
+            format!("{}.json", keys[2].hash),
+        ];
+        expected.sort();
+        assert_eq!(files(), expected);
+
+        // Expired files are removed even under the capacity
+        age(&keys[1].hash, 61);
+        age(&keys[2].hash, 61);
+        cache.store(&keys[3], &response("d")).await;
+        assert_eq!(files(), vec![format!("{}.json", keys[3].hash)]);
+
+        // Opening the directory prunes it too
+        age(&keys[3].hash, 61);
+        ResponseCache::disk(dir.path().to_path_buf(), 2, Duration::from_secs(60)).unwrap();
+        assert!(files().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_near_duplicates() {
+        use axum::{routing::post, Json, Router};
+
+        // Embeds texts mentioning "cat" and "dog" along different axes
+        let app = Router::new().route(
+            "/v1/embeddings",
+            post(|Json(body): Json<Value>| async move {
+                let input = body["input"].as_str().unwrap_or_default();
+                let cat = input.contains("cat") as u8 as f32;
+                let dog = input.contains("dog") as u8 as f32;
+                Json(json!({"data": [{"embedding": [cat, dog, 0.1]}]}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let cache = ResponseCache::memory(10, Duration::ZERO).with_embeddings(Embeddings {
+            url: format!("http://{}/v1", addr),
+            model: "embed".to_string(),
+            api_key: None,
+            threshold: 0.95,
+        });
+        let key = |content: &str, model: &str| {
+            let body = json!({
+                "model": model,
+                "temperature": 0,
+                "messages": [{"role": "user", "content": content}]
+            });
+            ResponseCache::key(&request(body)).unwrap()
+        };
+
+        // The first miss embeds the request so it can be matched later
+        let mut first = key("tell me about a cat", "m");
+        assert!(cache.get(&mut first).await.is_none());
+        cache.store(&first, &response("cats")).await;
+
+        let (hit, status) = cache
+            .get(&mut key("tell me about cats!", "m"))
+            .await
+            .unwrap();
+        assert_eq!(text(&hit), "cats");
+        assert!(matches!(status, CacheStatus::Hit { similarity: Some(s), .. } if s > 0.99));
+        assert!(cache
+            .get(&mut key("tell me about a dog", "m"))
+            .await
+            .is_none());
+        // Near duplicates must share the model and parameters
+        assert!(cache
+            .get(&mut key("tell me about cats!", "other"))
+            .await
+            .is_none());
+    }
+}
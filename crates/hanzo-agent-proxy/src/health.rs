@@ -0,0 +1,632 @@
+//! Backend health: probes, circuit breakers, and load balancing
+//!
+//! State is kept per backend name so it survives registry reloads. A backend
+//! is available unless its last probe failed or its circuit is open; open
+//! circuits let a single trial request through once the cooldown has passed,
+//! and are skipped again until that request is settled.
+//! Backends with `max_concurrency` hand out that many slots; further
+//! requests wait in a queue of up to `max_queue` or are refused.
+
+use crate::backends::{
+    Backend, HealthConfig, InputMode, Registry, RegistryHandle, Strategy, Target,
+};
+use crate::HTTP_CLIENT;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, warn};
+
+/// Circuit breaker state
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Circuit {
+    Closed,
+    Open,
+    /// Cooldown passed; one trial request may go through
+    HalfOpen,
+}
+
+#[derive(Default)]
+struct BackendState {
+    in_flight: usize,
+    /// Concurrency slots, with the limit they were created for
+    slots: Option<(usize, Arc<Semaphore>)>,
+    /// Requests waiting for a slot
+    queued: usize,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    /// A half-open circuit's trial request is running
+    trial_in_flight: bool,
+    probe: Option<ProbeStatus>,
+    last_error: Option<String>,
+}
+
+impl BackendState {
+    fn circuit(&self, config: &HealthConfig) -> Circuit {
+        match self.opened_at {
+            None => Circuit::Closed,
+            Some(at) if at.elapsed() < Duration::from_secs(config.cooldown_secs) => Circuit::Open,
+            Some(_) => Circuit::HalfOpen,
+        }
+    }
+
+    fn available(&self, config: &HealthConfig) -> bool {
+        let circuit = match self.circuit(config) {
+            Circuit::Closed => true,
+            Circuit::Open => false,
+            Circuit::HalfOpen => !self.trial_in_flight,
+        };
+        circuit && self.probe.as_ref().is_none_or(|p| p.healthy)
+    }
+
+    /// Start a request: `Some(true)` if it is a half-open circuit's trial,
+    /// `None` if another trial is already running
+    fn start_request(&mut self, config: &HealthConfig) -> Option<bool> {
+        match self.circuit(config) {
+            Circuit::HalfOpen if self.trial_in_flight => None,
+            Circuit::HalfOpen => {
+                self.trial_in_flight = true;
+                Some(true)
+            }
+            _ => Some(false),
+        }
+    }
+}
+
+/// Result of the last health probe
+#[derive(Clone, Serialize)]
+pub(crate) struct ProbeStatus {
+    healthy: bool,
+    /// UNIX timestamp of the probe
+    checked_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Reported state of one backend
+#[derive(Serialize)]
+pub(crate) struct BackendStatus {
+    name: String,
+    available: bool,
+    circuit: Circuit,
+    in_flight: usize,
+    queued: usize,
+    consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    probe: Option<ProbeStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+}
+
+impl BackendStatus {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn available(&self) -> bool {
+        self.available
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+}
+
+/// Tracks backend health and orders group members for each request
+#[derive(Default)]
+pub(crate) struct HealthMonitor {
+    states: Mutex<HashMap<String, BackendState>>,
+    /// Round-robin positions by group name
+    cursors: Mutex<HashMap<String, usize>>,
+}
+
+impl HealthMonitor {
+    fn with_state<T>(&self, name: &str, f: impl FnOnce(&mut BackendState) -> T) -> T {
+        let mut states = self.states.lock().unwrap();
+        f(states.entry(name.to_string()).or_default())
+    }
+
+    /// Backends to try for a target, in order
+    ///
+    /// Unavailable members are skipped; if none are available, all members
+    /// are tried as a last resort.
+    pub fn plan<'a>(&self, target: &Target<'a>, config: &HealthConfig) -> Vec<&'a Backend> {
+        let mut members: Vec<&'a Backend> = {
+            let states = self.states.lock().unwrap();
+            target
+                .members
+                .iter()
+                .copied()
+                .filter(|b| states.get(&b.name).is_none_or(|s| s.available(config)))
+                .collect()
+        };
+        if members.is_empty() {
+            members = target.members.clone();
+        }
+
+        match target.strategy {
+            Strategy::Priority => {}
+            Strategy::RoundRobin => {
+                let mut cursors = self.cursors.lock().unwrap();
+                let cursor = cursors.entry(target.name.to_string()).or_default();
+                let len = members.len();
+                members.rotate_left(*cursor % len);
+                *cursor = cursor.wrapping_add(1);
+            }
+            Strategy::LeastInFlight => {
+                let states = self.states.lock().unwrap();
+                members.sort_by_key(|b| states.get(&b.name).map_or(0, |s| s.in_flight));
+            }
+        }
+        members
+    }
+
+    /// Count a request in flight until the returned guard is dropped
+    pub fn begin(self: &Arc<Self>, name: &str) -> InFlight {
+        self.with_state(name, |s| s.in_flight += 1);
+        InFlight {
+            monitor: self.clone(),
+            name: name.to_string(),
+            trial: false,
+            _slot: None,
+        }
+    }
+
+    /// Take a concurrency slot on a backend, then count the request in flight
+    ///
+    /// Without a free slot the request waits if fewer than `max_queue`
+    /// requests already do, and is refused otherwise. A half-open backend
+    /// takes one trial request at a time and refuses others.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        backend: &Backend,
+        config: &HealthConfig,
+    ) -> Result<InFlight, Busy> {
+        let slot = self.take_slot(backend).await?;
+        let trial = self
+            .with_state(&backend.name, |s| s.start_request(config))
+            .ok_or_else(|| Busy(backend.name.clone()))?;
+        let mut guard = self.begin(&backend.name);
+        guard.trial = trial;
+        guard._slot = slot;
+        Ok(guard)
+    }
+
+    /// Wait for a concurrency slot on a backend with `max_concurrency`
+    async fn take_slot(&self, backend: &Backend) -> Result<Option<OwnedSemaphorePermit>, Busy> {
+        let Some(limit) = backend.max_concurrency else {
+            return Ok(None);
+        };
+        let limit = limit.max(1);
+        let slots = self.with_state(&backend.name, |s| match s.slots {
+            // A reload may change the limit; running requests keep their old slots
+            Some((size, ref slots)) if size == limit => slots.clone(),
+            _ => {
+                let slots = Arc::new(Semaphore::new(limit));
+                s.slots = Some((limit, slots.clone()));
+                slots
+            }
+        });
+
+        let slot = match slots.clone().try_acquire_owned() {
+            Ok(slot) => slot,
+            Err(_) => {
+                let queued = self.with_state(&backend.name, |s| {
+                    let room = s.queued < backend.max_queue;
+                    s.queued += room as usize;
+                    room
+                });
+                if !queued {
+                    return Err(Busy(backend.name.clone()));
+                }
+                let _waiting = Waiting {
+                    monitor: self,
+                    name: &backend.name,
+                };
+                debug!(backend = %backend.name, "Waiting for a free slot");
+                slots
+                    .acquire_owned()
+                    .await
+                    .map_err(|_| Busy(backend.name.clone()))?
+            }
+        };
+        Ok(Some(slot))
+    }
+
+    pub fn record_success(&self, name: &str) {
+        self.with_state(name, |s| {
+            s.consecutive_failures = 0;
+            s.opened_at = None;
+            s.trial_in_flight = false;
+        });
+    }
+
+    /// Count a failure, opening the circuit at the threshold
+    pub fn record_failure(&self, name: &str, config: &HealthConfig, error: &str) {
+        self.with_state(name, |s| {
+            s.consecutive_failures += 1;
+            s.last_error = Some(error.to_string());
+            s.trial_in_flight = false;
+            if s.consecutive_failures >= config.failure_threshold.max(1) {
+                if s.opened_at.is_none() {
+                    warn!(
+                        backend = name,
+                        failures = s.consecutive_failures,
+                        "Circuit opened"
+                    );
+                }
+                s.opened_at = Some(Instant::now());
+            }
+        });
+    }
+
+    pub fn record_probe(&self, name: &str, result: Result<(), String>) {
+        if let Err(ref e) = result {
+            debug!(backend = name, error = %e, "Health probe failed");
+        }
+        self.with_state(name, |s| {
+            s.probe = Some(ProbeStatus {
+                healthy: result.is_ok(),
+                checked_at: crate::unix_now(),
+                error: result.err(),
+            });
+        });
+    }
+
+    /// State of every backend in a registry
+    pub fn status(&self, registry: &Registry) -> Vec<BackendStatus> {
+        let states = self.states.lock().unwrap();
+        let empty = BackendState::default();
+        registry
+            .backends
+            .iter()
+            .map(|b| {
+                let state = states.get(&b.name).unwrap_or(&empty);
+                BackendStatus {
+                    name: b.name.clone(),
+                    available: state.available(&registry.health),
+                    circuit: state.circuit(&registry.health),
+                    in_flight: state.in_flight,
+                    queued: state.queued,
+                    consecutive_failures: state.consecutive_failures,
+                    probe: state.probe.clone(),
+                    last_error: state.last_error.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Probe every backend periodically in the background
+    pub fn spawn_probes(self: Arc<Self>, registry: Arc<RegistryHandle>) {
+        tokio::spawn(async move {
+            loop {
+                let snapshot = registry.snapshot();
+                let config = snapshot.health.clone();
+                if config.probe_interval_secs == 0 {
+                    // Probing may be enabled by a reload
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+
+                let timeout = Duration::from_secs(config.probe_timeout_secs);
+                let probes = snapshot
+                    .backends
+                    .iter()
+                    .map(|b| async move { (b.name.clone(), probe(b, timeout).await) });
+                for (name, result) in futures::future::join_all(probes).await {
+                    self.record_probe(&name, result);
+                }
+                tokio::time::sleep(Duration::from_secs(config.probe_interval_secs)).await;
+            }
+        });
+    }
+}
+
+/// Decrements a backend's in-flight count and frees its slot when dropped
+///
+/// A trial request that ends without a recorded outcome (e.g. a
+/// non-retryable error) lets the next request try again.
+pub(crate) struct InFlight {
+    monitor: Arc<HealthMonitor>,
+    name: String,
+    trial: bool,
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.monitor.with_state(&self.name, |s| {
+            s.in_flight = s.in_flight.saturating_sub(1);
+            if self.trial {
+                s.trial_in_flight = false;
+            }
+        });
+    }
+}
+
+/// Removes a request from a backend's queue when dropped
+struct Waiting<'a> {
+    monitor: &'a HealthMonitor,
+    name: &'a str,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.monitor
+            .with_state(self.name, |s| s.queued = s.queued.saturating_sub(1));
+    }
+}
+
+/// A backend at its concurrency limit with a full queue
+#[derive(Debug, thiserror::Error)]
+#[error("backend '{0}' is at capacity")]
+pub(crate) struct Busy(pub String);
+
+/// Check that a backend can serve requests
+///
+/// CLI backends need their command on `PATH`; HTTP backends must answer
+/// `GET {base_url}/models` successfully.
+pub(crate) async fn probe(backend: &Backend, timeout: Duration) -> Result<(), String> {
+    if backend.input_mode != InputMode::Http {
+        return find_executable(&backend.command)
+            .map(|_| ())
+            .ok_or_else(|| format!("command '{}' not found", backend.command));
+    }
+
+    let url = backend
+        .models_url()
+        .ok_or_else(|| "no base_url".to_string())?;
+    let mut request = HTTP_CLIENT.get(&url).timeout(timeout);
+    if let Some(key) = backend.api_key() {
+        request = request.bearer_auth(key);
+    }
+    for (name, value) in backend.expanded_headers() {
+        request = request.header(name, value);
+    }
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("GET {} returned {}", url, response.status()))
+    }
+}
+
+/// Locate a command, searching `PATH` for bare names
+fn find_executable(command: &str) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            path.metadata()
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        }
+        #[cfg(not(unix))]
+        {
+            path.is_file()
+        }
+    };
+
+    if command.contains(std::path::MAIN_SEPARATOR) {
+        let path = PathBuf::from(command);
+        return is_executable(&path).then_some(path);
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(command))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> Registry {
+        Registry::from_toml(
+            r#"
+[[backends]]
+name = "a"
+command = "a"
+
+[[backends]]
+name = "b"
+command = "b"
+
+[[backends]]
+name = "c"
+command = "c"
+
+[[groups]]
+name = "rr"
+strategy = "round-robin"
+members = ["a", "b", "c"]
+models = ["rr"]
+
+[[groups]]
+name = "least"
+strategy = "least-in-flight"
+members = ["a", "b", "c"]
+models = ["least"]
+
+[[groups]]
+name = "prio"
+members = ["a", "b", "c"]
+models = ["prio"]
+
+[health]
+failure_threshold = 2
+"#,
+        )
+        .unwrap()
+    }
+
+    fn names(backends: Vec<&Backend>) -> Vec<&str> {
+        backends.iter().map(|b| b.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let registry = registry();
+        let monitor = HealthMonitor::default();
+        let target = registry.resolve("rr").unwrap();
+        let firsts: Vec<String> = (0..4)
+            .map(|_| monitor.plan(&target, &registry.health)[0].name.clone())
+            .collect();
+        assert_eq!(firsts, vec!["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_least_in_flight() {
+        let registry = registry();
+        let monitor = Arc::new(HealthMonitor::default());
+        let target = registry.resolve("least").unwrap();
+
+        let _a = monitor.begin("a");
+        let _a2 = monitor.begin("a");
+        let b = monitor.begin("b");
+        assert_eq!(
+            names(monitor.plan(&target, &registry.health)),
+            vec!["c", "b", "a"]
+        );
+
+        drop(b);
+        assert_eq!(
+            names(monitor.plan(&target, &registry.health)),
+            vec!["b", "c", "a"]
+        );
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let mut registry = registry();
+        let monitor = HealthMonitor::default();
+
+        monitor.record_failure("a", &registry.health, "boom");
+        let target = registry.resolve("prio").unwrap();
+        assert_eq!(
+            names(monitor.plan(&target, &registry.health)),
+            vec!["a", "b", "c"]
+        );
+
+        // Second consecutive failure opens the circuit
+        monitor.record_failure("a", &registry.health, "boom");
+        assert_eq!(
+            names(monitor.plan(&target, &registry.health)),
+            vec!["b", "c"]
+        );
+        let status = monitor.status(&registry);
+        assert_eq!(status[0].circuit, Circuit::Open);
+        assert_eq!(status[0].last_error.as_deref(), Some("boom"));
+        drop(target);
+
+        // After the cooldown a trial request is allowed, and success closes it
+        registry.health.cooldown_secs = 0;
+        assert_eq!(monitor.status(&registry)[0].circuit, Circuit::HalfOpen);
+        monitor.record_success("a");
+        assert_eq!(monitor.status(&registry)[0].circuit, Circuit::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_half_open_single_trial() {
+        let mut registry = registry();
+        registry.health.cooldown_secs = 0;
+        let config = registry.health.clone();
+        let monitor = Arc::new(HealthMonitor::default());
+        let a = registry.backends[0].clone();
+        monitor.record_failure("a", &config, "boom");
+        monitor.record_failure("a", &config, "boom");
+        let target = registry.resolve("prio").unwrap();
+        assert_eq!(names(monitor.plan(&target, &config)), vec!["a", "b", "c"]);
+
+        // While the trial runs, other requests skip the backend
+        let trial = monitor.acquire(&a, &config).await.unwrap();
+        assert_eq!(names(monitor.plan(&target, &config)), vec!["b", "c"]);
+        assert!(monitor.acquire(&a, &config).await.is_err());
+
+        // A failed trial reopens the circuit; after the cooldown another may run
+        monitor.record_failure("a", &config, "still down");
+        drop(trial);
+        let trial = monitor.acquire(&a, &config).await.unwrap();
+        assert!(monitor.acquire(&a, &config).await.is_err());
+
+        // A trial that ends without an outcome frees the slot for another
+        drop(trial);
+        let trial = monitor.acquire(&a, &config).await.unwrap();
+        monitor.record_success("a");
+        let _second = monitor.acquire(&a, &config).await.unwrap();
+        drop(trial);
+        assert_eq!(monitor.status(&registry)[0].circuit, Circuit::Closed);
+    }
+
+    #[test]
+    fn test_failed_probes_and_last_resort() {
+        let registry = registry();
+        let monitor = HealthMonitor::default();
+        let target = registry.resolve("prio").unwrap();
+
+        monitor.record_probe("a", Err("down".to_string()));
+        assert_eq!(
+            names(monitor.plan(&target, &registry.health)),
+            vec!["b", "c"]
+        );
+
+        monitor.record_probe("b", Err("down".to_string()));
+        monitor.record_probe("c", Err("down".to_string()));
+        assert_eq!(
+            names(monitor.plan(&target, &registry.health)),
+            vec!["a", "b", "c"]
+        );
+        assert!(!monitor.status(&registry)[0].available());
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit() {
+        let monitor = Arc::new(HealthMonitor::default());
+        let backend = Backend {
+            name: "a".to_string(),
+            max_concurrency: Some(1),
+            max_queue: 1,
+            ..Default::default()
+        };
+
+        let config = HealthConfig::default();
+        let first = monitor.acquire(&backend, &config).await.unwrap();
+        let waiter = tokio::spawn({
+            let (monitor, backend, config) = (monitor.clone(), backend.clone(), config.clone());
+            async move { monitor.acquire(&backend, &config).await.map(|_| ()) }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let registry =
+            Registry::from_toml("[[backends]]\nname = \"a\"\ncommand = \"a\"\n").unwrap();
+        assert_eq!(monitor.status(&registry)[0].queued, 1);
+
+        // The queue is full, so a third request is refused
+        assert!(monitor.acquire(&backend, &config).await.is_err());
+
+        drop(first);
+        waiter.await.unwrap().unwrap();
+        let status = &monitor.status(&registry)[0];
+        assert_eq!((status.in_flight, status.queued), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_cli_probe() {
+        let backend = Backend {
+            name: "sh".to_string(),
+            command: "sh".to_string(),
+            ..Default::default()
+        };
+        assert!(probe(&backend, Duration::from_secs(1)).await.is_ok());
+
+        let missing = Backend {
+            command: "definitely-not-an-installed-cli".to_string(),
+            ..backend
+        };
+        assert!(probe(&missing, Duration::from_secs(1)).await.is_err());
+    }
+}
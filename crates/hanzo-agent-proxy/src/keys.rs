@@ -0,0 +1,432 @@
+//! Proxy API keys: authentication, allowed models, and per-key limits
+//!
+//! Keys are loaded from a TOML/YAML file or, with the `sqlite` feature, read
+//! from an `api_keys` table on every request so edits apply immediately.
+//! Without a key store the proxy is open and usage is recorded as
+//! `anonymous`.
+
+use crate::access::Access;
+use crate::backends::glob_to_regex;
+use crate::{anthropic, error_response, AppState};
+use anyhow::{bail, Context, Result};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// A proxy API key and its limits
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ApiKey {
+    /// Name usage is recorded under
+    pub name: String,
+    /// Secret sent as a bearer token or `x-api-key`
+    pub key: String,
+    /// Environment variable holding the secret, instead of `key`
+    pub key_env: Option<String>,
+    /// Model globs this key may use; empty allows every model
+    pub models: Vec<String>,
+    pub requests_per_minute: Option<u32>,
+    /// Prompt and completion tokens per calendar month (UTC)
+    pub monthly_tokens: Option<u64>,
+    /// May read usage for every key
+    pub admin: bool,
+    #[serde(skip)]
+    patterns: Vec<Regex>,
+}
+
+impl ApiKey {
+    /// Caller recorded when no key store is configured
+    fn anonymous() -> Self {
+        Self {
+            name: "anonymous".to_string(),
+            admin: true,
+            ..Default::default()
+        }
+    }
+
+    fn compile(&mut self) -> Result<()> {
+        self.patterns = self
+            .models
+            .iter()
+            .map(|glob| Regex::new(&glob_to_regex(glob)))
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("key '{}' has an invalid model pattern", self.name))?;
+        Ok(())
+    }
+
+    /// Whether this key may use a model
+    pub fn allows(&self, model: &str) -> bool {
+        self.patterns.is_empty() || self.patterns.iter().any(|p| p.is_match(model))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    keys: Vec<ApiKey>,
+}
+
+enum Source {
+    /// Keys by secret
+    File(HashMap<String, Arc<ApiKey>>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(Mutex<rusqlite::Connection>),
+}
+
+/// Request counts in the current one-minute window
+struct Window {
+    started: Instant,
+    requests: u32,
+}
+
+/// Looks up API keys and enforces their request rates
+#[derive(Default)]
+pub(crate) struct KeyStore {
+    source: Option<Source>,
+    /// Rate windows by key name
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl KeyStore {
+    /// Store for a key file, or a SQLite database (`.db`, `.sqlite`, `.sqlite3`)
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let source = match path.extension().and_then(|e| e.to_str()) {
+            Some("db" | "sqlite" | "sqlite3") => sqlite_source(path)?,
+            extension => {
+                let contents = std::fs::read_to_string(path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+                let file: KeyFile = match extension {
+                    Some("yaml" | "yml") => serde_yaml::from_str(&contents)?,
+                    _ => toml::from_str(&contents)?,
+                };
+                Source::File(
+                    keys_by_secret(file.keys)
+                        .with_context(|| format!("loading {}", path.display()))?,
+                )
+            }
+        };
+        Ok(Self {
+            source: Some(source),
+            ..Default::default()
+        })
+    }
+
+    /// Whether requests must present a key
+    pub fn enabled(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Key matching a secret
+    pub fn lookup(&self, secret: &str) -> Result<Option<Arc<ApiKey>>> {
+        match self.source {
+            None => Ok(None),
+            Some(Source::File(ref keys)) => Ok(keys.get(secret).cloned()),
+            #[cfg(feature = "sqlite")]
+            Some(Source::Sqlite(ref db)) => sqlite_lookup(&db.lock().unwrap(), secret),
+        }
+    }
+
+    /// Count a request against a key's rate, returning the wait if over it
+    pub fn check_rate(&self, key: &ApiKey) -> Result<(), Duration> {
+        let Some(limit) = key.requests_per_minute else {
+            return Ok(());
+        };
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(key.name.clone()).or_insert(Window {
+            started: Instant::now(),
+            requests: 0,
+        });
+        let elapsed = window.started.elapsed();
+        if elapsed >= Duration::from_secs(60) {
+            *window = Window {
+                started: Instant::now(),
+                requests: 0,
+            };
+        } else if window.requests >= limit {
+            return Err(Duration::from_secs(60) - elapsed);
+        }
+        window.requests += 1;
+        Ok(())
+    }
+}
+
+/// Validate keys, resolving `key_env` secrets
+fn keys_by_secret(keys: Vec<ApiKey>) -> Result<HashMap<String, Arc<ApiKey>>> {
+    let mut by_secret = HashMap::new();
+    let mut names = std::collections::HashSet::new();
+    for mut key in keys {
+        if key.name.is_empty() {
+            bail!("API key without a name");
+        }
+        if !names.insert(key.name.clone()) {
+            bail!("duplicate API key name '{}'", key.name);
+        }
+        if let Some(ref var) = key.key_env {
+            key.key = std::env::var(var).unwrap_or_default();
+        }
+        if key.key.is_empty() {
+            bail!("API key '{}' has no secret", key.name);
+        }
+        key.compile()?;
+        if by_secret.insert(key.key.clone(), Arc::new(key)).is_some() {
+            bail!("two API keys share a secret");
+        }
+    }
+    Ok(by_secret)
+}
+
+#[cfg(feature = "sqlite")]
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS api_keys (
+    key TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    models TEXT NOT NULL DEFAULT '',
+    requests_per_minute INTEGER,
+    monthly_tokens INTEGER,
+    admin INTEGER NOT NULL DEFAULT 0
+)";
+
+#[cfg(feature = "sqlite")]
+fn sqlite_source(path: &Path) -> Result<Source> {
+    let db =
+        rusqlite::Connection::open(path).with_context(|| format!("opening {}", path.display()))?;
+    db.execute(SCHEMA, [])?;
+    Ok(Source::Sqlite(Mutex::new(db)))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_source(path: &Path) -> Result<Source> {
+    bail!(
+        "{} is a SQLite database, but hanzo-proxy was built without the `sqlite` feature",
+        path.display()
+    )
+}
+
+/// Key row matching a secret; `models` is a comma-separated list of globs
+#[cfg(feature = "sqlite")]
+fn sqlite_lookup(db: &rusqlite::Connection, secret: &str) -> Result<Option<Arc<ApiKey>>> {
+    use rusqlite::OptionalExtension;
+
+    let key = db
+        .query_row(
+            "SELECT name, models, requests_per_minute, monthly_tokens, admin
+             FROM api_keys WHERE key = ?1",
+            [secret],
+            |row| {
+                let models: String = row.get(1)?;
+                Ok(ApiKey {
+                    name: row.get(0)?,
+                    key: secret.to_string(),
+                    models: models
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|m| !m.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                    requests_per_minute: row.get(2)?,
+                    monthly_tokens: row.get(3)?,
+                    admin: row.get(4)?,
+                    ..Default::default()
+                })
+            },
+        )
+        .optional()?;
+    let Some(mut key) = key else {
+        return Ok(None);
+    };
+    key.compile()?;
+    Ok(Some(Arc::new(key)))
+}
+
+/// Secret from `Authorization: Bearer` or Anthropic's `x-api-key`
+fn secret(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+        .map(str::trim)
+}
+
+/// Error in the format of the endpoint being called
+pub(crate) fn reject(path: &str, status: StatusCode, message: String, code: &str) -> Response {
+    if path.starts_with("/v1/messages") {
+        anthropic::error(status, message).into_response()
+    } else {
+        let r#type = match status {
+            StatusCode::UNAUTHORIZED => "authentication_error",
+            StatusCode::FORBIDDEN => "permission_error",
+            StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+            _ => "server_error",
+        };
+        error_response(status, message, r#type, code).into_response()
+    }
+}
+
+/// Authenticate a request and check its key's limits on completions
+///
+/// The caller's [`ApiKey`] is added to the request extensions.
+pub(crate) async fn authenticate(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    if !state.keys.enabled() {
+        request
+            .extensions_mut()
+            .insert(Arc::new(ApiKey::anonymous()));
+        return next.run(request).await;
+    }
+
+    let key = match secret(request.headers()).map(|s| state.keys.lookup(s)) {
+        Some(Ok(Some(key))) => key,
+        Some(Err(e)) => {
+            warn!(error = %e, "API key lookup failed");
+            let message = "API key lookup failed".to_string();
+            return reject(
+                &path,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                message,
+                "internal_error",
+            );
+        }
+        _ => {
+            let message = "Missing or invalid API key".to_string();
+            return reject(&path, StatusCode::UNAUTHORIZED, message, "invalid_api_key");
+        }
+    };
+    if let Some(access) = request.extensions().get::<Access>() {
+        access.key(&key.name);
+    }
+
+    // Limits apply to completions, so a limited key can still read its usage
+    if request.method() != Method::POST {
+        request.extensions_mut().insert(key);
+        return next.run(request).await;
+    }
+    if let Some(quota) = key.monthly_tokens {
+        if state.usage.month_tokens(&key.name) >= quota {
+            let message = format!(
+                "Key '{}' has used its monthly quota of {} tokens",
+                key.name, quota
+            );
+            return reject(
+                &path,
+                StatusCode::TOO_MANY_REQUESTS,
+                message,
+                "insufficient_quota",
+            );
+        }
+    }
+    if let Err(wait) = state.keys.check_rate(&key) {
+        let message = format!("Key '{}' is over its rate limit", key.name);
+        let mut response = reject(
+            &path,
+            StatusCode::TOO_MANY_REQUESTS,
+            message,
+            "rate_limit_exceeded",
+        );
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, (wait.as_secs() + 1).into());
+        return response;
+    }
+
+    request.extensions_mut().insert(key);
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_file() {
+        std::env::set_var("PROXY_TEST_KEY_BOB", "sk-bob");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.toml");
+        std::fs::write(
+            &path,
+            r#"
+[[keys]]
+name = "alice"
+key = "sk-alice"
+models = ["claude-*", "gemini"]
+requests_per_minute = 2
+
+[[keys]]
+name = "bob"
+key_env = "PROXY_TEST_KEY_BOB"
+admin = true
+"#,
+        )
+        .unwrap();
+        let store = KeyStore::from_file(&path).unwrap();
+        assert!(store.enabled());
+
+        let alice = store.lookup("sk-alice").unwrap().unwrap();
+        assert!(alice.allows("claude-opus"));
+        assert!(alice.allows("gemini"));
+        assert!(!alice.allows("gemini-2.5-pro"));
+        assert!(store.check_rate(&alice).is_ok());
+        assert!(store.check_rate(&alice).is_ok());
+        assert!(store.check_rate(&alice).unwrap_err() <= Duration::from_secs(60));
+
+        let bob = store.lookup("sk-bob").unwrap().unwrap();
+        assert!(bob.admin && bob.allows("anything"));
+        assert!(store.check_rate(&bob).is_ok());
+        assert!(store.lookup("sk-nobody").unwrap().is_none());
+
+        for keys in [
+            "[[keys]]\nkey = \"k\"\n",
+            "[[keys]]\nname = \"a\"\n",
+            "[[keys]]\nname = \"a\"\nkey = \"k\"\n[[keys]]\nname = \"a\"\nkey = \"j\"\n",
+            "[[keys]]\nname = \"a\"\nkey = \"k\"\n[[keys]]\nname = \"b\"\nkey = \"k\"\n",
+        ] {
+            std::fs::write(&path, keys).unwrap();
+            assert!(KeyStore::from_file(&path).is_err(), "{}", keys);
+        }
+    }
+
+    #[test]
+    fn test_secret_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(secret(&headers), None);
+        headers.insert("x-api-key", "sk-anthropic".parse().unwrap());
+        assert_eq!(secret(&headers), Some("sk-anthropic"));
+        headers.insert(header::AUTHORIZATION, "Bearer sk-openai".parse().unwrap());
+        assert_eq!(secret(&headers), Some("sk-openai"));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.db");
+        let store = KeyStore::from_file(&path).unwrap();
+        assert!(store.lookup("sk-carol").unwrap().is_none());
+
+        // Rows added while running are picked up
+        let db = rusqlite::Connection::open(&path).unwrap();
+        db.execute(
+            "INSERT INTO api_keys (key, name, models, monthly_tokens) VALUES ('sk-carol', 'carol', 'qwen*, ollama', 1000)",
+            [],
+        )
+        .unwrap();
+        let carol = store.lookup("sk-carol").unwrap().unwrap();
+        assert_eq!(carol.name, "carol");
+        assert_eq!(carol.monthly_tokens, Some(1000));
+        assert!(carol.allows("qwen-plus") && carol.allows("ollama"));
+        assert!(!carol.allows("claude-cli") && !carol.admin);
+    }
+}
@@ -8,23 +8,56 @@
 //! - Qwen CLI (qwen) - Alibaba account
 //! - Ollama (ollama) - Local models
 //! - Hanzo Engine (hanzo) - Local inference engine (OpenAI-compatible HTTP)
+//!
+//! Any OpenAI-compatible HTTP upstream can be added through the backend
+//! registry (see `backends.toml`).
+
+mod access;
+mod anthropic;
+mod backends;
+mod cache;
+mod health;
+mod keys;
+mod metrics;
+mod sandbox;
+mod sessions;
+mod stream;
+#[cfg(test)]
+mod testing;
+mod tools;
+mod usage;
 
+use access::{Access, AccessLog};
 use anyhow::Result;
 use axum::{
-    http::StatusCode,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
+use backends::{Backend, HealthConfig, InputMode, RegistryHandle, Target};
+use cache::{CacheBackend, Embeddings, ResponseCache};
 use clap::Parser;
+use hanzo_guard::middleware::{ApiFormat, GuardLayer};
+use hanzo_guard::Guard;
+use health::{BackendStatus, Busy, HealthMonitor, InFlight};
+use keys::{ApiKey, KeyStore};
+use sandbox::Process;
 use serde::{Deserialize, Serialize};
+use sessions::SessionStore;
+use std::future::Future;
+use std::path::PathBuf;
 use std::process::Stdio;
-use tokio::io::AsyncWriteExt;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use stream::StreamEvent;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::process::Command;
+use tokio::sync::mpsc;
 use tower_http::cors::CorsLayer;
 use tracing::{error, info, warn};
-
-/// Default Hanzo engine URL (can be overridden with HANZO_ENGINE_URL env var)
-const DEFAULT_HANZO_ENGINE_URL: &str = "http://localhost:8080";
+use usage::{count_tokens, StreamMeter, Totals, UsageLedger, UsageRecord};
 
 /// CLI proxy server arguments
 #[derive(Parser, Debug)]
@@ -34,332 +67,640 @@ struct Args {
     /// Port to listen on
     #[arg(short, long, default_value = "9999")]
     port: u16,
-}
 
-/// Backend configuration for a CLI tool
-#[derive(Clone)]
-struct Backend {
-    name: &'static str,
-    command: &'static str,
-    input_mode: InputMode,
-    models: Vec<&'static str>,
-    api_keys_to_clean: Vec<&'static str>,
-}
-
-#[derive(Clone, Copy, PartialEq, Debug)]
-enum InputMode {
-    Stdin,
-    Args,
-    Http, // Direct HTTP passthrough to local server
-}
-
-impl Backend {
-    fn build_args(&self, prompt: &str, model: &str) -> Vec<String> {
-        match self.name {
-            "claude" => {
-                let mut args = vec!["-p".to_string(), "--output-format".to_string(), "text".to_string()];
-                if !model.is_empty() && model != "claude-cli" && model.starts_with("claude-") {
-                    args.push("--model".to_string());
-                    args.push(model.to_string());
-                }
-                args
-            }
-            "codex" => {
-                let mut args = vec!["-q".to_string(), "--full-auto".to_string()];
-                if !model.is_empty() && model != "codex" && model.starts_with("codex-") {
-                    args.push("-m".to_string());
-                    args.push(model.to_string());
-                }
-                args.push(prompt.to_string());
-                args
-            }
-            "gemini" => {
-                let mut args = vec!["-p".to_string(), prompt.to_string(), "-y".to_string()];
-                if !model.is_empty() && model != "gemini" && model.starts_with("gemini-") {
-                    args.push("-m".to_string());
-                    args.push(model.to_string());
-                }
-                args
-            }
-            "vibe" => {
-                vec![
-                    "-p".to_string(),
-                    prompt.to_string(),
-                    "--output".to_string(),
-                    "text".to_string(),
-                    "--auto-approve".to_string(),
-                ]
-            }
-            "qwen" => {
-                vec!["-p".to_string(), prompt.to_string()]
-            }
-            "ollama" => {
-                let model_to_use = if model.is_empty() { "llama3.2" } else { model };
-                vec!["run".to_string(), model_to_use.to_string(), prompt.to_string()]
-            }
-            "hanzo" => {
-                // Hanzo uses HTTP passthrough, not CLI args
-                vec![]
-            }
-            _ => vec![prompt.to_string()],
-        }
-    }
-}
+    /// Sanitize chat completions and messages with hanzo-guard (PII, injection, rate limits)
+    #[arg(long)]
+    guard: bool,
 
-/// All supported backends
-fn get_backends() -> Vec<Backend> {
-    vec![
-        Backend {
-            name: "claude",
-            command: "claude",
-            input_mode: InputMode::Stdin,
-            models: vec!["claude-cli", "claude-opus", "claude-sonnet", "claude-haiku"],
-            api_keys_to_clean: vec!["ANTHROPIC_API_KEY", "ANTHROPIC_AUTH_TOKEN"],
-        },
-        Backend {
-            name: "codex",
-            command: "codex",
-            input_mode: InputMode::Args,
-            models: vec!["codex", "codex-mini", "codex-mini-latest", "gpt-4o", "gpt-4o-mini", "o1", "o3-mini"],
-            api_keys_to_clean: vec!["OPENAI_API_KEY", "OPENAI_ORG_ID"],
-        },
-        Backend {
-            name: "gemini",
-            command: "gemini",
-            input_mode: InputMode::Args,
-            models: vec!["gemini", "gemini-2.5-pro", "gemini-2.0-flash", "gemini-1.5-pro"],
-            api_keys_to_clean: vec!["GOOGLE_API_KEY", "GEMINI_API_KEY"],
-        },
-        Backend {
-            name: "vibe",
-            command: "vibe",
-            input_mode: InputMode::Args,
-            models: vec!["vibe", "mistral", "mistral-large", "mistral-small"],
-            api_keys_to_clean: vec!["MISTRAL_API_KEY"],
-        },
-        Backend {
-            name: "qwen",
-            command: "qwen",
-            input_mode: InputMode::Args,
-            models: vec!["qwen", "qwen-cli", "qwen-plus", "qwen-turbo", "qwen3"],
-            api_keys_to_clean: vec!["DASHSCOPE_API_KEY", "QWEN_API_KEY"],
-        },
-        Backend {
-            name: "ollama",
-            command: "ollama",
-            input_mode: InputMode::Args,
-            models: vec!["ollama", "llama3.2", "llama3.1", "codellama", "mixtral", "phi3"],
-            api_keys_to_clean: vec![],
-        },
-        Backend {
-            name: "hanzo",
-            command: "", // Not used - hanzo uses HTTP passthrough
-            input_mode: InputMode::Http,
-            models: vec!["hanzo", "hanzo-engine", "hanzo-local", "default"],
-            api_keys_to_clean: vec![],
-        },
-    ]
-}
-
-/// Find the right backend for a model
-fn get_backend_for_model(model: &str) -> Backend {
-    let backends = get_backends();
-
-    // Direct match
-    for backend in &backends {
-        if backend.models.contains(&model) {
-            return backend.clone();
-        }
-    }
+    /// Backend registry file (TOML or YAML); defaults to the built-in backends
+    #[arg(short, long)]
+    config: Option<PathBuf>,
 
-    // Prefix matching
-    if model.starts_with("claude") {
-        return backends.into_iter().find(|b| b.name == "claude").unwrap();
-    }
-    if model.starts_with("codex") || model.starts_with("gpt") || model.starts_with("o1") || model.starts_with("o3") {
-        return backends.into_iter().find(|b| b.name == "codex").unwrap();
-    }
-    if model.starts_with("gemini") {
-        return backends.into_iter().find(|b| b.name == "gemini").unwrap();
-    }
-    if model.starts_with("vibe") || model.starts_with("mistral") {
-        return backends.into_iter().find(|b| b.name == "vibe").unwrap();
-    }
-    if model.starts_with("qwen") {
-        return backends.into_iter().find(|b| b.name == "qwen").unwrap();
-    }
-    if model.starts_with("llama") || model.starts_with("ollama") || model.starts_with("phi") {
-        return backends.into_iter().find(|b| b.name == "ollama").unwrap();
-    }
-    if model.starts_with("hanzo") {
-        return backends.into_iter().find(|b| b.name == "hanzo").unwrap();
-    }
+    /// Seconds between checks for registry file changes (0 disables reloading)
+    #[arg(long, default_value = "2")]
+    reload_interval: u64,
+
+    /// API keys file (TOML or YAML), or a SQLite database with an `api_keys`
+    /// table; without it the proxy accepts any request
+    #[arg(long)]
+    keys: Option<PathBuf>,
+
+    /// Append per-request usage to this JSONL file and restore totals from it
+    #[arg(long)]
+    usage_log: Option<PathBuf>,
+
+    /// Cache responses to `temperature: 0` requests in memory or on disk
+    #[arg(long, value_enum)]
+    cache: Option<CacheBackend>,
+
+    /// Directory for the disk cache
+    #[arg(long, default_value = ".hanzo-proxy-cache")]
+    cache_dir: PathBuf,
 
-    // Default to Claude
-    backends.into_iter().find(|b| b.name == "claude").unwrap()
+    /// Entries kept by the cache, in memory or on disk (and embeddings kept for matching)
+    #[arg(long, default_value = "1000")]
+    cache_capacity: usize,
+
+    /// Seconds before cached responses expire (0 keeps them)
+    #[arg(long, default_value = "3600")]
+    cache_ttl: u64,
+
+    /// OpenAI-compatible base URL for embeddings, to also serve near-duplicate requests
+    #[arg(long)]
+    cache_embeddings_url: Option<String>,
+
+    /// Embedding model for near-duplicate matching
+    #[arg(long, default_value = "text-embedding-3-small")]
+    cache_embeddings_model: String,
+
+    /// Environment variable holding the embeddings API key
+    #[arg(long)]
+    cache_embeddings_key_env: Option<String>,
+
+    /// Minimum cosine similarity for a near-duplicate hit
+    #[arg(long, default_value = "0.97")]
+    cache_similarity: f32,
+
+    /// Seconds before an idle `x-conversation-id` session is forgotten
+    #[arg(long, default_value = "1800")]
+    session_idle: u64,
+
+    /// Write JSON access logs to this file (`-` for stdout)
+    #[arg(long)]
+    access_log: Option<PathBuf>,
 }
 
-/// Extract clean response from CLI output (filter telemetry/JSON)
-fn extract_response(output: &str) -> String {
-    let mut text_lines = Vec::new();
-    let mut in_json = false;
-    let mut brace_count = 0;
+/// State shared by all handlers
+#[derive(Clone)]
+struct AppState {
+    registry: Arc<RegistryHandle>,
+    health: Arc<HealthMonitor>,
+    keys: Arc<KeyStore>,
+    usage: Arc<UsageLedger>,
+    cache: Option<Arc<ResponseCache>>,
+    sessions: Arc<SessionStore>,
+    access_log: Option<Arc<AccessLog>>,
+}
 
-    for line in output.lines() {
-        let trimmed = line.trim();
+/// Line-by-line filter that drops telemetry JSON blocks and artifacts from CLI output
+#[derive(Default)]
+struct LineFilter {
+    in_json: bool,
+    brace_count: i32,
+}
 
-        // Skip empty lines at start
-        if trimmed.is_empty() && text_lines.is_empty() {
-            continue;
-        }
+impl LineFilter {
+    /// Whether a line of CLI output is part of the response text
+    fn accept(&mut self, line: &str) -> bool {
+        let trimmed = line.trim();
 
         // Track JSON blocks
         if trimmed.starts_with('{') {
-            in_json = true;
-            brace_count = 1;
-            continue;
+            self.brace_count =
+                trimmed.matches('{').count() as i32 - trimmed.matches('}').count() as i32;
+            self.in_json = self.brace_count > 0;
+            return false;
         }
 
-        if in_json {
-            brace_count += trimmed.matches('{').count() as i32;
-            brace_count -= trimmed.matches('}').count() as i32;
-            if brace_count <= 0 {
-                in_json = false;
+        if self.in_json {
+            self.brace_count += trimmed.matches('{').count() as i32;
+            self.brace_count -= trimmed.matches('}').count() as i32;
+            if self.brace_count <= 0 {
+                self.in_json = false;
             }
-            continue;
+            return false;
         }
 
         // Skip JSON artifacts
-        if trimmed.chars().all(|c| matches!(c, '[' | ']' | '{' | '}' | ',' | ' ')) {
-            continue;
+        if trimmed
+            .chars()
+            .all(|c| matches!(c, '[' | ']' | '{' | '}' | ',' | ' '))
+        {
+            return false;
         }
 
-        if !trimmed.is_empty() && !trimmed.starts_with('}') {
-            text_lines.push(line.to_string());
-        }
+        !trimmed.is_empty() && !trimmed.starts_with('}')
     }
+}
+
+/// Extract clean response from CLI output (filter telemetry/JSON)
+fn extract_response(output: &str) -> String {
+    let mut filter = LineFilter::default();
+    let text_lines: Vec<&str> = output.lines().filter(|line| filter.accept(line)).collect();
 
     let result = text_lines.join("\n").trim().to_string();
     // Clean trailing brackets
-    result.trim_end_matches(|c: char| matches!(c, '[' | ']' | '{' | '}' | ' ')).to_string()
+    result
+        .trim_end_matches(['[', ']', '{', '}', ' '])
+        .to_string()
 }
 
-/// Invoke Hanzo engine via HTTP passthrough
-async fn invoke_hanzo(request: &ChatRequest) -> Result<ChatResponse> {
-    let hanzo_url = std::env::var("HANZO_ENGINE_URL")
-        .unwrap_or_else(|_| DEFAULT_HANZO_ENGINE_URL.to_string());
-
-    // Get the model - use env var default or fallback to "default" (hanzo engine interprets this)
-    let default_model = std::env::var("HANZO_DEFAULT_MODEL")
-        .unwrap_or_else(|_| "default".to_string());
-
-    let endpoint = format!("{}/v1/chat/completions", hanzo_url);
-
-    info!(url = %endpoint, model = %default_model, "Forwarding to Hanzo engine");
+/// Shared client for HTTP upstreams
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// Build a request to an HTTP backend's chat completions endpoint
+///
+/// The full client request is forwarded with `model` and `stream` replaced.
+fn upstream_request(
+    backend: &Backend,
+    request: &ChatRequest,
+    model: &str,
+    stream: bool,
+) -> Result<reqwest::RequestBuilder> {
+    let endpoint = backend
+        .endpoint()
+        .ok_or_else(|| anyhow::anyhow!("backend '{}' has no base_url", backend.name))?;
+
+    info!(backend = %backend.name, url = %endpoint, model, stream, "Forwarding to HTTP upstream");
+
+    let mut body = serde_json::to_value(request)?;
+    body["model"] = model.into();
+    body["stream"] = stream.into();
+
+    let mut builder = HTTP_CLIENT.post(&endpoint).json(&body);
+    if let Some(key) = backend.api_key() {
+        builder = builder.bearer_auth(key);
+    }
+    for (name, value) in backend.expanded_headers() {
+        builder = builder.header(name, value);
+    }
+    Ok(builder)
+}
 
-    let client = reqwest::Client::new();
+/// Send a request to an HTTP backend, failing on non-success statuses
+async fn send_upstream(
+    backend: &Backend,
+    builder: reqwest::RequestBuilder,
+) -> Result<reqwest::Response> {
+    let response = builder.send().await?;
 
-    // Build the request body
-    let body = serde_json::json!({
-        "model": default_model,
-        "messages": request.messages.iter().map(|m| {
-            serde_json::json!({
-                "role": m.role,
-                "content": m.content
-            })
-        }).collect::<Vec<_>>()
-    });
-    
-    let response = client
-        .post(&endpoint)
-        .header("Content-Type", "application/json")
-        .header("Authorization", "Bearer EMPTY")
-        .json(&body)
-        .send()
-        .await?;
-    
     if !response.status().is_success() {
         let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        anyhow::bail!("Hanzo engine error ({}): {}", status, error_text);
+        let body = response.text().await.unwrap_or_default();
+        return Err(UpstreamError {
+            backend: backend.name.clone(),
+            status: status.as_u16(),
+            body,
+        }
+        .into());
+    }
+    Ok(response)
+}
+
+/// Non-success response from an HTTP upstream
+#[derive(Debug, thiserror::Error)]
+#[error("{backend} upstream error ({status}): {body}")]
+struct UpstreamError {
+    backend: String,
+    status: u16,
+    body: String,
+}
+
+impl UpstreamError {
+    /// Client errors other than timeouts and rate limits are the caller's fault
+    fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.status) && !matches!(self.status, 408 | 429)
     }
-    
-    let hanzo_response: ChatResponse = response.json().await?;
-    Ok(hanzo_response)
 }
 
-/// Invoke CLI backend and get response
-async fn invoke_cli(prompt: &str, model: &str) -> Result<String> {
-    let backend = get_backend_for_model(model);
+/// Whether a failed request should count against the backend and be retried
+fn is_retryable(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<UpstreamError>()
+        .is_none_or(|u| !u.is_client_error())
+}
+
+/// Invoke an HTTP backend (OpenAI-compatible passthrough)
+async fn invoke_http(
+    backend: &Backend,
+    request: &ChatRequest,
+    model: &str,
+) -> Result<ChatResponse> {
+    let builder = upstream_request(backend, request, model, false)?;
+    let response = send_upstream(backend, builder).await?;
+    Ok(response.json().await?)
+}
+
+/// Spawn a CLI backend with stdout and stderr piped
+///
+/// For stdin-mode backends the prompt is written and stdin closed before
+/// returning. The process is killed if its handle is dropped.
+async fn spawn_cli(backend: &Backend, prompt: &str, model: &str) -> Result<Process> {
     let args = backend.build_args(prompt, model);
 
     info!(
-        backend = backend.name,
-        command = backend.command,
+        backend = %backend.name,
+        command = %backend.command,
         "Invoking CLI"
     );
 
-    let mut cmd = Command::new(backend.command);
+    let mut cmd = Command::new(&backend.command);
     cmd.args(&args)
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
 
+    sandbox::restrict_env(&mut cmd, &backend.env_allow);
     // Explicitly remove API keys to force subscription/login mode
-    for key in &backend.api_keys_to_clean {
-        cmd.env_remove(*key);
+    for key in &backend.env_remove {
+        cmd.env_remove(key);
     }
     // Disable OTEL telemetry
     cmd.env("OTEL_SDK_DISABLED", "true");
+    cmd.envs(&backend.env);
+    if let Some(ref workdir) = backend.workdir {
+        cmd.current_dir(workdir);
+    }
 
     // For stdin input mode, we need to pipe stdin
     if matches!(backend.input_mode, InputMode::Stdin) {
         cmd.stdin(Stdio::piped());
     }
 
-    let mut child = cmd.spawn()?;
+    let mut process = sandbox::spawn(cmd, backend)?;
 
     // Write prompt to stdin if needed
     if matches!(backend.input_mode, InputMode::Stdin) {
-        if let Some(mut stdin) = child.stdin.take() {
+        if let Some(mut stdin) = process.child.stdin.take() {
             stdin.write_all(prompt.as_bytes()).await?;
             stdin.shutdown().await?;
         }
     }
 
-    let output = child.wait_with_output().await?;
+    Ok(process)
+}
 
-    if !output.status.success() && output.stdout.is_empty() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
+/// Invoke CLI backend and get the response text and any tool calls
+async fn invoke_cli(
+    backend: &Backend,
+    prompt: &str,
+    model: &str,
+    tools: bool,
+) -> Result<(String, Vec<ToolCall>)> {
+    let mut process = spawn_cli(backend, prompt, model).await?;
+    let child = &mut process.child;
+    let output = async {
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let (out, err, status) = tokio::join!(
+            read_pipe(child.stdout.take(), &mut stdout),
+            read_pipe(child.stderr.take(), &mut stderr),
+            child.wait()
+        );
+        out?;
+        err?;
+        anyhow::Ok((status?, stdout, stderr))
+    };
+    // Dropping the process on timeout kills it
+    let (status, stdout, stderr) = match backend.timeout() {
+        Some(timeout) => tokio::time::timeout(timeout, output)
+            .await
+            .map_err(|_| timed_out(backend))??,
+        None => output.await?,
+    };
+
+    if !status.success() && stdout.is_empty() {
+        let stderr = String::from_utf8_lossy(&stderr);
         anyhow::bail!("{} CLI failed: {}", backend.name, stderr);
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = String::from_utf8_lossy(&stdout);
+    // Tool calls are JSON, which the response filter would drop
+    let (stdout, calls) = if tools {
+        tools::parse(&stdout)
+    } else {
+        (stdout.into_owned(), vec![])
+    };
     let response = extract_response(&stdout);
 
-    if response.is_empty() {
-        Ok(stdout.to_string())
+    if response.is_empty() && calls.is_empty() {
+        Ok((stdout, calls))
     } else {
-        Ok(response)
+        Ok((response, calls))
+    }
+}
+
+/// Read a child's pipe to the end
+async fn read_pipe(pipe: Option<impl AsyncRead + Unpin>, buf: &mut Vec<u8>) -> std::io::Result<()> {
+    if let Some(mut pipe) = pipe {
+        pipe.read_to_end(buf).await?;
     }
+    Ok(())
+}
+
+/// Error for a CLI run that exceeded its backend's timeout
+fn timed_out(backend: &Backend) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{} CLI timed out after {}s",
+        backend.name,
+        backend.timeout_secs.unwrap_or_default()
+    )
+}
+
+/// Flatten a chat request into a single prompt for CLI backends
+///
+/// Tool definitions are described ahead of the conversation.
+fn build_prompt(request: &ChatRequest) -> String {
+    let tools = tools::instructions(request).map(|text| format!("[System]: {}", text));
+    tools
+        .into_iter()
+        .chain(request.messages.iter().map(|m| match m.role.as_str() {
+            "system" => format!("[System]: {}", m.content.text()),
+            "assistant" => {
+                let mut text = format!("[Assistant]: {}", m.content.text());
+                if !m.tool_calls.is_empty() {
+                    text.push('\n');
+                    text.push_str(&tools::render_calls(&m.tool_calls));
+                }
+                text
+            }
+            "tool" => match m.tool_call_id {
+                Some(ref id) => format!("[Tool result {}]: {}", id, m.content.text()),
+                None => format!("[Tool result]: {}", m.content.text()),
+            },
+            _ => m.content.text(),
+        }))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Run a non-streaming completion on a backend
+///
+/// `model` is the upstream model; the response reports the requested model.
+async fn complete(backend: &Backend, request: &ChatRequest, model: &str) -> Result<ChatResponse> {
+    let requested = request.model.clone().unwrap_or_else(|| model.to_string());
+
+    // Use HTTP passthrough for OpenAI-compatible upstreams
+    if backend.input_mode == InputMode::Http {
+        let mut response = invoke_http(backend, request, model).await?;
+        // Override model name in response to match requested model
+        response.model = requested;
+        info!(backend = %backend.name, "Upstream response received");
+        return Ok(response);
+    }
+
+    // Build prompt from messages for CLI backends
+    let prompt = build_prompt(request);
+    let (response, tool_calls) =
+        invoke_cli(backend, &prompt, model, tools::enabled(request)).await?;
+
+    info!(
+        response_len = response.len(),
+        tool_calls = tool_calls.len(),
+        "Got response"
+    );
+
+    let completion_tokens = count_tokens(&response)
+        + tool_calls
+            .iter()
+            .map(|c| count_tokens(&c.function.name) + count_tokens(&c.function.arguments))
+            .sum::<u32>();
+    let finish_reason = if tool_calls.is_empty() {
+        "stop"
+    } else {
+        "tool_calls"
+    };
+    let usage = usage::token_usage(count_tokens(&prompt), completion_tokens);
+
+    Ok(chat_response(
+        &requested,
+        response,
+        tool_calls,
+        finish_reason,
+        usage,
+    ))
+}
+
+/// Single-choice chat completion
+fn chat_response(
+    model: &str,
+    content: String,
+    tool_calls: Vec<ToolCall>,
+    finish_reason: &str,
+    usage: Usage,
+) -> ChatResponse {
+    let now = unix_now();
+    let content = if content.is_empty() && !tool_calls.is_empty() {
+        None
+    } else {
+        Some(content)
+    };
+    ChatResponse {
+        id: format!("chatcmpl-{}", now),
+        object: "chat.completion".to_string(),
+        created: now,
+        model: model.to_string(),
+        choices: vec![Choice {
+            index: 0,
+            message: ResponseMessage {
+                role: "assistant".to_string(),
+                content,
+                tool_calls,
+            },
+            finish_reason: finish_reason.to_string(),
+        }],
+        usage,
+    }
+}
+
+/// Status, message, and error code for a failed backend invocation
+fn backend_error(backend: &Backend, e: &anyhow::Error) -> (StatusCode, String, &'static str) {
+    if e.is::<Busy>() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            e.to_string(),
+            "backend_busy",
+        );
+    }
+    if let Some(upstream) = e
+        .downcast_ref::<UpstreamError>()
+        .filter(|u| u.is_client_error())
+    {
+        let status = StatusCode::from_u16(upstream.status).unwrap_or(StatusCode::BAD_REQUEST);
+        return (status, upstream.to_string(), "upstream_error");
+    }
+    if backend.input_mode == InputMode::Http {
+        warn!(backend = %backend.name, error = %e, "HTTP upstream not available");
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Upstream '{}' not available: {}", backend.name, e),
+            "upstream_unavailable",
+        )
+    } else {
+        error!(error = %e, "CLI invocation failed");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+            "internal_error",
+        )
+    }
+}
+
+/// Run an operation on a target's backends in balancing order
+///
+/// Retryable failures are recorded and the next backend is tried, as is the
+/// next backend when one is at capacity. On success
+/// the backend's in-flight guard is returned with the result; on failure the
+/// last backend tried is returned with its error.
+async fn dispatch<T, F, Fut>(
+    health: &Arc<HealthMonitor>,
+    target: &Target<'_>,
+    config: &HealthConfig,
+    mut op: F,
+) -> Result<(T, InFlight), (Backend, anyhow::Error)>
+where
+    F: FnMut(Backend, String) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut last = None;
+    for backend in health.plan(target, config) {
+        let model = backend.upstream_model(&target.model);
+        let guard = match health.acquire(backend, config).await {
+            Ok(guard) => guard,
+            Err(busy) => {
+                warn!(backend = %backend.name, target = target.name, "Backend at capacity");
+                last = Some((backend.clone(), busy.into()));
+                continue;
+            }
+        };
+        match op(backend.clone(), model).await {
+            Ok(value) => {
+                health.record_success(&backend.name);
+                return Ok((value, guard));
+            }
+            Err(e) if !is_retryable(&e) => return Err((backend.clone(), e)),
+            Err(e) => {
+                warn!(backend = %backend.name, target = target.name, error = %e, "Backend failed");
+                health.record_failure(&backend.name, config, &e.to_string());
+                last = Some((backend.clone(), e));
+            }
+        }
+    }
+    Err(last.expect("targets have at least one member"))
+}
+
+/// Current UNIX timestamp in seconds
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// OpenAI-style error response
+fn error_response(
+    status: StatusCode,
+    message: impl Into<String>,
+    r#type: &str,
+    code: &str,
+) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: ErrorDetail {
+                message: message.into(),
+                r#type: r#type.to_string(),
+                code: code.to_string(),
+            },
+        }),
+    )
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
 // API Types
 // ─────────────────────────────────────────────────────────────────────────────
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Default, Clone)]
 struct ChatRequest {
     model: Option<String>,
     messages: Vec<Message>,
+    /// Stream the response as `chat.completion.chunk` SSE events
+    #[serde(default)]
+    stream: bool,
+    /// Tool definitions in OpenAI `function` format
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+    /// `none`, `auto`, `required`, or a specific function
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    /// Remaining parameters (temperature, response_format, ...), passed to HTTP upstreams
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Default, Clone)]
 struct Message {
     role: String,
-    content: String,
+    #[serde(default, deserialize_with = "nullable_content")]
+    content: MessageContent,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+    /// Remaining fields (e.g. `name`), passed to HTTP upstreams
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Message content: a string or a list of content parts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<serde_json::Value>),
+}
+
+impl Default for MessageContent {
+    fn default() -> Self {
+        MessageContent::Text(String::new())
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+impl MessageContent {
+    /// Text content, joining the text parts of multi-part content
+    fn text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|p| p["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+/// Treat a `null` message content (assistant tool calls) as empty
+fn nullable_content<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<MessageContent, D::Error> {
+    Ok(Option::<MessageContent>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct ToolCall {
+    id: String,
+    #[serde(default = "function_type")]
+    r#type: String,
+    function: FunctionCall,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+struct FunctionCall {
+    name: String,
+    /// JSON-encoded arguments
+    arguments: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 struct ChatResponse {
     id: String,
     object: String,
@@ -369,20 +710,22 @@ struct ChatResponse {
     usage: Usage,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct Choice {
     index: u32,
     message: ResponseMessage,
     finish_reason: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct ResponseMessage {
     role: String,
-    content: String,
+    content: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ToolCall>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 struct Usage {
     prompt_tokens: u32,
     completion_tokens: u32,
@@ -405,8 +748,31 @@ struct ModelInfo {
 
 #[derive(Serialize)]
 struct HealthResponse {
+    /// `ok`, `degraded` (some backends unavailable), or `down`
     status: String,
-    backends: Vec<String>,
+    backends: Vec<BackendStatus>,
+}
+
+#[derive(Deserialize)]
+struct UsageQuery {
+    /// Calendar month (`YYYY-MM`); defaults to the current month
+    month: Option<String>,
+    /// Only this key's usage
+    key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct UsageResponse {
+    object: String,
+    month: String,
+    data: Vec<KeyUsage>,
+}
+
+#[derive(Serialize)]
+struct KeyUsage {
+    key: String,
+    #[serde(flatten)]
+    totals: Totals,
 }
 
 #[derive(Serialize)]
@@ -425,28 +791,46 @@ struct ErrorDetail {
 // Handlers
 // ─────────────────────────────────────────────────────────────────────────────
 
-async fn health_handler() -> Json<HealthResponse> {
-    let backends = get_backends().iter().map(|b| b.name.to_string()).collect();
-    Json(HealthResponse {
-        status: "ok".to_string(),
-        backends,
-    })
+async fn health_handler(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let backends = state.health.status(&state.registry.snapshot());
+    let available = backends.iter().filter(|b| b.available()).count();
+    let (code, status) = match available {
+        n if n == backends.len() => (StatusCode::OK, "ok"),
+        0 => (StatusCode::SERVICE_UNAVAILABLE, "down"),
+        _ => (StatusCode::OK, "degraded"),
+    };
+    (
+        code,
+        Json(HealthResponse {
+            status: status.to_string(),
+            backends,
+        }),
+    )
 }
 
-async fn models_handler() -> Json<ModelsResponse> {
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+async fn models_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Arc<ApiKey>>,
+) -> Json<ModelsResponse> {
+    let now = unix_now();
 
+    let registry = state.registry.snapshot();
     let mut data = Vec::new();
-    for backend in get_backends() {
-        for model in backend.models {
+    let owners = registry
+        .groups
+        .iter()
+        .map(|g| (&g.name, &g.models))
+        .chain(registry.backends.iter().map(|b| (&b.name, &b.models)));
+    for (owner, models) in owners {
+        for model in models {
+            if !caller.allows(model) || data.iter().any(|m: &ModelInfo| &m.id == model) {
+                continue;
+            }
             data.push(ModelInfo {
-                id: model.to_string(),
+                id: model.clone(),
                 object: "model".to_string(),
                 created: now,
-                owned_by: backend.name.to_string(),
+                owned_by: owner.clone(),
             });
         }
     }
@@ -457,93 +841,244 @@ async fn models_handler() -> Json<ModelsResponse> {
     })
 }
 
+/// Usage totals for a month: every key for admins, otherwise the caller's own
+async fn usage_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Arc<ApiKey>>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<UsageResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let key = match query.key {
+        Some(key) if caller.admin || key == caller.name => Some(key),
+        None if caller.admin => None,
+        Some(_) => {
+            return Err(error_response(
+                StatusCode::FORBIDDEN,
+                "Only admin keys can read other keys' usage",
+                "permission_error",
+                "forbidden",
+            ))
+        }
+        None => Some(caller.name.clone()),
+    };
+    let month = query.month.unwrap_or_else(|| usage::month_of(unix_now()));
+
+    let data = state
+        .usage
+        .month(&month)
+        .into_iter()
+        .filter(|(name, _)| key.as_ref().is_none_or(|key| key == name))
+        .map(|(key, totals)| KeyUsage { key, totals })
+        .collect();
+    Ok(Json(UsageResponse {
+        object: "usage".to_string(),
+        month,
+        data,
+    }))
+}
+
 async fn chat_completions_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Arc<ApiKey>>,
+    Extension(access): Extension<Access>,
+    headers: HeaderMap,
     Json(request): Json<ChatRequest>,
-) -> Result<Json<ChatResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let model = request.model.clone().unwrap_or_else(|| "claude-cli".to_string());
-    let backend = get_backend_for_model(&model);
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let served = serve_chat(&state, &caller, &access, &headers, &request, true)
+        .await
+        .map_err(|e| error_response(e.status, e.message, e.r#type, e.code))?;
+    let model = served.model.clone();
+    Ok(served.respond(|reply| match reply {
+        Reply::Complete(response) => Json(response).into_response(),
+        Reply::Stream(rx) => stream::openai_sse(rx, &model),
+        Reply::Proxied(response) => response,
+    }))
+}
 
-    info!(model = %model, backend = backend.name, "Processing chat completion");
+/// Output of [`serve_chat`], before translation into an API's format
+enum Reply {
+    /// A complete response
+    Complete(ChatResponse),
+    /// Stream events
+    Stream(mpsc::Receiver<StreamEvent>),
+    /// An HTTP upstream's OpenAI SSE, proxied chunk for chunk
+    Proxied(Response),
+}
 
-    // Use HTTP passthrough for hanzo backend
-    if backend.input_mode == InputMode::Http {
-        match invoke_hanzo(&request).await {
-            Ok(mut response) => {
-                // Override model name in response to match requested model
-                response.model = model;
-                info!("Hanzo engine response received");
-                return Ok(Json(response));
-            }
-            Err(e) => {
-                // If hanzo engine is not available, provide helpful error
-                warn!(error = %e, "Hanzo engine not available");
-                return Err((
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    Json(ErrorResponse {
-                        error: ErrorDetail {
-                            message: format!("Hanzo engine not available: {}. Start with: cd ~/work/hanzo/engine && cargo run --release -- --port 8080 plain -m <model>", e),
-                            r#type: "server_error".to_string(),
-                            code: "hanzo_unavailable".to_string(),
-                        },
-                    }),
-                ));
-            }
-        }
+/// A chat request that has been served
+struct Served {
+    reply: Reply,
+    /// Model the client asked for
+    model: String,
+    /// Prompt tokens of the request as sent to the backend
+    prompt_tokens: u32,
+    lookup: cache::Lookup,
+    /// In-flight guard of the backend that served it (none for cache hits)
+    guard: Option<InFlight>,
+}
+
+impl Served {
+    /// Build the response from the reply, marked with the cache outcome
+    ///
+    /// Streams stay in flight until the body is finished.
+    fn respond(self, translate: impl FnOnce(Reply) -> Response) -> Response {
+        let response = translate(self.reply);
+        let response = match self.guard {
+            Some(guard) => stream::hold(response, guard),
+            None => response,
+        };
+        self.lookup.mark(response)
     }
+}
 
-    // Build prompt from messages for CLI backends
-    let prompt: String = request
-        .messages
-        .iter()
-        .map(|m| match m.role.as_str() {
-            "system" => format!("[System]: {}", m.content),
-            "assistant" => format!("[Assistant]: {}", m.content),
-            _ => m.content.clone(),
-        })
-        .collect::<Vec<_>>()
-        .join("\n\n");
+/// A chat request that could not be served
+struct ChatError {
+    status: StatusCode,
+    message: String,
+    r#type: &'static str,
+    code: &'static str,
+}
 
-    match invoke_cli(&prompt, &model).await {
-        Ok(response) => {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
+/// Serve a chat request: key check, routing, cache, sessions, and dispatch
+///
+/// Shared by the OpenAI and Anthropic handlers, which only translate the
+/// request in and the [`Reply`] out. With `proxy_sse`, streams from HTTP
+/// upstreams that are not being cached pass through as OpenAI SSE.
+async fn serve_chat(
+    state: &AppState,
+    caller: &ApiKey,
+    access: &Access,
+    headers: &HeaderMap,
+    request: &ChatRequest,
+    proxy_sse: bool,
+) -> Result<Served, ChatError> {
+    let model = request
+        .model
+        .clone()
+        .unwrap_or_else(|| "claude-cli".to_string());
+    let registry = state.registry.snapshot();
+    let resolved = registry.resolve(&model);
+    // Metrics only use names from the registry, so clients cannot add series
+    let label = match resolved {
+        Some(_) if registry.lists(&model) => model.as_str(),
+        Some(ref target) => target.name,
+        None => "unknown",
+    };
+    access.model(&model, label);
+    if !caller.allows(&model) {
+        return Err(ChatError {
+            status: StatusCode::FORBIDDEN,
+            message: format!("Key '{}' may not use model '{}'", caller.name, model),
+            r#type: "permission_error",
+            code: "model_not_allowed",
+        });
+    }
+    let label = label.to_string();
+    let target = resolved.ok_or_else(|| ChatError {
+        status: StatusCode::NOT_FOUND,
+        message: format!("No backend serves model '{}'", model),
+        r#type: "invalid_request_error",
+        code: "model_not_found",
+    })?;
+
+    info!(model = %model, target = target.name, stream = request.stream, "Processing chat request");
+
+    let lookup = cache::lookup(state.cache.as_ref(), request, headers).await;
+    if let Some(hit) = lookup.hit.clone() {
+        access.backend("cache");
+        state.usage.record(
+            UsageRecord::new(&caller.name, &model, "cache", request.stream).labelled(&label),
+        );
+        let reply = if request.stream {
+            Reply::Stream(cache::replay(hit))
+        } else {
+            Reply::Complete(hit)
+        };
+        return Ok(Served {
+            reply,
+            model,
+            prompt_tokens: count_tokens(&build_prompt(request)),
+            lookup,
+            guard: None,
+        });
+    }
 
-            info!(response_len = response.len(), "Got response");
+    let result = {
+        let (model, label) = (&model, &label);
+        let (ledger, slot) = (&state.usage, &lookup.slot);
+        let (sessions, conversation) = (&state.sessions, sessions::conversation(headers));
+        dispatch(
+            &state.health,
+            &target,
+            &registry.health,
+            |backend, upstream| async move {
+                access.backend(&backend.name);
+                let record = UsageRecord::new(&caller.name, model, &backend.name, request.stream)
+                    .labelled(label);
+                let turn = sessions
+                    .begin(&caller.name, conversation, &backend, request)
+                    .await;
+                let (backend, request) = match turn {
+                    Some(ref turn) => (&turn.backend, &turn.request),
+                    None => (&backend, request),
+                };
+                let prompt = build_prompt(request);
+                let prompt_tokens = count_tokens(&prompt);
+                if !request.stream {
+                    let response = complete(backend, request, &upstream).await?;
+                    if let Some(turn) = turn {
+                        turn.commit();
+                    }
+                    ledger.record(record.with_usage(&response.usage));
+                    if let Some(slot) = slot {
+                        slot.store(&response).await;
+                    }
+                    return Ok((Reply::Complete(response), prompt_tokens));
+                }
 
-            Ok(Json(ChatResponse {
-                id: format!("chatcmpl-{}", now),
-                object: "chat.completion".to_string(),
-                created: now,
-                model: model.clone(),
-                choices: vec![Choice {
-                    index: 0,
-                    message: ResponseMessage {
-                        role: "assistant".to_string(),
-                        content: response.clone(),
-                    },
-                    finish_reason: "stop".to_string(),
-                }],
-                usage: Usage {
-                    prompt_tokens: (prompt.len() / 4) as u32,
-                    completion_tokens: (response.len() / 4) as u32,
-                    total_tokens: ((prompt.len() + response.len()) / 4) as u32,
-                },
-            }))
-        }
-        Err(e) => {
-            error!(error = %e, "CLI invocation failed");
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: ErrorDetail {
-                        message: e.to_string(),
-                        r#type: "server_error".to_string(),
-                        code: "internal_error".to_string(),
-                    },
-                }),
-            ))
+                let rx = match backend.input_mode {
+                    // Proxied chunk for chunk unless the stream is being cached
+                    InputMode::Http if proxy_sse && slot.is_none() => {
+                        let response = stream::stream_http(backend, request, &upstream).await?;
+                        let meter = StreamMeter::new(ledger.clone(), record, prompt_tokens);
+                        let response = stream::meter_sse(response, meter);
+                        return Ok((Reply::Proxied(response), prompt_tokens));
+                    }
+                    InputMode::Http => stream::http_events(backend, request, &upstream).await?,
+                    _ => {
+                        let tools = tools::enabled(request);
+                        stream::cli_events(backend, &prompt, &upstream, tools).await?
+                    }
+                };
+                let rx = match turn {
+                    Some(turn) => turn.tap(rx),
+                    None => rx,
+                };
+                let rx = match slot {
+                    Some(slot) => slot.capture(model, prompt_tokens, rx),
+                    None => rx,
+                };
+                let meter = StreamMeter::new(ledger.clone(), record, prompt_tokens);
+                Ok((Reply::Stream(meter.tap(rx)), prompt_tokens))
+            },
+        )
+        .await
+    };
+    match result {
+        Ok(((reply, prompt_tokens), guard)) => Ok(Served {
+            reply,
+            model,
+            prompt_tokens,
+            lookup,
+            guard: Some(guard),
+        }),
+        Err((backend, e)) => {
+            let (status, message, code) = backend_error(&backend, &e);
+            Err(ChatError {
+                status,
+                message,
+                r#type: "server_error",
+                code,
+            })
         }
     }
 }
@@ -552,29 +1087,124 @@ async fn chat_completions_handler(
 // Main
 // ─────────────────────────────────────────────────────────────────────────────
 
+/// Build the router; `/v1` routes require an API key when keys are configured
+fn router(state: AppState, guard: bool) -> Router {
+    let mut chat_completions = post(chat_completions_handler);
+    let mut messages = post(anthropic::messages_handler);
+    if guard {
+        // One guard, so rate limits span both APIs
+        let guard = Arc::new(Guard::default());
+        chat_completions = chat_completions.layer(GuardLayer::new(guard.clone()));
+        messages = messages.layer(GuardLayer::new(guard).with_format(ApiFormat::Anthropic));
+        info!("Guard enabled for /v1/chat/completions and /v1/messages");
+    }
+
+    let api = Router::new()
+        .route("/v1/models", get(models_handler))
+        .route("/v1/usage", get(usage_handler))
+        .route("/v1/chat/completions", chat_completions)
+        .route("/v1/messages", messages)
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            keys::authenticate,
+        ));
+    Router::new()
+        .route("/", get(health_handler))
+        .route("/health", get(health_handler))
+        .route("/metrics", get(metrics::metrics_handler))
+        .merge(api)
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            access::observe,
+        ))
+        .layer(CorsLayer::permissive())
+        .with_state(state)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging
     tracing_subscriber::fmt()
         .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "info".into()),
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
         )
         .init();
 
     let args = Args::parse();
 
-    // Build router
-    let app = Router::new()
-        .route("/", get(health_handler))
-        .route("/health", get(health_handler))
-        .route("/v1/models", get(models_handler))
-        .route("/v1/chat/completions", post(chat_completions_handler))
-        .layer(CorsLayer::permissive());
-
-    let backends: Vec<_> = get_backends().iter().map(|b| b.name).collect();
+    let registry = match args.config {
+        Some(ref path) => RegistryHandle::from_file(path.clone())?,
+        None => RegistryHandle::builtin(),
+    };
+    let registry = Arc::new(registry);
+    registry
+        .clone()
+        .watch(Duration::from_secs(args.reload_interval));
+    let health = Arc::new(HealthMonitor::default());
+    health.clone().spawn_probes(registry.clone());
+
+    let keys = match args.keys {
+        Some(ref path) => KeyStore::from_file(path)?,
+        None => {
+            warn!("No --keys given: the proxy accepts unauthenticated requests");
+            KeyStore::default()
+        }
+    };
+    let usage = match args.usage_log {
+        Some(ref path) => UsageLedger::with_log(path)?,
+        None => UsageLedger::default(),
+    };
+    let cache = match args.cache {
+        Some(CacheBackend::Memory) => Some(ResponseCache::memory(
+            args.cache_capacity,
+            Duration::from_secs(args.cache_ttl),
+        )),
+        Some(CacheBackend::Disk) => Some(ResponseCache::disk(
+            args.cache_dir.clone(),
+            args.cache_capacity,
+            Duration::from_secs(args.cache_ttl),
+        )?),
+        None => None,
+    };
+    let cache = cache.map(|cache| match args.cache_embeddings_url {
+        Some(ref url) => cache.with_embeddings(Embeddings {
+            url: url.clone(),
+            model: args.cache_embeddings_model.clone(),
+            api_key: args
+                .cache_embeddings_key_env
+                .as_ref()
+                .and_then(|var| std::env::var(var).ok()),
+            threshold: args.cache_similarity,
+        }),
+        None => cache,
+    });
+    let state = AppState {
+        registry: registry.clone(),
+        health,
+        keys: Arc::new(keys),
+        usage: Arc::new(usage),
+        cache: cache.map(Arc::new),
+        sessions: Arc::new(SessionStore::new(Duration::from_secs(args.session_idle))),
+        access_log: args
+            .access_log
+            .as_deref()
+            .map(AccessLog::open)
+            .transpose()?
+            .map(Arc::new),
+    };
+    state.sessions.clone().spawn_reaper();
+
+    let app = router(state, args.guard);
+
+    let backends: Vec<_> = registry
+        .snapshot()
+        .backends
+        .iter()
+        .map(|b| b.name.clone())
+        .collect();
 
-    println!(r#"
+    println!(
+        r#"
 ╔══════════════════════════════════════════════════════════════════╗
 ║     Multi-CLI → OpenAI API Proxy (Subscription Mode)             ║
 ╠══════════════════════════════════════════════════════════════════╣
@@ -591,7 +1221,11 @@ async fn main() -> Result<()> {
 ║    ollama, llama3.2        → Ollama (local, no keys needed)      ║
 ║    hanzo, hanzo-engine     → Hanzo Engine (local inference HTTP) ║
 ╚══════════════════════════════════════════════════════════════════╝
-"#, args.port, args.port, backends.join(", "));
+"#,
+        args.port,
+        args.port,
+        backends.join(", ")
+    );
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", args.port)).await?;
     info!(port = args.port, "Server listening");
@@ -604,6 +1238,12 @@ async fn main() -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{cli_backend, cli_registry, write_script};
+    use backends::Registry;
+
+    fn get_backend_for_model(model: &str) -> Backend {
+        Registry::builtin().resolve(model).unwrap().members[0].clone()
+    }
 
     #[test]
     fn test_get_backend_for_model() {
@@ -684,14 +1324,18 @@ Hello, world!
 
     #[test]
     fn test_get_backends_count() {
-        let backends = get_backends();
+        let backends = Registry::builtin().backends;
         assert_eq!(backends.len(), 7); // claude, codex, gemini, vibe, qwen, ollama, hanzo
     }
 
     #[test]
     fn test_all_backends_have_models() {
-        for backend in get_backends() {
-            assert!(!backend.models.is_empty(), "Backend {} has no models", backend.name);
+        for backend in Registry::builtin().backends {
+            assert!(
+                !backend.models.is_empty(),
+                "Backend {} has no models",
+                backend.name
+            );
         }
     }
 
@@ -701,4 +1345,607 @@ Hello, world!
         assert_eq!(backend.input_mode, InputMode::Http);
         assert!(backend.command.is_empty()); // HTTP mode doesn't use command
     }
+
+    #[tokio::test]
+    async fn test_http_upstream_passthrough() {
+        // Upstream that echoes the request it received as the reply
+        let upstream = Router::new().route(
+            "/v1/chat/completions",
+            post(
+                |headers: axum::http::HeaderMap, Json(body): Json<serde_json::Value>| async move {
+                    let echo = serde_json::json!({
+                        "authorization": headers.get("authorization").and_then(|v| v.to_str().ok()),
+                        "team": headers.get("x-team").and_then(|v| v.to_str().ok()),
+                        "body": body,
+                    });
+                    Json(serde_json::json!({
+                        "id": "chatcmpl-up",
+                        "object": "chat.completion",
+                        "created": 0,
+                        "model": body["model"],
+                        "choices": [{
+                            "index": 0,
+                            "message": {"role": "assistant", "content": echo.to_string()},
+                            "finish_reason": "stop"
+                        }],
+                        "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+                    }))
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+
+        std::env::set_var("PROXY_TEST_UPSTREAM_KEY", "sk-upstream");
+        let registry = Registry::from_toml(&format!(
+            r#"
+[[backends]]
+name = "upstream"
+input_mode = "http"
+base_url = "http://{}/v1"
+api_key_env = "PROXY_TEST_UPSTREAM_KEY"
+headers = {{ "X-Team" = "agents" }}
+
+[[routes]]
+regex = "^up/(.+)$"
+backend = "upstream"
+model = "$1"
+"#,
+            addr
+        ))
+        .unwrap();
+
+        let request: ChatRequest = serde_json::from_value(serde_json::json!({
+            "model": "up/gpt-4o",
+            "temperature": 0.2,
+            "response_format": {"type": "json_object"},
+            "tools": [{"type": "function", "function": {"name": "f", "parameters": {}}}],
+            "messages": [{"role": "user", "name": "ana", "content": [{"type": "text", "text": "hi"}]}]
+        }))
+        .unwrap();
+        let target = registry.resolve("up/gpt-4o").unwrap();
+        let response = complete(target.members[0], &request, &target.model)
+            .await
+            .unwrap();
+
+        assert_eq!(response.model, "up/gpt-4o");
+        let echo: serde_json::Value =
+            serde_json::from_str(response.choices[0].message.content.as_deref().unwrap()).unwrap();
+        assert_eq!(echo["authorization"], "Bearer sk-upstream");
+        assert_eq!(echo["team"], "agents");
+        assert_eq!(echo["body"]["model"], "gpt-4o");
+        assert_eq!(echo["body"]["stream"], false);
+        assert_eq!(echo["body"]["temperature"], 0.2);
+        assert_eq!(echo["body"]["response_format"]["type"], "json_object");
+        assert_eq!(echo["body"]["tools"][0]["function"]["name"], "f");
+        assert_eq!(echo["body"]["messages"][0]["name"], "ana");
+        assert_eq!(echo["body"]["messages"][0]["content"][0]["text"], "hi");
+    }
+
+    #[tokio::test]
+    async fn test_group_failover() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = write_script(dir.path(), "fake-cli", "echo \"answer from $0\"\n");
+
+        let registry = Registry::from_toml(&format!(
+            r#"
+[[backends]]
+name = "broken"
+command = "{}/missing-cli"
+
+[[backends]]
+name = "working"
+command = "{}"
+
+[[groups]]
+name = "pool"
+members = ["broken", "working"]
+models = ["pooled"]
+
+[health]
+failure_threshold = 1
+"#,
+            dir.path().display(),
+            script.display()
+        ))
+        .unwrap();
+        let health = Arc::new(HealthMonitor::default());
+        let request = ChatRequest {
+            model: Some("pooled".to_string()),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: "hi".to_string().into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let target = registry.resolve("pooled").unwrap();
+
+        let run = || {
+            dispatch(&health, &target, &registry.health, |backend, upstream| {
+                let request = &request;
+                async move { complete(&backend, request, &upstream).await }
+            })
+        };
+
+        // The missing CLI fails and the request falls through to the next member
+        let (response, _guard) = run().await.map_err(|(_, e)| e).unwrap();
+        assert!(response.choices[0]
+            .message
+            .content
+            .as_deref()
+            .unwrap()
+            .contains("answer"));
+
+        // Its circuit is now open, so it is skipped entirely
+        let status = health.status(&registry);
+        assert!(!status[0].available());
+        assert!(status[1].available());
+        assert_eq!(health.plan(&target, &registry.health).len(), 1);
+        assert!(run().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cli_tool_calling() {
+        // Calls the tool until it sees a result, then answers with it
+        let dir = tempfile::tempdir().unwrap();
+        let script = r#"prompt=$(cat)
+case "$prompt" in
+  *"[Tool result call_1]: 18C"*) echo "It is 18C in Paris." ;;
+  *"<tool_calls>"*)
+    echo "Let me check."
+    echo "<tool_calls>"
+    echo '[{"name": "get_weather", "arguments": {"city": "Paris"}}]'
+    echo "</tool_calls>" ;;
+  *) echo "no tools offered" ;;
+esac
+"#;
+        let backend = Backend {
+            input_mode: InputMode::Stdin,
+            ..cli_backend(dir.path(), "tool-cli", script)
+        };
+
+        let mut request: ChatRequest = serde_json::from_value(serde_json::json!({
+            "model": "tool-cli",
+            "tools": [{"type": "function", "function": {
+                "name": "get_weather",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }}],
+            "messages": [{"role": "user", "content": "Weather in Paris?"}]
+        }))
+        .unwrap();
+
+        let response = complete(&backend, &request, "tool-cli").await.unwrap();
+        let choice = &response.choices[0];
+        assert_eq!(choice.finish_reason, "tool_calls");
+        assert_eq!(choice.message.content.as_deref(), Some("Let me check."));
+        assert_eq!(choice.message.tool_calls[0].function.name, "get_weather");
+        assert_eq!(
+            choice.message.tool_calls[0].function.arguments,
+            r#"{"city":"Paris"}"#
+        );
+
+        // Streaming sends the text, then the parsed call
+        let mut rx = stream::cli_events(&backend, &build_prompt(&request), "tool-cli", true)
+            .await
+            .unwrap();
+        let mut events = vec![];
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        assert_eq!(
+            events[0],
+            stream::StreamEvent::Text("Let me check.".to_string())
+        );
+        assert!(
+            matches!(events[1], stream::StreamEvent::ToolCall(ref c) if c.function.name == "get_weather")
+        );
+        assert_eq!(
+            events[2],
+            stream::StreamEvent::Finish("tool_calls".to_string())
+        );
+
+        // The next turn carries the call and its result
+        let next: Vec<Message> = serde_json::from_value(serde_json::json!([
+            {"role": "assistant", "content": null, "tool_calls": [{
+                "id": "call_1", "type": "function",
+                "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+            }]},
+            {"role": "tool", "tool_call_id": "call_1", "content": "18C"}
+        ]))
+        .unwrap();
+        request.messages.extend(next);
+        let prompt = build_prompt(&request);
+        assert!(prompt
+            .contains(r#"[{"arguments":{"city":"Paris"},"id":"call_1","name":"get_weather"}]"#));
+
+        let response = complete(&backend, &request, "tool-cli").await.unwrap();
+        let choice = &response.choices[0];
+        assert_eq!(choice.finish_reason, "stop");
+        assert_eq!(
+            choice.message.content.as_deref(),
+            Some("It is 18C in Paris.")
+        );
+        assert!(choice.message.tool_calls.is_empty());
+
+        // With `tool_choice: none` no tools are described
+        request.messages.truncate(1);
+        request.tool_choice = Some("none".into());
+        let response = complete(&backend, &request, "tool-cli").await.unwrap();
+        assert_eq!(
+            response.choices[0].message.content.as_deref(),
+            Some("no tools offered")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_keys_and_usage() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let (dir, registry) = cli_registry(
+            "echo \"four score and seven\"\n",
+            r#"models = ["echo", "echo-big"]"#,
+        );
+        let keys_path = dir.path().join("keys.yaml");
+        std::fs::write(
+            &keys_path,
+            r#"
+keys:
+  - { name: alice, key: sk-alice, models: [echo], monthly_tokens: 1 }
+  - { name: bob, key: sk-bob, requests_per_minute: 1 }
+  - { name: root, key: sk-root, admin: true }
+"#,
+        )
+        .unwrap();
+        let log = dir.path().join("usage.jsonl");
+
+        let app = router(
+            AppState {
+                keys: Arc::new(KeyStore::from_file(&keys_path).unwrap()),
+                usage: Arc::new(UsageLedger::with_log(&log).unwrap()),
+                ..AppState::for_tests(registry)
+            },
+            false,
+        );
+        let send = |key: Option<&str>, method: &str, uri: &str, model: Option<&str>| {
+            let mut request = Request::builder().method(method).uri(uri);
+            if let Some(key) = key {
+                request = request.header("authorization", format!("Bearer {}", key));
+            }
+            let body = match model {
+                Some(model) => {
+                    request = request.header("content-type", "application/json");
+                    let body = serde_json::json!({
+                        "model": model,
+                        "messages": [{"role": "user", "content": "hi"}]
+                    });
+                    Body::from(body.to_string())
+                }
+                None => Body::empty(),
+            };
+            app.clone().oneshot(request.body(body).unwrap())
+        };
+        let json = |response: Response| async move {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
+        let chat = "/v1/chat/completions";
+
+        // Health stays open; the API needs a valid key
+        assert_eq!(
+            send(None, "GET", "/health", None).await.unwrap().status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(None, "GET", "/v1/models", None)
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(Some("sk-eve"), "POST", chat, Some("echo"))
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        // Model lists and requests are limited to allowed models
+        let models = json(
+            send(Some("sk-alice"), "GET", "/v1/models", None)
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(models["data"].as_array().unwrap().len(), 1);
+        let response = send(Some("sk-alice"), "POST", chat, Some("echo-big"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // Usage is counted with the tokenizer; the quota then blocks the key
+        let response = json(
+            send(Some("sk-alice"), "POST", chat, Some("echo"))
+                .await
+                .unwrap(),
+        )
+        .await;
+        let completion = count_tokens("four score and seven");
+        assert_eq!(response["usage"]["completion_tokens"], completion);
+        let response = send(Some("sk-alice"), "POST", chat, Some("echo"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(json(response).await["error"]["code"], "insufficient_quota");
+
+        // Rate limits answer with Retry-After
+        assert_eq!(
+            send(Some("sk-bob"), "POST", chat, Some("echo"))
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::OK
+        );
+        let response = send(Some("sk-bob"), "POST", chat, Some("echo"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key("retry-after"));
+
+        // Admins see every key; others only their own
+        let usage = json(
+            send(Some("sk-root"), "GET", "/v1/usage", None)
+                .await
+                .unwrap(),
+        )
+        .await;
+        let keys: Vec<&str> = usage["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|k| k["key"].as_str().unwrap())
+            .collect();
+        assert_eq!(keys, vec!["alice", "bob"]);
+        assert_eq!(usage["data"][0]["requests"], 1);
+        assert_eq!(usage["data"][0]["completion_tokens"], completion);
+        let usage = json(
+            send(Some("sk-bob"), "GET", "/v1/usage", None)
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(usage["data"].as_array().unwrap().len(), 1);
+        let response = send(Some("sk-bob"), "GET", "/v1/usage?key=alice", None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let lines = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+        assert!(lines.contains(r#""key":"alice","model":"echo","backend":"cli""#));
+    }
+
+    #[tokio::test]
+    async fn test_response_cache() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        // The CLI logs each run so hits can be told from misses
+        let (dir, registry) = cli_registry(
+            "echo run >> \"$(dirname \"$0\")/runs\"\necho \"cached answer\"\n",
+            r#"models = ["counter"]"#,
+        );
+        let runs = dir.path().join("runs");
+
+        let app = router(
+            AppState {
+                cache: Some(Arc::new(ResponseCache::memory(10, Duration::from_secs(60)))),
+                ..AppState::for_tests(registry)
+            },
+            false,
+        );
+        let send = |body: serde_json::Value, cache_control: Option<&str>| {
+            let mut request = Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("content-type", "application/json");
+            if let Some(value) = cache_control {
+                request = request.header("cache-control", value);
+            }
+            app.clone()
+                .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        };
+        let request = |temperature: f64, stream: bool| {
+            serde_json::json!({
+                "model": "counter",
+                "temperature": temperature,
+                "stream": stream,
+                "messages": [{"role": "user", "content": "hi"}]
+            })
+        };
+        let runs = || std::fs::read_to_string(&runs).map_or(0, |s| s.lines().count());
+        let x_cache =
+            |response: &Response| response.headers()["x-cache"].to_str().unwrap().to_string();
+
+        let response = send(request(0.0, false), None).await.unwrap();
+        assert_eq!(x_cache(&response), "MISS");
+        assert_eq!(runs(), 1);
+
+        // Repeats are served from the cache, streamed or not
+        let response = send(request(0.0, false), None).await.unwrap();
+        assert_eq!(x_cache(&response), "HIT");
+        assert!(response.headers().contains_key("age"));
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["choices"][0]["message"]["content"], "cached answer");
+        let response = send(request(0.0, true), None).await.unwrap();
+        assert_eq!(x_cache(&response), "HIT");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&bytes).contains("cached answer"));
+        assert_eq!(runs(), 1);
+
+        // no-cache and sampled requests reach the backend
+        let response = send(request(0.0, false), Some("no-cache")).await.unwrap();
+        assert_eq!(x_cache(&response), "BYPASS");
+        let response = send(request(0.7, false), None).await.unwrap();
+        assert_eq!(x_cache(&response), "BYPASS");
+        assert_eq!(runs(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cli_sessions() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        // The CLI logs its arguments and prompt for each run
+        let (dir, registry) = cli_registry(
+            "log=\"$(dirname \"$0\")/runs\"\necho \"args: $*\" >> \"$log\"\ncat >> \"$log\"\necho >> \"$log\"\necho \"reply\"\n",
+            r#"input_mode = "stdin"
+models = ["session"]
+session_args = ["--session-id", "{session}"]
+resume_args = ["--resume", "{session}"]"#,
+        );
+        let log = dir.path().join("runs");
+
+        let app = router(AppState::for_tests(registry), false);
+        let send = |messages: serde_json::Value| {
+            let body = serde_json::json!({"model": "session", "messages": messages});
+            let request = Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("content-type", "application/json")
+                .header("x-conversation-id", "conv-1")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+
+        let first = serde_json::json!([{"role": "user", "content": "first question"}]);
+        assert_eq!(send(first).await.unwrap().status(), StatusCode::OK);
+        let second = serde_json::json!([
+            {"role": "user", "content": "first question"},
+            {"role": "assistant", "content": "reply"},
+            {"role": "user", "content": "second question"}
+        ]);
+        assert_eq!(send(second).await.unwrap().status(), StatusCode::OK);
+
+        let runs = std::fs::read_to_string(&log).unwrap();
+        let runs: Vec<&str> = runs.split("args: ").skip(1).collect();
+        assert_eq!(runs.len(), 2);
+        let session = runs[0].split_whitespace().nth(1).unwrap();
+        assert!(runs[0].starts_with("--session-id "));
+        assert!(runs[0].contains("first question"));
+        // The second run resumes the session with only the new turn
+        assert!(runs[1].starts_with(&format!("--resume {}", session)));
+        assert!(runs[1].contains("second question"));
+        assert!(!runs[1].contains("first question"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_and_access_log() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let (dir, registry) = cli_registry(
+            "cat > /dev/null\necho \"observed reply\"\n",
+            "input_mode = \"stdin\"\nmodels = [\"observed\"]",
+        );
+        let log_path = dir.path().join("access.log");
+
+        let app = router(
+            AppState {
+                access_log: Some(Arc::new(AccessLog::open(&log_path).unwrap())),
+                ..AppState::for_tests(registry)
+            },
+            false,
+        );
+        let send = |request_id: Option<&str>, model: &str| {
+            let body = serde_json::json!({
+                "model": model,
+                "messages": [{"role": "user", "content": "Hi"}]
+            });
+            let mut request = Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("content-type", "application/json");
+            if let Some(id) = request_id {
+                request = request.header(access::REQUEST_ID, id);
+            }
+            app.clone()
+                .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        };
+
+        // Client ids are echoed, others generated
+        let response = send(Some("client-id-1"), "observed").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[access::REQUEST_ID], "client-id-1");
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let response = send(Some("not valid!"), "observed").await.unwrap();
+        let generated = response.headers()[access::REQUEST_ID]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(uuid::Uuid::parse_str(&generated).is_ok());
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        // Models the registry does not name are counted under their target
+        let response = send(None, "made-up-model-1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains(
+            r#"hanzo_proxy_requests_total{backend="cli",endpoint="/v1/chat/completions",model="observed",status="200"} 2"#
+        ));
+        assert!(text.contains(
+            r#"hanzo_proxy_tokens_total{backend="cli",kind="completion",model="observed"}"#
+        ));
+        assert!(text.contains(r#"hanzo_proxy_backend_up{backend="cli"} 1"#));
+        assert!(text.contains(
+            r#"hanzo_proxy_requests_total{backend="cli",endpoint="/v1/chat/completions",model="cli",status="200"} 1"#
+        ));
+        assert!(!text.contains("made-up-model-1"));
+
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let entries: Vec<serde_json::Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0]["request_id"], "client-id-1");
+        assert_eq!(entries[0]["path"], "/v1/chat/completions");
+        assert_eq!(entries[0]["status"], 200);
+        assert_eq!(entries[0]["model"], "observed");
+        assert_eq!(entries[0]["backend"], "cli");
+        assert_eq!(entries[1]["request_id"], generated.as_str());
+        assert_eq!(entries[2]["model"], "made-up-model-1");
+        assert_eq!(entries[3]["path"], "/metrics");
+    }
 }
@@ -0,0 +1,206 @@
+//! Prometheus metrics (`/metrics`)
+//!
+//! Request, error, latency and token counters are updated as requests
+//! finish. Backend gauges (in flight, queued, available) are read from the
+//! health monitor when scraped.
+
+use crate::AppState;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Process-wide metrics
+pub(crate) static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Latency buckets in seconds; CLI runs take from seconds to minutes
+const LATENCY_BUCKETS: &[f64] = &[
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+pub(crate) struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    errors: IntCounterVec,
+    latency: HistogramVec,
+    tokens: IntCounterVec,
+    in_flight: IntGaugeVec,
+    queued: IntGaugeVec,
+    up: IntGaugeVec,
+}
+
+/// A finished request, as counted in metrics
+pub(crate) struct Observation<'a> {
+    pub endpoint: &'a str,
+    pub model: &'a str,
+    pub backend: &'a str,
+    pub status: StatusCode,
+    /// Error code from the response body
+    pub code: Option<&'a str>,
+    pub latency: Duration,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("hanzo_proxy".to_string()), None)
+            .expect("valid metrics prefix");
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).expect("valid counter");
+            registry
+                .register(Box::new(counter.clone()))
+                .expect("unique metric");
+            counter
+        };
+        let gauge = |name: &str, help: &str| {
+            let gauge = IntGaugeVec::new(Opts::new(name, help), &["backend"]).expect("valid gauge");
+            registry
+                .register(Box::new(gauge.clone()))
+                .expect("unique metric");
+            gauge
+        };
+
+        let requests = counter(
+            "requests_total",
+            "Requests by endpoint, model, backend and status",
+            &["endpoint", "model", "backend", "status"],
+        );
+        let errors = counter(
+            "errors_total",
+            "Error responses by endpoint and error code",
+            &["endpoint", "code"],
+        );
+        let tokens = counter(
+            "tokens_total",
+            "Tokens by model, backend and kind (prompt or completion)",
+            &["model", "backend", "kind"],
+        );
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "request_duration_seconds",
+                "Time until the response body finished",
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["endpoint", "model", "backend"],
+        )
+        .expect("valid histogram");
+        registry
+            .register(Box::new(latency.clone()))
+            .expect("unique metric");
+
+        Self {
+            requests,
+            errors,
+            latency,
+            tokens,
+            in_flight: gauge("in_flight", "Requests running on a backend"),
+            queued: gauge("queued", "Requests waiting for a backend slot"),
+            up: gauge(
+                "backend_up",
+                "Whether a backend is available (1) or not (0)",
+            ),
+            registry,
+        }
+    }
+
+    /// Count a finished request
+    pub fn observe(&self, request: &Observation) {
+        let status = request.status.as_u16().to_string();
+        self.requests
+            .with_label_values(&[request.endpoint, request.model, request.backend, &status])
+            .inc();
+        self.latency
+            .with_label_values(&[request.endpoint, request.model, request.backend])
+            .observe(request.latency.as_secs_f64());
+        if let Some(code) = request.code {
+            self.errors
+                .with_label_values(&[request.endpoint, code])
+                .inc();
+        }
+    }
+
+    /// Count tokens used by a completion
+    pub fn tokens(&self, model: &str, backend: &str, prompt: u64, completion: u64) {
+        self.tokens
+            .with_label_values(&[model, backend, "prompt"])
+            .inc_by(prompt);
+        self.tokens
+            .with_label_values(&[model, backend, "completion"])
+            .inc_by(completion);
+    }
+
+    /// Text exposition of every metric
+    fn render(&self) -> String {
+        let mut buffer = vec![];
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::warn!(error = %e, "Failed to encode metrics");
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Prometheus scrape endpoint
+pub(crate) async fn metrics_handler(State(state): State<AppState>) -> Response {
+    let registry = state.registry.snapshot();
+    let metrics = &*METRICS;
+    for status in state.health.status(&registry) {
+        let name = [status.name()];
+        metrics
+            .in_flight
+            .with_label_values(&name)
+            .set(status.in_flight() as i64);
+        metrics
+            .queued
+            .with_label_values(&name)
+            .set(status.queued() as i64);
+        metrics
+            .up
+            .with_label_values(&name)
+            .set(status.available() as i64);
+    }
+    (
+        [(
+            header::CONTENT_TYPE,
+            TextEncoder::new().format_type().to_string(),
+        )],
+        metrics.render(),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.observe(&Observation {
+            endpoint: "/v1/chat/completions",
+            model: "m",
+            backend: "b",
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            code: Some("backend_busy"),
+            latency: Duration::from_millis(300),
+        });
+        metrics.tokens("m", "b", 12, 34);
+
+        let text = metrics.render();
+        assert!(text.contains(
+            r#"hanzo_proxy_requests_total{backend="b",endpoint="/v1/chat/completions",model="m",status="503"} 1"#
+        ));
+        assert!(text.contains(
+            r#"hanzo_proxy_errors_total{code="backend_busy",endpoint="/v1/chat/completions"} 1"#
+        ));
+        assert!(text.contains(
+            r#"hanzo_proxy_request_duration_seconds_bucket{backend="b",endpoint="/v1/chat/completions",model="m",le="0.5"} 1"#
+        ));
+        assert!(text
+            .contains(r#"hanzo_proxy_tokens_total{backend="b",kind="completion",model="m"} 34"#));
+    }
+}
@@ -0,0 +1,198 @@
+//! Process sandboxing for CLI backends
+//!
+//! Each CLI run can get an environment reduced to an allow-list, a fresh
+//! temporary working directory, and resource limits. On Unix the CLI runs
+//! in its own process group, which is killed as a whole when the run ends
+//! early (timeout, client disconnect) so tools it started don't linger.
+
+use crate::backends::Backend;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tempfile::TempDir;
+use tokio::process::{Child, Command};
+
+/// Resource limits applied to a CLI process and its children
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ResourceLimits {
+    /// CPU time before the process is killed (`RLIMIT_CPU`)
+    pub cpu_seconds: Option<u64>,
+    /// Address space (`RLIMIT_AS`)
+    pub memory_mb: Option<u64>,
+    /// Largest file the process may write (`RLIMIT_FSIZE`)
+    pub file_size_mb: Option<u64>,
+    /// Open file descriptors (`RLIMIT_NOFILE`)
+    pub open_files: Option<u64>,
+}
+
+/// A running CLI process
+///
+/// Dropping it before the child has been waited on kills the process group;
+/// either way a temporary workdir is removed.
+pub(crate) struct Process {
+    pub child: Child,
+    /// Process group id, the child's pid
+    #[cfg_attr(not(unix), allow(dead_code))]
+    group: Option<u32>,
+    _workdir: Option<TempDir>,
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        // Once `wait()` has reaped the child its id is `None` and the pid may
+        // already belong to another process group
+        #[cfg(unix)]
+        if let (Some(group), Some(_)) = (self.group, self.child.id()) {
+            // SAFETY: kill(2) has no memory effects; a gone group is ESRCH
+            unsafe {
+                libc::kill(-(group as libc::pid_t), libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// Apply a backend's sandbox settings and spawn the command
+pub(crate) fn spawn(mut cmd: Command, backend: &Backend) -> Result<Process> {
+    let workdir = if backend.temp_workdir {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("hanzo-proxy-{}-", backend.name))
+            .tempdir()
+            .context("creating temporary workdir")?;
+        cmd.current_dir(dir.path());
+        Some(dir)
+    } else {
+        None
+    };
+
+    #[cfg(unix)]
+    {
+        cmd.process_group(0);
+        let limits = rlimits(&backend.limits);
+        if !limits.is_empty() {
+            // SAFETY: only async-signal-safe setrlimit(2) runs between fork and exec
+            unsafe {
+                cmd.pre_exec(move || {
+                    for &(resource, value) in &limits {
+                        let limit = libc::rlimit {
+                            rlim_cur: value,
+                            rlim_max: value,
+                        };
+                        if libc::setrlimit(resource, &limit) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+    }
+
+    let child = cmd.spawn()?;
+    Ok(Process {
+        group: child.id(),
+        child,
+        _workdir: workdir,
+    })
+}
+
+/// Reduce a command's environment to a backend's allow-list
+///
+/// Entries are variable names, or prefixes ending in `*`. An empty list
+/// keeps the whole environment.
+pub(crate) fn restrict_env(cmd: &mut Command, allow: &[String]) {
+    if allow.is_empty() {
+        return;
+    }
+    cmd.env_clear();
+    for (name, value) in std::env::vars_os() {
+        let Some(key) = name.to_str() else {
+            continue;
+        };
+        if allow.iter().any(|a| match a.strip_suffix('*') {
+            Some(prefix) => key.starts_with(prefix),
+            None => key == a,
+        }) {
+            cmd.env(&name, value);
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type Resource = libc::c_int;
+
+/// `setrlimit` resources and values for a backend's limits
+#[cfg(unix)]
+fn rlimits(limits: &ResourceLimits) -> Vec<(Resource, libc::rlim_t)> {
+    const MB: u64 = 1024 * 1024;
+    [
+        (libc::RLIMIT_CPU, limits.cpu_seconds),
+        (libc::RLIMIT_AS, limits.memory_mb.map(|mb| mb * MB)),
+        (libc::RLIMIT_FSIZE, limits.file_size_mb.map(|mb| mb * MB)),
+        (libc::RLIMIT_NOFILE, limits.open_files),
+    ]
+    .into_iter()
+    .filter_map(|(resource, value)| Some((resource, value? as libc::rlim_t)))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+    use tokio::io::AsyncReadExt;
+
+    async fn run(backend: &Backend, script: &str) -> String {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]).stdout(Stdio::piped());
+        restrict_env(&mut cmd, &backend.env_allow);
+        let mut process = spawn(cmd, backend).unwrap();
+        let mut output = String::new();
+        let mut stdout = process.child.stdout.take().unwrap();
+        stdout.read_to_string(&mut output).await.unwrap();
+        process.child.wait().await.unwrap();
+        output.trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_env_allow_list() {
+        std::env::set_var("SANDBOX_TEST_SECRET", "s3cret");
+        std::env::set_var("SANDBOX_KEEP_ME", "kept");
+        let backend = Backend {
+            env_allow: vec!["PATH".to_string(), "SANDBOX_KEEP*".to_string()],
+            ..Default::default()
+        };
+        let output = run(&backend, "echo \"$SANDBOX_TEST_SECRET|$SANDBOX_KEEP_ME\"").await;
+        assert_eq!(output, "|kept");
+        let output = run(&Backend::default(), "echo \"$SANDBOX_TEST_SECRET\"").await;
+        assert_eq!(output, "s3cret");
+    }
+
+    #[tokio::test]
+    async fn test_temp_workdir() {
+        let backend = Backend {
+            name: "sandboxed".to_string(),
+            temp_workdir: true,
+            ..Default::default()
+        };
+        let dir = run(&backend, "pwd").await;
+        assert!(dir.contains("hanzo-proxy-sandboxed-"));
+        // Removed once the process is dropped
+        assert!(!std::path::Path::new(&dir).exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_rlimits() {
+        let backend = Backend {
+            limits: ResourceLimits {
+                cpu_seconds: Some(7),
+                open_files: Some(64),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(run(&backend, "ulimit -t; ulimit -n").await, "7\n64");
+    }
+}
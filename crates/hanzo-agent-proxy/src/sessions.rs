@@ -0,0 +1,315 @@
+//! Multi-turn CLI sessions
+//!
+//! A client that sends `x-conversation-id` gets session affinity on CLI
+//! backends that can resume their own sessions (`session_args` and
+//! `resume_args`). The first turn starts a CLI session under a fresh id
+//! with the whole conversation; later turns resume it and send only the
+//! messages after the last assistant reply. A conversation whose history no
+//! longer matches, or that moves to another backend, starts over. Idle
+//! sessions are forgotten by a reaper task.
+
+use crate::backends::Backend;
+use crate::stream::{self, StreamEvent};
+use crate::{ChatRequest, InputMode, Message};
+use axum::http::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, OwnedMutexGuard};
+use tracing::{debug, info};
+
+/// Header carrying the client's conversation id
+const CONVERSATION_HEADER: &str = "x-conversation-id";
+
+/// Conversation id sent by the client, if any
+pub(crate) fn conversation(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(CONVERSATION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+}
+
+/// Placeholder for the session id in `session_args` and `resume_args`
+const SESSION: &str = "{session}";
+
+/// What a CLI session has seen so far
+#[derive(Default)]
+struct State {
+    /// The CLI's session id
+    id: String,
+    /// Messages sent, up to the reply the CLI produced last
+    seen: usize,
+    /// Hash of those messages
+    history: String,
+}
+
+struct Session {
+    backend: String,
+    /// Held for the length of a turn so turns run one at a time
+    state: Arc<tokio::sync::Mutex<State>>,
+    last_used: Mutex<Instant>,
+}
+
+/// Sessions by API key name and conversation id
+pub(crate) struct SessionStore {
+    idle: Duration,
+    sessions: Mutex<HashMap<(String, String), Arc<Session>>>,
+}
+
+impl SessionStore {
+    pub fn new(idle: Duration) -> Self {
+        Self {
+            idle,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start a turn of a conversation, or `None` if the backend can't resume
+    ///
+    /// Waits for an earlier turn of the same conversation to finish.
+    pub async fn begin(
+        &self,
+        owner: &str,
+        conversation: Option<&str>,
+        backend: &Backend,
+        request: &ChatRequest,
+    ) -> Option<Turn> {
+        let conversation = conversation?;
+        if backend.input_mode == InputMode::Http || backend.resume_args.is_empty() {
+            return None;
+        }
+
+        let session = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let key = (owner.to_string(), conversation.to_string());
+            let session = sessions
+                .entry(key)
+                .and_modify(|s| {
+                    if s.backend != backend.name {
+                        *s = Arc::new(Session::new(&backend.name));
+                    }
+                })
+                .or_insert_with(|| Arc::new(Session::new(&backend.name)));
+            *session.last_used.lock().unwrap() = Instant::now();
+            session.clone()
+        };
+        let state = session.state.clone().lock_owned().await;
+
+        let messages = &request.messages;
+        let resume = state.seen > 0
+            && messages.len() > state.seen + 1
+            && messages[state.seen].role == "assistant"
+            && history(&messages[..state.seen]) == state.history;
+        let (id, new, args) = if resume {
+            (state.id.clone(), state.seen + 1, &backend.resume_args)
+        } else {
+            (uuid::Uuid::new_v4().to_string(), 0, &backend.session_args)
+        };
+        debug!(conversation, session = %id, resume, messages = messages.len() - new, "Session turn");
+
+        let mut backend = backend.clone();
+        backend
+            .args
+            .extend(args.iter().map(|arg| arg.replace(SESSION, &id)));
+        let request = ChatRequest {
+            messages: messages[new..].to_vec(),
+            ..request.clone()
+        };
+        Some(Turn {
+            backend,
+            request,
+            id,
+            seen: messages.len(),
+            history: history(messages),
+            state,
+        })
+    }
+
+    /// Forget sessions idle for longer than the idle timeout
+    pub fn reap(&self) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        // Sessions mid-turn are kept whatever their age
+        sessions.retain(|_, s| {
+            s.last_used.lock().unwrap().elapsed() < self.idle || s.state.try_lock().is_err()
+        });
+        if sessions.len() < before {
+            info!(reaped = before - sessions.len(), "Reaped idle sessions");
+        }
+    }
+
+    /// Reap idle sessions in the background
+    pub fn spawn_reaper(self: Arc<Self>) {
+        let period = (self.idle / 2).clamp(Duration::from_secs(1), Duration::from_secs(60));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                self.reap();
+            }
+        });
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+}
+
+impl Session {
+    fn new(backend: &str) -> Self {
+        Self {
+            backend: backend.to_string(),
+            state: Default::default(),
+            last_used: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+/// One turn of a session, holding it until committed or dropped
+pub(crate) struct Turn {
+    /// The backend with session arguments added
+    pub backend: Backend,
+    /// The request with only the messages the CLI hasn't seen
+    pub request: ChatRequest,
+    id: String,
+    seen: usize,
+    history: String,
+    state: OwnedMutexGuard<State>,
+}
+
+impl Turn {
+    /// Record the turn as seen by the CLI; uncommitted turns leave it unchanged
+    pub fn commit(mut self) {
+        self.state.id = self.id;
+        self.state.seen = self.seen;
+        self.state.history = self.history;
+    }
+
+    /// Commit once a streamed turn finishes without error
+    pub fn tap(self, rx: mpsc::Receiver<StreamEvent>) -> mpsc::Receiver<StreamEvent> {
+        let mut turn = Some(self);
+        stream::tap(rx, move |event| match event {
+            StreamEvent::Error(_) => turn = None,
+            StreamEvent::Finish(_) => {
+                if let Some(turn) = turn.take() {
+                    turn.commit();
+                }
+            }
+            _ => {}
+        })
+    }
+}
+
+fn history(messages: &[Message]) -> String {
+    let json = serde_json::to_vec(messages).unwrap_or_default();
+    hex::encode(Sha256::digest(json))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn backend() -> Backend {
+        Backend {
+            name: "cli".to_string(),
+            args: vec!["-p".to_string()],
+            session_args: vec!["--session-id".to_string(), SESSION.to_string()],
+            resume_args: vec!["--resume".to_string(), SESSION.to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn request(messages: &[(&str, &str)]) -> ChatRequest {
+        let messages: Vec<_> = messages
+            .iter()
+            .map(|(role, content)| json!({"role": role, "content": content}))
+            .collect();
+        serde_json::from_value(json!({"messages": messages})).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_turns() {
+        let store = SessionStore::new(Duration::from_secs(60));
+        let (backend, id) = (backend(), Some("c1"));
+        let first = request(&[("system", "Be brief."), ("user", "Hi")]);
+        let turn = store.begin("alice", id, &backend, &first).await.unwrap();
+        assert_eq!(turn.backend.args[1], "--session-id");
+        assert_eq!(turn.request.messages.len(), 2);
+        let session = turn.id.clone();
+        turn.commit();
+
+        // Only the new turn is sent, resuming the same session
+        let second = request(&[
+            ("system", "Be brief."),
+            ("user", "Hi"),
+            ("assistant", "Hello"),
+            ("user", "Bye"),
+        ]);
+        let turn = store.begin("alice", id, &backend, &second).await.unwrap();
+        assert_eq!(turn.backend.args, ["-p", "--resume", session.as_str()]);
+        assert_eq!(turn.request.messages.len(), 1);
+        assert_eq!(turn.request.messages[0].content.text(), "Bye");
+        drop(turn);
+
+        // Uncommitted turns are retried from the same point
+        let turn = store.begin("alice", id, &backend, &second).await.unwrap();
+        assert_eq!(turn.backend.args[1], "--resume");
+        turn.commit();
+
+        // Edited history, other keys, and other backends start over
+        let edited = request(&[
+            ("system", "Be brief."),
+            ("user", "Hey"),
+            ("assistant", "Hello"),
+            ("user", "Bye"),
+        ]);
+        let turn = store.begin("alice", id, &backend, &edited).await.unwrap();
+        assert_eq!(turn.backend.args[1], "--session-id");
+        assert_ne!(turn.id, session);
+        drop(turn);
+        let turn = store.begin("bob", id, &backend, &second).await.unwrap();
+        assert_eq!(turn.backend.args[1], "--session-id");
+        drop(turn);
+        let other = Backend {
+            name: "other".to_string(),
+            ..backend.clone()
+        };
+        let turn = store.begin("alice", id, &other, &second).await.unwrap();
+        assert_eq!(turn.backend.args[1], "--session-id");
+        drop(turn);
+
+        // No conversation id or no resume support: plain requests
+        assert!(store.begin("alice", None, &backend, &first).await.is_none());
+        let plain = Backend {
+            resume_args: vec![],
+            ..backend.clone()
+        };
+        assert!(store.begin("alice", id, &plain, &first).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reap() {
+        let store = SessionStore::new(Duration::ZERO);
+        let first = request(&[("user", "Hi")]);
+        let turn = store
+            .begin("alice", Some("c1"), &backend(), &first)
+            .await
+            .unwrap();
+        store
+            .begin("alice", Some("c2"), &backend(), &first)
+            .await
+            .unwrap()
+            .commit();
+        assert_eq!(store.len(), 2);
+
+        // The session mid-turn survives
+        store.reap();
+        assert_eq!(store.len(), 1);
+        turn.commit();
+        store.reap();
+        assert_eq!(store.len(), 0);
+    }
+}
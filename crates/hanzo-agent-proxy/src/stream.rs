@@ -0,0 +1,685 @@
+//! Streaming (SSE) chat completions
+//!
+//! CLI stdout and HTTP upstream SSE streams are both read into a channel of
+//! [`StreamEvent`]s, which the OpenAI and Anthropic endpoints format as their
+//! own SSE events. OpenAI-format upstream streams are proxied chunk for chunk.
+
+use crate::backends::Backend;
+use crate::tools;
+use crate::usage::StreamMeter;
+use crate::{
+    send_upstream, spawn_cli, timed_out, unix_now, upstream_request, ChatRequest, FunctionCall,
+    LineFilter, ToolCall,
+};
+use anyhow::Result;
+use axum::{
+    body::Body,
+    http::header,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Incremental output from a backend
+#[derive(Debug, PartialEq)]
+pub(crate) enum StreamEvent {
+    /// Response text
+    Text(String),
+    /// A complete tool call
+    ToolCall(ToolCall),
+    /// End of the response, with an OpenAI finish reason
+    Finish(String),
+    /// The backend failed after the stream started
+    Error(String),
+}
+
+/// Streamed chat completion chunk
+#[derive(Serialize)]
+struct ChatChunk {
+    id: String,
+    object: &'static str,
+    created: u64,
+    model: String,
+    choices: Vec<ChunkChoice>,
+}
+
+#[derive(Serialize)]
+struct ChunkChoice {
+    index: u32,
+    delta: Delta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+struct Delta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<serde_json::Value>>,
+}
+
+/// Builds chunks sharing one completion id, timestamp, and model
+struct ChunkBuilder {
+    id: String,
+    created: u64,
+    model: String,
+    tool_calls: usize,
+}
+
+impl ChunkBuilder {
+    fn new(model: &str) -> Self {
+        let created = unix_now();
+        Self {
+            id: format!("chatcmpl-{}", created),
+            created,
+            model: model.to_string(),
+            tool_calls: 0,
+        }
+    }
+
+    fn event(&self, delta: Delta, finish_reason: Option<&str>) -> Event {
+        let chunk = ChatChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk",
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![ChunkChoice {
+                index: 0,
+                delta,
+                finish_reason: finish_reason.map(str::to_string),
+            }],
+        };
+        Event::default().data(serde_json::to_string(&chunk).unwrap_or_default())
+    }
+
+    fn role(&self) -> Event {
+        self.event(
+            Delta {
+                role: Some("assistant".to_string()),
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    fn content(&self, content: String) -> Event {
+        self.event(
+            Delta {
+                content: Some(content),
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    fn tool_call(&mut self, call: ToolCall) -> Event {
+        let mut value = serde_json::to_value(call).unwrap_or_default();
+        value["index"] = self.tool_calls.into();
+        self.tool_calls += 1;
+        self.event(
+            Delta {
+                tool_calls: Some(vec![value]),
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    fn finish(&self, reason: &str) -> Event {
+        self.event(Delta::default(), Some(reason))
+    }
+}
+
+/// OpenAI-style error event for failures after the stream has started
+fn error_event(message: &str) -> Event {
+    let error = serde_json::json!({
+        "error": {
+            "message": message,
+            "type": "server_error",
+            "code": "internal_error",
+        }
+    });
+    Event::default().data(error.to_string())
+}
+
+/// Read a CLI backend's stdout into stream events, line by line
+///
+/// With `tools`, a tool call block is held back and sent as tool calls once
+/// the CLI exits. The process is killed when the receiver is dropped or the
+/// backend's timeout passes.
+pub(crate) async fn cli_events(
+    backend: &Backend,
+    prompt: &str,
+    model: &str,
+    tools: bool,
+) -> Result<mpsc::Receiver<StreamEvent>> {
+    let mut process = spawn_cli(backend, prompt, model).await?;
+    let stdout = process
+        .child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("{} CLI stdout unavailable", backend.name))?;
+    let mut stderr = process.child.stderr.take();
+    let name = backend.name.clone();
+    let deadline = backend.timeout().map(|timeout| Instant::now() + timeout);
+    let timeout_error = timed_out(backend).to_string();
+
+    let (tx, rx) = mpsc::channel(32);
+    tokio::spawn(async move {
+        // Drain stderr concurrently so a chatty CLI can't block on a full pipe
+        let stderr_task = tokio::spawn(async move {
+            let mut buf = String::new();
+            if let Some(ref mut stderr) = stderr {
+                let _ = stderr.read_to_string(&mut buf).await;
+            }
+            buf
+        });
+
+        let mut lines = BufReader::new(stdout).lines();
+        let mut filter = LineFilter::default();
+        let mut raw = String::new();
+        let mut emitted = false;
+        let mut block: Option<String> = None;
+        loop {
+            let line = tokio::select! {
+                line = lines.next_line() => line,
+                // Client went away: dropping the process kills it
+                _ = tx.closed() => {
+                    info!(backend = %name, "Client disconnected, stopping CLI");
+                    return;
+                }
+                _ = expire(deadline) => {
+                    warn!(backend = %name, "CLI timed out, stopping it");
+                    let _ = tx.send(StreamEvent::Error(timeout_error)).await;
+                    return;
+                }
+            };
+            let line = match line {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!(backend = %name, error = %e, "Failed reading CLI output");
+                    break;
+                }
+            };
+            raw.push_str(&line);
+            raw.push('\n');
+            let line = if let Some(ref mut block) = block {
+                block.push_str(&line);
+                block.push('\n');
+                continue;
+            } else if let Some(start) = line.find(tools::OPEN).filter(|_| tools) {
+                block = Some(format!("{}\n", &line[start..]));
+                line[..start].to_string()
+            } else {
+                line
+            };
+            if !filter.accept(&line) {
+                continue;
+            }
+
+            let content = if emitted { format!("\n{}", line) } else { line };
+            emitted = true;
+            // Client went away: dropping the child kills it
+            if tx.send(StreamEvent::Text(content)).await.is_err() {
+                info!(backend = %name, "Client disconnected, stopping CLI");
+                return;
+            }
+        }
+
+        let status = process.child.wait().await;
+        let stderr = stderr_task.await.unwrap_or_default();
+        let mut finish = "stop";
+        if let Some(block) = block {
+            let (text, calls) = tools::parse(&block);
+            let mut events = vec![];
+            if !text.is_empty() {
+                events.push(StreamEvent::Text(if emitted {
+                    format!("\n{}", text)
+                } else {
+                    text
+                }));
+            }
+            if !calls.is_empty() {
+                finish = "tool_calls";
+            }
+            events.extend(calls.into_iter().map(StreamEvent::ToolCall));
+            for event in events {
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+            emitted = true;
+        }
+        if !emitted {
+            let event = if !raw.trim().is_empty() {
+                StreamEvent::Text(raw)
+            } else if !matches!(status, Ok(ref s) if s.success()) {
+                StreamEvent::Error(format!("{} CLI failed: {}", name, stderr))
+            } else {
+                StreamEvent::Text(String::new())
+            };
+            let failed = matches!(event, StreamEvent::Error(_));
+            if tx.send(event).await.is_err() || failed {
+                return;
+            }
+        }
+
+        let _ = tx.send(StreamEvent::Finish(finish.to_string())).await;
+    });
+
+    Ok(rx)
+}
+
+/// Wait until a deadline, or forever without one
+async fn expire(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Pass events on to a new receiver, showing each to `observe`
+///
+/// The tap stops when either end goes away, so a client disconnect reaches
+/// the backend even while the backend is silent.
+pub(crate) fn tap(
+    mut rx: mpsc::Receiver<StreamEvent>,
+    mut observe: impl FnMut(&StreamEvent) + Send + 'static,
+) -> mpsc::Receiver<StreamEvent> {
+    let (tx, out) = mpsc::channel(32);
+    tokio::spawn(async move {
+        loop {
+            let event = tokio::select! {
+                event = rx.recv() => event,
+                _ = tx.closed() => None,
+            };
+            let Some(event) = event else {
+                break;
+            };
+            observe(&event);
+            if tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+    out
+}
+
+/// Read an HTTP upstream's SSE stream into stream events
+///
+/// Tool call fragments are assembled and sent once the upstream finishes.
+pub(crate) async fn http_events(
+    backend: &Backend,
+    request: &ChatRequest,
+    model: &str,
+) -> Result<mpsc::Receiver<StreamEvent>> {
+    let builder = upstream_request(backend, request, model, true)?;
+    let response = send_upstream(backend, builder).await?;
+
+    let (tx, rx) = mpsc::channel(32);
+    let mut body = response.bytes_stream();
+    tokio::spawn(async move {
+        let mut parser = SseParser::default();
+        let mut calls = ToolCallAssembler::default();
+        let mut finish = None;
+        'read: while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    let _ = tx.send(StreamEvent::Error(e.to_string())).await;
+                    return;
+                }
+            };
+            for data in parser.push(&chunk) {
+                if data == "[DONE]" {
+                    break 'read;
+                }
+                let Ok(value) = serde_json::from_str::<serde_json::Value>(&data) else {
+                    continue;
+                };
+                let choice = &value["choices"][0];
+                if let Some(text) = choice["delta"]["content"].as_str() {
+                    if !text.is_empty()
+                        && tx.send(StreamEvent::Text(text.to_string())).await.is_err()
+                    {
+                        return;
+                    }
+                }
+                if let Some(fragments) = choice["delta"]["tool_calls"].as_array() {
+                    calls.push(fragments);
+                }
+                if let Some(reason) = choice["finish_reason"].as_str() {
+                    finish = Some(reason.to_string());
+                }
+            }
+        }
+
+        for call in calls.finish() {
+            if tx.send(StreamEvent::ToolCall(call)).await.is_err() {
+                return;
+            }
+        }
+        let reason = finish.unwrap_or_else(|| "stop".to_string());
+        let _ = tx.send(StreamEvent::Finish(reason)).await;
+    });
+
+    Ok(rx)
+}
+
+/// Splits an SSE byte stream into `data` payloads
+///
+/// Bytes are buffered until an event is complete, so characters and line
+/// endings split across network chunks survive decoding.
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut payloads = vec![];
+        while let Some((end, next)) = event_end(&self.buffer) {
+            let event = String::from_utf8_lossy(&self.buffer[..end]).into_owned();
+            self.buffer.drain(..next);
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|l| l.strip_prefix("data:"))
+                .map(|d| d.strip_prefix(' ').unwrap_or(d))
+                .collect();
+            if !data.is_empty() {
+                payloads.push(data.join("\n"));
+            }
+        }
+        payloads
+    }
+}
+
+/// End of the first complete event (after its last line ending) and the
+/// start of the next, for events ending in a blank `\n` or `\r\n` line
+fn event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    buffer
+        .iter()
+        .enumerate()
+        .filter(|(_, &byte)| byte == b'\n')
+        .find_map(|(i, _)| match &buffer[i + 1..] {
+            [b'\n', ..] => Some((i + 1, i + 2)),
+            [b'\r', b'\n', ..] => Some((i + 1, i + 3)),
+            _ => None,
+        })
+}
+
+/// Assembles streamed OpenAI tool call fragments by index
+#[derive(Default)]
+struct ToolCallAssembler {
+    calls: BTreeMap<u64, ToolCall>,
+}
+
+impl ToolCallAssembler {
+    fn push(&mut self, fragments: &[serde_json::Value]) {
+        for fragment in fragments {
+            let index = fragment["index"].as_u64().unwrap_or(0);
+            let call = self.calls.entry(index).or_insert_with(|| ToolCall {
+                id: String::new(),
+                r#type: "function".to_string(),
+                function: FunctionCall::default(),
+            });
+            if let Some(id) = fragment["id"].as_str() {
+                call.id = id.to_string();
+            }
+            if let Some(name) = fragment["function"]["name"].as_str() {
+                call.function.name.push_str(name);
+            }
+            if let Some(arguments) = fragment["function"]["arguments"].as_str() {
+                call.function.arguments.push_str(arguments);
+            }
+        }
+    }
+
+    fn finish(self) -> impl Iterator<Item = ToolCall> {
+        self.calls.into_values()
+    }
+}
+
+/// Wrap a stream of SSE events as a response with keep-alives
+pub(crate) fn sse_response(events: impl Stream<Item = Event> + Send + 'static) -> Response {
+    Sse::new(events.map(Ok::<_, Infallible>))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Stream a receiver's events until the sender is dropped
+pub(crate) fn receiver_stream<T: Send + 'static>(
+    rx: mpsc::Receiver<T>,
+) -> impl Stream<Item = T> + Send + 'static {
+    stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    })
+}
+
+/// Keep a value alive until a response body has been sent or dropped
+pub(crate) fn hold<T: Send + 'static>(response: Response, value: T) -> Response {
+    let (parts, body) = response.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _ = &value;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// Count the chunks of a proxied OpenAI SSE response as they are sent
+pub(crate) fn meter_sse(response: Response, mut meter: StreamMeter) -> Response {
+    let (parts, body) = response.into_parts();
+    let mut parser = SseParser::default();
+    let body = body.into_data_stream().map(move |chunk| {
+        if let Ok(ref bytes) = chunk {
+            for data in parser.push(bytes) {
+                meter.observe_chunk(&data);
+            }
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// Format stream events as `chat.completion.chunk` SSE, ending with `[DONE]`
+pub(crate) fn openai_sse(rx: mpsc::Receiver<StreamEvent>, model: &str) -> Response {
+    let mut chunks = ChunkBuilder::new(model);
+    let role = stream::once(std::future::ready(chunks.role()));
+    let body = receiver_stream(rx).map(move |event| match event {
+        StreamEvent::Text(text) => chunks.content(text),
+        StreamEvent::ToolCall(call) => chunks.tool_call(call),
+        StreamEvent::Finish(reason) => chunks.finish(&reason),
+        StreamEvent::Error(message) => error_event(&message),
+    });
+    let done = stream::once(std::future::ready(Event::default().data("[DONE]")));
+    sse_response(role.chain(body).chain(done))
+}
+
+/// Proxy an HTTP upstream's SSE stream chunk for chunk
+pub(crate) async fn stream_http(
+    backend: &Backend,
+    request: &ChatRequest,
+    model: &str,
+) -> Result<Response> {
+    let builder = upstream_request(backend, request, model, true)?;
+    let response = send_upstream(backend, builder).await?;
+
+    let body = Body::from_stream(response.bytes_stream().map(|chunk| {
+        chunk.map_err(|e| {
+            warn!(error = %e, "Upstream stream interrupted");
+            e
+        })
+    }));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/event-stream"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::cli_backend;
+    use std::time::{Duration, Instant};
+
+    /// Fake CLI that starts a background tool, records its pid, and hangs
+    #[cfg(target_os = "linux")]
+    fn hanging_cli(dir: &std::path::Path) -> (Backend, std::path::PathBuf) {
+        let script =
+            "sleep 30 &\necho $! > \"$(dirname \"$0\")/tool.pid\"\necho started\nsleep 30\n";
+        (cli_backend(dir, "hanging", script), dir.join("tool.pid"))
+    }
+
+    /// Whether the background tool started by `hanging_cli` is still running
+    #[cfg(target_os = "linux")]
+    async fn tool_running(pid: &std::path::Path) -> bool {
+        // Killed processes are reaped by init shortly after
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let pid = std::fs::read_to_string(pid).unwrap();
+        let state = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()));
+        state.is_ok_and(|s| !s.contains(") Z "))
+    }
+
+    #[tokio::test]
+    #[cfg(target_os = "linux")]
+    async fn test_cli_timeout_kills_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut backend, pid) = hanging_cli(dir.path());
+        backend.timeout_secs = Some(1);
+
+        let start = Instant::now();
+        let e = crate::invoke_cli(&backend, "hi", "", false)
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("timed out after 1s"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!tool_running(&pid).await);
+
+        let mut rx = cli_events(&backend, "hi", "", false).await.unwrap();
+        assert_eq!(
+            rx.recv().await,
+            Some(StreamEvent::Text("started".to_string()))
+        );
+        assert!(matches!(rx.recv().await, Some(StreamEvent::Error(e)) if e.contains("timed out")));
+    }
+
+    #[tokio::test]
+    #[cfg(target_os = "linux")]
+    async fn test_disconnect_kills_silent_cli() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, pid) = hanging_cli(dir.path());
+
+        // The CLI prints nothing more, but dropping the client end still stops it
+        let record = crate::usage::UsageRecord::new("key", "hanging", "hanging", true);
+        let meter = StreamMeter::new(Default::default(), record, 0);
+        let mut rx = meter.tap(cli_events(&backend, "hi", "", false).await.unwrap());
+        assert!(rx.recv().await.is_some());
+        assert!(tool_running(&pid).await);
+        drop(rx);
+        assert!(!tool_running(&pid).await);
+    }
+
+    #[tokio::test]
+    async fn test_stream_cli_chunks() {
+        // The CLI holds its remaining lines until `go` exists, so the first
+        // line can only be seen if it is streamed while the CLI runs
+        let dir = tempfile::tempdir().unwrap();
+        let backend = cli_backend(
+            dir.path(),
+            "slow",
+            "echo one\nwhile [ ! -e \"$(dirname \"$0\")/go\" ]; do sleep 0.01; done\necho '{\"telemetry\": 1}'\necho two\n",
+        );
+
+        let rx = cli_events(&backend, "hi", "slow", false).await.unwrap();
+        let response = openai_sse(rx, "slow");
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+
+        let mut body = response.into_body().into_data_stream();
+        let mut text = String::new();
+        while !text.contains("\"content\"") {
+            let chunk = tokio::time::timeout(Duration::from_secs(10), body.next())
+                .await
+                .expect("first line was not streamed")
+                .unwrap();
+            text.push_str(&String::from_utf8_lossy(&chunk.unwrap()));
+        }
+        std::fs::write(dir.path().join("go"), "").unwrap();
+        while let Some(chunk) = body.next().await {
+            text.push_str(&String::from_utf8_lossy(&chunk.unwrap()));
+        }
+
+        let data: Vec<&str> = text
+            .lines()
+            .filter_map(|l| l.strip_prefix("data: "))
+            .collect();
+        assert_eq!(data.last(), Some(&"[DONE]"));
+
+        let chunks: Vec<serde_json::Value> = data[..data.len() - 1]
+            .iter()
+            .map(|d| serde_json::from_str(d).unwrap())
+            .collect();
+        assert!(chunks
+            .iter()
+            .all(|c| c["object"] == "chat.completion.chunk"));
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+
+        let content: String = chunks
+            .iter()
+            .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+            .collect();
+        assert_eq!(content, "one\ntwo");
+        assert_eq!(
+            chunks.last().unwrap()["choices"][0]["finish_reason"],
+            "stop"
+        );
+    }
+
+    #[test]
+    fn test_sse_parser_and_tool_calls() {
+        let mut parser = SseParser::default();
+        let mut payloads = parser.push(b"data: {\"a\":1}\r\n\r\ndata: [DO");
+        payloads.extend(parser.push(b"NE]\n\n"));
+        assert_eq!(payloads, vec!["{\"a\":1}", "[DONE]"]);
+
+        // Multi-byte characters and CRLFs split across chunks
+        let mut payloads = vec![];
+        for byte in "data: é\n\ndata: b\r\n\r\n".as_bytes() {
+            payloads.extend(parser.push(std::slice::from_ref(byte)));
+        }
+        assert_eq!(payloads, vec!["é", "b"]);
+
+        let mut calls = ToolCallAssembler::default();
+        calls.push(&[serde_json::json!({
+            "index": 0, "id": "call_1",
+            "function": {"name": "get_weather", "arguments": "{\"city\""}
+        })]);
+        calls.push(&[serde_json::json!({
+            "index": 0, "function": {"arguments": ": \"Paris\"}"}
+        })]);
+        let calls: Vec<ToolCall> = calls.finish().collect();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, "{\"city\": \"Paris\"}");
+    }
+}
@@ -0,0 +1,67 @@
+//! Fixtures shared by the unit tests
+
+use crate::backends::{Backend, RegistryHandle};
+use crate::health::HealthMonitor;
+use crate::keys::KeyStore;
+use crate::sessions::SessionStore;
+use crate::usage::UsageLedger;
+use crate::AppState;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Write an executable shell script to `dir/name`
+pub(crate) fn write_script(dir: &Path, name: &str, body: &str) -> PathBuf {
+    let script = dir.join(name);
+    std::fs::write(&script, format!("#!/bin/sh\n{}", body)).unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    script
+}
+
+/// A CLI backend named `name` that runs `script` from `dir`
+pub(crate) fn cli_backend(dir: &Path, name: &str, script: &str) -> Backend {
+    Backend {
+        name: name.to_string(),
+        command: write_script(dir, name, script)
+            .to_string_lossy()
+            .into_owned(),
+        ..Default::default()
+    }
+}
+
+/// A registry whose default backend `cli` runs `script` from a new tempdir
+///
+/// `registry_extra` is appended to the backend's table.
+pub(crate) fn cli_registry(script: &str, registry_extra: &str) -> (TempDir, RegistryHandle) {
+    let dir = tempfile::tempdir().unwrap();
+    let script = write_script(dir.path(), "cli", script);
+    let registry_path = dir.path().join("backends.toml");
+    std::fs::write(
+        &registry_path,
+        format!(
+            "default_backend = \"cli\"\n[[backends]]\nname = \"cli\"\ncommand = \"{}\"\n{}\n",
+            script.display(),
+            registry_extra
+        ),
+    )
+    .unwrap();
+    let registry = RegistryHandle::from_file(registry_path).unwrap();
+    (dir, registry)
+}
+
+impl AppState {
+    /// State with no keys, cache, or access log
+    pub(crate) fn for_tests(registry: RegistryHandle) -> Self {
+        Self {
+            registry: Arc::new(registry),
+            health: Arc::new(HealthMonitor::default()),
+            keys: Arc::new(KeyStore::default()),
+            usage: Arc::new(UsageLedger::default()),
+            cache: None,
+            sessions: Arc::new(SessionStore::new(Duration::from_secs(60))),
+            access_log: None,
+        }
+    }
+}
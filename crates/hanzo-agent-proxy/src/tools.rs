@@ -0,0 +1,245 @@
+//! Tool calling for CLI backends
+//!
+//! CLIs have no native function calling, so the tool schemas are described
+//! in the prompt and the model is asked to reply with a `<tool_calls>` block
+//! holding a JSON array of calls. That block is parsed back into OpenAI
+//! `tool_calls`; everything else in the output is response text.
+
+use crate::{unix_now, ChatRequest, FunctionCall, ToolCall};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Opening marker of a tool call block
+pub(crate) const OPEN: &str = "<tool_calls>";
+/// Closing marker of a tool call block
+pub(crate) const CLOSE: &str = "</tool_calls>";
+
+/// How the model may use tools, from a request's `tool_choice`
+#[derive(Debug, PartialEq)]
+enum ToolChoice {
+    None,
+    Auto,
+    Required,
+    Function(String),
+}
+
+impl ToolChoice {
+    fn from_request(request: &ChatRequest) -> Self {
+        match request.tool_choice {
+            None => ToolChoice::Auto,
+            Some(Value::String(ref choice)) => match choice.as_str() {
+                "none" => ToolChoice::None,
+                "required" => ToolChoice::Required,
+                _ => ToolChoice::Auto,
+            },
+            Some(ref choice) => choice["function"]["name"]
+                .as_str()
+                .map_or(ToolChoice::Auto, |name| {
+                    ToolChoice::Function(name.to_string())
+                }),
+        }
+    }
+}
+
+/// Whether tools should be offered to and parsed from a CLI backend
+pub(crate) fn enabled(request: &ChatRequest) -> bool {
+    !request.tools.is_empty() && ToolChoice::from_request(request) != ToolChoice::None
+}
+
+/// Prompt section describing the request's tools and the calling protocol
+pub(crate) fn instructions(request: &ChatRequest) -> Option<String> {
+    if !enabled(request) {
+        return None;
+    }
+
+    let tools: Vec<Value> = request
+        .tools
+        .iter()
+        .map(|tool| {
+            let function = &tool["function"];
+            json!({
+                "name": function["name"],
+                "description": function["description"],
+                "parameters": function["parameters"],
+            })
+        })
+        .collect();
+    let tools = serde_json::to_string_pretty(&tools).unwrap_or_default();
+
+    let mut text = format!(
+        "You can call the following tools, given as JSON Schema:\n{tools}\n\n\
+         To call tools, reply with only a block like this and nothing after it:\n\
+         {OPEN}\n[{{\"name\": \"tool_name\", \"arguments\": {{\"arg\": \"value\"}}}}]\n{CLOSE}\n\
+         List several calls to run them in parallel. Their results are sent back \
+         as [Tool result] messages. To answer without tools, reply with plain text."
+    );
+    match ToolChoice::from_request(request) {
+        ToolChoice::Required => text.push_str("\nYou must call at least one tool."),
+        ToolChoice::Function(name) => {
+            text.push_str(&format!("\nYou must call the `{}` tool.", name))
+        }
+        ToolChoice::None | ToolChoice::Auto => {}
+    }
+    Some(text)
+}
+
+/// Render an assistant's earlier tool calls in the protocol format
+pub(crate) fn render_calls(calls: &[ToolCall]) -> String {
+    let calls: Vec<Value> = calls
+        .iter()
+        .map(|call| {
+            json!({
+                "id": call.id,
+                "name": call.function.name,
+                "arguments": serde_json::from_str::<Value>(&call.function.arguments)
+                    .unwrap_or_else(|_| call.function.arguments.clone().into()),
+            })
+        })
+        .collect();
+    format!(
+        "{OPEN}\n{}\n{CLOSE}",
+        serde_json::to_string(&calls).unwrap_or_default()
+    )
+}
+
+/// Split CLI output into response text and tool calls
+///
+/// Output without a well-formed tool call block is returned unchanged.
+pub(crate) fn parse(output: &str) -> (String, Vec<ToolCall>) {
+    let unchanged = || (output.to_string(), vec![]);
+    let Some(start) = output.find(OPEN) else {
+        return unchanged();
+    };
+    let rest = &output[start + OPEN.len()..];
+    let (block, after) = match rest.find(CLOSE) {
+        Some(end) => (&rest[..end], &rest[end + CLOSE.len()..]),
+        None => (rest, ""),
+    };
+
+    let Some(calls) = parse_block(block) else {
+        return unchanged();
+    };
+    let text = format!("{}{}", &output[..start], after).trim().to_string();
+    (text, calls)
+}
+
+/// Parse the JSON inside a tool call block, tolerating code fences
+fn parse_block(block: &str) -> Option<Vec<ToolCall>> {
+    let json = block
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    let calls = match serde_json::from_str::<Value>(json).ok()? {
+        Value::Array(calls) => calls,
+        call @ Value::Object(_) => vec![call],
+        _ => return None,
+    };
+
+    calls
+        .iter()
+        .map(|call| {
+            // Accept both `{name, arguments}` and OpenAI's `{function: {...}}`
+            let function = call.get("function").unwrap_or(call);
+            let name = function["name"].as_str().filter(|n| !n.is_empty())?;
+            let arguments = match &function["arguments"] {
+                Value::Null => "{}".to_string(),
+                Value::String(arguments) => arguments.clone(),
+                arguments => arguments.to_string(),
+            };
+            Some(ToolCall {
+                id: call_id(),
+                r#type: "function".to_string(),
+                function: FunctionCall {
+                    name: name.to_string(),
+                    arguments,
+                },
+            })
+        })
+        .collect()
+}
+
+/// Unique id for a parsed tool call
+fn call_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "call_{}_{}",
+        unix_now(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(tool_choice: Option<Value>) -> ChatRequest {
+        ChatRequest {
+            tools: vec![json!({
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "description": "Current weather",
+                    "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+                }
+            })],
+            tool_choice,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_instructions() {
+        let text = instructions(&request(None)).unwrap();
+        assert!(text.contains("\"get_weather\""));
+        assert!(text.contains("\"city\""));
+        assert!(text.contains(OPEN));
+        assert!(!text.contains("must call"));
+
+        let text = instructions(&request(Some(json!("required")))).unwrap();
+        assert!(text.contains("must call at least one tool"));
+        let forced = json!({"type": "function", "function": {"name": "get_weather"}});
+        let text = instructions(&request(Some(forced))).unwrap();
+        assert!(text.contains("must call the `get_weather` tool"));
+
+        assert!(instructions(&request(Some(json!("none")))).is_none());
+        assert!(instructions(&ChatRequest::default()).is_none());
+    }
+
+    #[test]
+    fn test_parse() {
+        let output = "Checking.\n<tool_calls>\n```json\n[{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\"}}, {\"function\": {\"name\": \"get_time\", \"arguments\": \"{}\"}}]\n```\n</tool_calls>\n";
+        let (text, calls) = parse(output);
+        assert_eq!(text, "Checking.");
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Paris"}"#);
+        assert_eq!(calls[1].function.name, "get_time");
+        assert_eq!(calls[1].function.arguments, "{}");
+        assert_ne!(calls[0].id, calls[1].id);
+
+        // A single object and a missing closing marker are accepted
+        let (text, calls) = parse("<tool_calls>{\"name\": \"f\"}");
+        assert_eq!(text, "");
+        assert_eq!(calls[0].function.arguments, "{}");
+
+        // Malformed blocks are left as text
+        for output in [
+            "plain answer",
+            "<tool_calls>not json</tool_calls>",
+            "<tool_calls>[{\"arguments\": {}}]</tool_calls>",
+        ] {
+            assert_eq!(parse(output), (output.to_string(), vec![]));
+        }
+    }
+
+    #[test]
+    fn test_render_calls_round_trip() {
+        let (_, calls) = parse(&render_calls(
+            &parse("<tool_calls>[{\"name\": \"f\", \"arguments\": {\"x\": 1}}]</tool_calls>").1,
+        ));
+        assert_eq!(calls[0].function.name, "f");
+        assert_eq!(calls[0].function.arguments, r#"{"x":1}"#);
+    }
+}
@@ -0,0 +1,396 @@
+//! Token counting and per-key usage accounting
+//!
+//! Every completion is recorded under the caller's key name and month. With a
+//! usage log configured, records are appended to it as JSON lines and the
+//! monthly totals are restored from it at startup.
+
+use crate::metrics::METRICS;
+use crate::stream::{self, StreamEvent};
+use crate::{unix_now, Usage};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Number of tokens in a text
+#[cfg(feature = "tiktoken")]
+pub(crate) fn count_tokens(text: &str) -> u32 {
+    use std::sync::LazyLock;
+    static BPE: LazyLock<tiktoken_rs::CoreBPE> =
+        LazyLock::new(|| tiktoken_rs::o200k_base().expect("embedded o200k vocabulary"));
+    BPE.encode_ordinary(text).len() as u32
+}
+
+/// Approximate number of tokens in a text
+///
+/// Text is split roughly like a BPE pre-tokenizer: words (with their leading
+/// space) take one token per five letters, digits one per three, symbols one
+/// per two, and other scripts one per character.
+#[cfg(not(feature = "tiktoken"))]
+pub(crate) fn count_tokens(text: &str) -> u32 {
+    #[derive(PartialEq)]
+    enum Class {
+        Letter,
+        Digit,
+        Space,
+        Symbol,
+    }
+    let class = |c: char| match c {
+        c if c.is_ascii_alphabetic() => Class::Letter,
+        c if c.is_ascii_digit() => Class::Digit,
+        c if c.is_whitespace() => Class::Space,
+        _ => Class::Symbol,
+    };
+    let cost = |class: &Class, len: u32| match class {
+        Class::Letter => len.div_ceil(5),
+        Class::Digit => len.div_ceil(3),
+        Class::Symbol => len.div_ceil(2),
+        // Single spaces join the following word
+        Class::Space => u32::from(len > 1),
+    };
+
+    let mut tokens = 0;
+    let mut run: Option<(Class, u32)> = None;
+    for c in text.chars() {
+        if !c.is_ascii() {
+            tokens += 1;
+            if let Some((class, len)) = run.take() {
+                tokens += cost(&class, len);
+            }
+            continue;
+        }
+        let next = class(c);
+        match run {
+            Some((ref class, ref mut len)) if *class == next => *len += 1,
+            _ => {
+                if let Some((class, len)) = run.replace((next, 1)) {
+                    tokens += cost(&class, len);
+                }
+            }
+        }
+    }
+    if let Some((class, len)) = run {
+        tokens += cost(&class, len);
+    }
+    tokens
+}
+
+/// Token counts for a prompt and its completion
+pub(crate) fn token_usage(prompt_tokens: u32, completion_tokens: u32) -> Usage {
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    }
+}
+
+/// Calendar month (`YYYY-MM`, UTC) of a UNIX timestamp
+pub(crate) fn month_of(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_default()
+        .format("%Y-%m")
+        .to_string()
+}
+
+/// One usage log entry
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct UsageRecord {
+    /// UNIX timestamp of the completion
+    pub timestamp: u64,
+    /// Name of the API key
+    pub key: String,
+    /// Requested model
+    pub model: String,
+    /// Backend that served the request
+    pub backend: String,
+    /// Model as labelled in metrics
+    #[serde(skip)]
+    pub model_label: String,
+    pub stream: bool,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl UsageRecord {
+    pub fn new(key: &str, model: &str, backend: &str, stream: bool) -> Self {
+        Self {
+            timestamp: unix_now(),
+            key: key.to_string(),
+            model: model.to_string(),
+            backend: backend.to_string(),
+            model_label: model.to_string(),
+            stream,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+        }
+    }
+
+    /// Count the tokens under a metrics label other than the model
+    pub fn labelled(mut self, label: &str) -> Self {
+        self.model_label = label.to_string();
+        self
+    }
+
+    /// Set token counts from a completion's usage
+    pub fn with_usage(mut self, usage: &Usage) -> Self {
+        self.prompt_tokens = usage.prompt_tokens.into();
+        self.completion_tokens = usage.completion_tokens.into();
+        self
+    }
+}
+
+/// Usage totals for one key and month
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub(crate) struct Totals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+/// Per-key, per-month usage totals with an optional JSONL log
+#[derive(Default)]
+pub(crate) struct UsageLedger {
+    /// Totals by month, then key name
+    totals: Mutex<BTreeMap<String, BTreeMap<String, Totals>>>,
+    log: Option<Mutex<File>>,
+}
+
+impl UsageLedger {
+    /// Ledger appending to a JSONL log, restoring totals from its records
+    pub fn with_log(path: &Path) -> Result<Self> {
+        let ledger = Self::default();
+        if path.exists() {
+            let file = File::open(path).with_context(|| format!("reading {}", path.display()))?;
+            for (number, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<UsageRecord>(&line) {
+                    Ok(record) => ledger.add(&record),
+                    Err(e) => warn!(line = number + 1, error = %e, "Skipping bad usage log line"),
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening {}", path.display()))?;
+        Ok(Self {
+            log: Some(Mutex::new(file)),
+            ..ledger
+        })
+    }
+
+    fn add(&self, record: &UsageRecord) {
+        let mut totals = self.totals.lock().unwrap();
+        let totals = totals
+            .entry(month_of(record.timestamp))
+            .or_default()
+            .entry(record.key.clone())
+            .or_default();
+        totals.requests += 1;
+        totals.prompt_tokens += record.prompt_tokens;
+        totals.completion_tokens += record.completion_tokens;
+        totals.total_tokens += record.prompt_tokens + record.completion_tokens;
+    }
+
+    /// Add a completion to the totals and the log
+    pub fn record(&self, record: UsageRecord) {
+        self.add(&record);
+        METRICS.tokens(
+            &record.model_label,
+            &record.backend,
+            record.prompt_tokens,
+            record.completion_tokens,
+        );
+        if let Some(ref log) = self.log {
+            let line = serde_json::to_string(&record).unwrap_or_default();
+            if let Err(e) = writeln!(log.lock().unwrap(), "{}", line) {
+                warn!(error = %e, "Failed to write usage log");
+            }
+        }
+    }
+
+    /// Tokens used by a key in the current month
+    pub fn month_tokens(&self, key: &str) -> u64 {
+        let totals = self.totals.lock().unwrap();
+        totals
+            .get(&month_of(unix_now()))
+            .and_then(|keys| keys.get(key))
+            .map_or(0, |t| t.total_tokens)
+    }
+
+    /// Totals by key name for a month
+    pub fn month(&self, month: &str) -> BTreeMap<String, Totals> {
+        self.totals
+            .lock()
+            .unwrap()
+            .get(month)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Counts a streamed completion, recording it when the stream ends
+pub(crate) struct StreamMeter {
+    ledger: Arc<UsageLedger>,
+    record: UsageRecord,
+    /// Usage reported by the upstream, which takes precedence
+    reported: Option<Usage>,
+}
+
+impl StreamMeter {
+    pub fn new(ledger: Arc<UsageLedger>, record: UsageRecord, prompt_tokens: u32) -> Self {
+        Self {
+            ledger,
+            record: UsageRecord {
+                prompt_tokens: prompt_tokens.into(),
+                ..record
+            },
+            reported: None,
+        }
+    }
+
+    pub fn observe(&mut self, event: &StreamEvent) {
+        let tokens = match event {
+            StreamEvent::Text(text) => count_tokens(text),
+            StreamEvent::ToolCall(call) => {
+                count_tokens(&call.function.name) + count_tokens(&call.function.arguments)
+            }
+            StreamEvent::Finish(_) | StreamEvent::Error(_) => 0,
+        };
+        self.record.completion_tokens += u64::from(tokens);
+    }
+
+    /// Count an OpenAI `chat.completion.chunk` payload
+    pub fn observe_chunk(&mut self, data: &str) {
+        let Ok(chunk) = serde_json::from_str::<serde_json::Value>(data) else {
+            return;
+        };
+        if let Ok(usage) = serde_json::from_value::<Usage>(chunk["usage"].clone()) {
+            self.reported = Some(usage);
+        }
+        let delta = &chunk["choices"][0]["delta"];
+        if let Some(text) = delta["content"].as_str() {
+            self.observe(&StreamEvent::Text(text.to_string()));
+        }
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let function = &call["function"];
+            let tokens = [&function["name"], &function["arguments"]]
+                .iter()
+                .filter_map(|v| v.as_str())
+                .map(count_tokens)
+                .sum::<u32>();
+            self.record.completion_tokens += u64::from(tokens);
+        }
+    }
+
+    /// Meter events as they pass from a backend to a client
+    pub fn tap(mut self, rx: mpsc::Receiver<StreamEvent>) -> mpsc::Receiver<StreamEvent> {
+        stream::tap(rx, move |event| self.observe(event))
+    }
+}
+
+impl Drop for StreamMeter {
+    fn drop(&mut self) {
+        let mut record = self.record.clone();
+        if let Some(ref usage) = self.reported {
+            record = record.with_usage(usage);
+        }
+        self.ledger.record(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_tokens() {
+        assert_eq!(count_tokens(""), 0);
+        assert_eq!(count_tokens("Hello"), 1);
+        let sentence = count_tokens("The quick brown fox jumps over the lazy dog.");
+        assert!((9..=12).contains(&sentence), "{}", sentence);
+        // Far fewer tokens than bytes for prose, more per byte for numbers
+        let prose = "Tokens are counted the same way for prompts and completions. ".repeat(20);
+        assert!(count_tokens(&prose) < prose.len() as u32 / 3);
+        assert!(count_tokens("1234567890") >= 3);
+    }
+
+    #[test]
+    fn test_ledger_log_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("usage.jsonl");
+
+        let ledger = UsageLedger::with_log(&path).unwrap();
+        let record = UsageRecord::new("alice", "claude-cli", "claude", false);
+        ledger.record(record.clone().with_usage(&token_usage(10, 5)));
+        ledger.record(record.with_usage(&token_usage(3, 2)));
+        ledger.record(UsageRecord {
+            timestamp: 0,
+            ..UsageRecord::new("bob", "gemini", "gemini", true).with_usage(&token_usage(1, 1))
+        });
+        assert_eq!(ledger.month_tokens("alice"), 20);
+        assert_eq!(ledger.month_tokens("bob"), 0);
+        drop(ledger);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+
+        // Totals are restored from the log
+        std::fs::write(&path, format!("{}not json\n", contents)).unwrap();
+        let ledger = UsageLedger::with_log(&path).unwrap();
+        let month = ledger.month(&month_of(unix_now()));
+        assert_eq!(
+            month["alice"],
+            Totals {
+                requests: 2,
+                prompt_tokens: 13,
+                completion_tokens: 7,
+                total_tokens: 20,
+            }
+        );
+        assert_eq!(ledger.month("1970-01")["bob"].requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_meter() {
+        let ledger = Arc::new(UsageLedger::default());
+        let record = UsageRecord::new("alice", "m", "b", true);
+
+        let (tx, rx) = mpsc::channel(4);
+        let mut out = StreamMeter::new(ledger.clone(), record.clone(), 7).tap(rx);
+        tx.send(StreamEvent::Text("Hello".to_string()))
+            .await
+            .unwrap();
+        tx.send(StreamEvent::Finish("stop".to_string()))
+            .await
+            .unwrap();
+        drop(tx);
+        while out.recv().await.is_some() {}
+        assert_eq!(
+            ledger.month_tokens("alice"),
+            7 + u64::from(count_tokens("Hello"))
+        );
+
+        // Upstream-reported usage wins over counting
+        let mut meter = StreamMeter::new(ledger.clone(), record, 0);
+        meter.observe_chunk(r#"{"choices":[{"delta":{"content":"Hi there"}}]}"#);
+        meter.observe_chunk(r#"{"choices":[],"usage":{"prompt_tokens":40,"completion_tokens":2,"total_tokens":42}}"#);
+        drop(meter);
+        assert_eq!(ledger.month("2000-01").len(), 0);
+        assert_eq!(
+            ledger.month(&month_of(unix_now()))["alice"].total_tokens,
+            42 + 7 + 1
+        );
+    }
+}
@@ -138,12 +138,9 @@ impl ToolRegistry {
                     .await
                     .map_err(|e| AgentError::McpError(e.message))?
             }
-            McpConnection::WebSocket { url: _ } => {
-                // WebSocket support would go here
-                return Err(AgentError::ConfigError(
-                    "WebSocket MCP not yet implemented".to_string(),
-                ));
-            }
+            McpConnection::WebSocket { url } => mcp_methods::list_tools_via_websocket(url, None)
+                .await
+                .map_err(|e| AgentError::McpError(e.message))?,
         };
 
         let mut registered_names = Vec::new();
@@ -269,10 +266,10 @@ async fn execute_mcp_tool(
             .await
             .map_err(|e| AgentError::McpError(e.message))?
         }
-        McpConnection::WebSocket { url: _ } => {
-            return Err(AgentError::ConfigError(
-                "WebSocket MCP not yet implemented".to_string(),
-            ));
+        McpConnection::WebSocket { url } => {
+            mcp_methods::run_tool_via_websocket(url.clone(), tool_name.to_string(), params)
+                .await
+                .map_err(|e| AgentError::McpError(e.message))?
         }
     };
 
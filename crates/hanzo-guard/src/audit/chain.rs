@@ -0,0 +1,571 @@
+//! Hash-chained audit records and chain verification
+//!
+//! Every record carries a sequence number, the hash of the previous record,
+//! and its own SHA-256 hash over `{seq, prev_hash, entry}`. Editing,
+//! deleting, reordering, or inserting a record breaks the chain, which
+//! [`verify_log`] detects across rotated files. A chain must start at the
+//! genesis record, so deleting the oldest files is detected too; chains
+//! pruned on purpose are checked from an explicit anchor with
+//! [`verify_files`].
+//!
+//! The writer also chains [`ChainNotice`] records about the trail itself:
+//! entries it had to drop, or a restart at genesis when the previous trail
+//! could not be read. Verification reports each notice as a problem, so a
+//! trail with missing records never verifies clean.
+
+use crate::error::Result;
+use crate::types::AuditEntry;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// `prev_hash` of the first record in a chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// An audit entry or notice linked into the hash chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the chain, starting at 0
+    pub seq: u64,
+    /// Hash of the previous record
+    pub prev_hash: String,
+    /// What the record holds
+    #[serde(flatten)]
+    pub body: RecordBody,
+    /// Hash of this record
+    pub hash: String,
+}
+
+/// Contents of an [`AuditRecord`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordBody {
+    /// A guard decision
+    Entry(Box<AuditEntry>),
+    /// A note from the writer about the trail itself
+    Notice(ChainNotice),
+}
+
+/// Writer notes about the trail, reported by verification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainNotice {
+    /// Entries were dropped before reaching the chain (writer queue full)
+    Dropped {
+        /// Number of entries dropped
+        count: u64,
+    },
+    /// The previous trail could not be read, so the chain restarted at genesis
+    Restarted {
+        /// Why the previous trail could not be read
+        reason: String,
+    },
+}
+
+impl AuditRecord {
+    /// Link a record onto the chain after `prev_hash`
+    pub fn new(body: RecordBody, seq: u64, prev_hash: String) -> Result<Self> {
+        let mut record = Self {
+            seq,
+            prev_hash,
+            body,
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash()?;
+        Ok(record)
+    }
+
+    /// Recompute the record hash from its contents
+    pub fn compute_hash(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        if let Value::Object(ref mut map) = value {
+            map.remove("hash");
+        }
+        Ok(hash_value(&value))
+    }
+}
+
+/// Running state of a hash chain
+#[derive(Debug, Clone)]
+pub struct ChainState {
+    next_seq: u64,
+    last_hash: String,
+}
+
+impl Default for ChainState {
+    fn default() -> Self {
+        Self {
+            next_seq: 0,
+            last_hash: GENESIS_HASH.to_string(),
+        }
+    }
+}
+
+impl ChainState {
+    /// Resume a chain after the given record
+    pub fn resume(last: &AuditRecord) -> Self {
+        Self {
+            next_seq: last.seq + 1,
+            last_hash: last.hash.clone(),
+        }
+    }
+
+    /// Append an entry to the chain
+    pub fn append(&mut self, entry: AuditEntry) -> Result<AuditRecord> {
+        self.push(RecordBody::Entry(Box::new(entry)))
+    }
+
+    /// Append a notice to the chain
+    pub fn notice(&mut self, notice: ChainNotice) -> Result<AuditRecord> {
+        self.push(RecordBody::Notice(notice))
+    }
+
+    fn push(&mut self, body: RecordBody) -> Result<AuditRecord> {
+        let record = AuditRecord::new(body, self.next_seq, self.last_hash.clone())?;
+        self.next_seq += 1;
+        self.last_hash = record.hash.clone();
+        Ok(record)
+    }
+}
+
+/// Result of verifying an audit chain
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    /// Files checked, in chain order
+    pub files: Vec<PathBuf>,
+    /// Records checked
+    pub records: u64,
+    /// Sequence number of the last record
+    pub last_seq: Option<u64>,
+    /// Hash of the last record (anchor this externally to detect truncation)
+    pub last_hash: Option<String>,
+    /// Problems found
+    pub problems: Vec<ChainProblem>,
+}
+
+impl VerifyReport {
+    /// Whether the chain is intact
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// A break in the audit chain
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainProblem {
+    /// File containing the record
+    pub file: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+    /// What is wrong
+    pub kind: ChainProblemKind,
+}
+
+/// Kinds of chain breaks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ChainProblemKind {
+    /// Line is not a valid audit record
+    Malformed(String),
+    /// Record contents do not match its hash (edited)
+    HashMismatch,
+    /// `prev_hash` does not match the previous record (removed or reordered)
+    BrokenLink,
+    /// Sequence number is not contiguous (removed or inserted)
+    SequenceGap {
+        /// Expected sequence number
+        expected: u64,
+        /// Sequence number found
+        found: u64,
+    },
+    /// The writer dropped entries here
+    EntriesDropped(u64),
+    /// The writer restarted the chain at genesis here
+    Restarted(String),
+}
+
+impl std::fmt::Display for ChainProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: ", self.file.display(), self.line)?;
+        match &self.kind {
+            ChainProblemKind::Malformed(e) => write!(f, "malformed record ({})", e),
+            ChainProblemKind::HashMismatch => write!(f, "record hash mismatch"),
+            ChainProblemKind::BrokenLink => write!(f, "prev_hash does not match previous record"),
+            ChainProblemKind::SequenceGap { expected, found } => {
+                write!(f, "sequence gap (expected {}, found {})", expected, found)
+            }
+            ChainProblemKind::EntriesDropped(count) => {
+                write!(f, "{} entries dropped by the writer", count)
+            }
+            ChainProblemKind::Restarted(reason) => {
+                write!(f, "chain restarted at genesis ({})", reason)
+            }
+        }
+    }
+}
+
+/// Verify an audit log and its rotated siblings
+///
+/// Rotated files (`audit.<seq>.jsonl` next to `audit.jsonl`) are checked
+/// first, oldest to newest, then the active file. The chain must start at
+/// sequence 0 with [`GENESIS_HASH`].
+pub fn verify_log(path: impl AsRef<Path>) -> Result<VerifyReport> {
+    verify_files(&log_files(path.as_ref())?, None)
+}
+
+/// An active log file preceded by its rotated siblings, oldest first
+pub fn log_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = rotated_files(path)?;
+    if path.exists() {
+        files.push(path.to_path_buf());
+    }
+    Ok(files)
+}
+
+/// Verify a sequence of audit files as one continuous chain
+///
+/// The chain must start at `anchor`, the `(seq, prev_hash)` of its first
+/// record, or at genesis (`(0, GENESIS_HASH)`) when `None`. Pass the anchor
+/// recorded when older files were pruned.
+pub fn verify_files(files: &[PathBuf], anchor: Option<(u64, &str)>) -> Result<VerifyReport> {
+    let mut report = VerifyReport {
+        files: files.to_vec(),
+        ..Default::default()
+    };
+    let (first_seq, first_prev) = anchor.unwrap_or((0, GENESIS_HASH));
+    let mut expected_seq = first_seq;
+    let mut prev_hash = first_prev.to_string();
+
+    for file in files {
+        let contents = std::fs::read_to_string(file)?;
+        for (index, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut problem = |kind| {
+                report.problems.push(ChainProblem {
+                    file: file.clone(),
+                    line: index + 1,
+                    kind,
+                })
+            };
+
+            let (seq, record_prev, hash, computed, notice) = match parse_record(line) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    problem(ChainProblemKind::Malformed(e));
+                    continue;
+                }
+            };
+
+            match notice {
+                Some(ChainNotice::Dropped { count }) => {
+                    problem(ChainProblemKind::EntriesDropped(count))
+                }
+                Some(ChainNotice::Restarted { reason }) => {
+                    problem(ChainProblemKind::Restarted(reason));
+                    // A restart links from genesis; check what follows against it
+                    if seq == 0 && record_prev == GENESIS_HASH {
+                        expected_seq = 0;
+                        prev_hash = GENESIS_HASH.to_string();
+                    }
+                }
+                None => {}
+            }
+
+            if hash != computed {
+                problem(ChainProblemKind::HashMismatch);
+            }
+            if seq != expected_seq {
+                problem(ChainProblemKind::SequenceGap {
+                    expected: expected_seq,
+                    found: seq,
+                });
+            }
+            if record_prev != prev_hash {
+                problem(ChainProblemKind::BrokenLink);
+            }
+
+            report.records += 1;
+            report.last_seq = Some(seq);
+            report.last_hash = Some(hash.clone());
+            expected_seq = seq + 1;
+            prev_hash = hash;
+        }
+    }
+
+    Ok(report)
+}
+
+/// A parsed record line: `(seq, prev_hash, hash, recomputed hash, notice)`
+type ParsedRecord = (u64, String, String, String, Option<ChainNotice>);
+
+/// Parse a record line into its chain fields and notice, if any
+fn parse_record(line: &str) -> std::result::Result<ParsedRecord, String> {
+    let mut value: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let map = value.as_object_mut().ok_or("not a JSON object")?;
+    let hash = match map.remove("hash") {
+        Some(Value::String(hash)) => hash,
+        _ => return Err("missing hash".to_string()),
+    };
+    let seq = map
+        .get("seq")
+        .and_then(Value::as_u64)
+        .ok_or("missing seq")?;
+    let prev_hash = map
+        .get("prev_hash")
+        .and_then(Value::as_str)
+        .ok_or("missing prev_hash")?
+        .to_string();
+    let notice = match map.get("notice") {
+        Some(notice) => Some(serde_json::from_value(notice.clone()).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    Ok((seq, prev_hash, hash, hash_value(&value), notice))
+}
+
+/// Rotated siblings of an active log file, oldest first
+pub(crate) fn rotated_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let (stem, ext) = stem_and_ext(path);
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|candidate| {
+            let name = candidate.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            name.strip_prefix(&format!("{}.", stem))
+                .and_then(|rest| rest.strip_suffix(&ext))
+                .is_some_and(|seq| seq.len() == 20 && seq.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Path a rotated file is moved to, keyed by its last sequence number
+pub(crate) fn rotated_path(path: &Path, last_seq: u64) -> PathBuf {
+    let (stem, ext) = stem_and_ext(path);
+    path.with_file_name(format!("{}.{:020}{}", stem, last_seq, ext))
+}
+
+/// File stem and extension (with leading dot, or empty)
+fn stem_and_ext(path: &Path) -> (String, String) {
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("audit")
+        .to_string();
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| format!(".{}", e))
+        .unwrap_or_default();
+    (stem, ext)
+}
+
+/// SHA-256 over the canonical (sorted-key) JSON encoding
+fn hash_value(value: &Value) -> String {
+    let mut canonical = String::new();
+    write_canonical(value, &mut canonical);
+    hex::encode(Sha256::digest(canonical.as_bytes()))
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{AuditResult, Direction, GuardContext};
+
+    fn entry() -> AuditEntry {
+        AuditEntry {
+            context: GuardContext::new().with_user_id("user1"),
+            direction: Direction::Input,
+            content_hash: "abc".to_string(),
+            result: AuditResult::Passed,
+            processing_time_ms: 1,
+        }
+    }
+
+    fn write_chain(path: &Path, count: usize) -> Vec<String> {
+        let mut chain = ChainState::default();
+        let lines: Vec<String> = (0..count)
+            .map(|_| serde_json::to_string(&chain.append(entry()).unwrap()).unwrap())
+            .collect();
+        std::fs::write(path, lines.join("\n") + "\n").unwrap();
+        lines
+    }
+
+    #[test]
+    fn test_valid_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        write_chain(&path, 3);
+
+        let report = verify_log(&path).unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.records, 3);
+        assert_eq!(report.last_seq, Some(2));
+    }
+
+    #[test]
+    fn test_detects_edit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let mut lines = write_chain(&path, 3);
+        lines[1] = lines[1].replace("user1", "user2");
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        let report = verify_log(&path).unwrap();
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].line, 2);
+        assert_eq!(report.problems[0].kind, ChainProblemKind::HashMismatch);
+    }
+
+    #[test]
+    fn test_detects_deletion() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let mut lines = write_chain(&path, 3);
+        lines.remove(1);
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        let report = verify_log(&path).unwrap();
+        let kinds: Vec<_> = report.problems.iter().map(|p| p.kind.clone()).collect();
+        assert!(kinds.contains(&ChainProblemKind::BrokenLink));
+        assert!(kinds.contains(&ChainProblemKind::SequenceGap {
+            expected: 1,
+            found: 2
+        }));
+    }
+
+    #[test]
+    fn test_chain_across_rotated_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let lines = write_chain(&path, 4);
+        std::fs::write(rotated_path(&path, 1), lines[..2].join("\n")).unwrap();
+        std::fs::write(&path, lines[2..].join("\n")).unwrap();
+
+        let report = verify_log(&path).unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.records, 4);
+    }
+
+    #[test]
+    fn test_detects_deleted_rotated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let lines = write_chain(&path, 6);
+        let oldest = rotated_path(&path, 1);
+        std::fs::write(&oldest, lines[..2].join("\n")).unwrap();
+        std::fs::write(rotated_path(&path, 3), lines[2..4].join("\n")).unwrap();
+        std::fs::write(&path, lines[4..].join("\n")).unwrap();
+        std::fs::remove_file(&oldest).unwrap();
+
+        let report = verify_log(&path).unwrap();
+        let kinds: Vec<_> = report.problems.iter().map(|p| p.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ChainProblemKind::SequenceGap {
+                    expected: 0,
+                    found: 2
+                },
+                ChainProblemKind::BrokenLink
+            ]
+        );
+
+        // Verifying from the recorded anchor accepts the pruned chain
+        let anchor: AuditRecord = serde_json::from_str(&lines[1]).unwrap();
+        let files = log_files(&path).unwrap();
+        let report = verify_files(&files, Some((2, &anchor.hash))).unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.records, 4);
+    }
+
+    #[test]
+    fn test_reports_notices() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let mut chain = ChainState::default();
+        let mut records = vec![
+            chain.append(entry()).unwrap(),
+            chain.notice(ChainNotice::Dropped { count: 3 }).unwrap(),
+            chain.append(entry()).unwrap(),
+        ];
+        // A writer that could not read the trail starts over from genesis
+        let mut chain = ChainState::default();
+        records.push(
+            chain
+                .notice(ChainNotice::Restarted {
+                    reason: "unreadable".to_string(),
+                })
+                .unwrap(),
+        );
+        records.push(chain.append(entry()).unwrap());
+        let lines: Vec<String> = records
+            .iter()
+            .map(|r| serde_json::to_string(r).unwrap())
+            .collect();
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        let report = verify_log(&path).unwrap();
+        let kinds: Vec<_> = report.problems.iter().map(|p| p.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ChainProblemKind::EntriesDropped(3),
+                ChainProblemKind::Restarted("unreadable".to_string())
+            ]
+        );
+        assert_eq!(report.records, 5);
+    }
+
+    #[test]
+    fn test_detects_truncated_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let lines = write_chain(&path, 3);
+        std::fs::write(&path, lines[1..].join("\n")).unwrap();
+
+        let report = verify_log(&path).unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.problems[0].line, 1);
+    }
+}
@@ -0,0 +1,636 @@
+//! Audit logging for Guard
+//!
+//! Entries go to stdout and `tracing` inline, and to [`AuditSink`]s through
+//! a background writer that batches them and links them into a hash chain
+//! (see [`chain`]). Use [`verify_log`] to check a file trail for tampering.
+//!
+//! The writer runs on its own thread and queues at most
+//! `AuditConfig::queue_capacity` entries; entries logged while the queue is
+//! full (e.g. behind a stalled webhook) are dropped and counted in
+//! [`AuditLogger::dropped_entries`], and the writer chains a
+//! [`ChainNotice::Dropped`] record in their place so verification reports
+//! the gap. Dropping the logger writes out everything still queued.
+
+pub mod chain;
+pub mod sink;
+
+pub use chain::{
+    log_files, verify_files, verify_log, AuditRecord, ChainNotice, ChainProblem, RecordBody,
+    VerifyReport,
+};
+#[cfg(feature = "audit-webhook")]
+pub use sink::WebhookSink;
+pub use sink::{AuditSink, FileSink};
+
+use crate::config::AuditConfig;
+use crate::error::SafetyCategory;
+use crate::types::{AuditEntry, AuditResult, Direction, GuardContext, SanitizeResult};
+use chain::ChainState;
+
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+#[cfg(feature = "audit")]
+use tracing::{info, warn};
+
+/// Audit logger
+pub struct AuditLogger {
+    config: AuditConfig,
+    writer: Option<Writer>,
+    failed_writes: Arc<AtomicU64>,
+    dropped_entries: AtomicU64,
+    /// Drops the writer has not yet recorded in the chain
+    unrecorded_drops: Arc<AtomicU64>,
+}
+
+/// Handle to the background writer thread
+struct Writer {
+    commands: mpsc::Sender<WriterCommand>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+/// Messages to the background writer
+enum WriterCommand {
+    Record(Box<AuditEntry>),
+    Flush(oneshot::Sender<()>),
+}
+
+impl AuditLogger {
+    /// Create a new audit logger with the sinks named in the config
+    pub fn new(config: AuditConfig) -> Self {
+        let sinks = Self::config_sinks(&config);
+        Self::with_sinks(config, sinks)
+    }
+
+    /// Create an audit logger writing to the given sinks
+    pub fn with_sinks(config: AuditConfig, sinks: Vec<Arc<dyn AuditSink>>) -> Self {
+        let failed_writes = Arc::new(AtomicU64::new(0));
+        let unrecorded_drops = Arc::new(AtomicU64::new(0));
+        let writer = if config.enabled && !sinks.is_empty() {
+            let (commands, rx) = mpsc::channel(config.queue_capacity.max(1));
+            spawn_writer(run_writer(
+                rx,
+                sinks,
+                config.batch_size.max(1),
+                Duration::from_millis(config.flush_interval_ms.max(1)),
+                failed_writes.clone(),
+                unrecorded_drops.clone(),
+            ))
+            .map(|thread| Writer { commands, thread })
+        } else {
+            None
+        };
+
+        Self {
+            config,
+            writer,
+            failed_writes,
+            dropped_entries: AtomicU64::new(0),
+            unrecorded_drops,
+        }
+    }
+
+    /// Sinks configured by [`AuditConfig`] (file and webhook)
+    pub fn config_sinks(config: &AuditConfig) -> Vec<Arc<dyn AuditSink>> {
+        let mut sinks: Vec<Arc<dyn AuditSink>> = vec![];
+
+        if let Some(ref path) = config.log_file {
+            let mut sink = FileSink::new(path);
+            if let Some(max_bytes) = config.rotate_max_bytes {
+                sink = sink.with_max_bytes(max_bytes);
+            }
+            if let Some(secs) = config.rotate_interval_secs {
+                sink = sink.with_max_age(Duration::from_secs(secs));
+            }
+            sinks.push(Arc::new(sink));
+        }
+
+        #[cfg(feature = "audit-webhook")]
+        if let Some(ref url) = config.webhook_url {
+            sinks.push(Arc::new(WebhookSink::new(
+                url.clone(),
+                config.webhook_token.clone(),
+            )));
+        }
+
+        sinks
+    }
+
+    /// Log a sanitization event
+    pub fn log(
+        &self,
+        context: &GuardContext,
+        direction: Direction,
+        content: &str,
+        result: &SanitizeResult,
+        duration_ms: u64,
+    ) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let entry = AuditEntry {
+            context: context.clone(),
+            direction,
+            content_hash: hash_content(content),
+            result: match result {
+                SanitizeResult::Clean(_) => AuditResult::Passed,
+                SanitizeResult::Redacted { redactions, .. } => AuditResult::Redacted {
+                    count: redactions.len(),
+                },
+                SanitizeResult::Blocked { category, .. } => AuditResult::Blocked {
+                    category: *category,
+                },
+            },
+            processing_time_ms: duration_ms,
+        };
+
+        self.emit(entry, content);
+    }
+
+    /// Log a blocked request
+    pub fn log_blocked(
+        &self,
+        context: &GuardContext,
+        direction: Direction,
+        content: &str,
+        reason: &str,
+        category: SafetyCategory,
+    ) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let entry = AuditEntry {
+            context: context.clone(),
+            direction,
+            content_hash: hash_content(content),
+            result: AuditResult::Blocked { category },
+            processing_time_ms: 0,
+        };
+
+        #[cfg(feature = "audit")]
+        warn!(
+            request_id = %context.request_id,
+            user_id = ?context.user_id,
+            direction = ?direction,
+            category = ?category,
+            reason = reason,
+            "Request blocked"
+        );
+        #[cfg(not(feature = "audit"))]
+        let _ = reason;
+
+        self.emit(entry, content);
+    }
+
+    /// Wait until every entry logged so far has been written and flushed
+    pub async fn flush(&self) {
+        if let Some(ref writer) = self.writer {
+            let (done, wait) = oneshot::channel();
+            if writer
+                .commands
+                .send(WriterCommand::Flush(done))
+                .await
+                .is_ok()
+            {
+                let _ = wait.await;
+            }
+        }
+    }
+
+    /// Number of batch writes that failed on some sink
+    pub fn failed_writes(&self) -> u64 {
+        self.failed_writes.load(Ordering::Relaxed)
+    }
+
+    /// Number of entries dropped because the writer's queue was full
+    pub fn dropped_entries(&self) -> u64 {
+        self.dropped_entries.load(Ordering::Relaxed)
+    }
+
+    /// Emit an audit entry
+    fn emit(&self, entry: AuditEntry, content: &str) {
+        // Log to stdout
+        if self.config.log_stdout {
+            let content_info = if self.config.log_content {
+                format!(", content={}", truncate(content, 100))
+            } else {
+                String::new()
+            };
+
+            println!(
+                "[AUDIT] {} | {} | {:?} | result={:?} | {}ms{}",
+                entry.context.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                entry.context.request_id,
+                entry.direction,
+                entry.result,
+                entry.processing_time_ms,
+                content_info
+            );
+        }
+
+        // Log via tracing
+        #[cfg(feature = "audit")]
+        {
+            let content_field = if self.config.log_content {
+                Some(truncate(content, 500))
+            } else {
+                None
+            };
+
+            info!(
+                request_id = %entry.context.request_id,
+                user_id = ?entry.context.user_id,
+                session_id = ?entry.context.session_id,
+                direction = ?entry.direction,
+                content_hash = %entry.content_hash,
+                result = ?entry.result,
+                processing_time_ms = entry.processing_time_ms,
+                content = ?content_field,
+                "Guard audit"
+            );
+        }
+
+        // Hand off to the background writer
+        if let Some(ref writer) = self.writer {
+            match writer
+                .commands
+                .try_send(WriterCommand::Record(Box::new(entry)))
+            {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    self.unrecorded_drops.fetch_add(1, Ordering::Relaxed);
+                    let _dropped = self.dropped_entries.fetch_add(1, Ordering::Relaxed) + 1;
+                    // Warn on the 1st, 2nd, 4th, ... drop rather than every one
+                    #[cfg(feature = "audit")]
+                    if _dropped.is_power_of_two() {
+                        warn!(dropped = _dropped, "Audit queue full, dropping entries");
+                    }
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    self.failed_writes.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for AuditLogger {
+    /// Write out queued entries before going away
+    fn drop(&mut self) {
+        if let Some(Writer { commands, thread }) = self.writer.take() {
+            // Closing the queue makes the writer drain it, flush, and exit
+            drop(commands);
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Run the writer on a dedicated thread, so it outlives the caller's runtime
+fn spawn_writer(
+    writer: impl std::future::Future<Output = ()> + Send + 'static,
+) -> Option<std::thread::JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name("hanzo-guard-audit".to_string())
+        .spawn(move || {
+            if let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                runtime.block_on(writer);
+            }
+        })
+        .ok()
+}
+
+/// Background writer: chains entries and writes them to sinks in batches
+async fn run_writer(
+    mut rx: mpsc::Receiver<WriterCommand>,
+    sinks: Vec<Arc<dyn AuditSink>>,
+    batch_size: usize,
+    flush_interval: Duration,
+    failed_writes: Arc<AtomicU64>,
+    unrecorded_drops: Arc<AtomicU64>,
+) {
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut chain = ChainState::default();
+    let mut unreadable = None;
+    for sink in &sinks {
+        match sink.last_record().await {
+            Ok(Some(last)) => {
+                chain = ChainState::resume(&last);
+                unreadable = None;
+                break;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                unreadable.get_or_insert_with(|| format!("{}: {}", sink.name(), e));
+            }
+        }
+    }
+    // Starting over silently would hide the break; record it in the chain
+    if let Some(reason) = unreadable {
+        #[cfg(feature = "audit")]
+        warn!(reason = %reason, "Audit trail unreadable, restarting the chain");
+        let notice = chain.notice(ChainNotice::Restarted { reason });
+        push_record(&mut batch, notice, &failed_writes);
+    }
+
+    let mut ticker = tokio::time::interval(flush_interval);
+
+    loop {
+        tokio::select! {
+            command = rx.recv() => match command {
+                Some(WriterCommand::Record(entry)) => {
+                    record_drops(&mut chain, &mut batch, &unrecorded_drops, &failed_writes);
+                    push_record(&mut batch, chain.append(*entry), &failed_writes);
+                    if batch.len() >= batch_size {
+                        write_batch(&sinks, &mut batch, &failed_writes).await;
+                    }
+                }
+                Some(WriterCommand::Flush(done)) => {
+                    record_drops(&mut chain, &mut batch, &unrecorded_drops, &failed_writes);
+                    write_batch(&sinks, &mut batch, &failed_writes).await;
+                    flush_sinks(&sinks, &failed_writes).await;
+                    let _ = done.send(());
+                }
+                None => {
+                    record_drops(&mut chain, &mut batch, &unrecorded_drops, &failed_writes);
+                    write_batch(&sinks, &mut batch, &failed_writes).await;
+                    flush_sinks(&sinks, &failed_writes).await;
+                    break;
+                }
+            },
+            _ = ticker.tick() => {
+                if !batch.is_empty() {
+                    write_batch(&sinks, &mut batch, &failed_writes).await;
+                }
+            }
+        }
+    }
+}
+
+/// Add a chained record to the batch, counting chaining failures
+fn push_record(
+    batch: &mut Vec<AuditRecord>,
+    record: crate::error::Result<AuditRecord>,
+    failed_writes: &AtomicU64,
+) {
+    match record {
+        Ok(record) => batch.push(record),
+        Err(_) => {
+            failed_writes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Chain a notice for entries dropped since the last one
+fn record_drops(
+    chain: &mut ChainState,
+    batch: &mut Vec<AuditRecord>,
+    unrecorded_drops: &AtomicU64,
+    failed_writes: &AtomicU64,
+) {
+    let count = unrecorded_drops.swap(0, Ordering::Relaxed);
+    if count > 0 {
+        let notice = chain.notice(ChainNotice::Dropped { count });
+        push_record(batch, notice, failed_writes);
+    }
+}
+
+async fn write_batch(
+    sinks: &[Arc<dyn AuditSink>],
+    batch: &mut Vec<AuditRecord>,
+    failed_writes: &AtomicU64,
+) {
+    if batch.is_empty() {
+        return;
+    }
+    for sink in sinks {
+        if let Err(_e) = sink.write_batch(batch).await {
+            failed_writes.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "audit")]
+            warn!(sink = sink.name(), error = %_e, "Audit sink write failed");
+        }
+    }
+    batch.clear();
+}
+
+async fn flush_sinks(sinks: &[Arc<dyn AuditSink>], failed_writes: &AtomicU64) {
+    for sink in sinks {
+        if let Err(_e) = sink.flush().await {
+            failed_writes.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "audit")]
+            warn!(sink = sink.name(), error = %_e, "Audit sink flush failed");
+        }
+    }
+}
+
+/// Hash content for audit (privacy-preserving)
+fn hash_content(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+/// Truncate string for logging
+fn truncate(s: &str, max_len: usize) -> String {
+    if s.len() <= max_len {
+        s.to_string()
+    } else {
+        let mut end = max_len;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...", &s[..end])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_content() {
+        let hash1 = hash_content("test");
+        let hash2 = hash_content("test");
+        let hash3 = hash_content("different");
+
+        assert_eq!(hash1, hash2);
+        assert_ne!(hash1, hash3);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("this is a longer string", 10), "this is a ...");
+    }
+
+    #[test]
+    fn test_audit_disabled() {
+        let config = AuditConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        let logger = AuditLogger::new(config);
+
+        // Should not panic when disabled
+        let ctx = GuardContext::default();
+        logger.log(
+            &ctx,
+            Direction::Input,
+            "test content",
+            &SanitizeResult::Clean("test content".to_string()),
+            10,
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_trail_resumes_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let config = AuditConfig {
+            log_file: Some(path.display().to_string()),
+            ..Default::default()
+        };
+        let clean = SanitizeResult::Clean("hi".to_string());
+
+        let logger = AuditLogger::new(config.clone());
+        logger.log(&GuardContext::new(), Direction::Input, "hi", &clean, 1);
+        logger.log(&GuardContext::new(), Direction::Output, "hi", &clean, 1);
+        logger.flush().await;
+
+        // A new logger continues the existing chain
+        let logger = AuditLogger::new(config);
+        logger.log(&GuardContext::new(), Direction::Input, "hi", &clean, 1);
+        logger.flush().await;
+
+        let report = verify_log(&path).unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.records, 3);
+        assert_eq!(logger.failed_writes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drop_writes_queued_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let config = AuditConfig {
+            log_file: Some(path.display().to_string()),
+            flush_interval_ms: 60_000,
+            ..Default::default()
+        };
+        let clean = SanitizeResult::Clean("hi".to_string());
+
+        let logger = AuditLogger::new(config);
+        logger.log(&GuardContext::new(), Direction::Input, "hi", &clean, 1);
+        logger.log(&GuardContext::new(), Direction::Output, "hi", &clean, 1);
+        drop(logger);
+
+        let report = verify_log(&path).unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.records, 2);
+    }
+
+    /// Sink that blocks every write until released
+    struct StalledSink {
+        gate: tokio::sync::Semaphore,
+        written: AtomicU64,
+        noted_drops: AtomicU64,
+    }
+
+    #[async_trait::async_trait]
+    impl AuditSink for StalledSink {
+        fn name(&self) -> &str {
+            "stalled"
+        }
+
+        async fn write_batch(&self, records: &[AuditRecord]) -> crate::error::Result<()> {
+            let _permit = self.gate.acquire().await;
+            for record in records {
+                match record.body {
+                    RecordBody::Entry(_) => self.written.fetch_add(1, Ordering::Relaxed),
+                    RecordBody::Notice(ChainNotice::Dropped { count }) => {
+                        self.noted_drops.fetch_add(count, Ordering::Relaxed)
+                    }
+                    RecordBody::Notice(_) => 0,
+                };
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_entries() {
+        let sink = Arc::new(StalledSink {
+            gate: tokio::sync::Semaphore::new(0),
+            written: AtomicU64::new(0),
+            noted_drops: AtomicU64::new(0),
+        });
+        let config = AuditConfig {
+            batch_size: 1,
+            queue_capacity: 2,
+            ..Default::default()
+        };
+        let clean = SanitizeResult::Clean("hi".to_string());
+
+        let logger = AuditLogger::with_sinks(config, vec![sink.clone()]);
+        for _ in 0..10 {
+            logger.log(&GuardContext::new(), Direction::Input, "hi", &clean, 1);
+        }
+        // At most one entry is held by the writer and two are queued
+        let dropped = logger.dropped_entries();
+        assert!(dropped >= 7, "dropped {}", dropped);
+
+        sink.gate.add_permits(100);
+        drop(logger);
+        assert_eq!(sink.written.load(Ordering::Relaxed) + dropped, 10);
+        // The chain records every drop
+        assert_eq!(sink.noted_drops.load(Ordering::Relaxed), dropped);
+    }
+
+    /// Sink whose previous trail cannot be read
+    struct UnreadableSink {
+        records: std::sync::Mutex<Vec<AuditRecord>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AuditSink for UnreadableSink {
+        fn name(&self) -> &str {
+            "unreadable"
+        }
+
+        async fn write_batch(&self, records: &[AuditRecord]) -> crate::error::Result<()> {
+            self.records.lock().unwrap().extend_from_slice(records);
+            Ok(())
+        }
+
+        async fn last_record(&self) -> crate::error::Result<Option<AuditRecord>> {
+            Err(std::io::Error::other("corrupt tail").into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_trail_records_restart() {
+        let sink = Arc::new(UnreadableSink {
+            records: std::sync::Mutex::new(vec![]),
+        });
+        let clean = SanitizeResult::Clean("hi".to_string());
+
+        let logger = AuditLogger::with_sinks(AuditConfig::default(), vec![sink.clone()]);
+        logger.log(&GuardContext::new(), Direction::Input, "hi", &clean, 1);
+        logger.flush().await;
+
+        let records = sink.records.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        match &records[0].body {
+            RecordBody::Notice(ChainNotice::Restarted { reason }) => {
+                assert!(reason.contains("corrupt tail"), "{}", reason)
+            }
+            other => panic!("expected a restart notice, got {:?}", other),
+        }
+        assert!(matches!(records[1].body, RecordBody::Entry(_)));
+    }
+}
@@ -0,0 +1,281 @@
+//! Audit sinks
+//!
+//! Sinks receive batches of hash-chained [`AuditRecord`]s from the
+//! [`AuditLogger`](super::AuditLogger) background writer.
+
+use super::chain::{rotated_files, rotated_path, AuditRecord};
+#[cfg(feature = "audit-webhook")]
+use crate::error::GuardError;
+use crate::error::Result;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Destination for audit records
+#[async_trait]
+pub trait AuditSink: Send + Sync {
+    /// Sink name, used in error reports
+    fn name(&self) -> &str;
+
+    /// Write a batch of records, in chain order
+    async fn write_batch(&self, records: &[AuditRecord]) -> Result<()>;
+
+    /// Flush buffered data to durable storage
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Last record previously written, used to resume the chain on startup
+    async fn last_record(&self) -> Result<Option<AuditRecord>> {
+        Ok(None)
+    }
+}
+
+/// JSONL file sink with size- and time-based rotation
+///
+/// Rotated files are renamed to `<stem>.<last seq>.<ext>` next to the
+/// active file, so [`verify_log`](super::verify_log) can follow the chain.
+pub struct FileSink {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+    state: Mutex<FileState>,
+}
+
+struct FileState {
+    file: Option<tokio::fs::File>,
+    size: u64,
+    opened_at: Instant,
+    last_seq: Option<u64>,
+}
+
+impl FileSink {
+    /// Create a file sink without rotation
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes: None,
+            max_age: None,
+            state: Mutex::new(FileState {
+                file: None,
+                size: 0,
+                opened_at: Instant::now(),
+                last_seq: None,
+            }),
+        }
+    }
+
+    /// Rotate once the active file reaches `max_bytes`
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Rotate once the active file has been open for `max_age`
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Whether the active file is due for rotation
+    fn should_rotate(&self, state: &FileState) -> bool {
+        if state.size == 0 {
+            return false;
+        }
+        self.max_bytes.is_some_and(|max| state.size >= max)
+            || self
+                .max_age
+                .is_some_and(|max| state.opened_at.elapsed() >= max)
+    }
+
+    async fn rotate(&self, state: &mut FileState) -> Result<()> {
+        if let Some(mut file) = state.file.take() {
+            file.flush().await?;
+        }
+        let last_seq = match state.last_seq {
+            Some(seq) => seq,
+            None => match read_last_record(&self.path).await? {
+                Some(record) => record.seq,
+                None => return Ok(()),
+            },
+        };
+        tokio::fs::rename(&self.path, rotated_path(&self.path, last_seq)).await?;
+        state.size = 0;
+        state.opened_at = Instant::now();
+        Ok(())
+    }
+
+    async fn open(&self, state: &mut FileState) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        state.size = file.metadata().await?.len();
+        state.file = Some(file);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditSink for FileSink {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn write_batch(&self, records: &[AuditRecord]) -> Result<()> {
+        let mut state = self.state.lock().await;
+
+        if state.file.is_none() {
+            self.open(&mut state).await?;
+        }
+        if self.should_rotate(&state) {
+            self.rotate(&mut state).await?;
+            self.open(&mut state).await?;
+        }
+
+        let mut buf = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut buf, record)?;
+            buf.push(b'\n');
+        }
+
+        let file = state.file.as_mut().expect("file opened above");
+        file.write_all(&buf).await?;
+        state.size += buf.len() as u64;
+        if let Some(record) = records.last() {
+            state.last_seq = Some(record.seq);
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(ref mut file) = state.file {
+            file.flush().await?;
+            file.sync_data().await?;
+        }
+        Ok(())
+    }
+
+    async fn last_record(&self) -> Result<Option<AuditRecord>> {
+        if let Some(record) = read_last_record(&self.path).await? {
+            return Ok(Some(record));
+        }
+        // The active file may be empty right after a rotation
+        match rotated_files(&self.path)?.last() {
+            Some(rotated) => read_last_record(rotated).await,
+            None => Ok(None),
+        }
+    }
+}
+
+/// Read the last record of a JSONL audit file
+async fn read_last_record(path: &std::path::Path) -> Result<Option<AuditRecord>> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    match contents.lines().rev().find(|l| !l.trim().is_empty()) {
+        Some(line) => Ok(Some(serde_json::from_str(line)?)),
+        None => Ok(None),
+    }
+}
+
+/// Webhook sink that POSTs each batch as a JSON array
+#[cfg(feature = "audit-webhook")]
+pub struct WebhookSink {
+    url: String,
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "audit-webhook")]
+impl WebhookSink {
+    /// Create a webhook sink
+    pub fn new(url: impl Into<String>, token: Option<String>) -> Self {
+        Self {
+            url: url.into(),
+            token,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(feature = "audit-webhook")]
+#[async_trait]
+impl AuditSink for WebhookSink {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn write_batch(&self, records: &[AuditRecord]) -> Result<()> {
+        let mut req = self.client.post(&self.url).json(records);
+        if let Some(ref token) = self.token {
+            req = req.header("Authorization", format!("Bearer {}", token));
+        }
+
+        let response = req.send().await?;
+        if !response.status().is_success() {
+            return Err(GuardError::IoError(std::io::Error::other(format!(
+                "Audit webhook returned status: {}",
+                response.status()
+            ))));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::chain::ChainState;
+    use crate::types::{AuditEntry, AuditResult, Direction, GuardContext};
+
+    fn records(chain: &mut ChainState, count: usize) -> Vec<AuditRecord> {
+        (0..count)
+            .map(|_| {
+                chain
+                    .append(AuditEntry {
+                        context: GuardContext::new(),
+                        direction: Direction::Output,
+                        content_hash: "abc".to_string(),
+                        result: AuditResult::Passed,
+                        processing_time_ms: 0,
+                    })
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_file_rotation_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let sink = FileSink::new(&path).with_max_bytes(1);
+        let mut chain = ChainState::default();
+
+        sink.write_batch(&records(&mut chain, 2)).await.unwrap();
+        sink.write_batch(&records(&mut chain, 1)).await.unwrap();
+        sink.flush().await.unwrap();
+
+        let rotated = rotated_files(&path).unwrap();
+        assert_eq!(rotated, vec![rotated_path(&path, 1)]);
+        assert_eq!(sink.last_record().await.unwrap().unwrap().seq, 2);
+
+        let report = crate::audit::verify_log(&path).unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.records, 3);
+    }
+}
@@ -0,0 +1,356 @@
+//! CLI for scanning files and datasets with Hanzo Guard
+//!
+//! Usage:
+//!   hanzo-guard --config guard.toml --fail-on redact data/ notes.txt
+//!   cat prompts.jsonl | hanzo-guard --jsonl --format json -
+//!   hanzo-guard verify /var/log/hanzo-guard.jsonl
+//!
+//! Exits 0 when the scan passes (or the audit chain is intact), 1 when
+//! findings exceed the threshold (or the chain is broken), and 2 on errors.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use hanzo_guard::audit::{log_files, verify_files, VerifyReport};
+use hanzo_guard::scan::{FileReport, ScanFormat, Scanner};
+use hanzo_guard::{Direction, Guard, GuardConfig, PolicyAction};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Scan text, JSONL, and directories for PII, injections, and unsafe content
+#[derive(Parser, Debug)]
+#[command(
+    name = "hanzo-guard",
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Files or directories to scan (`-` reads stdin)
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// GuardConfig TOML file (defaults to the built-in config)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+
+    /// Treat every input as JSONL, whatever its extension
+    #[arg(long)]
+    jsonl: bool,
+
+    /// Scan content as LLM output (skips injection detection)
+    #[arg(long)]
+    output: bool,
+
+    /// Write sanitized copies into this directory
+    #[arg(short, long, value_name = "DIR")]
+    redact_to: Option<PathBuf>,
+
+    /// Fail when any finding reaches this action
+    #[arg(long, value_name = "ACTION", default_value = "block", value_parser = parse_action)]
+    fail_on: PolicyAction,
+
+    /// Fail when the total number of findings exceeds this
+    #[arg(long, value_name = "N")]
+    max_findings: Option<usize>,
+
+    /// Explain which rule (or default) decided each finding
+    #[arg(long)]
+    explain: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Verify a hash-chained audit log and its rotated files
+    Verify {
+        /// Active audit log file
+        log: PathBuf,
+
+        /// Start of a pruned chain: `<seq>:<prev_hash>` of its first record
+        #[arg(long, value_name = "SEQ:HASH", value_parser = parse_anchor)]
+        anchor: Option<(u64, String)>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+}
+
+fn parse_action(s: &str) -> Result<PolicyAction, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_string())).map_err(|_| {
+        "expected one of: allow, warn, tokenize, redact, require-review, block".to_string()
+    })
+}
+
+fn parse_anchor(s: &str) -> Result<(u64, String), String> {
+    s.split_once(':')
+        .and_then(|(seq, hash)| Some((seq.parse().ok()?, hash.to_string())))
+        .ok_or_else(|| "expected <seq>:<prev_hash>".to_string())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let result = match args.command {
+        Some(Command::Verify {
+            ref log,
+            ref anchor,
+            format,
+        }) => verify(log, anchor.as_ref(), format),
+        None => run(&args).await,
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Run the scan, returning whether it passed the threshold
+async fn run(args: &Args) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut config = match args.config {
+        Some(ref path) => GuardConfig::from_file(path)?,
+        None => GuardConfig::default(),
+    };
+    // Bulk scans would trip per-user limits, and stdout carries the report
+    config.rate_limit.enabled = false;
+    config.audit.log_stdout = false;
+
+    let direction = if args.output {
+        Direction::Output
+    } else {
+        Direction::Input
+    };
+    let mut scanner = Scanner::new(Guard::new(config)).with_direction(direction);
+    if args.jsonl {
+        scanner = scanner.with_format(ScanFormat::Jsonl);
+    }
+
+    // Each report is paired with the root it was found under
+    let mut reports: Vec<(PathBuf, FileReport)> = vec![];
+    for path in &args.paths {
+        if path == Path::new("-") {
+            let mut contents = String::new();
+            std::io::stdin().read_to_string(&mut contents)?;
+            let format = if args.jsonl {
+                ScanFormat::Jsonl
+            } else {
+                ScanFormat::Text
+            };
+            let report = scanner.scan_str("<stdin>", &contents, format).await?;
+            reports.push((PathBuf::new(), report));
+            continue;
+        }
+
+        for report in scanner.scan_path(path).await? {
+            reports.push((path.clone(), report));
+        }
+    }
+    scanner.guard().flush_audit().await;
+
+    if let Some(ref dir) = args.redact_to {
+        for (root, report) in &reports {
+            write_copy(dir, root, report)?;
+        }
+    }
+
+    let total: usize = reports.iter().map(|(_, r)| r.findings.len()).sum();
+    let failing: usize = reports
+        .iter()
+        .flat_map(|(_, r)| &r.findings)
+        .filter(|f| f.matched.action >= args.fail_on)
+        .count();
+    let passed = failing == 0 && args.max_findings.is_none_or(|max| total <= max);
+
+    match args.format {
+        OutputFormat::Table => print_table(args, &reports, total, failing, passed),
+        OutputFormat::Json => print_json(args, &reports, total, failing, passed)?,
+    }
+    Ok(passed)
+}
+
+/// Verify an audit chain, returning whether it is intact
+fn verify(
+    log: &Path,
+    anchor: Option<&(u64, String)>,
+    format: OutputFormat,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let files = log_files(log)?;
+    if files.is_empty() {
+        return Err(format!("no audit log at {}", log.display()).into());
+    }
+    let anchor = anchor.map(|(seq, hash)| (*seq, hash.as_str()));
+    let report = verify_files(&files, anchor)?;
+    match format {
+        OutputFormat::Table => print_verify_report(&report),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(report.is_valid())
+}
+
+fn print_verify_report(report: &VerifyReport) {
+    for file in &report.files {
+        println!("{}", file.display());
+    }
+    for problem in &report.problems {
+        println!("  {}", problem);
+    }
+    println!(
+        "Verified {} records in {} files, last seq {}, head {} - {}",
+        report.records,
+        report.files.len(),
+        report
+            .last_seq
+            .map_or_else(|| "-".to_string(), |seq| seq.to_string()),
+        report.last_hash.as_deref().unwrap_or("-"),
+        if report.is_valid() {
+            "OK".to_string()
+        } else {
+            format!("{} problems", report.problems.len())
+        }
+    );
+}
+
+/// Write a sanitized copy, mirroring the path relative to its scan root
+fn write_copy(dir: &Path, root: &Path, report: &FileReport) -> std::io::Result<()> {
+    let relative = if root.as_os_str().is_empty() {
+        PathBuf::from("stdin")
+    } else if root.is_dir() {
+        report
+            .path
+            .strip_prefix(root)
+            .unwrap_or(&report.path)
+            .to_path_buf()
+    } else {
+        report
+            .path
+            .file_name()
+            .map(PathBuf::from)
+            .unwrap_or_default()
+    };
+    let target = dir.join(relative);
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(target, &report.sanitized)
+}
+
+fn print_table(
+    args: &Args,
+    reports: &[(PathBuf, FileReport)],
+    total: usize,
+    failing: usize,
+    passed: bool,
+) {
+    for (_, report) in reports {
+        if report.findings.is_empty() {
+            continue;
+        }
+        println!(
+            "{} ({}, {} scanned, {} findings, {} blocked)",
+            report.path.display(),
+            report.format,
+            report.units,
+            report.findings.len(),
+            report.blocked
+        );
+
+        let rows: Vec<[String; 5]> = report
+            .findings
+            .iter()
+            .map(|f| {
+                [
+                    f.line.to_string(),
+                    f.field.clone().unwrap_or_else(|| "-".to_string()),
+                    f.matched.finding.to_string(),
+                    f.matched.action.to_string(),
+                    f.matched.rule.clone().unwrap_or_else(|| "-".to_string()),
+                ]
+            })
+            .collect();
+        let header = ["LINE", "FIELD", "FINDING", "ACTION", "RULE"];
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let line = |cells: [&str; 5]| {
+            let cells: Vec<String> = cells
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            format!("  {}", cells.join("  ").trim_end())
+        };
+        println!("{}", line(header));
+        for (row, finding) in rows.iter().zip(&report.findings) {
+            println!("{}", line(row.each_ref().map(String::as_str)));
+            if args.explain {
+                println!("    {}", finding.matched);
+            }
+        }
+        println!();
+    }
+
+    println!(
+        "Scanned {} files: {} findings, {} at or above '{}' - {}",
+        reports.len(),
+        total,
+        failing,
+        args.fail_on,
+        if passed { "PASS" } else { "FAIL" }
+    );
+}
+
+fn print_json(
+    args: &Args,
+    reports: &[(PathBuf, FileReport)],
+    total: usize,
+    failing: usize,
+    passed: bool,
+) -> serde_json::Result<()> {
+    let mut files = vec![];
+    for (_, report) in reports {
+        let mut file = serde_json::to_value(report)?;
+        if args.explain {
+            for (finding, value) in report
+                .findings
+                .iter()
+                .zip(file["findings"].as_array_mut().into_iter().flatten())
+            {
+                value["explanation"] = finding.matched.to_string().into();
+            }
+        }
+        files.push(file);
+    }
+
+    let output = serde_json::json!({
+        "files": files,
+        "summary": {
+            "files": reports.len(),
+            "findings": total,
+            "failing": failing,
+            "fail_on": args.fail_on,
+            "passed": passed,
+        },
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
@@ -0,0 +1,539 @@
+//! Pluggable content-safety classifiers
+//!
+//! [`ContentFilter`](crate::content::ContentFilter) delegates scoring to a
+//! [`SafetyClassifier`]. Three backends ship with the crate:
+//!
+//! - [`ZenGuardClassifier`]: the hosted Zen Guard API (`content-filter` feature)
+//! - [`ModerationClassifier`]: any OpenAI-compatible moderation endpoint,
+//!   e.g. a local model server (`content-filter` feature)
+//! - [`TaxonomyClassifier`]: an offline keyword/regex taxonomy for
+//!   air-gapped deployments (`local-classifier` feature)
+
+use crate::content::ContentFilterResult;
+use crate::error::Result;
+#[cfg(any(feature = "content-filter", feature = "local-classifier"))]
+use crate::error::{GuardError, SafetyCategory};
+#[cfg(any(feature = "content-filter", feature = "local-classifier"))]
+use crate::types::SafetyLevel;
+use async_trait::async_trait;
+
+#[cfg(feature = "content-filter")]
+use crate::config::ContentFilterConfig;
+#[cfg(feature = "local-classifier")]
+use crate::config::TaxonomyRule;
+#[cfg(feature = "local-classifier")]
+use regex::Regex;
+#[cfg(feature = "content-filter")]
+use serde::{Deserialize, Serialize};
+
+/// A backend that classifies content safety
+#[async_trait]
+pub trait SafetyClassifier: Send + Sync {
+    /// Backend name, used in logs and block reasons
+    fn name(&self) -> &str;
+
+    /// Classify content; `is_response` is true when checking LLM output
+    async fn classify(&self, content: &str, is_response: bool) -> Result<ContentFilterResult>;
+}
+
+/// Request to Zen Guard API
+#[cfg(feature = "content-filter")]
+#[derive(Debug, Serialize)]
+struct GuardRequest {
+    messages: Vec<GuardMessage>,
+}
+
+#[cfg(feature = "content-filter")]
+#[derive(Debug, Serialize)]
+struct GuardMessage {
+    role: String,
+    content: String,
+}
+
+/// Response from Zen Guard API
+#[cfg(feature = "content-filter")]
+#[derive(Debug, Deserialize)]
+struct GuardResponse {
+    safety: String,
+    categories: Vec<String>,
+    refusal: Option<String>,
+}
+
+/// Classifier backed by the hosted Zen Guard API
+#[cfg(feature = "content-filter")]
+pub struct ZenGuardClassifier {
+    endpoint: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "content-filter")]
+impl ZenGuardClassifier {
+    /// Create a Zen Guard classifier from the content filter configuration
+    pub fn new(config: &ContentFilterConfig) -> Self {
+        Self {
+            endpoint: config.api_endpoint.clone(),
+            api_key: config.api_key.clone(),
+            client: http_client(config.timeout_ms),
+        }
+    }
+}
+
+#[cfg(feature = "content-filter")]
+#[async_trait]
+impl SafetyClassifier for ZenGuardClassifier {
+    fn name(&self) -> &str {
+        "zen-guard"
+    }
+
+    async fn classify(&self, content: &str, is_response: bool) -> Result<ContentFilterResult> {
+        let messages = if is_response {
+            vec![
+                GuardMessage {
+                    role: "user".to_string(),
+                    content: "[Checking response]".to_string(),
+                },
+                GuardMessage {
+                    role: "assistant".to_string(),
+                    content: content.to_string(),
+                },
+            ]
+        } else {
+            vec![GuardMessage {
+                role: "user".to_string(),
+                content: content.to_string(),
+            }]
+        };
+
+        let request = GuardRequest { messages };
+
+        let mut req = self.client.post(&self.endpoint).json(&request);
+
+        if let Some(ref api_key) = self.api_key {
+            req = req.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let response = req
+            .send()
+            .await
+            .map_err(|e| GuardError::ContentFilterError(format!("API request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(GuardError::ContentFilterError(format!(
+                "API returned status: {}",
+                response.status()
+            )));
+        }
+
+        let guard_response: GuardResponse = response.json().await.map_err(|e| {
+            GuardError::ContentFilterError(format!("Failed to parse response: {}", e))
+        })?;
+
+        let safety_level = match guard_response.safety.to_lowercase().as_str() {
+            "safe" => SafetyLevel::Safe,
+            "controversial" => SafetyLevel::Controversial,
+            "unsafe" => SafetyLevel::Unsafe,
+            _ => SafetyLevel::Safe,
+        };
+
+        let categories = guard_response
+            .categories
+            .iter()
+            .filter_map(|c| parse_category(c))
+            .collect();
+
+        let refused = guard_response.refusal.as_deref() == Some("Yes");
+
+        Ok(ContentFilterResult {
+            safety_level,
+            categories,
+            refused,
+        })
+    }
+}
+
+/// Request to an OpenAI-compatible moderation endpoint
+#[cfg(feature = "content-filter")]
+#[derive(Debug, Serialize)]
+struct ModerationRequest<'a> {
+    input: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+}
+
+/// Response from an OpenAI-compatible moderation endpoint
+#[cfg(feature = "content-filter")]
+#[derive(Debug, Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationResult>,
+}
+
+#[cfg(feature = "content-filter")]
+#[derive(Debug, Deserialize)]
+struct ModerationResult {
+    flagged: bool,
+    #[serde(default)]
+    categories: std::collections::HashMap<String, bool>,
+}
+
+/// Classifier backed by an OpenAI-compatible `/v1/moderations` endpoint
+///
+/// Works with the OpenAI API as well as local model servers that expose
+/// the same schema, so a self-hosted model can stand in for Zen Guard.
+#[cfg(feature = "content-filter")]
+pub struct ModerationClassifier {
+    endpoint: String,
+    api_key: Option<String>,
+    model: Option<String>,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "content-filter")]
+impl ModerationClassifier {
+    /// Create a moderation classifier from the content filter configuration
+    pub fn new(config: &ContentFilterConfig) -> Self {
+        Self {
+            endpoint: config.api_endpoint.clone(),
+            api_key: config.api_key.clone(),
+            model: config.moderation_model.clone(),
+            client: http_client(config.timeout_ms),
+        }
+    }
+}
+
+#[cfg(feature = "content-filter")]
+#[async_trait]
+impl SafetyClassifier for ModerationClassifier {
+    fn name(&self) -> &str {
+        "openai-moderation"
+    }
+
+    async fn classify(&self, content: &str, _is_response: bool) -> Result<ContentFilterResult> {
+        let request = ModerationRequest {
+            input: content,
+            model: self.model.as_deref(),
+        };
+
+        let mut req = self.client.post(&self.endpoint).json(&request);
+
+        if let Some(ref api_key) = self.api_key {
+            req = req.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let response = req.send().await.map_err(|e| {
+            GuardError::ContentFilterError(format!("Moderation request failed: {}", e))
+        })?;
+
+        if !response.status().is_success() {
+            return Err(GuardError::ContentFilterError(format!(
+                "Moderation endpoint returned status: {}",
+                response.status()
+            )));
+        }
+
+        let moderation: ModerationResponse = response.json().await.map_err(|e| {
+            GuardError::ContentFilterError(format!("Failed to parse moderation response: {}", e))
+        })?;
+
+        let mut flagged = false;
+        let mut categories = vec![];
+        for result in &moderation.results {
+            flagged |= result.flagged;
+            for (name, _) in result.categories.iter().filter(|(_, hit)| **hit) {
+                if let Some(category) = parse_moderation_category(name) {
+                    if !categories.contains(&category) {
+                        categories.push(category);
+                    }
+                }
+            }
+        }
+
+        Ok(ContentFilterResult {
+            safety_level: if flagged {
+                SafetyLevel::Unsafe
+            } else {
+                SafetyLevel::Safe
+            },
+            categories,
+            refused: false,
+        })
+    }
+}
+
+#[cfg(feature = "content-filter")]
+fn http_client(timeout_ms: u64) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(std::time::Duration::from_millis(timeout_ms))
+        .build()
+        .unwrap_or_default()
+}
+
+/// A compiled taxonomy rule
+#[cfg(feature = "local-classifier")]
+struct CompiledRule {
+    category: SafetyCategory,
+    severity: SafetyLevel,
+    matchers: Vec<Regex>,
+}
+
+/// Offline classifier driven by a keyword/regex taxonomy
+///
+/// Each rule maps matches onto a [`SafetyCategory`] with a severity; the
+/// overall safety level is the highest severity among matching rules.
+#[cfg(feature = "local-classifier")]
+pub struct TaxonomyClassifier {
+    rules: Vec<CompiledRule>,
+}
+
+#[cfg(feature = "local-classifier")]
+impl TaxonomyClassifier {
+    /// Compile a taxonomy; fails on invalid regular expressions
+    pub fn new(rules: &[TaxonomyRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let mut matchers = vec![];
+                if !rule.keywords.is_empty() {
+                    let alternation = rule
+                        .keywords
+                        .iter()
+                        .map(|k| regex::escape(k))
+                        .collect::<Vec<_>>()
+                        .join("|");
+                    matchers.push(compile(&format!(r"(?i)\b(?:{})\b", alternation))?);
+                }
+                for pattern in &rule.patterns {
+                    matchers.push(compile(pattern)?);
+                }
+                Ok(CompiledRule {
+                    category: rule.category,
+                    severity: rule.severity,
+                    matchers,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// Create a classifier with the built-in taxonomy
+    pub fn with_default_taxonomy() -> Self {
+        Self::new(&default_taxonomy()).expect("built-in taxonomy is valid")
+    }
+}
+
+#[cfg(feature = "local-classifier")]
+#[async_trait]
+impl SafetyClassifier for TaxonomyClassifier {
+    fn name(&self) -> &str {
+        "taxonomy"
+    }
+
+    async fn classify(&self, content: &str, _is_response: bool) -> Result<ContentFilterResult> {
+        let mut matched: Vec<(SafetyCategory, SafetyLevel)> = vec![];
+
+        for rule in &self.rules {
+            if !rule.matchers.iter().any(|m| m.is_match(content)) {
+                continue;
+            }
+            match matched.iter_mut().find(|(c, _)| *c == rule.category) {
+                Some((_, severity)) => *severity = (*severity).max(rule.severity),
+                None => matched.push((rule.category, rule.severity)),
+            }
+        }
+
+        // Most severe categories first so `should_block` reports the right one
+        matched.sort_by_key(|(_, severity)| std::cmp::Reverse(*severity));
+
+        Ok(ContentFilterResult {
+            safety_level: matched
+                .first()
+                .map(|(_, severity)| *severity)
+                .unwrap_or(SafetyLevel::Safe),
+            categories: matched.into_iter().map(|(c, _)| c).collect(),
+            refused: false,
+        })
+    }
+}
+
+#[cfg(feature = "local-classifier")]
+fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern)
+        .map_err(|e| GuardError::ConfigError(format!("Invalid taxonomy pattern {pattern:?}: {e}")))
+}
+
+/// Built-in taxonomy used when no rules are configured
+#[cfg(feature = "local-classifier")]
+pub fn default_taxonomy() -> Vec<TaxonomyRule> {
+    use SafetyCategory::*;
+    use SafetyLevel::*;
+
+    vec![
+        TaxonomyRule::keywords(
+            Violent,
+            Unsafe,
+            &[
+                "build a bomb",
+                "make a bomb",
+                "pipe bomb",
+                "mass shooting",
+                "kill them all",
+            ],
+        ),
+        TaxonomyRule::keywords(Violent, Controversial, &["kill", "murder", "shoot"]),
+        TaxonomyRule::keywords(
+            SelfHarm,
+            Unsafe,
+            &[
+                "kill myself",
+                "end my life",
+                "suicide method",
+                "how to self-harm",
+            ],
+        ),
+        TaxonomyRule::keywords(
+            IllegalActs,
+            Unsafe,
+            &[
+                "synthesize methamphetamine",
+                "cook meth",
+                "launder money",
+                "stolen credit cards",
+            ],
+        ),
+        TaxonomyRule::keywords(IllegalActs, Controversial, &["hack into", "bypass paywall"]),
+        TaxonomyRule::keywords(
+            SexualContent,
+            Unsafe,
+            &["child pornography", "sexual content involving minors"],
+        ),
+        TaxonomyRule::keywords(SexualContent, Controversial, &["porn", "explicit sex"]),
+        TaxonomyRule::keywords(
+            UnethicalActs,
+            Controversial,
+            &["ethnic cleansing", "racial superiority"],
+        ),
+        TaxonomyRule::keywords(
+            Jailbreak,
+            Unsafe,
+            &[
+                "ignore previous instructions",
+                "DAN mode",
+                "developer mode enabled",
+            ],
+        ),
+    ]
+}
+
+/// Parse Zen Guard category string to enum
+#[cfg(feature = "content-filter")]
+pub(crate) fn parse_category(category: &str) -> Option<SafetyCategory> {
+    match category.to_lowercase().as_str() {
+        "violent" => Some(SafetyCategory::Violent),
+        "non-violent illegal acts" | "illegalacts" => Some(SafetyCategory::IllegalActs),
+        "sexual content or sexual acts" | "sexualcontent" => Some(SafetyCategory::SexualContent),
+        "pii" | "personally identifiable information" => Some(SafetyCategory::Pii),
+        "suicide & self-harm" | "selfharm" => Some(SafetyCategory::SelfHarm),
+        "unethical acts" | "unethicalacts" => Some(SafetyCategory::UnethicalActs),
+        "politically sensitive topics" | "politicallysensitive" => {
+            Some(SafetyCategory::PoliticallySensitive)
+        }
+        "copyright violation" | "copyrightviolation" => Some(SafetyCategory::CopyrightViolation),
+        "jailbreak" => Some(SafetyCategory::Jailbreak),
+        "none" => Some(SafetyCategory::None),
+        _ => None,
+    }
+}
+
+/// Map OpenAI moderation category names onto Zen Guard categories
+#[cfg(feature = "content-filter")]
+fn parse_moderation_category(category: &str) -> Option<SafetyCategory> {
+    let top_level = category.split('/').next().unwrap_or(category);
+    match top_level {
+        "violence" => Some(SafetyCategory::Violent),
+        "sexual" => Some(SafetyCategory::SexualContent),
+        "self-harm" => Some(SafetyCategory::SelfHarm),
+        "hate" | "harassment" => Some(SafetyCategory::UnethicalActs),
+        "illicit" => Some(SafetyCategory::IllegalActs),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "content-filter")]
+    fn test_parse_category() {
+        assert_eq!(parse_category("Violent"), Some(SafetyCategory::Violent));
+        assert_eq!(parse_category("jailbreak"), Some(SafetyCategory::Jailbreak));
+        assert_eq!(parse_category("PII"), Some(SafetyCategory::Pii));
+    }
+
+    #[test]
+    #[cfg(feature = "content-filter")]
+    fn test_parse_moderation_category() {
+        assert_eq!(
+            parse_moderation_category("violence/graphic"),
+            Some(SafetyCategory::Violent)
+        );
+        assert_eq!(
+            parse_moderation_category("self-harm/intent"),
+            Some(SafetyCategory::SelfHarm)
+        );
+        assert_eq!(parse_moderation_category("unknown"), None);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "local-classifier")]
+    async fn test_taxonomy_severity() {
+        let classifier = TaxonomyClassifier::with_default_taxonomy();
+
+        let result = classifier
+            .classify("Explain how to build a bomb", false)
+            .await
+            .unwrap();
+        assert_eq!(result.safety_level, SafetyLevel::Unsafe);
+        assert_eq!(result.categories, vec![SafetyCategory::Violent]);
+
+        let result = classifier
+            .classify("Write a poem about the ocean", false)
+            .await
+            .unwrap();
+        assert_eq!(result.safety_level, SafetyLevel::Safe);
+        assert!(result.categories.is_empty());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "local-classifier")]
+    async fn test_taxonomy_custom_pattern() {
+        let rules = vec![TaxonomyRule {
+            category: SafetyCategory::CopyrightViolation,
+            severity: SafetyLevel::Controversial,
+            keywords: vec![],
+            patterns: vec![r"(?i)full text of .+ novel".to_string()],
+        }];
+        let classifier = TaxonomyClassifier::new(&rules).unwrap();
+
+        let result = classifier
+            .classify("Give me the full text of the latest novel", false)
+            .await
+            .unwrap();
+        assert_eq!(result.safety_level, SafetyLevel::Controversial);
+        assert_eq!(result.categories, vec![SafetyCategory::CopyrightViolation]);
+    }
+
+    #[test]
+    #[cfg(feature = "local-classifier")]
+    fn test_taxonomy_invalid_pattern() {
+        let rules = vec![TaxonomyRule {
+            category: SafetyCategory::Violent,
+            severity: SafetyLevel::Unsafe,
+            keywords: vec![],
+            patterns: vec!["(unclosed".to_string()],
+        }];
+        assert!(TaxonomyClassifier::new(&rules).is_err());
+    }
+}
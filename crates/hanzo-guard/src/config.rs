@@ -1,9 +1,17 @@
 //! Configuration for Hanzo Guard
 
+use crate::error::SafetyCategory;
+#[cfg(any(feature = "policy", feature = "cli"))]
+use crate::error::{GuardError, Result};
+use crate::policy::PolicySet;
+use crate::types::SafetyLevel;
 use serde::{Deserialize, Serialize};
 
 /// Main configuration for Guard
+///
+/// Omitted sections and fields take their defaults when deserialized.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct GuardConfig {
     /// PII detection configuration
     pub pii: PiiConfig,
@@ -15,6 +23,9 @@ pub struct GuardConfig {
     pub rate_limit: RateLimitConfig,
     /// Audit configuration
     pub audit: AuditConfig,
+    /// Declarative policy (built-in behavior when unset)
+    #[serde(default)]
+    pub policy: Option<PolicySet>,
 }
 
 impl GuardConfig {
@@ -41,9 +52,23 @@ impl GuardConfig {
                 enabled: true,
                 ..Default::default()
             },
+            policy: None,
         }
     }
 
+    /// Parse a config from TOML
+    #[cfg(any(feature = "policy", feature = "cli"))]
+    pub fn from_toml(source: &str) -> Result<Self> {
+        toml::from_str(source)
+            .map_err(|e| GuardError::ConfigError(format!("Invalid config TOML: {}", e)))
+    }
+
+    /// Load a config from a TOML file
+    #[cfg(any(feature = "policy", feature = "cli"))]
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
     /// Create a minimal config (PII only)
     pub fn minimal() -> Self {
         Self {
@@ -67,12 +92,14 @@ impl GuardConfig {
                 enabled: false,
                 ..Default::default()
             },
+            policy: None,
         }
     }
 }
 
 /// PII detection configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PiiConfig {
     /// Enable PII detection
     pub enabled: bool,
@@ -109,6 +136,7 @@ impl Default for PiiConfig {
 
 /// Injection detection configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct InjectionConfig {
     /// Enable injection detection
     pub enabled: bool,
@@ -133,13 +161,23 @@ impl Default for InjectionConfig {
 
 /// Content filter configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ContentFilterConfig {
     /// Enable content filtering
     pub enabled: bool,
-    /// Zen Guard API endpoint
+    /// Classifier backend used to score content
+    #[serde(default)]
+    pub backend: ClassifierBackend,
+    /// Classifier API endpoint (Zen Guard or OpenAI-compatible moderation)
     pub api_endpoint: String,
-    /// API key for Zen Guard
+    /// API key for the classifier endpoint
     pub api_key: Option<String>,
+    /// Model name sent to OpenAI-compatible moderation endpoints
+    #[serde(default)]
+    pub moderation_model: Option<String>,
+    /// Rules for the offline taxonomy classifier (built-in taxonomy if empty)
+    #[serde(default)]
+    pub taxonomy: Vec<TaxonomyRule>,
     /// Block controversial content (not just unsafe)
     pub block_controversial: bool,
     /// Categories to block
@@ -152,8 +190,11 @@ impl Default for ContentFilterConfig {
     fn default() -> Self {
         Self {
             enabled: false, // Disabled by default as it requires API
+            backend: ClassifierBackend::ZenGuard,
             api_endpoint: "https://api.zenlm.ai/v1/guard".to_string(),
             api_key: None,
+            moderation_model: None,
+            taxonomy: vec![],
             block_controversial: false,
             blocked_categories: vec![
                 "Violent".to_string(),
@@ -167,8 +208,49 @@ impl Default for ContentFilterConfig {
     }
 }
 
+/// Content classifier backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClassifierBackend {
+    /// Hosted Zen Guard API
+    #[default]
+    ZenGuard,
+    /// Offline keyword/regex taxonomy (no network access)
+    Taxonomy,
+    /// Any OpenAI-compatible `/v1/moderations` endpoint
+    OpenAiModeration,
+}
+
+/// A taxonomy rule for the offline classifier
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaxonomyRule {
+    /// Category reported when the rule matches
+    pub category: SafetyCategory,
+    /// Severity assigned to matching content
+    pub severity: SafetyLevel,
+    /// Case-insensitive keywords (matched on word boundaries)
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Regular expressions matched against the content
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+impl TaxonomyRule {
+    /// Create a rule from a list of keywords
+    pub fn keywords(category: SafetyCategory, severity: SafetyLevel, keywords: &[&str]) -> Self {
+        Self {
+            category,
+            severity,
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            patterns: vec![],
+        }
+    }
+}
+
 /// Rate limiting configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RateLimitConfig {
     /// Enable rate limiting
     pub enabled: bool,
@@ -193,6 +275,7 @@ impl Default for RateLimitConfig {
 
 /// Audit logging configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AuditConfig {
     /// Enable audit logging
     pub enabled: bool,
@@ -200,8 +283,42 @@ pub struct AuditConfig {
     pub log_content: bool,
     /// Log to stdout
     pub log_stdout: bool,
-    /// Log file path
+    /// Log file path (hash-chained JSONL)
     pub log_file: Option<String>,
+    /// Rotate the log file once it reaches this many bytes
+    #[serde(default)]
+    pub rotate_max_bytes: Option<u64>,
+    /// Rotate the log file after this many seconds
+    #[serde(default)]
+    pub rotate_interval_secs: Option<u64>,
+    /// Webhook receiving batches of audit records (`audit-webhook` feature)
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// Bearer token for the webhook
+    #[serde(default)]
+    pub webhook_token: Option<String>,
+    /// Maximum records per batch written to sinks
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Maximum time a record waits before its batch is written
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// Maximum entries waiting for the writer; further entries are dropped
+    /// and counted until it catches up
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+}
+
+fn default_batch_size() -> usize {
+    64
+}
+
+fn default_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_queue_capacity() -> usize {
+    10_000
 }
 
 impl Default for AuditConfig {
@@ -211,6 +328,44 @@ impl Default for AuditConfig {
             log_content: false, // Privacy by default
             log_stdout: false,
             log_file: None,
+            rotate_max_bytes: None,
+            rotate_interval_secs: None,
+            webhook_url: None,
+            webhook_token: None,
+            batch_size: default_batch_size(),
+            flush_interval_ms: default_flush_interval_ms(),
+            queue_capacity: default_queue_capacity(),
         }
     }
 }
+
+#[cfg(test)]
+#[cfg(any(feature = "policy", feature = "cli"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_toml() {
+        let config = GuardConfig::from_toml(
+            r#"
+[injection]
+sensitivity = 0.9
+
+[rate_limit]
+enabled = false
+
+[[policy.rules]]
+name = "warn-emails"
+action = "warn"
+when = { pii_types = ["Email"] }
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.injection.sensitivity, 0.9);
+        assert!(config.injection.block_on_detection);
+        assert!(!config.rate_limit.enabled);
+        assert!(config.pii.detect_email);
+        assert_eq!(config.policy.unwrap().rules[0].name, "warn-emails");
+    }
+}
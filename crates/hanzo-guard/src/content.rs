@@ -1,38 +1,21 @@
-//! Content filtering via Zen Guard API
+//! Content filtering via pluggable safety classifiers
 
-use crate::config::ContentFilterConfig;
-use crate::error::{Result, SafetyCategory};
+use crate::classifier::SafetyClassifier;
+#[cfg(feature = "local-classifier")]
+use crate::classifier::{default_taxonomy, TaxonomyClassifier};
+#[cfg(feature = "content-filter")]
+use crate::classifier::{ModerationClassifier, ZenGuardClassifier};
+#[allow(unused_imports)]
+use crate::config::{ClassifierBackend, ContentFilterConfig};
+use crate::error::{GuardError, Result, SafetyCategory};
 use crate::types::SafetyLevel;
-use serde::{Deserialize, Serialize};
+use async_trait::async_trait;
+use std::sync::Arc;
 
-/// Content filter using Zen Guard models
+/// Content filter using a [`SafetyClassifier`] backend
 pub struct ContentFilter {
     config: ContentFilterConfig,
-    #[cfg(feature = "content-filter")]
-    client: reqwest::Client,
-}
-
-/// Request to Zen Guard API
-#[allow(dead_code)]
-#[derive(Debug, Serialize)]
-struct GuardRequest {
-    messages: Vec<GuardMessage>,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Serialize)]
-struct GuardMessage {
-    role: String,
-    content: String,
-}
-
-/// Response from Zen Guard API
-#[allow(dead_code)]
-#[derive(Debug, Deserialize)]
-struct GuardResponse {
-    safety: String,
-    categories: Vec<String>,
-    refusal: Option<String>,
+    classifier: Option<Arc<dyn SafetyClassifier>>,
 }
 
 /// Content filter result
@@ -47,105 +30,43 @@ pub struct ContentFilterResult {
 }
 
 impl ContentFilter {
-    /// Create a new content filter
+    /// Create a new content filter with the backend selected in the config
+    ///
+    /// Backends whose feature is not compiled in fail every check with a
+    /// configuration error.
     pub fn new(config: ContentFilterConfig) -> Self {
+        let classifier = build_classifier(&config);
+        Self { config, classifier }
+    }
+
+    /// Create a content filter with a custom classifier
+    pub fn with_classifier(
+        config: ContentFilterConfig,
+        classifier: Arc<dyn SafetyClassifier>,
+    ) -> Self {
         Self {
             config,
-            #[cfg(feature = "content-filter")]
-            client: reqwest::Client::builder()
-                .timeout(std::time::Duration::from_millis(5000))
-                .build()
-                .unwrap_or_default(),
+            classifier: Some(classifier),
         }
     }
 
+    /// Name of the active classifier backend, if any
+    pub fn classifier_name(&self) -> Option<&str> {
+        self.classifier.as_deref().map(|c| c.name())
+    }
+
     /// Check content safety
-    #[cfg(feature = "content-filter")]
     pub async fn check(&self, content: &str, is_response: bool) -> Result<ContentFilterResult> {
-        if !self.config.enabled {
-            return Ok(ContentFilterResult {
+        match self.classifier {
+            Some(ref classifier) if self.config.enabled => {
+                classifier.classify(content, is_response).await
+            }
+            _ => Ok(ContentFilterResult {
                 safety_level: SafetyLevel::Safe,
                 categories: vec![],
                 refused: false,
-            });
+            }),
         }
-
-        let messages = if is_response {
-            vec![
-                GuardMessage {
-                    role: "user".to_string(),
-                    content: "[Checking response]".to_string(),
-                },
-                GuardMessage {
-                    role: "assistant".to_string(),
-                    content: content.to_string(),
-                },
-            ]
-        } else {
-            vec![GuardMessage {
-                role: "user".to_string(),
-                content: content.to_string(),
-            }]
-        };
-
-        let request = GuardRequest { messages };
-
-        let mut req = self
-            .client
-            .post(&self.config.api_endpoint)
-            .json(&request)
-            .timeout(std::time::Duration::from_millis(self.config.timeout_ms));
-
-        if let Some(ref api_key) = self.config.api_key {
-            req = req.header("Authorization", format!("Bearer {}", api_key));
-        }
-
-        let response = req
-            .send()
-            .await
-            .map_err(|e| GuardError::ContentFilterError(format!("API request failed: {}", e)))?;
-
-        if !response.status().is_success() {
-            return Err(GuardError::ContentFilterError(format!(
-                "API returned status: {}",
-                response.status()
-            )));
-        }
-
-        let guard_response: GuardResponse = response.json().await.map_err(|e| {
-            GuardError::ContentFilterError(format!("Failed to parse response: {}", e))
-        })?;
-
-        let safety_level = match guard_response.safety.to_lowercase().as_str() {
-            "safe" => SafetyLevel::Safe,
-            "controversial" => SafetyLevel::Controversial,
-            "unsafe" => SafetyLevel::Unsafe,
-            _ => SafetyLevel::Safe,
-        };
-
-        let categories = guard_response
-            .categories
-            .iter()
-            .filter_map(|c| parse_category(c))
-            .collect();
-
-        let refused = guard_response.refusal.as_deref() == Some("Yes");
-
-        Ok(ContentFilterResult {
-            safety_level,
-            categories,
-            refused,
-        })
-    }
-
-    /// Check content safety (stub when feature disabled)
-    #[cfg(not(feature = "content-filter"))]
-    pub async fn check(&self, _content: &str, _is_response: bool) -> Result<ContentFilterResult> {
-        Ok(ContentFilterResult {
-            safety_level: SafetyLevel::Safe,
-            categories: vec![],
-            refused: false,
-        })
     }
 
     /// Check if content should be blocked based on result
@@ -181,23 +102,63 @@ impl ContentFilter {
     }
 }
 
-/// Parse category string to enum
-#[allow(dead_code)]
-fn parse_category(category: &str) -> Option<SafetyCategory> {
-    match category.to_lowercase().as_str() {
-        "violent" => Some(SafetyCategory::Violent),
-        "non-violent illegal acts" | "illegalacts" => Some(SafetyCategory::IllegalActs),
-        "sexual content or sexual acts" | "sexualcontent" => Some(SafetyCategory::SexualContent),
-        "pii" | "personally identifiable information" => Some(SafetyCategory::Pii),
-        "suicide & self-harm" | "selfharm" => Some(SafetyCategory::SelfHarm),
-        "unethical acts" | "unethicalacts" => Some(SafetyCategory::UnethicalActs),
-        "politically sensitive topics" | "politicallysensitive" => {
-            Some(SafetyCategory::PoliticallySensitive)
+/// Build the classifier for the configured backend
+fn build_classifier(config: &ContentFilterConfig) -> Option<Arc<dyn SafetyClassifier>> {
+    match config.backend {
+        #[cfg(feature = "content-filter")]
+        ClassifierBackend::ZenGuard => Some(Arc::new(ZenGuardClassifier::new(config))),
+        #[cfg(feature = "content-filter")]
+        ClassifierBackend::OpenAiModeration => Some(Arc::new(ModerationClassifier::new(config))),
+        #[cfg(feature = "local-classifier")]
+        ClassifierBackend::Taxonomy => {
+            let rules = if config.taxonomy.is_empty() {
+                default_taxonomy()
+            } else {
+                config.taxonomy.clone()
+            };
+            Some(match TaxonomyClassifier::new(&rules) {
+                Ok(classifier) => Arc::new(classifier),
+                Err(e) => Arc::new(MisconfiguredClassifier {
+                    error: e.to_string(),
+                }),
+            })
         }
-        "copyright violation" | "copyrightviolation" => Some(SafetyCategory::CopyrightViolation),
-        "jailbreak" => Some(SafetyCategory::Jailbreak),
-        "none" => Some(SafetyCategory::None),
-        _ => None,
+        #[allow(unreachable_patterns)]
+        backend => Some(Arc::new(MisconfiguredClassifier {
+            error: format!(
+                "classifier backend {:?} requires the `{}` feature",
+                backend,
+                required_feature(backend)
+            ),
+        })),
+    }
+}
+
+/// Cargo feature that compiles in a backend
+#[allow(dead_code)]
+fn required_feature(backend: ClassifierBackend) -> &'static str {
+    match backend {
+        ClassifierBackend::ZenGuard | ClassifierBackend::OpenAiModeration => "content-filter",
+        ClassifierBackend::Taxonomy => "local-classifier",
+    }
+}
+
+/// Placeholder for a backend that failed to build or is not compiled in;
+/// every check reports the configuration error instead of silently passing
+/// content through.
+#[allow(dead_code)]
+struct MisconfiguredClassifier {
+    error: String,
+}
+
+#[async_trait]
+impl SafetyClassifier for MisconfiguredClassifier {
+    fn name(&self) -> &str {
+        "misconfigured"
+    }
+
+    async fn classify(&self, _content: &str, _is_response: bool) -> Result<ContentFilterResult> {
+        Err(GuardError::ConfigError(self.error.clone()))
     }
 }
 
@@ -205,13 +166,6 @@ fn parse_category(category: &str) -> Option<SafetyCategory> {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_parse_category() {
-        assert_eq!(parse_category("Violent"), Some(SafetyCategory::Violent));
-        assert_eq!(parse_category("jailbreak"), Some(SafetyCategory::Jailbreak));
-        assert_eq!(parse_category("PII"), Some(SafetyCategory::Pii));
-    }
-
     #[test]
     fn test_should_block_unsafe() {
         let config = ContentFilterConfig::default();
@@ -239,4 +193,72 @@ mod tests {
 
         assert!(filter.should_block(&result).is_none());
     }
+
+    struct AlwaysUnsafe;
+
+    #[async_trait]
+    impl SafetyClassifier for AlwaysUnsafe {
+        fn name(&self) -> &str {
+            "always-unsafe"
+        }
+
+        async fn classify(
+            &self,
+            _content: &str,
+            _is_response: bool,
+        ) -> Result<ContentFilterResult> {
+            Ok(ContentFilterResult {
+                safety_level: SafetyLevel::Unsafe,
+                categories: vec![SafetyCategory::IllegalActs],
+                refused: false,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_classifier() {
+        let config = ContentFilterConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let filter = ContentFilter::with_classifier(config, Arc::new(AlwaysUnsafe));
+
+        assert_eq!(filter.classifier_name(), Some("always-unsafe"));
+        let result = filter.check("anything", false).await.unwrap();
+        let (_, category) = filter.should_block(&result).unwrap();
+        assert_eq!(category, SafetyCategory::IllegalActs);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "local-classifier")]
+    async fn test_misconfigured_taxonomy_errors() {
+        let config = ContentFilterConfig {
+            enabled: true,
+            backend: ClassifierBackend::Taxonomy,
+            taxonomy: vec![crate::config::TaxonomyRule {
+                category: SafetyCategory::Violent,
+                severity: SafetyLevel::Unsafe,
+                keywords: vec![],
+                patterns: vec!["[".to_string()],
+            }],
+            ..Default::default()
+        };
+        let filter = ContentFilter::new(config);
+
+        assert!(filter.check("hello", false).await.is_err());
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "content-filter"))]
+    async fn test_backend_without_feature_errors() {
+        let config = ContentFilterConfig {
+            enabled: true,
+            backend: ClassifierBackend::ZenGuard,
+            ..Default::default()
+        };
+        let filter = ContentFilter::new(config);
+
+        assert_eq!(filter.classifier_name(), Some("misconfigured"));
+        assert!(filter.check("hello", false).await.is_err());
+    }
 }
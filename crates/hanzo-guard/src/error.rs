@@ -43,8 +43,8 @@ pub enum GuardError {
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
-    /// HTTP error (when content filter is enabled)
-    #[cfg(feature = "content-filter")]
+    /// HTTP error (content filter or audit webhook)
+    #[cfg(any(feature = "content-filter", feature = "audit-webhook"))]
     #[error("HTTP error: {0}")]
     HttpError(#[from] reqwest::Error),
 }
@@ -7,10 +7,14 @@
 //!
 //! - **PII Leakage**: Detects and redacts personal identifiable information
 //! - **Prompt Injection**: Detects jailbreak and manipulation attempts
-//! - **Unsafe Content**: Filters harmful content via Zen Guard models
+//! - **Unsafe Content**: Filters harmful content via Zen Guard models, any
+//!   OpenAI-compatible moderation endpoint, or an offline taxonomy
 //! - **Rate Abuse**: Prevents excessive API usage
 //! - **Audit Violations**: Logs all requests for compliance
 //!
+//! What happens to each finding can be tuned per tenant, direction, or
+//! model with a declarative [`PolicySet`](policy::PolicySet).
+//!
 //! ## Quick Start
 //!
 //! ```rust
@@ -73,24 +77,34 @@
 //! ```
 
 pub mod audit;
+pub mod classifier;
 pub mod config;
 pub mod content;
 pub mod error;
 pub mod guard;
 pub mod injection;
+#[cfg(feature = "middleware")]
+pub mod middleware;
 pub mod pii;
+pub mod policy;
 pub mod rate_limit;
+#[cfg(feature = "cli")]
+pub mod scan;
 pub mod types;
 
+pub use classifier::SafetyClassifier;
 pub use config::GuardConfig;
 pub use error::{GuardError, Result};
 pub use guard::Guard;
+pub use policy::{GuardDecision, PolicyAction, PolicySet};
 pub use types::*;
 
 /// Prelude for convenient imports
 pub mod prelude {
+    pub use crate::classifier::SafetyClassifier;
     pub use crate::config::GuardConfig;
     pub use crate::error::{GuardError, Result};
     pub use crate::guard::Guard;
+    pub use crate::policy::{GuardDecision, PolicyAction, PolicySet};
     pub use crate::types::*;
 }
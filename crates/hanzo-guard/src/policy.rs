@@ -0,0 +1,417 @@
+//! Declarative policy engine
+//!
+//! Policies map detector findings onto actions, optionally scoped to a
+//! tenant, direction, or model. Rules are evaluated top to bottom and the
+//! first match wins; findings no rule matches fall back to the built-in
+//! behavior (redact PII, block injections and unsafe content).
+//!
+//! ```yaml
+//! rules:
+//!   - name: acme-no-emails
+//!     tenants: [acme]
+//!     directions: [input]
+//!     when:
+//!       detector: pii
+//!       pii_types: [Email]
+//!     action: block
+//!   - name: internal-tokenize
+//!     models: ["internal-*"]
+//!     when:
+//!       detector: pii
+//!     action: tokenize
+//! ```
+
+use crate::error::SafetyCategory;
+#[cfg(feature = "policy")]
+use crate::error::{GuardError, Result};
+use crate::types::{Direction, GuardContext, RedactionType, SanitizeResult};
+use serde::{Deserialize, Serialize};
+
+/// A set of policy rules
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolicySet {
+    /// Rules, evaluated in order (first match wins)
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+/// A single policy rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// Rule name, reported when the rule matches
+    pub name: String,
+    /// Tenants the rule applies to (empty = all)
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    /// Directions the rule applies to (empty = both)
+    #[serde(default)]
+    pub directions: Vec<Direction>,
+    /// Models the rule applies to; a trailing `*` matches a prefix (empty = all)
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Condition a finding must satisfy
+    #[serde(default)]
+    pub when: PolicyCondition,
+    /// Action taken on matching findings
+    pub action: PolicyAction,
+}
+
+/// Conditions on a detector finding (all set fields must hold)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolicyCondition {
+    /// Detector that produced the finding
+    #[serde(default)]
+    pub detector: Option<Detector>,
+    /// Safety categories to match (empty = any)
+    #[serde(default)]
+    pub categories: Vec<SafetyCategory>,
+    /// Minimum detector confidence (0.0-1.0)
+    #[serde(default)]
+    pub min_confidence: Option<f32>,
+    /// PII types to match (empty = any)
+    #[serde(default)]
+    pub pii_types: Vec<RedactionType>,
+}
+
+/// Detectors that produce findings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Detector {
+    /// PII detector
+    Pii,
+    /// Prompt injection detector
+    Injection,
+    /// Content-safety classifier
+    ContentFilter,
+}
+
+impl std::fmt::Display for Detector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Detector::Pii => write!(f, "pii"),
+            Detector::Injection => write!(f, "injection"),
+            Detector::ContentFilter => write!(f, "content-filter"),
+        }
+    }
+}
+
+/// Policy actions, ordered from least to most restrictive
+///
+/// `Redact` and `Tokenize` only transform PII findings; on other detectors
+/// they behave like `Warn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PolicyAction {
+    /// Let the finding through unchanged
+    Allow,
+    /// Let the finding through and record a warning
+    Warn,
+    /// Replace PII with a stable per-value token
+    Tokenize,
+    /// Replace PII with the redaction placeholder
+    Redact,
+    /// Hold the content for human review (not forwarded)
+    RequireReview,
+    /// Block the content
+    Block,
+}
+
+impl std::fmt::Display for PolicyAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyAction::Allow => write!(f, "allow"),
+            PolicyAction::Warn => write!(f, "warn"),
+            PolicyAction::Tokenize => write!(f, "tokenize"),
+            PolicyAction::Redact => write!(f, "redact"),
+            PolicyAction::RequireReview => write!(f, "require-review"),
+            PolicyAction::Block => write!(f, "block"),
+        }
+    }
+}
+
+/// A finding produced by a detector
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    /// Detector that produced the finding
+    pub detector: Detector,
+    /// Safety category of the finding
+    pub category: SafetyCategory,
+    /// Detector confidence (0.0-1.0)
+    pub confidence: f32,
+    /// PII type, for PII findings
+    pub pii_type: Option<RedactionType>,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.pii_type {
+            Some(pii_type) => write!(f, "{}:{}", self.detector, pii_type),
+            None => write!(
+                f,
+                "{}:{} ({:.2})",
+                self.detector, self.category, self.confidence
+            ),
+        }
+    }
+}
+
+/// Why a finding received its action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyMatch {
+    /// The finding
+    pub finding: Finding,
+    /// Action applied
+    pub action: PolicyAction,
+    /// Matching rule, or `None` for the built-in default
+    pub rule: Option<String>,
+}
+
+impl std::fmt::Display for PolicyMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.rule {
+            Some(ref rule) => write!(
+                f,
+                "rule \"{}\" matched {} -> {}",
+                rule, self.finding, self.action
+            ),
+            None => write!(f, "default for {} -> {}", self.finding, self.action),
+        }
+    }
+}
+
+/// Outcome of [`Guard::evaluate`](crate::Guard::evaluate)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardDecision {
+    /// Sanitized result (require-review yields `Blocked`)
+    pub result: SanitizeResult,
+    /// Most restrictive action applied
+    pub action: PolicyAction,
+    /// Per-finding explanation
+    pub matches: Vec<PolicyMatch>,
+}
+
+impl GuardDecision {
+    /// Human-readable explanation, one line per finding
+    pub fn explain(&self) -> Vec<String> {
+        self.matches.iter().map(|m| m.to_string()).collect()
+    }
+
+    /// Whether any finding was flagged with `warn`
+    pub fn has_warnings(&self) -> bool {
+        self.matches.iter().any(|m| m.action == PolicyAction::Warn)
+    }
+
+    /// Whether the content needs human review
+    pub fn requires_review(&self) -> bool {
+        self.action == PolicyAction::RequireReview
+    }
+}
+
+impl PolicySet {
+    /// Parse a policy set from YAML
+    #[cfg(feature = "policy")]
+    pub fn from_yaml(source: &str) -> Result<Self> {
+        serde_yaml::from_str(source)
+            .map_err(|e| GuardError::ConfigError(format!("Invalid policy YAML: {}", e)))
+    }
+
+    /// Parse a policy set from TOML
+    #[cfg(feature = "policy")]
+    pub fn from_toml(source: &str) -> Result<Self> {
+        toml::from_str(source)
+            .map_err(|e| GuardError::ConfigError(format!("Invalid policy TOML: {}", e)))
+    }
+
+    /// Load a policy set from a `.yaml`, `.yml`, or `.toml` file
+    #[cfg(feature = "policy")]
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => Self::from_yaml(&source),
+            Some("toml") => Self::from_toml(&source),
+            _ => Err(GuardError::ConfigError(format!(
+                "Unsupported policy file extension: {}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Find the first rule matching a finding in the given context
+    pub fn evaluate(
+        &self,
+        finding: &Finding,
+        direction: Direction,
+        context: &GuardContext,
+    ) -> Option<&PolicyRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.applies_to(direction, context) && rule.when.matches(finding))
+    }
+}
+
+impl PolicyRule {
+    /// Whether the rule's scope covers this request
+    pub fn applies_to(&self, direction: Direction, context: &GuardContext) -> bool {
+        let tenant_ok = self.tenants.is_empty()
+            || context
+                .tenant_id
+                .as_ref()
+                .is_some_and(|t| self.tenants.contains(t));
+        let direction_ok = self.directions.is_empty() || self.directions.contains(&direction);
+        let model_ok = self.models.is_empty()
+            || context
+                .model
+                .as_deref()
+                .is_some_and(|m| self.models.iter().any(|p| model_matches(p, m)));
+
+        tenant_ok && direction_ok && model_ok
+    }
+}
+
+impl PolicyCondition {
+    /// Whether a finding satisfies this condition
+    pub fn matches(&self, finding: &Finding) -> bool {
+        self.detector.is_none_or(|d| d == finding.detector)
+            && (self.categories.is_empty() || self.categories.contains(&finding.category))
+            && self.min_confidence.is_none_or(|c| finding.confidence >= c)
+            && (self.pii_types.is_empty()
+                || finding
+                    .pii_type
+                    .is_some_and(|t| self.pii_types.contains(&t)))
+    }
+}
+
+/// Match a model name against a pattern with an optional trailing `*`
+fn model_matches(pattern: &str, model: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.starts_with(prefix),
+        None => pattern == model,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pii_finding(pii_type: RedactionType) -> Finding {
+        Finding {
+            detector: Detector::Pii,
+            category: SafetyCategory::Pii,
+            confidence: 1.0,
+            pii_type: Some(pii_type),
+        }
+    }
+
+    fn rule(name: &str, when: PolicyCondition, action: PolicyAction) -> PolicyRule {
+        PolicyRule {
+            name: name.to_string(),
+            tenants: vec![],
+            directions: vec![],
+            models: vec![],
+            when,
+            action,
+        }
+    }
+
+    #[test]
+    fn test_first_match_wins() {
+        let policy = PolicySet {
+            rules: vec![
+                rule(
+                    "emails",
+                    PolicyCondition {
+                        pii_types: vec![RedactionType::Email],
+                        ..Default::default()
+                    },
+                    PolicyAction::Block,
+                ),
+                rule(
+                    "all-pii",
+                    PolicyCondition {
+                        detector: Some(Detector::Pii),
+                        ..Default::default()
+                    },
+                    PolicyAction::Tokenize,
+                ),
+            ],
+        };
+        let ctx = GuardContext::new();
+
+        let email = policy.evaluate(&pii_finding(RedactionType::Email), Direction::Input, &ctx);
+        assert_eq!(email.map(|r| r.name.as_str()), Some("emails"));
+
+        let phone = policy.evaluate(&pii_finding(RedactionType::Phone), Direction::Input, &ctx);
+        assert_eq!(phone.map(|r| r.name.as_str()), Some("all-pii"));
+    }
+
+    #[test]
+    fn test_scope() {
+        let mut scoped = rule("acme", PolicyCondition::default(), PolicyAction::Block);
+        scoped.tenants = vec!["acme".to_string()];
+        scoped.directions = vec![Direction::Output];
+        scoped.models = vec!["gpt-4*".to_string()];
+
+        let ctx = GuardContext::new()
+            .with_tenant_id("acme")
+            .with_model("gpt-4o");
+        assert!(scoped.applies_to(Direction::Output, &ctx));
+        assert!(!scoped.applies_to(Direction::Input, &ctx));
+        assert!(!scoped.applies_to(Direction::Output, &ctx.clone().with_tenant_id("other")));
+        assert!(!scoped.applies_to(Direction::Output, &ctx.with_model("claude")));
+        assert!(!scoped.applies_to(Direction::Output, &GuardContext::new()));
+    }
+
+    #[test]
+    fn test_min_confidence() {
+        let condition = PolicyCondition {
+            detector: Some(Detector::Injection),
+            min_confidence: Some(0.5),
+            ..Default::default()
+        };
+        let mut finding = Finding {
+            detector: Detector::Injection,
+            category: SafetyCategory::Jailbreak,
+            confidence: 0.4,
+            pii_type: None,
+        };
+        assert!(!condition.matches(&finding));
+        finding.confidence = 0.6;
+        assert!(condition.matches(&finding));
+    }
+
+    #[test]
+    #[cfg(feature = "policy")]
+    fn test_from_yaml_and_toml() {
+        let yaml = r#"
+rules:
+  - name: acme-no-emails
+    tenants: [acme]
+    directions: [input]
+    when:
+      detector: pii
+      pii_types: [Email]
+    action: block
+  - name: review-violence
+    when:
+      categories: [Violent]
+      min_confidence: 0.5
+    action: require-review
+"#;
+        let policy = PolicySet::from_yaml(yaml).unwrap();
+        assert_eq!(policy.rules.len(), 2);
+        assert_eq!(policy.rules[0].directions, vec![Direction::Input]);
+        assert_eq!(policy.rules[1].action, PolicyAction::RequireReview);
+
+        let toml = r#"
+[[rules]]
+name = "tokenize-internal"
+models = ["internal-*"]
+action = "tokenize"
+when = { detector = "pii" }
+"#;
+        let policy = PolicySet::from_toml(toml).unwrap();
+        assert_eq!(policy.rules[0].action, PolicyAction::Tokenize);
+        assert_eq!(policy.rules[0].when.detector, Some(Detector::Pii));
+    }
+}
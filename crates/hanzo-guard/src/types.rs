@@ -100,8 +100,8 @@ impl std::fmt::Display for RedactionType {
     }
 }
 
-/// Safety level classification
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Safety level classification (ordered from least to most severe)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum SafetyLevel {
     /// Content is safe
     Safe,
@@ -120,6 +120,12 @@ pub struct GuardContext {
     pub user_id: Option<String>,
     /// Session identifier (optional)
     pub session_id: Option<String>,
+    /// Tenant identifier, used to scope policies (optional)
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    /// Target model, used to scope policies (optional)
+    #[serde(default)]
+    pub model: Option<String>,
     /// Timestamp
     pub timestamp: DateTime<Utc>,
     /// Source IP (for rate limiting)
@@ -134,6 +140,8 @@ impl Default for GuardContext {
             request_id: Uuid::new_v4(),
             user_id: None,
             session_id: None,
+            tenant_id: None,
+            model: None,
             timestamp: Utc::now(),
             source_ip: None,
             metadata: serde_json::Value::Null,
@@ -159,6 +167,18 @@ impl GuardContext {
         self
     }
 
+    /// Set the tenant ID
+    pub fn with_tenant_id(mut self, tenant_id: impl Into<String>) -> Self {
+        self.tenant_id = Some(tenant_id.into());
+        self
+    }
+
+    /// Set the target model
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
     /// Set the source IP
     pub fn with_source_ip(mut self, ip: impl Into<String>) -> Self {
         self.source_ip = Some(ip.into());
@@ -191,8 +211,10 @@ pub struct AuditEntry {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Direction {
     /// Input to LLM
+    #[serde(alias = "input")]
     Input,
     /// Output from LLM
+    #[serde(alias = "output")]
     Output,
 }
 
@@ -0,0 +1,941 @@
+//! OAuth 2.1 authorization for remote MCP servers
+//!
+//! Follows the MCP authorization spec. The server's protected resource
+//! metadata (RFC 9728) names its authorization server, whose metadata
+//! (RFC 8414) gives the endpoints. Without a configured client id the client
+//! registers itself (RFC 7591). It then runs the authorization code flow with
+//! PKCE and receives the code on a loopback redirect. Tokens are kept in a
+//! [`TokenStore`] and refreshed once they expire or the server rejects them.
+
+use crate::error::McpError;
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hanzo_mcp_core::OAuthConfig;
+use rand::RngCore;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+type Result<T> = std::result::Result<T, McpError>;
+
+/// How long to wait for the user to finish authorizing in the browser
+const AUTHORIZATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Tokens this close to expiring are refreshed first
+const EXPIRY_MARGIN_SECS: u64 = 30;
+
+/// Largest loopback request read
+const CALLBACK_REQUEST_LIMIT: usize = 8 * 1024;
+
+/// How long a loopback connection may take to send its request
+const CALLBACK_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Opens an authorization URL for the user, usually in a browser
+pub type OpenUrl = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Client registration and tokens for one server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    pub access_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// Unix time the access token expires at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl Credentials {
+    fn expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|at| unix_now() + EXPIRY_MARGIN_SECS >= at)
+    }
+}
+
+/// Where credentials are kept, keyed by server URL
+#[async_trait]
+pub trait TokenStore: Send + Sync {
+    async fn load(&self, server_url: &str) -> Result<Option<Credentials>>;
+    async fn save(&self, server_url: &str, credentials: &Credentials) -> Result<()>;
+    async fn clear(&self, server_url: &str) -> Result<()>;
+}
+
+/// Keeps credentials for the life of the process
+#[derive(Default)]
+pub struct MemoryTokenStore {
+    credentials: Mutex<HashMap<String, Credentials>>,
+}
+
+#[async_trait]
+impl TokenStore for MemoryTokenStore {
+    async fn load(&self, server_url: &str) -> Result<Option<Credentials>> {
+        Ok(self.credentials.lock().unwrap().get(server_url).cloned())
+    }
+
+    async fn save(&self, server_url: &str, credentials: &Credentials) -> Result<()> {
+        self.credentials
+            .lock()
+            .unwrap()
+            .insert(server_url.to_string(), credentials.clone());
+        Ok(())
+    }
+
+    async fn clear(&self, server_url: &str) -> Result<()> {
+        self.credentials.lock().unwrap().remove(server_url);
+        Ok(())
+    }
+}
+
+/// Keeps credentials in a JSON file readable only by the user
+pub struct FileTokenStore {
+    path: PathBuf,
+    /// Serializes read-modify-write cycles
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl FileTokenStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// `~/.hanzo/mcp/tokens.json`
+    pub fn default_path() -> Option<PathBuf> {
+        home::home_dir().map(|home| home.join(".hanzo").join("mcp").join("tokens.json"))
+    }
+
+    async fn read(&self) -> Result<HashMap<String, Credentials>> {
+        match tokio::fs::read(&self.path).await {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| {
+                McpError::new(format!(
+                    "Invalid token store {}: {}",
+                    self.path.display(),
+                    e
+                ))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(store_error(&self.path, e)),
+        }
+    }
+
+    async fn write(&self, credentials: &HashMap<String, Credentials>) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|e| store_error(&self.path, e))?;
+        }
+        let data = serde_json::to_vec_pretty(credentials)
+            .map_err(|e| McpError::new(format!("Failed to encode tokens: {}", e)))?;
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options
+            .open(&self.path)
+            .await
+            .map_err(|e| store_error(&self.path, e))?;
+        file.write_all(&data)
+            .await
+            .map_err(|e| store_error(&self.path, e))
+    }
+}
+
+fn store_error(path: &std::path::Path, error: std::io::Error) -> McpError {
+    McpError::new(format!("Token store {}: {}", path.display(), error))
+}
+
+#[async_trait]
+impl TokenStore for FileTokenStore {
+    async fn load(&self, server_url: &str) -> Result<Option<Credentials>> {
+        let _guard = self.lock.lock().await;
+        Ok(self.read().await?.remove(server_url))
+    }
+
+    async fn save(&self, server_url: &str, credentials: &Credentials) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut all = self.read().await?;
+        all.insert(server_url.to_string(), credentials.clone());
+        self.write(&all).await
+    }
+
+    async fn clear(&self, server_url: &str) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut all = self.read().await?;
+        if all.remove(server_url).is_some() {
+            self.write(&all).await?;
+        }
+        Ok(())
+    }
+}
+
+/// RFC 9728 protected resource metadata
+#[derive(Deserialize)]
+struct ResourceMetadata {
+    resource: Option<String>,
+    #[serde(default)]
+    authorization_servers: Vec<String>,
+    #[serde(default)]
+    scopes_supported: Vec<String>,
+}
+
+/// RFC 8414 authorization server metadata
+#[derive(Deserialize)]
+struct ServerMetadata {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    registration_endpoint: Option<String>,
+    code_challenge_methods_supported: Option<Vec<String>>,
+    #[serde(default)]
+    scopes_supported: Vec<String>,
+}
+
+/// Where and how to authorize for a server
+struct Endpoints {
+    server: ServerMetadata,
+    /// Canonical server URI, sent as the `resource` parameter
+    resource: String,
+    scopes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Registration {
+    client_id: String,
+    client_secret: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+impl TokenResponse {
+    /// Credentials holding these tokens; a previous refresh token is kept
+    /// if no new one came
+    fn into_credentials(
+        self,
+        client_id: String,
+        client_secret: Option<String>,
+        refresh_token: Option<String>,
+    ) -> Credentials {
+        Credentials {
+            client_id,
+            client_secret,
+            access_token: self.access_token,
+            refresh_token: self.refresh_token.or(refresh_token),
+            expires_at: self.expires_in.map(|secs| unix_now() + secs),
+        }
+    }
+}
+
+/// Gets and refreshes access tokens for one MCP server
+pub struct OAuthClient {
+    server_url: String,
+    config: OAuthConfig,
+    store: Arc<dyn TokenStore>,
+    open_url: OpenUrl,
+    http: reqwest::Client,
+    /// Held while getting a token, so concurrent connects authorize once
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl OAuthClient {
+    /// Authorization URLs are logged until [`with_open_url`](Self::with_open_url) sets a handler
+    pub fn new(
+        server_url: impl Into<String>,
+        config: OAuthConfig,
+        store: Arc<dyn TokenStore>,
+    ) -> Self {
+        Self {
+            server_url: server_url.into(),
+            config,
+            store,
+            open_url: Arc::new(|url: &str| info!("Authorize MCP access at: {}", url)),
+            http: reqwest::Client::new(),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Send authorization URLs to `open_url`, e.g. to launch a browser
+    pub fn with_open_url(mut self, open_url: OpenUrl) -> Self {
+        self.open_url = open_url;
+        self
+    }
+
+    /// A usable access token: the stored one, refreshed if it expired, or a
+    /// new one from the authorization flow
+    pub async fn access_token(&self) -> Result<String> {
+        self.token(None).await
+    }
+
+    /// A new access token after the server answered `401` to `rejected`
+    ///
+    /// The tokens are refreshed, or cleared and authorized again if that
+    /// fails. If another connection already replaced `rejected`, its token is
+    /// returned instead.
+    pub async fn renew(&self, rejected: &str) -> Result<String> {
+        self.token(Some(rejected)).await
+    }
+
+    async fn token(&self, rejected: Option<&str>) -> Result<String> {
+        let _guard = self.lock.lock().await;
+        if let Some(credentials) = self.store.load(&self.server_url).await? {
+            let rejected = rejected == Some(credentials.access_token.as_str());
+            if !rejected && !credentials.expired() {
+                return Ok(credentials.access_token);
+            }
+            if credentials.refresh_token.is_some() {
+                match self.refresh(&credentials).await {
+                    Ok(refreshed) => {
+                        self.store.save(&self.server_url, &refreshed).await?;
+                        return Ok(refreshed.access_token);
+                    }
+                    Err(e) => warn!("Token refresh for {} failed: {}", self.server_url, e),
+                }
+            }
+            self.clear().await?;
+        }
+        let credentials = self.authorize().await?;
+        self.store.save(&self.server_url, &credentials).await?;
+        Ok(credentials.access_token)
+    }
+
+    /// Forget the stored tokens, e.g. after the server rejected them
+    pub async fn clear(&self) -> Result<()> {
+        self.store.clear(&self.server_url).await
+    }
+
+    /// Run the authorization code flow with PKCE
+    async fn authorize(&self) -> Result<Credentials> {
+        let endpoints = self.discover().await?;
+        let listener = TcpListener::bind(("127.0.0.1", self.config.redirect_port.unwrap_or(0)))
+            .await
+            .map_err(|e| McpError::new(format!("Failed to listen for OAuth redirect: {}", e)))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| McpError::new(e.to_string()))?
+            .port();
+        let redirect_uri = format!("http://127.0.0.1:{}/callback", port);
+
+        let (client_id, client_secret) = match self.config.client_id {
+            Some(ref client_id) => (client_id.clone(), self.config.client_secret.clone()),
+            None => {
+                let registration = self.register(&endpoints, &redirect_uri).await?;
+                (registration.client_id, registration.client_secret)
+            }
+        };
+
+        let verifier = random_string();
+        let state = random_string();
+        let mut url = Url::parse(&endpoints.server.authorization_endpoint)
+            .map_err(|e| McpError::new(format!("Invalid authorization endpoint: {}", e)))?;
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("response_type", "code")
+                .append_pair("client_id", &client_id)
+                .append_pair("redirect_uri", &redirect_uri)
+                .append_pair("code_challenge", &pkce_challenge(&verifier))
+                .append_pair("code_challenge_method", "S256")
+                .append_pair("state", &state)
+                .append_pair("resource", &endpoints.resource);
+            if !endpoints.scopes.is_empty() {
+                query.append_pair("scope", &endpoints.scopes.join(" "));
+            }
+        }
+        (self.open_url)(url.as_str());
+
+        let code = tokio::time::timeout(AUTHORIZATION_TIMEOUT, receive_code(&listener, &state))
+            .await
+            .map_err(|_| McpError::new("Timed out waiting for OAuth authorization"))??;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("client_id", client_id.as_str()),
+            ("code_verifier", verifier.as_str()),
+            ("resource", endpoints.resource.as_str()),
+        ];
+        if let Some(ref secret) = client_secret {
+            form.push(("client_secret", secret));
+        }
+        let tokens = self
+            .token_request(&endpoints.server.token_endpoint, &form)
+            .await?;
+        info!("Authorized MCP server {}", self.server_url);
+        Ok(tokens.into_credentials(client_id, client_secret, None))
+    }
+
+    async fn refresh(&self, credentials: &Credentials) -> Result<Credentials> {
+        let endpoints = self.discover().await?;
+        let refresh_token = credentials.refresh_token.as_deref().unwrap_or_default();
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", credentials.client_id.as_str()),
+            ("resource", endpoints.resource.as_str()),
+        ];
+        if let Some(ref secret) = credentials.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let tokens = self
+            .token_request(&endpoints.server.token_endpoint, &form)
+            .await?;
+        debug!("Refreshed access token for {}", self.server_url);
+        Ok(tokens.into_credentials(
+            credentials.client_id.clone(),
+            credentials.client_secret.clone(),
+            credentials.refresh_token.clone(),
+        ))
+    }
+
+    async fn token_request(&self, endpoint: &str, form: &[(&str, &str)]) -> Result<TokenResponse> {
+        let response = self
+            .http
+            .post(endpoint)
+            .form(form)
+            .send()
+            .await
+            .map_err(|e| McpError::new(format!("Token request failed: {}", e)))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(McpError::new(format!(
+                "Token request failed with {}: {}",
+                status, body
+            )));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| McpError::new(format!("Invalid token response: {}", e)))
+    }
+
+    /// Dynamic client registration
+    async fn register(&self, endpoints: &Endpoints, redirect_uri: &str) -> Result<Registration> {
+        let endpoint = endpoints
+            .server
+            .registration_endpoint
+            .as_deref()
+            .ok_or_else(|| {
+                McpError::new(format!(
+                    "{} doesn't support client registration; configure a client_id",
+                    self.server_url
+                ))
+            })?;
+        let client_name = self
+            .config
+            .client_name
+            .clone()
+            .unwrap_or_else(|| "Hanzo MCP Client".to_string());
+        let request = serde_json::json!({
+            "client_name": client_name,
+            "redirect_uris": [redirect_uri],
+            "grant_types": ["authorization_code", "refresh_token"],
+            "response_types": ["code"],
+            "token_endpoint_auth_method": "none",
+        });
+        let response = self
+            .http
+            .post(endpoint)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| McpError::new(format!("Client registration failed: {}", e)))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(McpError::new(format!(
+                "Client registration failed with {}: {}",
+                status, body
+            )));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| McpError::new(format!("Invalid registration response: {}", e)))
+    }
+
+    /// Find the authorization server and its endpoints
+    async fn discover(&self) -> Result<Endpoints> {
+        let server_url = Url::parse(&self.server_url)
+            .map_err(|e| McpError::new(format!("Invalid server URL {}: {}", self.server_url, e)))?;
+        let resource_metadata = self.resource_metadata(&server_url).await;
+
+        // Servers without resource metadata are their own authorization server
+        let (issuer, resource, scopes) = match resource_metadata {
+            Some(metadata) => {
+                let issuer = metadata
+                    .authorization_servers
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| {
+                        McpError::new("Protected resource metadata names no authorization server")
+                    })?;
+                // Metadata for another resource could send our token elsewhere
+                // (RFC 9728 section 3.3)
+                let resource = canonical(&server_url);
+                if let Some(ref named) = metadata.resource {
+                    let matches = Url::parse(named).is_ok_and(|url| canonical(&url) == resource);
+                    if !matches {
+                        return Err(McpError::new(format!(
+                            "Protected resource metadata for {} names another resource: {}",
+                            resource, named
+                        )));
+                    }
+                }
+                (issuer, resource, metadata.scopes_supported)
+            }
+            None => (origin(&server_url), canonical(&server_url), Vec::new()),
+        };
+        let issuer = Url::parse(&issuer).map_err(|e| {
+            McpError::new(format!("Invalid authorization server {}: {}", issuer, e))
+        })?;
+        let server = self.server_metadata(&issuer).await?;
+
+        if let Some(ref methods) = server.code_challenge_methods_supported {
+            if !methods.iter().any(|m| m == "S256") {
+                return Err(McpError::new(format!(
+                    "Authorization server {} doesn't support PKCE with S256",
+                    issuer
+                )));
+            }
+        }
+        let scopes = if !self.config.scopes.is_empty() {
+            self.config.scopes.clone()
+        } else if !scopes.is_empty() {
+            scopes
+        } else {
+            server.scopes_supported.clone()
+        };
+        Ok(Endpoints {
+            server,
+            resource,
+            scopes,
+        })
+    }
+
+    /// The server's protected resource metadata, if it publishes any
+    ///
+    /// A `401` names the metadata in `WWW-Authenticate`; otherwise the
+    /// well-known locations are tried.
+    async fn resource_metadata(&self, server_url: &Url) -> Option<ResourceMetadata> {
+        let mut candidates = Vec::new();
+        if let Ok(response) = self.http.get(server_url.clone()).send().await {
+            if response.status() == StatusCode::UNAUTHORIZED {
+                let challenge = response
+                    .headers()
+                    .get(reqwest::header::WWW_AUTHENTICATE)
+                    .and_then(|v| v.to_str().ok());
+                if let Some(url) = challenge.and_then(resource_metadata_url) {
+                    candidates.push(url);
+                }
+            }
+        }
+        let path = server_url.path().trim_end_matches('/');
+        let base = format!(
+            "{}/.well-known/oauth-protected-resource",
+            origin(server_url)
+        );
+        if !path.is_empty() {
+            candidates.push(format!("{}{}", base, path));
+        }
+        candidates.push(base);
+
+        for url in candidates {
+            match self.get_json(&url).await {
+                Some(metadata) => return Some(metadata),
+                None => debug!("No protected resource metadata at {}", url),
+            }
+        }
+        None
+    }
+
+    async fn server_metadata(&self, issuer: &Url) -> Result<ServerMetadata> {
+        let origin = origin(issuer);
+        let path = issuer.path().trim_end_matches('/');
+        let candidates = if path.is_empty() {
+            vec![
+                format!("{}/.well-known/oauth-authorization-server", origin),
+                format!("{}/.well-known/openid-configuration", origin),
+            ]
+        } else {
+            vec![
+                format!("{}/.well-known/oauth-authorization-server{}", origin, path),
+                format!("{}/.well-known/openid-configuration{}", origin, path),
+                format!("{}{}/.well-known/openid-configuration", origin, path),
+            ]
+        };
+        for url in &candidates {
+            if let Some(metadata) = self.get_json(url).await {
+                return Ok(metadata);
+            }
+        }
+        Err(McpError::new(format!(
+            "No authorization server metadata found for {}",
+            issuer
+        )))
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Option<T> {
+        let response = self.http.get(url).send().await.ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+        response.json().await.ok()
+    }
+}
+
+/// Wait for the authorization redirect and return its code
+///
+/// Requests without our `state` (stray or forged redirects) get an error
+/// page and are otherwise ignored; only a redirect carrying it ends the wait.
+/// Connections that don't send a request in time are dropped.
+async fn receive_code(listener: &TcpListener, state: &str) -> Result<String> {
+    loop {
+        let (mut stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| McpError::new(format!("OAuth redirect listener failed: {}", e)))?;
+
+        let mut request = Vec::new();
+        let read = tokio::time::timeout(CALLBACK_READ_TIMEOUT, async {
+            let mut buffer = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n")
+                && request.len() < CALLBACK_REQUEST_LIMIT
+            {
+                match stream.read(&mut buffer).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buffer[..n]),
+                }
+            }
+        })
+        .await;
+        if read.is_err() {
+            debug!("Dropping OAuth redirect connection that sent no request");
+            continue;
+        }
+        let request = String::from_utf8_lossy(&request);
+        let target = request.split_whitespace().nth(1).unwrap_or("/");
+        let Ok(url) = Url::parse(&format!("http://127.0.0.1{}", target)) else {
+            continue;
+        };
+        if url.path() != "/callback" {
+            respond(&mut stream, "404 Not Found", "").await;
+            continue;
+        }
+
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        if params.get("state").map(String::as_str) != Some(state) {
+            warn!("Ignoring OAuth redirect with a missing or wrong state");
+            respond(
+                &mut stream,
+                "400 Bad Request",
+                "Invalid authorization response. Retry from the original browser window.",
+            )
+            .await;
+            continue;
+        }
+        let result = if let Some(error) = params.get("error") {
+            Err(McpError::new(format!(
+                "Authorization denied: {}",
+                params.get("error_description").unwrap_or(error)
+            )))
+        } else {
+            match params.get("code") {
+                Some(code) => Ok(code.clone()),
+                None => {
+                    respond(
+                        &mut stream,
+                        "400 Bad Request",
+                        "Authorization response had no code.",
+                    )
+                    .await;
+                    continue;
+                }
+            }
+        };
+
+        let page = match result {
+            Ok(_) => "Authorization complete. You can close this window.",
+            Err(_) => "Authorization failed. You can close this window.",
+        };
+        respond(&mut stream, "200 OK", page).await;
+        return result;
+    }
+}
+
+/// Answer a loopback request with a plain text page
+async fn respond(stream: &mut tokio::net::TcpStream, status: &str, page: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        page.len(),
+        page
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+/// The `resource_metadata` parameter of a `WWW-Authenticate` challenge
+fn resource_metadata_url(challenge: &str) -> Option<String> {
+    let start = challenge.find("resource_metadata=")? + "resource_metadata=".len();
+    let value = &challenge[start..];
+    let value = match value.strip_prefix('"') {
+        Some(quoted) => &quoted[..quoted.find('"')?],
+        None => value.split([',', ' ']).next()?,
+    };
+    Some(value.to_string())
+}
+
+fn origin(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+/// Server URL without fragment or trailing slash, per RFC 8707
+fn canonical(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url.as_str().trim_end_matches('/').to_string()
+}
+
+/// 32 random bytes, base64url encoded
+fn random_string() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use axum::{
+        extract::State,
+        http::header,
+        response::IntoResponse,
+        routing::{get, post, MethodRouter},
+        Form, Json, Router,
+    };
+    use serde_json::json;
+
+    #[derive(Clone, Default)]
+    pub(crate) struct Mock {
+        base: Arc<Mutex<String>>,
+        challenge: Arc<Mutex<Option<String>>>,
+        /// Resource the metadata names instead of `/mcp`
+        resource: Arc<Mutex<Option<String>>>,
+    }
+
+    async fn mcp(State(mock): State<Mock>) -> impl IntoResponse {
+        let challenge = format!(
+            "Bearer resource_metadata=\"{}/.well-known/oauth-protected-resource/mcp\"",
+            mock.base.lock().unwrap()
+        );
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, challenge)],
+        )
+    }
+
+    async fn resource(State(mock): State<Mock>) -> Json<serde_json::Value> {
+        let base = mock.base.lock().unwrap().clone();
+        let resource = mock.resource.lock().unwrap().clone();
+        Json(json!({
+            "resource": resource.unwrap_or_else(|| format!("{}/mcp", base)),
+            "authorization_servers": [base],
+            "scopes_supported": ["tools"]
+        }))
+    }
+
+    async fn server(State(mock): State<Mock>) -> Json<serde_json::Value> {
+        let base = mock.base.lock().unwrap().clone();
+        Json(json!({
+            "issuer": base,
+            "authorization_endpoint": format!("{}/authorize", base),
+            "token_endpoint": format!("{}/token", base),
+            "registration_endpoint": format!("{}/register", base),
+            "code_challenge_methods_supported": ["S256"]
+        }))
+    }
+
+    async fn token(
+        State(mock): State<Mock>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> axum::response::Response {
+        let ok = match form["grant_type"].as_str() {
+            "authorization_code" => {
+                form["code"] == "code-1"
+                    && form["client_id"] == "client-1"
+                    && mock.challenge.lock().unwrap().as_deref()
+                        == Some(pkce_challenge(&form["code_verifier"]).as_str())
+            }
+            "refresh_token" => form["refresh_token"] == "refresh-1",
+            _ => false,
+        };
+        if !ok {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "invalid_grant"})),
+            )
+                .into_response();
+        }
+        if form["grant_type"] == "authorization_code" {
+            // Expires at once, so the next call refreshes
+            Json(json!({"access_token": "access-1", "expires_in": 0, "refresh_token": "refresh-1"}))
+                .into_response()
+        } else {
+            Json(json!({"access_token": "access-2", "expires_in": 3600})).into_response()
+        }
+    }
+
+    /// Serve an authorization server for a protected `/mcp` endpoint
+    ///
+    /// The refresh token `refresh-1` gets the access token `access-2`.
+    pub(crate) async fn serve_mock(mcp: MethodRouter<Mock>) -> (Mock, String) {
+        let mock = Mock::default();
+        let app = Router::new()
+            .route("/mcp", mcp)
+            .route("/.well-known/oauth-protected-resource/mcp", get(resource))
+            .route("/.well-known/oauth-authorization-server", get(server))
+            .route(
+                "/register",
+                post(|| async { Json(json!({"client_id": "client-1"})) }),
+            )
+            .route("/token", post(token))
+            .with_state(mock.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        *mock.base.lock().unwrap() = base.clone();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (mock, base)
+    }
+
+    #[tokio::test]
+    async fn test_authorization_flow() {
+        let (mock, base) = serve_mock(get(mcp)).await;
+
+        // Stands in for the user approving in a browser
+        let challenge = mock.challenge.clone();
+        let turned_away = Arc::new(Mutex::new(Vec::new()));
+        let statuses = turned_away.clone();
+        let browser: OpenUrl = Arc::new(move |url: &str| {
+            let url = Url::parse(url).unwrap();
+            let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+            assert_eq!(params["scope"], "tools");
+            assert!(params["resource"].ends_with("/mcp"));
+            *challenge.lock().unwrap() = Some(params["code_challenge"].clone());
+            let redirect = format!(
+                "{}?code=code-1&state={}",
+                params["redirect_uri"], params["state"]
+            );
+            // Redirects without our state are turned away without ending the flow
+            let forged = format!("{}?error=access_denied&state=other", params["redirect_uri"]);
+            let stray = format!("{}?code=code-2", params["redirect_uri"]);
+            let statuses = statuses.clone();
+            tokio::spawn(async move {
+                for url in [forged, stray] {
+                    let status = reqwest::get(url).await.unwrap().status();
+                    statuses.lock().unwrap().push(status);
+                }
+                reqwest::get(redirect).await.unwrap()
+            });
+        });
+
+        let store = Arc::new(MemoryTokenStore::default());
+        let client = OAuthClient::new(
+            format!("{}/mcp", base),
+            OAuthConfig::default(),
+            store.clone(),
+        )
+        .with_open_url(browser);
+        assert_eq!(client.access_token().await.unwrap(), "access-1");
+        assert_eq!(
+            *turned_away.lock().unwrap(),
+            vec![StatusCode::BAD_REQUEST, StatusCode::BAD_REQUEST]
+        );
+        assert_eq!(client.access_token().await.unwrap(), "access-2");
+        assert_eq!(client.access_token().await.unwrap(), "access-2");
+
+        let stored = store.load(&format!("{}/mcp", base)).await.unwrap().unwrap();
+        assert_eq!(stored.client_id, "client-1");
+        assert_eq!(stored.refresh_token.as_deref(), Some("refresh-1"));
+
+        // A token another connection already replaced isn't renewed again
+        assert_eq!(client.renew("access-1").await.unwrap(), "access-2");
+
+        // A rejected token whose refresh fails is cleared and authorized again
+        let revoked = Credentials {
+            refresh_token: Some("revoked".to_string()),
+            ..stored
+        };
+        store
+            .save(&format!("{}/mcp", base), &revoked)
+            .await
+            .unwrap();
+        assert_eq!(client.renew("access-2").await.unwrap(), "access-1");
+        assert_eq!(turned_away.lock().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_other_resource() {
+        let (mock, base) = serve_mock(get(mcp)).await;
+        *mock.resource.lock().unwrap() = Some("https://other.example/mcp".to_string());
+        let client = OAuthClient::new(
+            format!("{}/mcp", base),
+            OAuthConfig::default(),
+            Arc::new(MemoryTokenStore::default()),
+        )
+        .with_open_url(Arc::new(|_: &str| panic!("authorized another resource")));
+        let error = client.access_token().await.unwrap_err();
+        assert!(error.message.contains("names another resource"));
+    }
+
+    #[tokio::test]
+    async fn test_silent_redirect_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let silent = tokio::net::TcpStream::connect(addr).await.unwrap();
+        tokio::spawn(async move {
+            let url = format!("http://{}/callback?code=code-1&state=state-1", addr);
+            reqwest::get(url).await.unwrap()
+        });
+        assert_eq!(receive_code(&listener, "state-1").await.unwrap(), "code-1");
+        drop(silent);
+    }
+
+    #[test]
+    fn test_resource_metadata_url() {
+        assert_eq!(
+            resource_metadata_url(
+                r#"Bearer error="invalid_token", resource_metadata="https://a.example/.well-known/oauth-protected-resource""#
+            )
+            .as_deref(),
+            Some("https://a.example/.well-known/oauth-protected-resource")
+        );
+        assert_eq!(resource_metadata_url("Bearer realm=\"mcp\""), None);
+    }
+}
@@ -0,0 +1,204 @@
+//! Running tool calls
+//!
+//! A [`ToolCall`] streams the call's progress and its server's log messages
+//! while a background task waits for the response. Cancelling the call, its
+//! timeout running out, or dropping the handle before the result arrives
+//! sends `notifications/cancelled` so the server can stop working on it.
+
+use crate::content;
+use crate::error::McpError;
+use crate::events::{CallEvent, SessionEvents};
+use crate::session::ServerSession;
+use hanzo_mcp_core::ToolResult;
+use rmcp::{
+    model::{
+        CallToolRequest, CallToolRequestParam, CallToolResult, CancelledNotificationParam,
+        ClientRequest, Meta, ProgressToken, RequestId, ServerResult,
+    },
+    service::{Peer, PeerRequestOptions, RequestHandle},
+    RoleClient,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit};
+use tracing::warn;
+
+type Result<T> = std::result::Result<T, McpError>;
+
+/// What a call needs besides its request
+pub(crate) struct CallOptions {
+    /// Name the caller used, for errors
+    pub name: String,
+    pub output_schema: Option<serde_json::Value>,
+    pub timeout: Duration,
+    /// Concurrency slot, held until the call ends
+    pub permit: OwnedSemaphorePermit,
+}
+
+/// Handle to a running tool call
+///
+/// Dropping the handle before the result arrives cancels the call.
+pub struct ToolCall {
+    name: String,
+    events: mpsc::UnboundedReceiver<CallEvent>,
+    result: oneshot::Receiver<Result<ToolResult>>,
+    cancel: Option<oneshot::Sender<Option<String>>>,
+}
+
+impl ToolCall {
+    /// Send `tools/call` and return once the server has the request
+    pub(crate) async fn start(
+        session: &ServerSession,
+        tool_name: &str,
+        arguments: serde_json::Value,
+        options: CallOptions,
+    ) -> Result<Self> {
+        let params = arguments.as_object().cloned().unwrap_or_default();
+        // Registered before sending so progress sent right away is not lost
+        let token = session.events().next_token();
+        let events = session.events().register(token.clone());
+        let mut meta = Meta::new();
+        meta.set_progress_token(token.clone());
+        let sent = session
+            .request(|peer| {
+                let request = ClientRequest::CallToolRequest(CallToolRequest {
+                    method: Default::default(),
+                    params: CallToolRequestParam {
+                        name: tool_name.to_string().into(),
+                        arguments: Some(params.clone()),
+                    },
+                    extensions: Default::default(),
+                });
+                let options = PeerRequestOptions {
+                    meta: Some(meta.clone()),
+                    ..PeerRequestOptions::no_options()
+                };
+                async move { peer.send_cancellable_request(request, options).await }
+            })
+            .await;
+        let handle = match sent {
+            Ok(handle) => handle,
+            Err(e) => {
+                session.events().unregister(&token);
+                return Err(e);
+            }
+        };
+
+        let (result_tx, result) = oneshot::channel();
+        let (cancel, cancelled) = oneshot::channel();
+        let name = options.name.clone();
+        tokio::spawn(run(
+            handle,
+            token,
+            session.events().clone(),
+            options,
+            cancelled,
+            result_tx,
+        ));
+
+        Ok(Self {
+            name,
+            events,
+            result,
+            cancel: Some(cancel),
+        })
+    }
+
+    /// Name of the tool being called
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Next progress update or log message; `None` once the call has ended
+    pub async fn next_event(&mut self) -> Option<CallEvent> {
+        self.events.recv().await
+    }
+
+    /// Cancel the call; [`result`](Self::result) then fails
+    pub fn cancel(&mut self, reason: Option<String>) {
+        if let Some(cancel) = self.cancel.take() {
+            let _ = cancel.send(reason);
+        }
+    }
+
+    /// Wait for the call's result
+    pub async fn result(mut self) -> Result<ToolResult> {
+        // Dropping this before the result arrives would cancel the call
+        let _cancel = self.cancel.take();
+        (&mut self.result)
+            .await
+            .unwrap_or_else(|_| Err(McpError::new("Tool call ended without a result")))
+    }
+}
+
+/// Wait for a call's response, its cancellation or its timeout
+async fn run(
+    handle: RequestHandle<RoleClient>,
+    token: ProgressToken,
+    events: Arc<SessionEvents>,
+    options: CallOptions,
+    cancelled: oneshot::Receiver<Option<String>>,
+    result_tx: oneshot::Sender<Result<ToolResult>>,
+) {
+    let (peer, id) = (handle.peer.clone(), handle.id.clone());
+    let name = options.name;
+
+    let result = tokio::select! {
+        response = tokio::time::timeout(options.timeout, handle.await_response()) => {
+            match response {
+                Ok(Ok(ServerResult::CallToolResult(result))) => {
+                    let result = tool_result(result);
+                    match options.output_schema {
+                        Some(ref schema) => {
+                            content::check_output(&name, schema, &result).map(|_| result)
+                        }
+                        None => Ok(result),
+                    }
+                }
+                Ok(Ok(other)) => Err(McpError::new(format!(
+                    "Unexpected response to tools/call: {:?}",
+                    other
+                ))),
+                Ok(Err(e)) => Err(McpError::new(e.to_string())),
+                Err(_) => {
+                    let message = format!(
+                        "Tool call timed out after {} seconds",
+                        options.timeout.as_secs()
+                    );
+                    notify_cancelled(&peer, id, Some(message.clone())).await;
+                    Err(McpError::new(message))
+                }
+            }
+        }
+        // A dropped handle cancels without a reason
+        reason = cancelled => {
+            notify_cancelled(&peer, id, reason.ok().flatten()).await;
+            Err(McpError::new(format!("Tool call {} was cancelled", name)))
+        }
+    };
+
+    // Ends the call's event stream
+    events.unregister(&token);
+    drop(options.permit);
+    let _ = result_tx.send(result);
+}
+
+async fn notify_cancelled(peer: &Peer<RoleClient>, request_id: RequestId, reason: Option<String>) {
+    let params = CancelledNotificationParam { request_id, reason };
+    if let Err(e) = peer.notify_cancelled(params).await {
+        warn!("Failed to send notifications/cancelled: {}", e);
+    }
+}
+
+/// Convert a `tools/call` result, keeping every content type
+fn tool_result(result: CallToolResult) -> ToolResult {
+    ToolResult {
+        content: result
+            .content
+            .iter()
+            .filter_map(content::content_block)
+            .collect(),
+        is_error: result.is_error.unwrap_or(false),
+        structured_content: result.structured_content,
+    }
+}
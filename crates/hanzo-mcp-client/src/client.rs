@@ -2,21 +2,35 @@
 //!
 //! This module provides a high-level client that manages connections to multiple
 //! MCP servers simultaneously, with support for:
-//! - Multiple transport protocols (HTTP, SSE, Process)
+//! - Multiple transport protocols (HTTP, SSE, WebSocket, Process)
+//! - One long-lived session per server, reconnected when it drops
 //! - Automatic tool discovery and registration
+//! - Resource templates, binary reads and subscriptions
+//! - Prompt discovery and retrieval across servers
 //! - Concurrency control via semaphore
-//! - Per-tool timeouts
+//! - Per-tool timeouts, progress and cancellation
+//! - Server notifications, with the tool registry refreshed on list changes
+//! - OAuth 2.1 authorization for remote servers
+//! - Sampling and elicitation requests answered by host handlers
 //! - Tool name prefixing to avoid conflicts
 
+use crate::auth::{MemoryTokenStore, OAuthClient, OpenUrl, TokenStore};
+use crate::call::{CallOptions, ToolCall};
+use crate::content;
 use crate::error::McpError;
-use crate::mcp_methods;
+use crate::events::{ServerEvent, EVENT_CAPACITY};
+use crate::host::{ElicitationHandler, HostHandlers, SamplingHandler};
+use crate::session::{ConnectionState, ServerSession, ServerStatus};
+use crate::subscription::ResourceSubscription;
 use hanzo_mcp_core::{
-    McpClientConfig, McpServerConfig, McpServerSource, McpToolInfo, ToolDefinition, ToolResult,
+    McpClientConfig, McpServerConfig, McpServerSource, McpToolInfo, PromptArgument,
+    PromptDefinition, PromptResult, ResourceContents, ResourceTemplate, ToolDefinition, ToolResult,
 };
+use rmcp::model::{GetPromptRequestParam, ReadResourceRequestParam};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::{RwLock, Semaphore};
+use tokio::sync::{broadcast, RwLock, Semaphore};
 use tracing::{debug, error, info, warn};
 
 type Result<T> = std::result::Result<T, McpError>;
@@ -42,6 +56,7 @@ pub struct CalledFunction {
 struct ServerConnection {
     config: McpServerConfig,
     tools: Vec<McpToolInfo>,
+    session: Arc<ServerSession>,
 }
 
 /// Unified MCP client that manages connections to multiple MCP servers
@@ -49,6 +64,7 @@ struct ServerConnection {
 /// # Features
 ///
 /// - **Multi-server Management**: Connects to and manages multiple MCP servers simultaneously
+/// - **Persistent Sessions**: Keeps one connection per server, reconnecting when it drops
 /// - **Automatic Tool Discovery**: Discovers available tools from connected servers
 /// - **Tool Name Prefixing**: Prefixes tool names to avoid conflicts between servers
 /// - **Concurrency Control**: Limits concurrent tool calls via semaphore
@@ -85,6 +101,7 @@ struct ServerConnection {
 ///     let tools = client.list_tools().await;
 ///     println!("Found {} tools", tools.len());
 ///
+///     client.close().await;
 ///     Ok(())
 /// }
 /// ```
@@ -93,6 +110,10 @@ pub struct McpClient {
     servers: RwLock<HashMap<String, ServerConnection>>,
     tools: RwLock<HashMap<String, McpToolInfo>>,
     concurrency_semaphore: Arc<Semaphore>,
+    events: broadcast::Sender<ServerEvent>,
+    token_store: Arc<dyn TokenStore>,
+    open_url: Option<OpenUrl>,
+    host: HostHandlers,
 }
 
 impl McpClient {
@@ -104,9 +125,48 @@ impl McpClient {
             servers: RwLock::new(HashMap::new()),
             tools: RwLock::new(HashMap::new()),
             concurrency_semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            token_store: Arc::new(MemoryTokenStore::default()),
+            open_url: None,
+            host: HostHandlers::default(),
         }
     }
 
+    /// Keep OAuth tokens in `store` instead of in memory
+    pub fn with_token_store(mut self, store: Arc<dyn TokenStore>) -> Self {
+        self.token_store = store;
+        self
+    }
+
+    /// Send OAuth authorization URLs to `open_url` instead of logging them
+    pub fn with_open_url(mut self, open_url: OpenUrl) -> Self {
+        self.open_url = Some(open_url);
+        self
+    }
+
+    /// Answer servers' `sampling/createMessage` requests with `handler`
+    ///
+    /// Each server's `sampling` policy decides whether it may use it.
+    pub fn with_sampling_handler(mut self, handler: Arc<dyn SamplingHandler>) -> Self {
+        self.host.sampling = Some(handler);
+        self
+    }
+
+    /// Answer servers' `elicitation/create` requests with `handler`
+    ///
+    /// Each server's `elicitation` policy decides whether it may use it.
+    pub fn with_elicitation_handler(mut self, handler: Arc<dyn ElicitationHandler>) -> Self {
+        self.host.elicitation = Some(handler);
+        self
+    }
+
+    /// Receive notifications from all servers
+    ///
+    /// A receiver that falls behind misses the oldest events.
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.events.subscribe()
+    }
+
     /// Initialize connections to all configured servers
     ///
     /// This method connects to each enabled server and discovers available tools.
@@ -140,40 +200,62 @@ impl McpClient {
 
     /// Connect to a single server and discover its tools
     async fn connect_server(&self, config: McpServerConfig) -> Result<()> {
-        let tools = self.list_server_tools(&config).await?;
+        let oauth = self.oauth_client(&config).map(Arc::new);
+        let session =
+            ServerSession::connect(config.clone(), self.events.clone(), oauth, &self.host)
+                .await
+                .map(Arc::new)?;
+        let tools = match Self::list_server_tools(&session, &config).await {
+            Ok(tools) => tools,
+            Err(e) => {
+                session.close().await;
+                return Err(e);
+            }
+        };
 
         let mut servers = self.servers.write().await;
-        servers.insert(config.id.clone(), ServerConnection { config, tools });
+        let connection = ServerConnection {
+            config,
+            tools,
+            session,
+        };
+        if let Some(old) = servers.insert(connection.config.id.clone(), connection) {
+            old.session.close().await;
+        }
 
         Ok(())
     }
 
-    /// List tools from a specific server
-    async fn list_server_tools(&self, config: &McpServerConfig) -> Result<Vec<McpToolInfo>> {
-        let raw_tools = match &config.source {
-            McpServerSource::Http { url, .. } => {
-                mcp_methods::list_tools_via_http(url, None).await?
-            }
-            McpServerSource::Sse { url, .. } => mcp_methods::list_tools_via_sse(url, None).await?,
-            McpServerSource::Process {
-                command, args, env, ..
-            } => {
-                let cmd_str = if args.is_empty() {
-                    command.clone()
-                } else {
-                    format!("{} {}", command, args.join(" "))
-                };
-                mcp_methods::list_tools_via_command(&cmd_str, env.clone()).await?
-            }
-            McpServerSource::WebSocket { .. } => {
-                // WebSocket not yet implemented in rmcp-based client
-                warn!(
-                    "WebSocket transport not yet supported, skipping server: {}",
-                    config.name
-                );
-                return Ok(Vec::new());
+    /// OAuth token source for a remote server configured to use it
+    fn oauth_client(&self, config: &McpServerConfig) -> Option<OAuthClient> {
+        let oauth = config.oauth.clone()?;
+        // Authorization is discovered over HTTP, also for WebSocket servers
+        let url = match &config.source {
+            McpServerSource::Http { url, .. } | McpServerSource::Sse { url, .. } => url.clone(),
+            McpServerSource::WebSocket { url, .. } => url
+                .strip_prefix("ws")
+                .map(|rest| format!("http{}", rest))
+                .unwrap_or_else(|| url.clone()),
+            McpServerSource::Process { .. } => {
+                warn!("Ignoring OAuth settings of process server {}", config.name);
+                return None;
             }
         };
+        let client = OAuthClient::new(url, oauth, self.token_store.clone());
+        Some(match &self.open_url {
+            Some(open_url) => client.with_open_url(open_url.clone()),
+            None => client,
+        })
+    }
+
+    /// List tools from a specific server
+    async fn list_server_tools(
+        session: &ServerSession,
+        config: &McpServerConfig,
+    ) -> Result<Vec<McpToolInfo>> {
+        let raw_tools = session
+            .request(|peer| async move { peer.list_all_tools().await })
+            .await?;
 
         // Convert to McpToolInfo with server context
         let tools: Vec<McpToolInfo> = raw_tools
@@ -182,6 +264,9 @@ impl McpClient {
                 name: t.name.to_string(),
                 description: t.description.map(|d| d.to_string()),
                 input_schema: serde_json::Value::Object((*t.input_schema).clone()),
+                output_schema: t
+                    .output_schema
+                    .map(|schema| serde_json::Value::Object((*schema).clone())),
                 server_id: config.id.clone(),
                 server_name: config.name.clone(),
             })
@@ -195,37 +280,62 @@ impl McpClient {
         let servers = self.servers.read().await;
         let mut all_tools = self.tools.write().await;
 
-        for (server_id, connection) in servers.iter() {
-            for tool in &connection.tools {
-                // Apply tool prefix if configured
-                let tool_name = if let Some(prefix) = &connection.config.tool_prefix {
-                    format!("{}_{}", prefix, tool.name)
-                } else {
-                    tool.name.clone()
-                };
-
-                let mut tool_info = tool.clone();
-                tool_info.name = tool_name.clone();
-                all_tools.insert(tool_name, tool_info);
-            }
-            debug!(
-                "Registered {} tools from server {}",
-                connection.tools.len(),
-                server_id
-            );
+        for connection in servers.values() {
+            register_tools(&mut all_tools, &connection.config, &connection.tools);
         }
 
         info!("Total tools registered: {}", all_tools.len());
         Ok(())
     }
 
+    /// Re-list the tools of servers that sent `tools/list_changed`
+    async fn refresh_tools(&self) {
+        let changed: Vec<_> = self
+            .servers
+            .read()
+            .await
+            .iter()
+            .filter(|(_, connection)| connection.session.events().take_tools_changed())
+            .map(|(id, connection)| {
+                (
+                    id.clone(),
+                    connection.session.clone(),
+                    connection.config.clone(),
+                )
+            })
+            .collect();
+
+        for (server_id, session, config) in changed {
+            let tools = match Self::list_server_tools(&session, &config).await {
+                Ok(tools) => tools,
+                Err(e) => {
+                    warn!("Failed to refresh tools of server {}: {}", server_id, e);
+                    continue;
+                }
+            };
+            match self.servers.write().await.get_mut(&server_id) {
+                Some(connection) => connection.tools = tools.clone(),
+                None => continue,
+            }
+            // Not under the servers lock: callers take the tools lock first
+            if self.config.auto_register_tools {
+                let mut all_tools = self.tools.write().await;
+                all_tools.retain(|_, tool| tool.server_id != server_id);
+                register_tools(&mut all_tools, &config, &tools);
+            }
+            info!("Refreshed tools of server {}", server_id);
+        }
+    }
+
     /// Get all discovered tools
     pub async fn list_tools(&self) -> Vec<McpToolInfo> {
+        self.refresh_tools().await;
         self.tools.read().await.values().cloned().collect()
     }
 
     /// Get tools as ToolDefinition format (for use with agent systems)
     pub async fn get_tool_definitions(&self) -> Vec<ToolDefinition> {
+        self.refresh_tools().await;
         self.tools
             .read()
             .await
@@ -248,8 +358,22 @@ impl McpClient {
         tool_name: &str,
         arguments: serde_json::Value,
     ) -> Result<ToolResult> {
+        self.start_tool(tool_name, arguments).await?.result().await
+    }
+
+    /// Start a tool call, returning a handle that streams its progress
+    ///
+    /// The call counts against the concurrency limit and is cancelled when it
+    /// runs past the tool timeout.
+    pub async fn start_tool(
+        &self,
+        tool_name: &str,
+        arguments: serde_json::Value,
+    ) -> Result<ToolCall> {
+        self.refresh_tools().await;
+
         // Find the tool and its server
-        let (original_name, server_config) = {
+        let (original_name, output_schema, session) = {
             let tools = self.tools.read().await;
             let tool_info = tools
                 .get(tool_name)
@@ -260,98 +384,30 @@ impl McpClient {
                 McpError::new(format!("Server not found: {}", tool_info.server_id))
             })?;
 
-            // Extract original tool name (remove prefix if present)
-            let original_name = if let Some(prefix) = &connection.config.tool_prefix {
-                let prefix_with_underscore = format!("{}_", prefix);
-                if tool_name.starts_with(&prefix_with_underscore) {
-                    tool_name[prefix_with_underscore.len()..].to_string()
-                } else {
-                    tool_name.to_string()
-                }
-            } else {
-                tool_name.to_string()
-            };
+            let original_name = unprefixed(&connection.config, tool_name);
 
-            (original_name, connection.config.clone())
+            (
+                original_name,
+                tool_info.output_schema.clone(),
+                connection.session.clone(),
+            )
         };
 
         // Acquire concurrency permit
-        let _permit = self
+        let permit = self
             .concurrency_semaphore
-            .acquire()
+            .clone()
+            .acquire_owned()
             .await
             .map_err(|_| McpError::new("Failed to acquire concurrency permit"))?;
 
-        // Execute with timeout
-        let timeout_duration = Duration::from_secs(self.config.tool_timeout_secs.unwrap_or(30));
-
-        let result = tokio::time::timeout(
-            timeout_duration,
-            self.execute_tool(&server_config, &original_name, arguments),
-        )
-        .await
-        .map_err(|_| {
-            McpError::new(format!(
-                "Tool call timed out after {} seconds",
-                timeout_duration.as_secs()
-            ))
-        })??;
-
-        Ok(result)
-    }
-
-    /// Execute a tool on a specific server
-    async fn execute_tool(
-        &self,
-        config: &McpServerConfig,
-        tool_name: &str,
-        arguments: serde_json::Value,
-    ) -> Result<ToolResult> {
-        let params = arguments.as_object().cloned().unwrap_or_default();
-
-        let result = match &config.source {
-            McpServerSource::Http { url, .. } => {
-                mcp_methods::run_tool_via_http(url.clone(), tool_name.to_string(), params).await?
-            }
-            McpServerSource::Sse { url, .. } => {
-                mcp_methods::run_tool_via_sse(url.clone(), tool_name.to_string(), params).await?
-            }
-            McpServerSource::Process {
-                command, args, env, ..
-            } => {
-                let cmd_str = if args.is_empty() {
-                    command.clone()
-                } else {
-                    format!("{} {}", command, args.join(" "))
-                };
-                mcp_methods::run_tool_via_command(
-                    cmd_str,
-                    tool_name.to_string(),
-                    env.clone().unwrap_or_default(),
-                    params,
-                )
-                .await?
-            }
-            McpServerSource::WebSocket { .. } => {
-                return Err(McpError::new("WebSocket transport not yet implemented"));
-            }
+        let options = CallOptions {
+            name: tool_name.to_string(),
+            output_schema,
+            timeout: Duration::from_secs(self.config.tool_timeout_secs.unwrap_or(30)),
+            permit,
         };
-
-        // Convert CallToolResult to ToolResult
-        let content: Vec<hanzo_mcp_core::ContentBlock> = result
-            .content
-            .iter()
-            .filter_map(|c| {
-                c.as_text().map(|t| hanzo_mcp_core::ContentBlock::Text {
-                    text: t.text.clone(),
-                })
-            })
-            .collect();
-
-        Ok(ToolResult {
-            content,
-            is_error: result.is_error.unwrap_or(false),
-        })
+        ToolCall::start(&session, &original_name, arguments, options).await
     }
 
     /// Get the configuration
@@ -371,7 +427,36 @@ impl McpClient {
 
     /// Check if a specific server is connected
     pub async fn is_server_connected(&self, server_id: &str) -> bool {
-        self.servers.read().await.contains_key(server_id)
+        self.server_status(server_id)
+            .await
+            .is_some_and(|status| status.state == ConnectionState::Connected)
+    }
+
+    /// Connection health of a server, if it was connected during `initialize`
+    pub async fn server_status(&self, server_id: &str) -> Option<ServerStatus> {
+        let servers = self.servers.read().await;
+        servers.get(server_id).map(|c| c.session.status())
+    }
+
+    /// Connection health of every server, by server id
+    pub async fn server_statuses(&self) -> HashMap<String, ServerStatus> {
+        let servers = self.servers.read().await;
+        servers
+            .iter()
+            .map(|(id, c)| (id.clone(), c.session.status()))
+            .collect()
+    }
+
+    /// Shut down every server session
+    ///
+    /// Process servers are stopped; later calls fail until `initialize` is
+    /// called again.
+    pub async fn close(&self) {
+        let servers = self.servers.read().await;
+        for (server_id, connection) in servers.iter() {
+            connection.session.close().await;
+            debug!("Closed MCP server: {}", server_id);
+        }
     }
 
     // =========================================================================
@@ -384,7 +469,7 @@ impl McpClient {
         let mut all_resources = Vec::new();
 
         for (server_id, connection) in servers.iter() {
-            match self.list_server_resources(&connection.config).await {
+            match Self::list_server_resources(&connection.session).await {
                 Ok(resources) => {
                     for resource in resources {
                         all_resources.push(McpResourceInfo {
@@ -407,75 +492,274 @@ impl McpClient {
     }
 
     /// List resources from a specific server
-    async fn list_server_resources(
-        &self,
-        config: &McpServerConfig,
-    ) -> Result<Vec<rmcp::model::Resource>> {
-        match &config.source {
-            McpServerSource::Http { url, .. } => {
-                mcp_methods::list_resources_via_http(url, None).await
-            }
-            McpServerSource::Sse { url, .. } => {
-                mcp_methods::list_resources_via_sse(url, None).await
-            }
-            McpServerSource::Process {
-                command, args, env, ..
-            } => {
-                let cmd_str = if args.is_empty() {
-                    command.clone()
-                } else {
-                    format!("{} {}", command, args.join(" "))
-                };
-                mcp_methods::list_resources_via_command(&cmd_str, env.clone()).await
-            }
-            McpServerSource::WebSocket { .. } => {
-                warn!("WebSocket transport not yet supported for resources");
-                Ok(Vec::new())
+    async fn list_server_resources(session: &ServerSession) -> Result<Vec<rmcp::model::Resource>> {
+        session
+            .request(|peer| async move { peer.list_all_resources().await })
+            .await
+    }
+
+    /// List resource templates from all connected servers
+    pub async fn list_resource_templates(&self) -> Result<Vec<McpResourceTemplateInfo>> {
+        let servers = self.servers.read().await;
+        let mut all_templates = Vec::new();
+
+        for (server_id, connection) in servers.iter() {
+            match Self::list_server_templates(&connection.session).await {
+                Ok(templates) => {
+                    for template in templates {
+                        all_templates.push(McpResourceTemplateInfo {
+                            template: ResourceTemplate {
+                                uri_template: template.uri_template.to_string(),
+                                name: template.name.to_string(),
+                                description: template.description.as_ref().map(|d| d.to_string()),
+                                mime_type: template.mime_type.as_ref().map(|m| m.to_string()),
+                            },
+                            server_id: server_id.clone(),
+                            server_name: connection.config.name.clone(),
+                        });
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to list resource templates from server {}: {}",
+                        server_id, e
+                    );
+                }
             }
         }
+
+        Ok(all_templates)
+    }
+
+    /// List resource templates from a specific server
+    async fn list_server_templates(
+        session: &ServerSession,
+    ) -> Result<Vec<rmcp::model::ResourceTemplate>> {
+        session
+            .request(|peer| async move { peer.list_all_resource_templates().await })
+            .await
     }
 
-    /// Read a resource by URI
+    /// Find the session serving a resource URI
     ///
-    /// The URI must match a resource from one of the connected servers.
-    pub async fn read_resource(&self, uri: &str) -> Result<String> {
-        // First, find which server has this resource
-        let resources = self.list_resources().await?;
-        let resource_info = resources
-            .iter()
+    /// Listed resources are checked first, then resource templates.
+    async fn resource_session(&self, uri: &str) -> Result<Arc<ServerSession>> {
+        let server_id = match self
+            .list_resources()
+            .await?
+            .into_iter()
             .find(|r| r.uri == uri)
-            .ok_or_else(|| McpError::new(format!("Resource not found: {}", uri)))?;
+        {
+            Some(resource) => resource.server_id,
+            None => self
+                .list_resource_templates()
+                .await?
+                .into_iter()
+                .find(|t| t.template.matches(uri))
+                .map(|t| t.server_id)
+                .ok_or_else(|| McpError::new(format!("Resource not found: {}", uri)))?,
+        };
 
         let servers = self.servers.read().await;
-        let connection = servers.get(&resource_info.server_id).ok_or_else(|| {
-            McpError::new(format!("Server not found: {}", resource_info.server_id))
-        })?;
+        let connection = servers
+            .get(&server_id)
+            .ok_or_else(|| McpError::new(format!("Server not found: {}", server_id)))?;
+        Ok(connection.session.clone())
+    }
+
+    /// Read a text resource by URI
+    ///
+    /// The URI must be a resource of one of the connected servers or match
+    /// one of their resource templates. Use
+    /// [`read_resource_contents`](Self::read_resource_contents) for binary
+    /// resources.
+    pub async fn read_resource(&self, uri: &str) -> Result<String> {
+        let contents = self.read_resource_contents(uri).await?;
+        let text: Vec<&str> = contents.iter().filter_map(|c| c.as_text()).collect();
+        if text.is_empty() && !contents.is_empty() {
+            return Err(McpError::new(format!(
+                "Resource {} is binary; read it with read_resource_contents",
+                uri
+            )));
+        }
+        Ok(text.join("\n"))
+    }
 
-        self.read_server_resource(&connection.config, uri).await
+    /// Read a resource by URI, keeping binary contents
+    pub async fn read_resource_contents(&self, uri: &str) -> Result<Vec<ResourceContents>> {
+        let session = self.resource_session(uri).await?;
+        Self::read_server_resource(&session, uri).await
     }
 
     /// Read a resource from a specific server
-    async fn read_server_resource(&self, config: &McpServerConfig, uri: &str) -> Result<String> {
-        match &config.source {
-            McpServerSource::Http { url, .. } => {
-                mcp_methods::read_resource_via_http(url, uri).await
-            }
-            McpServerSource::Sse { url, .. } => mcp_methods::read_resource_via_sse(url, uri).await,
-            McpServerSource::Process {
-                command, args, env, ..
-            } => {
-                let cmd_str = if args.is_empty() {
-                    command.clone()
-                } else {
-                    format!("{} {}", command, args.join(" "))
+    async fn read_server_resource(
+        session: &ServerSession,
+        uri: &str,
+    ) -> Result<Vec<ResourceContents>> {
+        let result = session
+            .request(|peer| {
+                let request = ReadResourceRequestParam {
+                    uri: uri.to_string().into(),
                 };
-                mcp_methods::read_resource_via_command(&cmd_str, uri, env.clone()).await
+                async move { peer.read_resource(request).await }
+            })
+            .await?;
+
+        Ok(result
+            .contents
+            .iter()
+            .filter_map(content::resource_contents)
+            .collect())
+    }
+
+    /// Subscribe to updates of a resource
+    ///
+    /// The server must support resource subscriptions.
+    pub async fn subscribe_resource(&self, uri: &str) -> Result<ResourceSubscription> {
+        let session = self.resource_session(uri).await?;
+        ResourceSubscription::start(session, uri).await
+    }
+
+    // =========================================================================
+    // Prompt Methods
+    // =========================================================================
+
+    /// List prompts from all connected servers
+    ///
+    /// Names carry the server's tool prefix, like tool names.
+    pub async fn list_prompts(&self) -> Result<Vec<McpPromptInfo>> {
+        let servers = self.servers.read().await;
+        let mut all_prompts = Vec::new();
+
+        for (server_id, connection) in servers.iter() {
+            let prompts = connection
+                .session
+                .request(|peer| async move { peer.list_all_prompts().await })
+                .await;
+            match prompts {
+                Ok(prompts) => {
+                    for prompt in prompts {
+                        let arguments = prompt
+                            .arguments
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|a| PromptArgument {
+                                name: a.name.to_string(),
+                                description: a.description.map(|d| d.to_string()),
+                                required: a.required.unwrap_or(false),
+                            })
+                            .collect();
+                        all_prompts.push(McpPromptInfo {
+                            name: prefixed(&connection.config, &prompt.name),
+                            description: prompt.description.map(|d| d.to_string()),
+                            arguments,
+                            server_id: server_id.clone(),
+                            server_name: connection.config.name.clone(),
+                        });
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to list prompts from server {}: {}", server_id, e);
+                }
             }
-            McpServerSource::WebSocket { .. } => Err(McpError::new(
-                "WebSocket transport not yet supported for resources",
-            )),
         }
+
+        Ok(all_prompts)
+    }
+
+    /// Get a prompt by its prefixed name, filled in with `arguments`
+    ///
+    /// Arguments are checked against the prompt's declared arguments before
+    /// the server is asked.
+    pub async fn get_prompt(
+        &self,
+        name: &str,
+        arguments: HashMap<String, String>,
+    ) -> Result<PromptResult> {
+        let prompts = self.list_prompts().await?;
+        let prompt = prompts
+            .into_iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| McpError::new(format!("Prompt not found: {}", name)))?;
+
+        let (original_name, session) = {
+            let servers = self.servers.read().await;
+            let connection = servers
+                .get(&prompt.server_id)
+                .ok_or_else(|| McpError::new(format!("Server not found: {}", prompt.server_id)))?;
+            (
+                unprefixed(&connection.config, name),
+                connection.session.clone(),
+            )
+        };
+
+        let definition = PromptDefinition {
+            name: original_name.clone(),
+            description: prompt.description,
+            arguments: prompt.arguments,
+        };
+        definition
+            .validate_arguments(&arguments)
+            .map_err(|e| McpError::new(e.to_string()))?;
+
+        let arguments: serde_json::Map<String, serde_json::Value> = arguments
+            .into_iter()
+            .map(|(k, v)| (k, serde_json::Value::String(v)))
+            .collect();
+        let result = session
+            .request(|peer| {
+                let request = GetPromptRequestParam {
+                    name: original_name.clone(),
+                    arguments: Some(arguments.clone()),
+                };
+                async move { peer.get_prompt(request).await }
+            })
+            .await?;
+
+        let messages = result
+            .messages
+            .iter()
+            .filter_map(content::prompt_message)
+            .collect();
+        Ok(PromptResult {
+            description: result.description.map(|d| d.to_string()),
+            messages,
+        })
+    }
+}
+
+/// Add a server's tools to the registry under their prefixed names
+fn register_tools(
+    all_tools: &mut HashMap<String, McpToolInfo>,
+    config: &McpServerConfig,
+    tools: &[McpToolInfo],
+) {
+    for tool in tools {
+        let tool_name = prefixed(config, &tool.name);
+
+        let mut tool_info = tool.clone();
+        tool_info.name = tool_name.clone();
+        all_tools.insert(tool_name, tool_info);
     }
+    debug!("Registered {} tools from server {}", tools.len(), config.id);
+}
+
+/// Name of a tool or prompt as exposed by the client
+fn prefixed(config: &McpServerConfig, name: &str) -> String {
+    match &config.tool_prefix {
+        Some(prefix) => format!("{}_{}", prefix, name),
+        None => name.to_string(),
+    }
+}
+
+/// Name of a tool or prompt on its server
+fn unprefixed(config: &McpServerConfig, name: &str) -> String {
+    config
+        .tool_prefix
+        .as_ref()
+        .and_then(|prefix| name.strip_prefix(&format!("{}_", prefix)))
+        .unwrap_or(name)
+        .to_string()
 }
 
 /// Information about an MCP resource with server context
@@ -495,6 +779,33 @@ pub struct McpResourceInfo {
     pub server_name: String,
 }
 
+/// Information about an MCP resource template with server context
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct McpResourceTemplateInfo {
+    /// The template; `expand` it to get a resource URI
+    #[serde(flatten)]
+    pub template: ResourceTemplate,
+    /// ID of the server providing these resources
+    pub server_id: String,
+    /// Name of the server providing these resources
+    pub server_name: String,
+}
+
+/// Information about an MCP prompt with server context
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct McpPromptInfo {
+    /// Prompt name, with the server's tool prefix
+    pub name: String,
+    /// Description of the prompt
+    pub description: Option<String>,
+    /// Arguments the prompt accepts
+    pub arguments: Vec<PromptArgument>,
+    /// ID of the server providing this prompt
+    pub server_id: String,
+    /// Name of the server providing this prompt
+    pub server_name: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -516,4 +827,21 @@ mod tests {
         let client = McpClient::new(config);
         assert_eq!(client.concurrency_semaphore.available_permits(), 5);
     }
+
+    #[test]
+    fn test_name_prefixing() {
+        let config = McpServerConfig {
+            tool_prefix: Some("fs".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(prefixed(&config, "summarize"), "fs_summarize");
+        assert_eq!(unprefixed(&config, "fs_summarize"), "summarize");
+        assert_eq!(unprefixed(&config, "other"), "other");
+
+        let bare = McpServerConfig {
+            tool_prefix: None,
+            ..Default::default()
+        };
+        assert_eq!(prefixed(&bare, "summarize"), "summarize");
+    }
 }
@@ -0,0 +1,300 @@
+//! Conversion of MCP content onto `hanzo_mcp_core` types
+//!
+//! Content is read from its wire JSON (`{"type": "text", ...}`) so every
+//! `rmcp` content type converts the same way wherever it appears. Sampling
+//! requests are read, and their results written, the same way. Structured
+//! tool output and elicited input are checked against their schemas here too.
+
+use crate::error::McpError;
+use hanzo_mcp_core::{
+    ContentBlock, ElicitationRequest, ModelPreferences, PromptMessage, PromptRole,
+    ResourceContents, SamplingRequest, SamplingResponse, ToolResult,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+/// Convert one MCP content item, or `None` for an unknown type
+pub(crate) fn content_block(content: &impl Serialize) -> Option<ContentBlock> {
+    let value = serde_json::to_value(content).ok()?;
+    let text = |field: &str| value[field].as_str().map(str::to_string);
+    let block = match value["type"].as_str()? {
+        "text" => ContentBlock::Text {
+            text: text("text")?,
+        },
+        "image" => ContentBlock::Image {
+            data: text("data")?,
+            mime_type: text("mimeType")?,
+        },
+        "audio" => ContentBlock::Audio {
+            data: text("data")?,
+            mime_type: text("mimeType")?,
+        },
+        "resource" => {
+            let resource = &value["resource"];
+            let field = |name: &str| resource[name].as_str().map(str::to_string);
+            ContentBlock::Resource {
+                uri: field("uri")?,
+                text: field("text"),
+                blob: field("blob"),
+                mime_type: field("mimeType"),
+            }
+        }
+        "resource_link" => ContentBlock::ResourceLink {
+            uri: text("uri")?,
+            name: text("name")?,
+            description: text("description"),
+            mime_type: text("mimeType"),
+        },
+        other => {
+            debug!("Skipping unsupported MCP content type: {}", other);
+            return None;
+        }
+    };
+    Some(block)
+}
+
+/// Convert one item of a `resources/read` result
+pub(crate) fn resource_contents(contents: &impl Serialize) -> Option<ResourceContents> {
+    let value = serde_json::to_value(contents).ok()?;
+    let text = |field: &str| value[field].as_str().map(str::to_string);
+    let (uri, mime_type) = (text("uri")?, text("mimeType"));
+    match (text("text"), text("blob")) {
+        (Some(text), _) => Some(ResourceContents::Text {
+            uri,
+            mime_type,
+            text,
+        }),
+        (None, Some(blob)) => Some(ResourceContents::Blob {
+            uri,
+            mime_type,
+            blob,
+        }),
+        (None, None) => None,
+    }
+}
+
+/// Convert a prompt message from `prompts/get`
+pub(crate) fn prompt_message(message: &impl Serialize) -> Option<PromptMessage> {
+    let value = serde_json::to_value(message).ok()?;
+    let role = match value["role"].as_str()? {
+        "assistant" => PromptRole::Assistant,
+        _ => PromptRole::User,
+    };
+    Some(PromptMessage {
+        role,
+        content: content_block(&value["content"])?,
+    })
+}
+
+/// Convert a `sampling/createMessage` request
+pub(crate) fn sampling_request(params: &impl Serialize) -> Option<SamplingRequest> {
+    let value = serde_json::to_value(params).ok()?;
+    let text = |field: &str| value[field].as_str().map(str::to_string);
+    let preferences = &value["modelPreferences"];
+    let model_preferences = preferences.is_object().then(|| ModelPreferences {
+        hints: preferences["hints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|hint| hint["name"].as_str().map(str::to_string))
+            .collect(),
+        cost_priority: preferences["costPriority"].as_f64(),
+        speed_priority: preferences["speedPriority"].as_f64(),
+        intelligence_priority: preferences["intelligencePriority"].as_f64(),
+    });
+    Some(SamplingRequest {
+        messages: value["messages"]
+            .as_array()?
+            .iter()
+            .map(prompt_message)
+            .collect::<Option<_>>()?,
+        system_prompt: text("systemPrompt"),
+        model_preferences,
+        include_context: text("includeContext"),
+        temperature: value["temperature"].as_f64(),
+        max_tokens: value["maxTokens"].as_u64()?.try_into().ok()?,
+        stop_sequences: value["stopSequences"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|stop| stop.as_str().map(str::to_string))
+            .collect(),
+        metadata: value.get("metadata").filter(|m| !m.is_null()).cloned(),
+    })
+}
+
+/// Wire JSON of a `sampling/createMessage` result, or `None` if its content
+/// is not text, image or audio
+pub(crate) fn create_message_result(response: &SamplingResponse) -> Option<Value> {
+    let content = match &response.content {
+        ContentBlock::Text { text } => json!({"type": "text", "text": text}),
+        ContentBlock::Image { data, mime_type } => {
+            json!({"type": "image", "data": data, "mimeType": mime_type})
+        }
+        ContentBlock::Audio { data, mime_type } => {
+            json!({"type": "audio", "data": data, "mimeType": mime_type})
+        }
+        _ => return None,
+    };
+    let mut result = json!({
+        "role": "assistant",
+        "content": content,
+        "model": response.model,
+    });
+    if let Some(ref stop_reason) = response.stop_reason {
+        result["stopReason"] = json!(stop_reason);
+    }
+    Some(result)
+}
+
+/// Convert an `elicitation/create` request
+pub(crate) fn elicitation_request(params: &impl Serialize) -> Option<ElicitationRequest> {
+    let value = serde_json::to_value(params).ok()?;
+    Some(ElicitationRequest {
+        message: value["message"].as_str()?.to_string(),
+        requested_schema: value.get("requestedSchema").cloned()?,
+    })
+}
+
+/// Describe how `value` fails to match `schema`, or `None` if it matches
+pub(crate) fn schema_mismatch(schema: &Value, value: &Value) -> Result<Option<String>, String> {
+    let validator = jsonschema::validator_for(schema).map_err(|e| e.to_string())?;
+    let errors: Vec<String> = validator
+        .iter_errors(value)
+        .map(|e| match e.instance_path.to_string() {
+            path if path.is_empty() => e.to_string(),
+            path => format!("{} at {}", e, path),
+        })
+        .collect();
+    Ok((!errors.is_empty()).then(|| errors.join("; ")))
+}
+
+/// Check a tool result's structured output against the tool's output schema
+///
+/// Tools that declare an output schema must return matching structured
+/// content; error results are exempt.
+pub(crate) fn check_output(
+    tool: &str,
+    schema: &Value,
+    result: &ToolResult,
+) -> Result<(), McpError> {
+    if result.is_error {
+        return Ok(());
+    }
+    let output = result.structured_content.as_ref().ok_or_else(|| {
+        McpError::new(format!(
+            "Tool {} declares an output schema but returned no structured content",
+            tool
+        ))
+    })?;
+    let mismatch = schema_mismatch(schema, output)
+        .map_err(|e| McpError::new(format!("Invalid output schema for tool {}: {}", tool, e)))?;
+    match mismatch {
+        None => Ok(()),
+        Some(errors) => Err(McpError::new(format!(
+            "Structured output of tool {} does not match its output schema: {}",
+            tool, errors
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_message() {
+        let message = prompt_message(&json!({
+            "role": "assistant",
+            "content": {"type": "text", "text": "Looks good"}
+        }))
+        .unwrap();
+        assert_eq!(message.role, PromptRole::Assistant);
+        assert_eq!(message.content.as_text(), Some("Looks good"));
+
+        let image =
+            content_block(&json!({"type": "image", "data": "aGk=", "mimeType": "image/png"}));
+        assert!(
+            matches!(image, Some(ContentBlock::Image { ref mime_type, .. }) if mime_type == "image/png")
+        );
+        assert!(content_block(&json!({"type": "hologram"})).is_none());
+    }
+
+    #[test]
+    fn test_content_types() {
+        let audio =
+            content_block(&json!({"type": "audio", "data": "AA==", "mimeType": "audio/wav"}));
+        assert!(matches!(audio, Some(ContentBlock::Audio { .. })));
+
+        let blob = content_block(&json!({
+            "type": "resource",
+            "resource": {"uri": "file:///shot.png", "blob": "iVBO", "mimeType": "image/png"}
+        }));
+        assert!(
+            matches!(blob, Some(ContentBlock::Resource { ref blob, text: None, .. }) if blob.as_deref() == Some("iVBO"))
+        );
+
+        let link = content_block(&json!({
+            "type": "resource_link",
+            "uri": "file:///shot.png",
+            "name": "shot.png"
+        }));
+        assert!(
+            matches!(link, Some(ContentBlock::ResourceLink { ref name, mime_type: None, .. }) if name == "shot.png")
+        );
+    }
+
+    #[test]
+    fn test_resource_contents() {
+        let blob = resource_contents(&json!({
+            "uri": "file:///logo.png",
+            "mimeType": "image/png",
+            "blob": "iVBO"
+        }))
+        .unwrap();
+        assert_eq!(blob.mime_type(), Some("image/png"));
+        assert!(blob.as_text().is_none());
+
+        let text = resource_contents(&json!({"uri": "tickets://HAN-42", "text": "Open"})).unwrap();
+        assert_eq!(text.as_text(), Some("Open"));
+    }
+
+    #[test]
+    fn test_sampling_request() {
+        let request = sampling_request(&json!({
+            "messages": [{"role": "user", "content": {"type": "text", "text": "Summarize"}}],
+            "modelPreferences": {"hints": [{"name": "claude"}], "speedPriority": 0.8},
+            "systemPrompt": "Be brief",
+            "maxTokens": 200
+        }))
+        .unwrap();
+        assert_eq!(request.messages[0].content.as_text(), Some("Summarize"));
+        assert_eq!(request.model_preferences.unwrap().hints, vec!["claude"]);
+        assert_eq!(request.max_tokens, 200);
+        assert!(sampling_request(&json!({"messages": []})).is_none());
+
+        let result = create_message_result(&SamplingResponse::text("zen", "Done")).unwrap();
+        assert_eq!(result["role"], "assistant");
+        assert_eq!(result["content"]["text"], "Done");
+        assert_eq!(result["stopReason"], "endTurn");
+    }
+
+    #[test]
+    fn test_check_output() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"width": {"type": "integer"}},
+            "required": ["width"]
+        });
+        let valid = ToolResult::structured(json!({"width": 1280}));
+        assert!(check_output("screenshot", &schema, &valid).is_ok());
+
+        let invalid = ToolResult::structured(json!({"width": "wide"}));
+        let error = check_output("screenshot", &schema, &invalid).unwrap_err();
+        assert!(error.message.contains("/width"));
+
+        assert!(check_output("screenshot", &schema, &ToolResult::text("1280")).is_err());
+        assert!(check_output("screenshot", &schema, &ToolResult::error("failed")).is_ok());
+    }
+}
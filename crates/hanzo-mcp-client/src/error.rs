@@ -15,3 +15,26 @@ impl McpError {
         }
     }
 }
+
+/// A failed attempt to connect to a server
+#[derive(Debug)]
+pub(crate) struct ConnectFailure {
+    pub error: McpError,
+    /// The server answered `401`, rejecting the bearer token
+    pub unauthorized: bool,
+}
+
+impl From<McpError> for ConnectFailure {
+    fn from(error: McpError) -> Self {
+        Self {
+            error,
+            unauthorized: false,
+        }
+    }
+}
+
+impl From<ConnectFailure> for McpError {
+    fn from(failure: ConnectFailure) -> Self {
+        failure.error
+    }
+}
@@ -0,0 +1,402 @@
+//! Server notifications
+//!
+//! Every session connection runs a [`Handler`] that routes what the server
+//! sends: progress goes to the tool call that asked for it, log messages to
+//! the calls running on that server and to [`McpClient::subscribe`]
+//! receivers, resource updates to the subscriptions watching them, and list
+//! changes are published and mark the session so the client refreshes its
+//! tool registry before next using it. Sampling and elicitation requests are
+//! passed on to the host (see [`crate::host`]).
+//!
+//! [`McpClient::subscribe`]: crate::McpClient::subscribe
+
+use crate::content;
+use crate::host::HostRequests;
+use rmcp::{
+    handler::client::ClientHandler,
+    model::{
+        ClientInfo, CreateElicitationRequestParam, CreateElicitationResult,
+        CreateMessageRequestParam, CreateMessageResult, ErrorData, LoggingMessageNotificationParam,
+        NumberOrString, ProgressNotificationParam, ProgressToken, ResourceUpdatedNotificationParam,
+    },
+    service::{NotificationContext, RequestContext},
+    RoleClient,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc, OnceCell};
+use tracing::debug;
+
+/// Events buffered per subscriber; slower subscribers miss the oldest
+pub(crate) const EVENT_CAPACITY: usize = 256;
+
+/// Notification from a server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// `notifications/message`
+    Log {
+        server_id: String,
+        message: LogMessage,
+    },
+    /// `notifications/tools/list_changed`; the registry is refreshed on next use
+    ToolsChanged { server_id: String },
+    /// `notifications/resources/list_changed`
+    ResourcesChanged { server_id: String },
+    /// `notifications/resources/updated` for a subscribed resource
+    ResourceUpdated { server_id: String, uri: String },
+    /// `notifications/prompts/list_changed`
+    PromptsChanged { server_id: String },
+}
+
+/// Log message sent by a server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogMessage {
+    /// Syslog level name, e.g. `info` or `error`
+    pub level: String,
+    pub logger: Option<String>,
+    pub data: Value,
+}
+
+/// Progress reported for a tool call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Progress {
+    pub progress: f64,
+    pub total: Option<f64>,
+    pub message: Option<String>,
+}
+
+/// Update from a running tool call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CallEvent {
+    Progress(Progress),
+    /// Logged by the call's server while the call ran
+    Log(LogMessage),
+}
+
+/// Outcome of the `resources/subscribe` shared by a URI's subscriptions
+pub(crate) type Subscribed = Arc<OnceCell<std::result::Result<(), String>>>;
+
+/// Subscriptions to one URI
+#[derive(Default)]
+struct Watchers {
+    subscribed: Subscribed,
+    senders: Vec<(u64, mpsc::UnboundedSender<String>)>,
+}
+
+/// Notification routing for a session, kept across its reconnects
+pub(crate) struct SessionEvents {
+    server_id: String,
+    events: broadcast::Sender<ServerEvent>,
+    /// Running calls by progress token
+    calls: Mutex<HashMap<ProgressToken, mpsc::UnboundedSender<CallEvent>>>,
+    /// Resource subscriptions by URI
+    watchers: Mutex<HashMap<String, Watchers>>,
+    next_watcher: AtomicU64,
+    next_call: AtomicU64,
+    tools_changed: AtomicBool,
+}
+
+impl SessionEvents {
+    pub fn new(server_id: String, events: broadcast::Sender<ServerEvent>) -> Self {
+        Self {
+            server_id,
+            events,
+            calls: Mutex::new(HashMap::new()),
+            watchers: Mutex::new(HashMap::new()),
+            next_watcher: AtomicU64::new(0),
+            next_call: AtomicU64::new(0),
+            tools_changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Route updates for a call until it is unregistered
+    pub fn register(&self, token: ProgressToken) -> mpsc::UnboundedReceiver<CallEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.calls.lock().unwrap().insert(token, sender);
+        receiver
+    }
+
+    /// Progress token for a new call, unique across the session's reconnects
+    pub fn next_token(&self) -> ProgressToken {
+        let id = self.next_call.fetch_add(1, Ordering::Relaxed);
+        ProgressToken(NumberOrString::Number(id as i64))
+    }
+
+    /// Stop routing updates to a call, ending its event stream
+    pub fn unregister(&self, token: &ProgressToken) {
+        self.calls.lock().unwrap().remove(token);
+    }
+
+    /// Route updates of a resource to a new subscription
+    ///
+    /// Also returns the outcome of subscribing to `uri`, which the first of
+    /// its subscriptions sets and the others wait for.
+    pub fn watch(&self, uri: &str) -> (u64, mpsc::UnboundedReceiver<String>, Subscribed) {
+        let id = self.next_watcher.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut watchers = self.watchers.lock().unwrap();
+        let subscriptions = watchers.entry(uri.to_string()).or_default();
+        subscriptions.senders.push((id, sender));
+        (id, receiver, subscriptions.subscribed.clone())
+    }
+
+    /// Drop every subscription waiting on a failed `resources/subscribe`
+    ///
+    /// Subscriptions started after the failure share a new one and are kept.
+    pub fn forget(&self, uri: &str, subscribed: &Subscribed) {
+        let mut watchers = self.watchers.lock().unwrap();
+        if let Some(subscriptions) = watchers.get(uri) {
+            if Arc::ptr_eq(&subscriptions.subscribed, subscribed) {
+                watchers.remove(uri);
+            }
+        }
+    }
+
+    /// Drop a subscription, returning whether it was the last one to `uri`
+    pub fn unwatch(&self, uri: &str, id: u64) -> bool {
+        let mut watchers = self.watchers.lock().unwrap();
+        let Some(subscriptions) = watchers.get_mut(uri) else {
+            return false;
+        };
+        subscriptions.senders.retain(|(watcher, _)| *watcher != id);
+        if subscriptions.senders.is_empty() {
+            watchers.remove(uri);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Subscribed URIs, to renew on a new connection
+    pub fn watched(&self) -> Vec<String> {
+        self.watchers.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Whether the server's tools changed since last asked
+    pub fn take_tools_changed(&self) -> bool {
+        self.tools_changed.swap(false, Ordering::AcqRel)
+    }
+
+    fn publish(&self, event: ServerEvent) {
+        // No receivers is fine
+        let _ = self.events.send(event);
+    }
+
+    fn progress(&self, params: ProgressNotificationParam) {
+        let calls = self.calls.lock().unwrap();
+        match calls.get(&params.progress_token) {
+            Some(call) => {
+                let _ = call.send(CallEvent::Progress(Progress {
+                    progress: params.progress,
+                    total: params.total,
+                    message: params.message,
+                }));
+            }
+            None => debug!(
+                "Progress from {} for unknown token {:?}",
+                self.server_id, params.progress_token
+            ),
+        }
+    }
+
+    fn resource_updated(&self, uri: String) {
+        for (watched, subscriptions) in self.watchers.lock().unwrap().iter() {
+            if covers(watched, &uri) {
+                for (_, subscription) in &subscriptions.senders {
+                    let _ = subscription.send(uri.clone());
+                }
+            }
+        }
+        self.publish(ServerEvent::ResourceUpdated {
+            server_id: self.server_id.clone(),
+            uri,
+        });
+    }
+
+    fn log(&self, params: LoggingMessageNotificationParam) {
+        let level = serde_json::to_value(params.level)
+            .ok()
+            .and_then(|level| level.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let message = LogMessage {
+            level,
+            logger: params.logger,
+            data: params.data,
+        };
+        for call in self.calls.lock().unwrap().values() {
+            let _ = call.send(CallEvent::Log(message.clone()));
+        }
+        self.publish(ServerEvent::Log {
+            server_id: self.server_id.clone(),
+            message,
+        });
+    }
+}
+
+/// Whether an update of `uri` concerns a subscription to `watched`
+///
+/// Servers may report a sub-resource of the subscribed URI.
+fn covers(watched: &str, uri: &str) -> bool {
+    uri.strip_prefix(watched)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || watched.ends_with('/'))
+}
+
+/// `rmcp` client handler for one connection of a session
+pub(crate) struct Handler {
+    info: ClientInfo,
+    events: Arc<SessionEvents>,
+    host: Arc<HostRequests>,
+}
+
+impl Handler {
+    pub fn new(info: ClientInfo, events: Arc<SessionEvents>, host: Arc<HostRequests>) -> Self {
+        Self { info, events, host }
+    }
+}
+
+impl ClientHandler for Handler {
+    fn get_info(&self) -> ClientInfo {
+        self.info.clone()
+    }
+
+    async fn create_message(
+        &self,
+        params: CreateMessageRequestParam,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<CreateMessageResult, ErrorData> {
+        let request = content::sampling_request(&params)
+            .ok_or_else(|| ErrorData::invalid_params("Unsupported sampling request", None))?;
+        let response = self.host.create_message(request).await?;
+        content::create_message_result(&response)
+            .and_then(|result| serde_json::from_value(result).ok())
+            .ok_or_else(|| ErrorData::internal_error("Sampling produced unsupported content", None))
+    }
+
+    async fn create_elicitation(
+        &self,
+        params: CreateElicitationRequestParam,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<CreateElicitationResult, ErrorData> {
+        let request = content::elicitation_request(&params)
+            .ok_or_else(|| ErrorData::invalid_params("Unsupported elicitation request", None))?;
+        let response = self.host.elicit(request).await?;
+        serde_json::to_value(response)
+            .and_then(serde_json::from_value)
+            .map_err(|e| ErrorData::internal_error(e.to_string(), None))
+    }
+
+    async fn on_progress(
+        &self,
+        params: ProgressNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.events.progress(params);
+    }
+
+    async fn on_logging_message(
+        &self,
+        params: LoggingMessageNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.events.log(params);
+    }
+
+    async fn on_tool_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        debug!("Tools changed on server {}", self.events.server_id);
+        self.events.tools_changed.store(true, Ordering::Release);
+        self.events.publish(ServerEvent::ToolsChanged {
+            server_id: self.events.server_id.clone(),
+        });
+    }
+
+    async fn on_resource_updated(
+        &self,
+        params: ResourceUpdatedNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.events.resource_updated(params.uri);
+    }
+
+    async fn on_resource_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        self.events.publish(ServerEvent::ResourcesChanged {
+            server_id: self.events.server_id.clone(),
+        });
+    }
+
+    async fn on_prompt_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        self.events.publish(ServerEvent::PromptsChanged {
+            server_id: self.events.server_id.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_routing() {
+        let (sender, mut subscriber) = broadcast::channel(EVENT_CAPACITY);
+        let events = SessionEvents::new("files".to_string(), sender);
+        let token = ProgressToken(NumberOrString::Number(1));
+        let mut call = events.register(token.clone());
+
+        events.progress(ProgressNotificationParam {
+            progress_token: token.clone(),
+            progress: 50.0,
+            total: Some(100.0),
+            message: None,
+        });
+        assert!(matches!(
+            call.try_recv(),
+            Ok(CallEvent::Progress(Progress { progress, .. })) if progress == 50.0
+        ));
+
+        events.log(LoggingMessageNotificationParam {
+            level: rmcp::model::LoggingLevel::Warning,
+            logger: None,
+            data: Value::from("disk almost full"),
+        });
+        assert!(matches!(call.try_recv(), Ok(CallEvent::Log(ref m)) if m.level == "warning"));
+        assert!(matches!(
+            subscriber.try_recv(),
+            Ok(ServerEvent::Log { ref server_id, .. }) if server_id == "files"
+        ));
+
+        events.unregister(&token);
+        assert!(call.try_recv().is_err());
+        assert!(!events.take_tools_changed());
+    }
+
+    #[test]
+    fn test_resource_watchers() {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        let events = SessionEvents::new("files".to_string(), sender);
+        let (first, mut updates, subscribed) = events.watch("file:///repo");
+        let (second, _, second_subscribed) = events.watch("file:///repo");
+        assert!(Arc::ptr_eq(&subscribed, &second_subscribed));
+
+        events.resource_updated("file:///repo/src/main.rs".to_string());
+        events.resource_updated("file:///repository".to_string());
+        assert_eq!(updates.try_recv().unwrap(), "file:///repo/src/main.rs");
+        assert!(updates.try_recv().is_err());
+
+        assert!(!events.unwatch("file:///repo", first));
+        assert!(events.unwatch("file:///repo", second));
+        assert!(events.watched().is_empty());
+
+        // A failed subscribe drops its URI's subscriptions, but not later ones
+        let (_, mut failed, subscribed) = events.watch("file:///repo");
+        events.forget("file:///repo", &subscribed);
+        let (_, _, retried) = events.watch("file:///repo");
+        events.forget("file:///repo", &subscribed);
+        assert!(failed.try_recv().is_err());
+        assert!(!Arc::ptr_eq(&subscribed, &retried));
+        assert_eq!(events.watched(), vec!["file:///repo".to_string()]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Requests from servers to the host
+//!
+//! Servers can ask the host for LLM completions (`sampling/createMessage`)
+//! and for user input (`elicitation/create`). The host answers them through
+//! the handlers registered on [`McpClient`], under each server's
+//! [`ApprovalPolicy`]; a capability is only advertised to a server that may
+//! use it.
+//!
+//! [`McpClient`]: crate::McpClient
+
+use crate::content;
+use crate::error::McpError;
+use async_trait::async_trait;
+use hanzo_mcp_core::{
+    ApprovalPolicy, ElicitationAction, ElicitationRequest, ElicitationResponse, McpServerConfig,
+    SamplingRequest, SamplingResponse,
+};
+use rmcp::model::{ClientCapabilities, ErrorCode, ErrorData};
+use std::sync::Arc;
+use tracing::debug;
+
+type Result<T> = std::result::Result<T, McpError>;
+
+/// Code of the error sent when the host refuses a request
+const REJECTED: ErrorCode = ErrorCode(-1);
+
+/// Runs LLM completions for servers
+#[async_trait]
+pub trait SamplingHandler: Send + Sync {
+    /// Approve a request from a server with the `ask` policy
+    ///
+    /// Refuses unless overridden, e.g. to show the request to the user.
+    async fn approve(&self, _server_id: &str, _request: &SamplingRequest) -> bool {
+        false
+    }
+
+    /// Run the completion
+    async fn create_message(
+        &self,
+        server_id: &str,
+        request: SamplingRequest,
+    ) -> Result<SamplingResponse>;
+}
+
+/// Asks the user for input on behalf of servers
+#[async_trait]
+pub trait ElicitationHandler: Send + Sync {
+    /// Approve a request from a server with the `ask` policy
+    ///
+    /// The user answers the request anyway, so this approves unless overridden.
+    async fn approve(&self, _server_id: &str, _request: &ElicitationRequest) -> bool {
+        true
+    }
+
+    /// Show the request and return the user's answer
+    async fn elicit(
+        &self,
+        server_id: &str,
+        request: ElicitationRequest,
+    ) -> Result<ElicitationResponse>;
+}
+
+/// Handlers registered on the client
+#[derive(Clone, Default)]
+pub(crate) struct HostHandlers {
+    pub sampling: Option<Arc<dyn SamplingHandler>>,
+    pub elicitation: Option<Arc<dyn ElicitationHandler>>,
+}
+
+/// The handlers one server may use, with its policies
+pub(crate) struct HostRequests {
+    server_id: String,
+    sampling: Option<(ApprovalPolicy, Arc<dyn SamplingHandler>)>,
+    elicitation: Option<(ApprovalPolicy, Arc<dyn ElicitationHandler>)>,
+}
+
+impl HostRequests {
+    pub fn new(config: &McpServerConfig, handlers: &HostHandlers) -> Self {
+        fn allowed<T: ?Sized>(
+            policy: ApprovalPolicy,
+            handler: &Option<Arc<T>>,
+        ) -> Option<(ApprovalPolicy, Arc<T>)> {
+            match policy {
+                ApprovalPolicy::Deny => None,
+                _ => handler.clone().map(|handler| (policy, handler)),
+            }
+        }
+        Self {
+            server_id: config.id.clone(),
+            sampling: allowed(config.sampling, &handlers.sampling),
+            elicitation: allowed(config.elicitation, &handlers.elicitation),
+        }
+    }
+
+    /// Capabilities to advertise to the server
+    pub fn capabilities(&self) -> ClientCapabilities {
+        ClientCapabilities {
+            sampling: self.sampling.as_ref().map(|_| Default::default()),
+            elicitation: self.elicitation.as_ref().map(|_| Default::default()),
+            ..Default::default()
+        }
+    }
+
+    /// Answer `sampling/createMessage`
+    pub async fn create_message(
+        &self,
+        request: SamplingRequest,
+    ) -> std::result::Result<SamplingResponse, ErrorData> {
+        let Some((policy, ref handler)) = self.sampling else {
+            return Err(not_allowed("Sampling", &self.server_id));
+        };
+        if policy == ApprovalPolicy::Ask && !handler.approve(&self.server_id, &request).await {
+            debug!("Sampling request from {} was refused", self.server_id);
+            return Err(ErrorData::new(
+                REJECTED,
+                "User rejected sampling request",
+                None,
+            ));
+        }
+        handler
+            .create_message(&self.server_id, request)
+            .await
+            .map_err(|e| ErrorData::internal_error(e.message, None))
+    }
+
+    /// Answer `elicitation/create`
+    ///
+    /// A refused request is answered as declined. Accepted input must match
+    /// the requested schema.
+    pub async fn elicit(
+        &self,
+        request: ElicitationRequest,
+    ) -> std::result::Result<ElicitationResponse, ErrorData> {
+        let Some((policy, ref handler)) = self.elicitation else {
+            return Err(not_allowed("Elicitation", &self.server_id));
+        };
+        if policy == ApprovalPolicy::Ask && !handler.approve(&self.server_id, &request).await {
+            debug!("Elicitation request from {} was refused", self.server_id);
+            return Ok(ElicitationResponse::decline());
+        }
+        let schema = request.requested_schema.clone();
+        let response = handler
+            .elicit(&self.server_id, request)
+            .await
+            .map_err(|e| ErrorData::internal_error(e.message, None))?;
+        if response.action != ElicitationAction::Accept {
+            return Ok(ElicitationResponse {
+                content: None,
+                ..response
+            });
+        }
+        let input = response.content.clone().unwrap_or_default();
+        match content::schema_mismatch(&schema, &input) {
+            Ok(None) => Ok(response),
+            Ok(Some(errors)) => Err(ErrorData::internal_error(
+                format!("Input does not match the requested schema: {}", errors),
+                None,
+            )),
+            Err(e) => Err(ErrorData::invalid_params(
+                format!("Invalid requested schema: {}", e),
+                None,
+            )),
+        }
+    }
+}
+
+fn not_allowed(what: &str, server_id: &str) -> ErrorData {
+    ErrorData::new(
+        ErrorCode::METHOD_NOT_FOUND,
+        format!("{} is not enabled for server {}", what, server_id),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hanzo_mcp_core::{ContentBlock, PromptMessage, PromptRole};
+    use serde_json::json;
+
+    struct Host;
+
+    #[async_trait]
+    impl SamplingHandler for Host {
+        async fn create_message(
+            &self,
+            _server_id: &str,
+            request: SamplingRequest,
+        ) -> Result<SamplingResponse> {
+            let prompt = request.messages[0].content.as_text().unwrap_or_default();
+            Ok(SamplingResponse::text("echo", prompt))
+        }
+    }
+
+    #[async_trait]
+    impl ElicitationHandler for Host {
+        async fn elicit(
+            &self,
+            _server_id: &str,
+            request: ElicitationRequest,
+        ) -> Result<ElicitationResponse> {
+            Ok(ElicitationResponse::accept(
+                json!({"name": request.message}),
+            ))
+        }
+    }
+
+    fn requests(sampling: ApprovalPolicy, elicitation: ApprovalPolicy) -> HostRequests {
+        let config = McpServerConfig {
+            id: "writer".to_string(),
+            sampling,
+            elicitation,
+            ..Default::default()
+        };
+        let host = Arc::new(Host);
+        HostRequests::new(
+            &config,
+            &HostHandlers {
+                sampling: Some(host.clone()),
+                elicitation: Some(host),
+            },
+        )
+    }
+
+    fn sampling_request() -> SamplingRequest {
+        SamplingRequest {
+            messages: vec![PromptMessage {
+                role: PromptRole::User,
+                content: ContentBlock::Text {
+                    text: "hello".to_string(),
+                },
+            }],
+            system_prompt: None,
+            model_preferences: None,
+            include_context: None,
+            temperature: None,
+            max_tokens: 100,
+            stop_sequences: Vec::new(),
+            metadata: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sampling_policy() {
+        let allowed = requests(ApprovalPolicy::Allow, ApprovalPolicy::Deny);
+        assert!(allowed.capabilities().sampling.is_some());
+        assert!(allowed.capabilities().elicitation.is_none());
+        let response = allowed.create_message(sampling_request()).await.unwrap();
+        assert_eq!(response.content.as_text(), Some("hello"));
+
+        // `Host` doesn't override `approve`, so asking refuses
+        let asked = requests(ApprovalPolicy::Ask, ApprovalPolicy::Ask);
+        let error = asked.create_message(sampling_request()).await.unwrap_err();
+        assert_eq!(error.code, REJECTED);
+
+        let denied = requests(ApprovalPolicy::Deny, ApprovalPolicy::Deny);
+        assert!(denied.capabilities().sampling.is_none());
+        assert!(denied.create_message(sampling_request()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_elicitation_schema() {
+        let requests = requests(ApprovalPolicy::Deny, ApprovalPolicy::Ask);
+        let request = |schema| ElicitationRequest {
+            message: "Ada".to_string(),
+            requested_schema: schema,
+        };
+
+        let accepted = requests
+            .elicit(request(json!({
+                "type": "object",
+                "properties": {"name": {"type": "string"}},
+                "required": ["name"]
+            })))
+            .await
+            .unwrap();
+        assert_eq!(accepted.content, Some(json!({"name": "Ada"})));
+
+        let mismatched = requests
+            .elicit(request(json!({
+                "type": "object",
+                "properties": {"name": {"type": "integer"}}
+            })))
+            .await;
+        assert!(mismatched.is_err());
+    }
+}
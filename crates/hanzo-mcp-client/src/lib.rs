@@ -5,11 +5,18 @@
 //!
 //! # Features
 //!
-//! - **Multiple Transports**: HTTP, SSE, Process (stdio)
+//! - **Multiple Transports**: HTTP, SSE, WebSocket, Process (stdio)
 //! - **Multi-Server Management**: Connect to multiple MCP servers simultaneously
+//! - **Persistent Sessions**: One long-lived connection per server, reconnected on drop
 //! - **Automatic Tool Discovery**: Discover and register tools from servers
+//! - **Resources**: Templates, binary contents and update subscriptions
+//! - **Prompts**: List server prompt templates and fill them in
 //! - **Concurrency Control**: Limit concurrent tool calls
 //! - **Timeout Support**: Per-tool execution timeouts
+//! - **Progress and Cancellation**: Stream tool progress, cancel running calls
+//! - **Notifications**: Server log messages and list changes, with automatic tool refresh
+//! - **OAuth 2.1**: Authorization for remote servers, with token refresh and storage
+//! - **Sampling and Elicitation**: Servers can ask the host for completions and user input
 //!
 //! # Quick Start
 //!
@@ -48,7 +55,7 @@
 //!
 //! ## Using low-level mcp_methods
 //!
-//! For simple one-off operations:
+//! For simple one-off operations (each call opens and closes its own connection):
 //!
 //! ```rust,no_run
 //! use hanzo_mcp_client::mcp_methods;
@@ -61,17 +68,36 @@
 //! }
 //! ```
 
+mod auth;
+mod call;
 mod client;
 mod command;
+mod content;
 pub mod error;
+mod events;
+mod host;
 pub mod mcp_methods;
+mod session;
+mod subscription;
 mod utils;
+mod websocket;
 
 // Re-export the high-level client
-pub use client::{CalledFunction, McpClient, McpResourceInfo, ToolCallback, ToolCallbackWithTool};
+pub use auth::{Credentials, FileTokenStore, MemoryTokenStore, OAuthClient, OpenUrl, TokenStore};
+pub use call::ToolCall;
+pub use client::{
+    CalledFunction, McpClient, McpPromptInfo, McpResourceInfo, McpResourceTemplateInfo,
+    ToolCallback, ToolCallbackWithTool,
+};
+pub use events::{CallEvent, LogMessage, Progress, ServerEvent};
+pub use host::{ElicitationHandler, SamplingHandler};
+pub use session::{ConnectionState, ServerStatus};
+pub use subscription::ResourceSubscription;
 
 // Re-export core types for convenience
 pub use hanzo_mcp_core::{
-    McpClientConfig, McpServerConfig, McpServerSource, McpToolInfo, ResourceDefinition,
-    ToolDefinition, ToolResult,
+    ApprovalPolicy, ElicitationAction, ElicitationRequest, ElicitationResponse, McpClientConfig,
+    McpServerConfig, McpServerSource, McpToolInfo, ModelPreferences, OAuthConfig, PromptArgument,
+    PromptMessage, PromptResult, PromptRole, ResourceContents, ResourceDefinition,
+    ResourceTemplate, SamplingRequest, SamplingResponse, ToolDefinition, ToolResult,
 };
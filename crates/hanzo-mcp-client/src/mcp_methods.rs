@@ -1,3 +1,5 @@
+use crate::session::http_client;
+use crate::websocket::{self, WebSocketOptions};
 use crate::{command::CommandWrappedInShellBuilder, error::McpError, utils::disect_command};
 
 type Result<T> = std::result::Result<T, McpError>;
@@ -5,12 +7,44 @@ use rmcp::{
     model::{
         CallToolRequestParam, CallToolResult, ClientCapabilities, ClientInfo, Implementation, Tool,
     },
-    transport::{SseClientTransport, StreamableHttpClientTransport, TokioChildProcess},
+    transport::{
+        sse_client::SseClientConfig, streamable_http_client::StreamableHttpClientTransportConfig,
+        SseClientTransport, StreamableHttpClientTransport, TokioChildProcess,
+    },
     ServiceExt,
 };
 use std::collections::HashMap;
 use tokio::process::Command;
 
+/// Streamable HTTP transport sending extra `headers` with every request
+fn http_transport(
+    url: &str,
+    headers: Option<&HashMap<String, String>>,
+) -> Result<StreamableHttpClientTransport<reqwest::Client>> {
+    Ok(StreamableHttpClientTransport::with_client(
+        http_client(headers, None)?,
+        StreamableHttpClientTransportConfig::with_uri(url),
+    ))
+}
+
+/// SSE transport sending extra `headers` with every request
+async fn sse_transport(
+    url: &str,
+    headers: Option<&HashMap<String, String>>,
+) -> Result<SseClientTransport<reqwest::Client>> {
+    SseClientTransport::start_with_client(
+        http_client(headers, None)?,
+        SseClientConfig {
+            sse_endpoint: url.into(),
+            ..Default::default()
+        },
+    )
+    .await
+    .map_err(|e| McpError {
+        message: format!("{}", e),
+    })
+}
+
 pub async fn list_tools_via_command(
     cmd_str: &str,
     config: Option<HashMap<String, String>>,
@@ -53,17 +87,12 @@ pub async fn list_tools_via_command(
     Ok(tools.unwrap())
 }
 
+/// List tools via SSE, sending `headers` (e.g. `Authorization`) with each request
 pub async fn list_tools_via_sse(
     sse_url: &str,
-    _config: Option<HashMap<String, String>>,
+    headers: Option<HashMap<String, String>>,
 ) -> Result<Vec<Tool>> {
-    // TODO: The config parameter is not currently used by SseTransport or ClientInfo setup in the example.
-    // It might be used in the future for authentication headers or other SSE-specific configurations.
-    let transport = SseClientTransport::start(sse_url)
-        .await
-        .map_err(|e| McpError {
-            message: format!("{}", e),
-        })?;
+    let transport = sse_transport(sse_url, headers.as_ref()).await?;
     let client_info = ClientInfo {
         protocol_version: Default::default(),
         capabilities: ClientCapabilities::default(),
@@ -97,13 +126,12 @@ pub async fn list_tools_via_sse(
     Ok(tools_result.unwrap())
 }
 
+/// List tools via Streamable HTTP, sending `headers` with each request
 pub async fn list_tools_via_http(
     sse_url: &str,
-    _config: Option<HashMap<String, String>>,
+    headers: Option<HashMap<String, String>>,
 ) -> Result<Vec<Tool>> {
-    // TODO: The config parameter is not currently used by SseTransport or ClientInfo setup in the example.
-    // It might be used in the future for authentication headers or other SSE-specific configurations.
-    let transport = StreamableHttpClientTransport::from_uri(sse_url);
+    let transport = http_transport(sse_url, headers.as_ref())?;
     let client_info = ClientInfo {
         protocol_version: Default::default(),
         capabilities: ClientCapabilities::default(),
@@ -290,18 +318,96 @@ pub async fn run_tool_via_http(
     })?)
 }
 
+pub async fn list_tools_via_websocket(
+    url: &str,
+    headers: Option<HashMap<String, String>>,
+) -> Result<Vec<Tool>> {
+    let options = WebSocketOptions {
+        headers: headers.unwrap_or_default(),
+        ..Default::default()
+    };
+    let transport = websocket::connect(url, &options).await?;
+    let client_info = ClientInfo {
+        protocol_version: Default::default(),
+        capabilities: ClientCapabilities::default(),
+        client_info: Implementation {
+            name: "hanzo_mcp_websocket_client".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            title: None,
+            icons: None,
+            website_url: None,
+        },
+    };
+    let client = client_info.serve(transport).await.map_err(|e| McpError {
+        message: format!("WebSocket client connection error: {:?}", e),
+    })?;
+
+    let _ = client.peer_info();
+
+    let tools_result = client
+        .list_all_tools()
+        .await
+        .inspect_err(|e| log::error!("error listing tools: {:?}", e));
+
+    let _ = client
+        .cancel()
+        .await
+        .inspect_err(|e| log::error!("error cancelling websocket service: {:?}", e));
+
+    tools_result.map_err(|e| McpError {
+        message: format!("{}", e),
+    })
+}
+
+pub async fn run_tool_via_websocket(
+    url: String,
+    tool: String,
+    parameters: serde_json::Map<String, serde_json::Value>,
+) -> Result<CallToolResult> {
+    let transport = websocket::connect(&url, &WebSocketOptions::default()).await?;
+    let client_info = ClientInfo {
+        protocol_version: Default::default(),
+        capabilities: ClientCapabilities::default(),
+        client_info: Implementation {
+            name: "hanzo_mcp_websocket_client".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            title: None,
+            icons: None,
+            website_url: None,
+        },
+    };
+    let client = client_info.serve(transport).await.map_err(|e| McpError {
+        message: format!("WebSocket client connection error: {:?}", e),
+    })?;
+
+    let call_tool_result = client
+        .call_tool(CallToolRequestParam {
+            name: tool.into(),
+            arguments: Some(parameters),
+        })
+        .await
+        .inspect_err(|e| log::error!("error calling tool: {:?}", e));
+    let _ = client
+        .cancel()
+        .await
+        .inspect_err(|e| log::error!("error cancelling websocket service: {:?}", e));
+    call_tool_result.map_err(|e| McpError {
+        message: format!("{}", e),
+    })
+}
+
 // =============================================================================
 // Resource Methods
 // =============================================================================
 
 use rmcp::model::{ReadResourceRequestParam, Resource};
 
-/// List resources from an MCP server via HTTP
+/// List resources from an MCP server via HTTP, sending `headers` with each request
 pub async fn list_resources_via_http(
     url: &str,
-    _config: Option<HashMap<String, String>>,
+    headers: Option<HashMap<String, String>>,
 ) -> Result<Vec<Resource>> {
-    let transport = StreamableHttpClientTransport::from_uri(url);
+    let transport = http_transport(url, headers.as_ref())?;
     let client_info = ClientInfo {
         protocol_version: Default::default(),
         capabilities: ClientCapabilities::default(),
@@ -334,14 +440,12 @@ pub async fn list_resources_via_http(
     })?)
 }
 
-/// List resources from an MCP server via SSE
+/// List resources from an MCP server via SSE, sending `headers` with each request
 pub async fn list_resources_via_sse(
     url: &str,
-    _config: Option<HashMap<String, String>>,
+    headers: Option<HashMap<String, String>>,
 ) -> Result<Vec<Resource>> {
-    let transport = SseClientTransport::start(url).await.map_err(|e| McpError {
-        message: format!("{}", e),
-    })?;
+    let transport = sse_transport(url, headers.as_ref()).await?;
     let client_info = ClientInfo {
         protocol_version: Default::default(),
         capabilities: ClientCapabilities::default(),
@@ -576,6 +680,250 @@ pub async fn read_resource_via_command(
     Ok(text)
 }
 
+// =============================================================================
+// Prompt Methods
+// =============================================================================
+
+use rmcp::model::{GetPromptRequestParam, GetPromptResult, Prompt};
+
+fn prompt_request(name: &str, arguments: HashMap<String, String>) -> GetPromptRequestParam {
+    GetPromptRequestParam {
+        name: name.to_string(),
+        arguments: Some(
+            arguments
+                .into_iter()
+                .map(|(k, v)| (k, serde_json::Value::String(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// List prompts from an MCP server via HTTP
+pub async fn list_prompts_via_http(url: &str) -> Result<Vec<Prompt>> {
+    let transport = StreamableHttpClientTransport::from_uri(url);
+    let client_info = ClientInfo {
+        protocol_version: Default::default(),
+        capabilities: ClientCapabilities::default(),
+        client_info: Implementation {
+            name: "hanzo_mcp_http_client".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            title: None,
+            icons: None,
+            website_url: None,
+        },
+    };
+    let client = client_info.serve(transport).await.map_err(|e| McpError {
+        message: format!("HTTP client connection error: {:?}", e),
+    })?;
+
+    let prompts = client
+        .list_all_prompts()
+        .await
+        .inspect_err(|e| log::error!("error listing prompts: {:?}", e));
+
+    let _ = client
+        .cancel()
+        .await
+        .inspect_err(|e| log::error!("error cancelling http service: {:?}", e));
+
+    prompts.map_err(|e| McpError {
+        message: format!("{}", e),
+    })
+}
+
+/// List prompts from an MCP server via SSE
+pub async fn list_prompts_via_sse(url: &str) -> Result<Vec<Prompt>> {
+    let transport = SseClientTransport::start(url).await.map_err(|e| McpError {
+        message: format!("{}", e),
+    })?;
+    let client_info = ClientInfo {
+        protocol_version: Default::default(),
+        capabilities: ClientCapabilities::default(),
+        client_info: Implementation {
+            name: "hanzo_mcp_sse_client".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            title: None,
+            icons: None,
+            website_url: None,
+        },
+    };
+    let client = client_info.serve(transport).await.map_err(|e| McpError {
+        message: format!("SSE client connection error: {:?}", e),
+    })?;
+
+    let prompts = client
+        .list_all_prompts()
+        .await
+        .inspect_err(|e| log::error!("error listing prompts: {:?}", e));
+
+    let _ = client
+        .cancel()
+        .await
+        .inspect_err(|e| log::error!("error cancelling sse service: {:?}", e));
+
+    prompts.map_err(|e| McpError {
+        message: format!("{}", e),
+    })
+}
+
+/// List prompts from an MCP server via command (stdio)
+pub async fn list_prompts_via_command(
+    cmd_str: &str,
+    config: Option<HashMap<String, String>>,
+) -> Result<Vec<Prompt>> {
+    let (env_vars, cmd_executable, cmd_args) = disect_command(cmd_str.to_string());
+    let (adapted_program, adapted_args, adapted_envs) =
+        CommandWrappedInShellBuilder::wrap_in_shell_as_values(
+            cmd_executable,
+            Some(cmd_args),
+            Some(env_vars),
+        );
+    let mut cmd = Command::new(adapted_program);
+    cmd.kill_on_drop(true);
+    cmd.envs(adapted_envs);
+    cmd.envs(config.unwrap_or_default());
+    cmd.args(adapted_args);
+
+    let child_process = TokioChildProcess::new(cmd).map_err(|e| McpError {
+        message: format!("{}", e),
+    })?;
+    let service = ().serve(child_process).await.map_err(|e| McpError {
+        message: format!("{}", e),
+    })?;
+
+    let prompts = service
+        .list_all_prompts()
+        .await
+        .inspect_err(|e| log::error!("error listing prompts: {:?}", e));
+
+    let _ = service
+        .cancel()
+        .await
+        .inspect_err(|e| log::error!("error cancelling stdio service: {:?}", e));
+
+    prompts.map_err(|e| McpError {
+        message: format!("{}", e),
+    })
+}
+
+/// Get a prompt from an MCP server via HTTP
+pub async fn get_prompt_via_http(
+    url: &str,
+    name: &str,
+    arguments: HashMap<String, String>,
+) -> Result<GetPromptResult> {
+    let transport = StreamableHttpClientTransport::from_uri(url);
+    let client_info = ClientInfo {
+        protocol_version: Default::default(),
+        capabilities: ClientCapabilities::default(),
+        client_info: Implementation {
+            name: "hanzo_mcp_http_client".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            title: None,
+            icons: None,
+            website_url: None,
+        },
+    };
+    let client = client_info.serve(transport).await.map_err(|e| McpError {
+        message: format!("HTTP client connection error: {:?}", e),
+    })?;
+
+    let result = client
+        .get_prompt(prompt_request(name, arguments))
+        .await
+        .inspect_err(|e| log::error!("error getting prompt: {:?}", e));
+
+    let _ = client
+        .cancel()
+        .await
+        .inspect_err(|e| log::error!("error cancelling http service: {:?}", e));
+
+    result.map_err(|e| McpError {
+        message: format!("{}", e),
+    })
+}
+
+/// Get a prompt from an MCP server via SSE
+pub async fn get_prompt_via_sse(
+    url: &str,
+    name: &str,
+    arguments: HashMap<String, String>,
+) -> Result<GetPromptResult> {
+    let transport = SseClientTransport::start(url).await.map_err(|e| McpError {
+        message: format!("{}", e),
+    })?;
+    let client_info = ClientInfo {
+        protocol_version: Default::default(),
+        capabilities: ClientCapabilities::default(),
+        client_info: Implementation {
+            name: "hanzo_mcp_sse_client".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            title: None,
+            icons: None,
+            website_url: None,
+        },
+    };
+    let client = client_info.serve(transport).await.map_err(|e| McpError {
+        message: format!("SSE client connection error: {:?}", e),
+    })?;
+
+    let result = client
+        .get_prompt(prompt_request(name, arguments))
+        .await
+        .inspect_err(|e| log::error!("error getting prompt: {:?}", e));
+
+    let _ = client
+        .cancel()
+        .await
+        .inspect_err(|e| log::error!("error cancelling sse service: {:?}", e));
+
+    result.map_err(|e| McpError {
+        message: format!("{}", e),
+    })
+}
+
+/// Get a prompt from an MCP server via command (stdio)
+pub async fn get_prompt_via_command(
+    cmd_str: &str,
+    name: &str,
+    arguments: HashMap<String, String>,
+    config: Option<HashMap<String, String>>,
+) -> Result<GetPromptResult> {
+    let (env_vars, cmd_executable, cmd_args) = disect_command(cmd_str.to_string());
+    let (adapted_program, adapted_args, adapted_envs) =
+        CommandWrappedInShellBuilder::wrap_in_shell_as_values(
+            cmd_executable,
+            Some(cmd_args),
+            Some(env_vars),
+        );
+    let mut cmd = Command::new(adapted_program);
+    cmd.kill_on_drop(true);
+    cmd.envs(adapted_envs);
+    cmd.envs(config.unwrap_or_default());
+    cmd.args(adapted_args);
+
+    let child_process = TokioChildProcess::new(cmd).map_err(|e| McpError {
+        message: format!("{}", e),
+    })?;
+    let service = ().serve(child_process).await.map_err(|e| McpError {
+        message: format!("{}", e),
+    })?;
+
+    let result = service
+        .get_prompt(prompt_request(name, arguments))
+        .await
+        .inspect_err(|e| log::error!("error getting prompt: {:?}", e));
+
+    let _ = service
+        .cancel()
+        .await
+        .inspect_err(|e| log::error!("error cancelling stdio service: {:?}", e));
+
+    result.map_err(|e| McpError {
+        message: format!("{}", e),
+    })
+}
+
 #[cfg(test)]
 pub mod tests_mcp_manager {
     use super::*;
@@ -0,0 +1,564 @@
+//! Long-lived MCP sessions
+//!
+//! [`McpClient`](crate::McpClient) keeps one `rmcp` client service running per
+//! configured server instead of connecting for every call, so stateful servers
+//! keep their state and calls don't pay process startup or handshake latency.
+//! A session that finds its connection gone reconnects (with backoff) and
+//! retries the request once; `close` shuts the service down. Notification
+//! routing lives in [`SessionEvents`], which outlives each connection, and
+//! resource subscriptions are renewed on every new connection. Each connection
+//! advertises the sampling and elicitation capabilities the server may use.
+//! A connection refused with `401` renews the OAuth token and tries once more.
+
+use crate::auth::OAuthClient;
+use crate::error::{ConnectFailure, McpError};
+use crate::events::{Handler, ServerEvent, SessionEvents};
+use crate::host::{HostHandlers, HostRequests};
+use crate::websocket::{self, WebSocketOptions};
+use crate::{command::CommandWrappedInShellBuilder, utils::disect_command};
+use hanzo_mcp_core::{McpServerConfig, McpServerSource};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::StatusCode;
+use rmcp::{
+    model::{ClientCapabilities, ClientInfo, Implementation, SubscribeRequestParam},
+    service::{ClientInitializeError, Peer, RunningService, ServiceError},
+    transport::{
+        sse_client::{SseClientConfig, SseTransportError},
+        streamable_http_client::{StreamableHttpClientTransportConfig, StreamableHttpError},
+        SseClientTransport, StreamableHttpClientTransport, TokioChildProcess,
+    },
+    RoleClient, ServiceExt,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+type Result<T> = std::result::Result<T, McpError>;
+
+type Service = RunningService<RoleClient, Handler>;
+
+/// First wait before reconnecting after a failed attempt; doubles up to the max
+const BACKOFF_MIN: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Connection state of a server session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    /// Connected and initialized
+    Connected,
+    /// The connection dropped or failed; the next call reconnects
+    Disconnected,
+    /// Shut down by `close`
+    Closed,
+}
+
+/// Health of a server session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub state: ConnectionState,
+    /// Successful reconnects since the first connection
+    pub reconnects: u32,
+    /// Why the connection last dropped or failed to connect
+    pub last_error: Option<String>,
+}
+
+struct Health {
+    status: ServerStatus,
+    /// Counts connections, so a failed request only drops the one it used
+    generation: u64,
+    /// No reconnect attempt before this after a failed one
+    retry_at: Option<Instant>,
+    backoff: Duration,
+}
+
+/// One long-lived client service for a configured server
+pub(crate) struct ServerSession {
+    config: McpServerConfig,
+    /// Held while connecting so reconnects happen once
+    service: tokio::sync::Mutex<Option<Service>>,
+    health: Mutex<Health>,
+    events: Arc<SessionEvents>,
+    /// Token source for servers that use OAuth
+    oauth: Option<Arc<OAuthClient>>,
+    /// Host handlers the server may use
+    host: Arc<HostRequests>,
+}
+
+impl ServerSession {
+    /// Connect to a server, failing if the first connection fails
+    ///
+    /// Server notifications are published on `events`; server requests go to
+    /// the `handlers` its policies allow.
+    pub async fn connect(
+        config: McpServerConfig,
+        events: broadcast::Sender<ServerEvent>,
+        oauth: Option<Arc<OAuthClient>>,
+        handlers: &HostHandlers,
+    ) -> Result<Self> {
+        let events = Arc::new(SessionEvents::new(config.id.clone(), events));
+        let host = Arc::new(HostRequests::new(&config, handlers));
+        let service = connect(&config, &events, &host, oauth.as_deref()).await?;
+        Ok(Self {
+            config,
+            service: tokio::sync::Mutex::new(Some(service)),
+            health: Mutex::new(Health {
+                status: ServerStatus {
+                    state: ConnectionState::Connected,
+                    reconnects: 0,
+                    last_error: None,
+                },
+                generation: 0,
+                retry_at: None,
+                backoff: BACKOFF_MIN,
+            }),
+            events,
+            oauth,
+            host,
+        })
+    }
+
+    pub fn events(&self) -> &Arc<SessionEvents> {
+        &self.events
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        self.health.lock().unwrap().status.clone()
+    }
+
+    /// Run a request on the session, reconnecting if the connection dropped
+    ///
+    /// A request that fails because the transport closed is retried once on a
+    /// fresh connection.
+    pub async fn request<T, F, Fut>(&self, op: F) -> Result<T>
+    where
+        F: Fn(Peer<RoleClient>) -> Fut,
+        Fut: Future<Output = std::result::Result<T, ServiceError>>,
+    {
+        let (peer, generation) = self.peer().await?;
+        match op(peer).await {
+            Ok(value) => Ok(value),
+            Err(e) if is_disconnect(&e) => {
+                self.disconnected(generation, &e.to_string()).await;
+                let (peer, _) = self.peer().await?;
+                op(peer).await.map_err(|e| McpError::new(e.to_string()))
+            }
+            Err(e) => Err(McpError::new(e.to_string())),
+        }
+    }
+
+    /// Shut the service down; later requests fail
+    pub async fn close(&self) {
+        let service = self.service.lock().await.take();
+        self.health.lock().unwrap().status.state = ConnectionState::Closed;
+        if let Some(service) = service {
+            if let Err(e) = service.cancel().await {
+                warn!("Error closing MCP server {}: {}", self.config.name, e);
+            }
+        }
+    }
+
+    /// The connected peer, reconnecting first if needed
+    async fn peer(&self) -> Result<(Peer<RoleClient>, u64)> {
+        let mut service = self.service.lock().await;
+        if let Some(ref service) = *service {
+            let generation = self.health.lock().unwrap().generation;
+            return Ok((service.peer().clone(), generation));
+        }
+
+        {
+            let health = self.health.lock().unwrap();
+            let status = &health.status;
+            if status.state == ConnectionState::Closed {
+                return Err(McpError::new(format!(
+                    "MCP server {} is closed",
+                    self.config.name
+                )));
+            }
+            if health.retry_at.is_some_and(|at| Instant::now() < at) {
+                return Err(McpError::new(format!(
+                    "MCP server {} is disconnected: {}",
+                    self.config.name,
+                    status.last_error.as_deref().unwrap_or("unknown error")
+                )));
+            }
+        }
+
+        match connect(
+            &self.config,
+            &self.events,
+            &self.host,
+            self.oauth.as_deref(),
+        )
+        .await
+        {
+            Ok(connected) => {
+                let peer = connected.peer().clone();
+                for uri in self.events.watched() {
+                    let request = SubscribeRequestParam { uri: uri.clone() };
+                    if let Err(e) = peer.subscribe(request).await {
+                        warn!("Failed to renew subscription to {}: {}", uri, e);
+                    }
+                }
+                *service = Some(connected);
+                let mut health = self.health.lock().unwrap();
+                health.status.state = ConnectionState::Connected;
+                health.status.reconnects += 1;
+                health.generation += 1;
+                health.retry_at = None;
+                health.backoff = BACKOFF_MIN;
+                info!("Reconnected to MCP server: {}", self.config.name);
+                Ok((peer, health.generation))
+            }
+            Err(e) => {
+                let mut health = self.health.lock().unwrap();
+                health.status.last_error = Some(e.message.clone());
+                health.retry_at = Some(Instant::now() + health.backoff);
+                health.backoff = (health.backoff * 2).min(BACKOFF_MAX);
+                Err(e)
+            }
+        }
+    }
+
+    /// Drop a dead connection so the next request reconnects
+    ///
+    /// Does nothing if another request already replaced that connection.
+    async fn disconnected(&self, generation: u64, error: &str) {
+        let service = {
+            let mut service = self.service.lock().await;
+            let mut health = self.health.lock().unwrap();
+            if health.generation != generation || service.is_none() {
+                return;
+            }
+            warn!("MCP server {} disconnected: {}", self.config.name, error);
+            health.status.state = ConnectionState::Disconnected;
+            health.status.last_error = Some(error.to_string());
+            service.take()
+        };
+        if let Some(service) = service {
+            let _ = service.cancel().await;
+        }
+    }
+}
+
+/// Errors meaning the connection is gone rather than the request failing
+fn is_disconnect(error: &ServiceError) -> bool {
+    matches!(
+        error,
+        ServiceError::TransportClosed | ServiceError::TransportSend(_)
+    )
+}
+
+fn client_info(capabilities: ClientCapabilities) -> ClientInfo {
+    ClientInfo {
+        protocol_version: Default::default(),
+        capabilities,
+        client_info: Implementation {
+            name: "hanzo-mcp-client".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            title: None,
+            icons: None,
+            website_url: None,
+        },
+    }
+}
+
+/// HTTP client that sends a server's configured headers and bearer token
+pub(crate) fn http_client(
+    headers: Option<&HashMap<String, String>>,
+    bearer_token: Option<&str>,
+) -> Result<reqwest::Client> {
+    let mut default_headers = HeaderMap::new();
+    for (name, value) in headers.into_iter().flatten() {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| McpError::new(format!("Invalid header name {}: {}", name, e)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| McpError::new(format!("Invalid value for header {}: {}", name, e)))?;
+        default_headers.insert(name, value);
+    }
+    if let Some(token) = bearer_token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|e| McpError::new(format!("Invalid bearer token: {}", e)))?;
+        value.set_sensitive(true);
+        default_headers.insert(AUTHORIZATION, value);
+    }
+    reqwest::Client::builder()
+        .default_headers(default_headers)
+        .build()
+        .map_err(|e| McpError::new(format!("Failed to build HTTP client: {}", e)))
+}
+
+/// The token to send: from OAuth if the server uses it, else the static one
+async fn bearer_token(
+    config: &McpServerConfig,
+    oauth: Option<&OAuthClient>,
+) -> Result<Option<String>> {
+    match oauth {
+        Some(oauth) => oauth.access_token().await.map(Some),
+        None => Ok(config.bearer_token.clone()),
+    }
+}
+
+/// Start a client service for a server's transport
+///
+/// If the server rejects the OAuth access token, it is renewed and the
+/// connection tried once more.
+async fn connect(
+    config: &McpServerConfig,
+    events: &Arc<SessionEvents>,
+    host: &Arc<HostRequests>,
+    oauth: Option<&OAuthClient>,
+) -> Result<Service> {
+    let token = bearer_token(config, oauth).await?;
+    let failure = match open(config, events, host, token.as_deref()).await {
+        Ok(service) => return Ok(service),
+        Err(failure) => failure,
+    };
+    match (oauth, token) {
+        (Some(oauth), Some(rejected)) if failure.unauthorized => {
+            warn!(
+                "MCP server {} rejected its access token, renewing it",
+                config.name
+            );
+            let token = oauth.renew(&rejected).await?;
+            Ok(open(config, events, host, Some(&token)).await?)
+        }
+        _ => Err(failure.error),
+    }
+}
+
+/// Connect over a server's transport, sending `token` to remote servers
+async fn open(
+    config: &McpServerConfig,
+    events: &Arc<SessionEvents>,
+    host: &Arc<HostRequests>,
+    token: Option<&str>,
+) -> std::result::Result<Service, ConnectFailure> {
+    let handler = || {
+        Handler::new(
+            client_info(host.capabilities()),
+            events.clone(),
+            host.clone(),
+        )
+    };
+    match &config.source {
+        McpServerSource::Http { url, headers, .. } => {
+            let transport = StreamableHttpClientTransport::with_client(
+                http_client(headers.as_ref(), token)?,
+                StreamableHttpClientTransportConfig::with_uri(url.as_str()),
+            );
+            handler()
+                .serve(transport)
+                .await
+                .map_err(|e| initialize_failure(config, e))
+        }
+        McpServerSource::Sse { url, headers, .. } => {
+            let transport = SseClientTransport::start_with_client(
+                http_client(headers.as_ref(), token)?,
+                SseClientConfig {
+                    sse_endpoint: url.as_str().into(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| ConnectFailure {
+                unauthorized: sse_unauthorized(&e),
+                error: McpError::new(format!("SSE connection error: {}", e)),
+            })?;
+            handler()
+                .serve(transport)
+                .await
+                .map_err(|e| initialize_failure(config, e))
+        }
+        McpServerSource::Process {
+            command,
+            args,
+            work_dir,
+            env,
+        } => {
+            let cmd_str = if args.is_empty() {
+                command.clone()
+            } else {
+                format!("{} {}", command, args.join(" "))
+            };
+            let (env_vars, cmd_executable, cmd_args) = disect_command(cmd_str);
+            let (program, args, envs) = CommandWrappedInShellBuilder::wrap_in_shell_as_values(
+                cmd_executable,
+                Some(cmd_args),
+                Some(env_vars),
+            );
+            let mut cmd = Command::new(program);
+            cmd.kill_on_drop(true);
+            cmd.envs(envs);
+            cmd.envs(env.clone().unwrap_or_default());
+            cmd.args(args);
+            if let Some(dir) = work_dir {
+                cmd.current_dir(dir);
+            }
+            let transport =
+                TokioChildProcess::new(cmd).map_err(|e| McpError::new(e.to_string()))?;
+            handler()
+                .serve(transport)
+                .await
+                .map_err(|e| connect_error(config, e).into())
+        }
+        McpServerSource::WebSocket {
+            url,
+            timeout_secs,
+            headers,
+            ping_interval_secs,
+        } => {
+            let options = WebSocketOptions {
+                headers: headers.clone().unwrap_or_default(),
+                bearer_token: token.map(str::to_string),
+                connect_timeout: timeout_secs.map(Duration::from_secs),
+                ping_interval: ping_interval_secs.map(Duration::from_secs),
+            };
+            let transport = websocket::connect(url, &options).await?;
+            handler()
+                .serve(transport)
+                .await
+                .map_err(|e| connect_error(config, e).into())
+        }
+    }
+}
+
+/// A failed initialization, noting whether the server answered `401`
+fn initialize_failure(config: &McpServerConfig, error: ClientInitializeError) -> ConnectFailure {
+    let unauthorized = match error {
+        ClientInitializeError::TransportError { ref error, .. } => {
+            if let Some(e) = error
+                .error
+                .downcast_ref::<StreamableHttpError<reqwest::Error>>()
+            {
+                match e {
+                    StreamableHttpError::AuthRequired(_) => true,
+                    StreamableHttpError::Client(e) => e.status() == Some(StatusCode::UNAUTHORIZED),
+                    _ => false,
+                }
+            } else {
+                error
+                    .error
+                    .downcast_ref::<SseTransportError<reqwest::Error>>()
+                    .is_some_and(sse_unauthorized)
+            }
+        }
+        _ => false,
+    };
+    ConnectFailure {
+        error: connect_error(config, error),
+        unauthorized,
+    }
+}
+
+fn sse_unauthorized(error: &SseTransportError<reqwest::Error>) -> bool {
+    matches!(error, SseTransportError::Client(e) if e.status() == Some(StatusCode::UNAUTHORIZED))
+}
+
+fn connect_error(config: &McpServerConfig, error: impl std::fmt::Display) -> McpError {
+    McpError::new(format!(
+        "Failed to connect to MCP server {}: {}",
+        config.name, error
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connect_failure() {
+        let config = McpServerConfig {
+            name: "missing".to_string(),
+            source: McpServerSource::Process {
+                command: "hanzo-mcp-client-test-missing-binary".to_string(),
+                args: vec![],
+                work_dir: None,
+                env: None,
+            },
+            ..Default::default()
+        };
+        let (events, _) = broadcast::channel(1);
+        let connected =
+            ServerSession::connect(config, events, None, &HostHandlers::default()).await;
+        assert!(connected.is_err());
+    }
+
+    /// Answers MCP requests only with the bearer token `access-2`
+    async fn mcp(
+        headers: axum::http::HeaderMap,
+        axum::Json(message): axum::Json<serde_json::Value>,
+    ) -> axum::response::Response {
+        use axum::response::IntoResponse;
+
+        if headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok()) != Some("Bearer access-2") {
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(reqwest::header::WWW_AUTHENTICATE, "Bearer")],
+            )
+                .into_response();
+        }
+        if message["method"] != "initialize" {
+            return StatusCode::ACCEPTED.into_response();
+        }
+        let result = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": message["id"],
+            "result": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "serverInfo": {"name": "mock", "version": "1.0.0"}
+            }
+        });
+        ([("mcp-session-id", "session-1")], axum::Json(result)).into_response()
+    }
+
+    #[tokio::test]
+    async fn test_rejected_token_is_refreshed() {
+        use crate::auth::{tests::serve_mock, MemoryTokenStore, TokenStore};
+        use crate::Credentials;
+
+        let (_, base) = serve_mock(axum::routing::post(mcp)).await;
+        let url = format!("{}/mcp", base);
+
+        // No expiry is known, so only the 401 shows the token is stale
+        let store = Arc::new(MemoryTokenStore::default());
+        let stale = Credentials {
+            client_id: "client-1".to_string(),
+            client_secret: None,
+            access_token: "access-1".to_string(),
+            refresh_token: Some("refresh-1".to_string()),
+            expires_at: None,
+        };
+        store.save(&url, &stale).await.unwrap();
+        let oauth = OAuthClient::new(url.clone(), Default::default(), store.clone());
+
+        let config = McpServerConfig {
+            name: "protected".to_string(),
+            source: McpServerSource::Http {
+                url: url.clone(),
+                timeout_secs: None,
+                headers: None,
+            },
+            ..Default::default()
+        };
+        let (events, _) = broadcast::channel(1);
+        let session = ServerSession::connect(
+            config,
+            events,
+            Some(Arc::new(oauth)),
+            &HostHandlers::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(session.status().state, ConnectionState::Connected);
+        let stored = store.load(&url).await.unwrap().unwrap();
+        assert_eq!(stored.access_token, "access-2");
+        session.close().await;
+    }
+}
@@ -0,0 +1,107 @@
+//! Resource subscriptions
+//!
+//! Subscriptions to the same URI on a server share one `resources/subscribe`,
+//! and all of them fail if it does; the server is sent
+//! `resources/unsubscribe` when the last of them ends.
+//! Sessions renew their subscriptions when they reconnect.
+
+use crate::error::McpError;
+use crate::session::ServerSession;
+use rmcp::model::{SubscribeRequestParam, UnsubscribeRequestParam};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+type Result<T> = std::result::Result<T, McpError>;
+
+/// Updates of a subscribed resource
+///
+/// Dropping the subscription ends it.
+pub struct ResourceSubscription {
+    uri: String,
+    id: u64,
+    session: Arc<ServerSession>,
+    updates: mpsc::UnboundedReceiver<String>,
+    active: bool,
+}
+
+impl ResourceSubscription {
+    /// Watch `uri` on a session, subscribing on the server if needed
+    pub(crate) async fn start(session: Arc<ServerSession>, uri: &str) -> Result<Self> {
+        let (id, updates, subscribed) = session.events().watch(uri);
+        // Concurrent subscriptions to `uri` wait for the first one's request
+        let outcome = subscribed
+            .get_or_init(|| async {
+                session
+                    .request(|peer| {
+                        let request = SubscribeRequestParam {
+                            uri: uri.to_string(),
+                        };
+                        async move { peer.subscribe(request).await }
+                    })
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+            .await;
+        if let Err(e) = outcome {
+            session.events().forget(uri, &subscribed);
+            return Err(McpError::new(e.clone()));
+        }
+        Ok(Self {
+            uri: uri.to_string(),
+            id,
+            session,
+            updates,
+            active: true,
+        })
+    }
+
+    /// The subscribed URI
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// URI of the next update: the subscribed resource or one under it
+    pub async fn next_update(&mut self) -> Option<String> {
+        self.updates.recv().await
+    }
+
+    /// End the subscription, waiting for the server to confirm if it was the
+    /// last one to this URI
+    pub async fn unsubscribe(mut self) -> Result<()> {
+        self.active = false;
+        if self.session.events().unwatch(&self.uri, self.id) {
+            unsubscribe(&self.session, &self.uri).await
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for ResourceSubscription {
+    fn drop(&mut self) {
+        if !self.active || !self.session.events().unwatch(&self.uri, self.id) {
+            return;
+        }
+        // Without a runtime the session is going away anyway
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let (session, uri) = (self.session.clone(), std::mem::take(&mut self.uri));
+            runtime.spawn(async move {
+                if let Err(e) = unsubscribe(&session, &uri).await {
+                    warn!("Failed to unsubscribe from {}: {}", uri, e);
+                }
+            });
+        }
+    }
+}
+
+async fn unsubscribe(session: &ServerSession, uri: &str) -> Result<()> {
+    session
+        .request(|peer| {
+            let request = UnsubscribeRequestParam {
+                uri: uri.to_string(),
+            };
+            async move { peer.unsubscribe(request).await }
+        })
+        .await
+}
@@ -0,0 +1,175 @@
+//! WebSocket transport for MCP
+//!
+//! Each JSON-RPC message travels as one text frame. A background task owns
+//! the socket: it forwards messages between the socket and the channels
+//! handed to `rmcp`, pings the server every interval, and drops the
+//! connection when nothing (not even a pong) arrives for two intervals.
+
+use crate::error::{ConnectFailure, McpError};
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use rmcp::model::{ClientJsonRpcMessage, ServerJsonRpcMessage};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::{
+    self,
+    client::IntoClientRequest,
+    http::{header::AUTHORIZATION, HeaderName, HeaderValue, StatusCode},
+    Message,
+};
+use tracing::{debug, warn};
+
+type Result<T> = std::result::Result<T, ConnectFailure>;
+
+/// Default time between keepalive pings
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default time to wait for the WebSocket handshake
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Messages buffered in each direction
+const CHANNEL_CAPACITY: usize = 64;
+
+/// How to open a WebSocket connection
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WebSocketOptions {
+    /// Extra handshake headers
+    pub headers: HashMap<String, String>,
+    /// Sent as `Authorization: Bearer <token>`
+    pub bearer_token: Option<String>,
+    /// Handshake timeout; 30 seconds if unset
+    pub connect_timeout: Option<Duration>,
+    /// Keepalive ping interval; 30 seconds if unset
+    pub ping_interval: Option<Duration>,
+}
+
+/// Client side of a WebSocket connection, usable as an `rmcp` transport
+pub(crate) type WebSocketTransport = (
+    mpsc::Sender<ClientJsonRpcMessage>,
+    mpsc::Receiver<ServerJsonRpcMessage>,
+);
+
+/// Open a WebSocket connection to an MCP server
+pub(crate) async fn connect(url: &str, options: &WebSocketOptions) -> Result<WebSocketTransport> {
+    let mut request = url
+        .into_client_request()
+        .map_err(|e| McpError::new(format!("Invalid WebSocket URL {}: {}", url, e)))?;
+    let headers = request.headers_mut();
+    for (name, value) in &options.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| McpError::new(format!("Invalid header name {}: {}", name, e)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| McpError::new(format!("Invalid value for header {}: {}", name, e)))?;
+        headers.insert(name, value);
+    }
+    if let Some(ref token) = options.bearer_token {
+        let value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|e| McpError::new(format!("Invalid bearer token: {}", e)))?;
+        headers.insert(AUTHORIZATION, value);
+    }
+
+    let timeout = options.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT);
+    let (socket, _) = tokio::time::timeout(timeout, tokio_tungstenite::connect_async(request))
+        .await
+        .map_err(|_| {
+            McpError::new(format!(
+                "WebSocket handshake with {} timed out after {} seconds",
+                url,
+                timeout.as_secs()
+            ))
+        })?
+        .map_err(|e| ConnectFailure {
+            unauthorized: matches!(
+                e,
+                tungstenite::Error::Http(ref response) if response.status() == StatusCode::UNAUTHORIZED
+            ),
+            error: McpError::new(format!("WebSocket connection error: {}", e)),
+        })?;
+    debug!("WebSocket connected to {}", url);
+
+    let (outgoing_tx, outgoing) = mpsc::channel(CHANNEL_CAPACITY);
+    let (incoming, incoming_rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let ping_interval = options.ping_interval.unwrap_or(DEFAULT_PING_INTERVAL);
+    tokio::spawn(pump(socket, outgoing, incoming, ping_interval));
+    Ok((outgoing_tx, incoming_rx))
+}
+
+/// Move messages between the socket and the transport channels until either
+/// side closes or the server stops answering
+async fn pump<S>(
+    socket: S,
+    mut outgoing: mpsc::Receiver<ClientJsonRpcMessage>,
+    mut incoming: mpsc::Sender<ServerJsonRpcMessage>,
+    ping_interval: Duration,
+) where
+    S: futures::Sink<Message, Error = tokio_tungstenite::tungstenite::Error>
+        + futures::Stream<Item = std::result::Result<Message, tokio_tungstenite::tungstenite::Error>>
+        + Unpin,
+{
+    let (mut sink, mut stream) = socket.split();
+    let mut ping =
+        tokio::time::interval_at(tokio::time::Instant::now() + ping_interval, ping_interval);
+    let mut last_seen = Instant::now();
+
+    loop {
+        tokio::select! {
+            message = outgoing.next() => {
+                let Some(message) = message else {
+                    // The client service shut down
+                    let _ = sink.send(Message::Close(None)).await;
+                    break;
+                };
+                let text = match serde_json::to_string(&message) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        warn!("Failed to encode MCP message: {}", e);
+                        continue;
+                    }
+                };
+                if let Err(e) = sink.send(Message::Text(text.into())).await {
+                    warn!("WebSocket send failed: {}", e);
+                    break;
+                }
+            }
+            frame = stream.next() => {
+                last_seen = Instant::now();
+                let text = match frame {
+                    Some(Ok(Message::Text(text))) => text.to_string(),
+                    Some(Ok(Message::Binary(data))) => String::from_utf8_lossy(&data).into_owned(),
+                    // Pings are answered by tungstenite itself
+                    Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => continue,
+                    Some(Ok(Message::Close(_))) | None => {
+                        debug!("WebSocket closed by server");
+                        break;
+                    }
+                    Some(Err(e)) => {
+                        warn!("WebSocket receive failed: {}", e);
+                        break;
+                    }
+                };
+                match serde_json::from_str::<ServerJsonRpcMessage>(&text) {
+                    Ok(message) => {
+                        if incoming.send(message).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => warn!("Ignoring invalid MCP message: {}", e),
+                }
+            }
+            _ = ping.tick() => {
+                if last_seen.elapsed() > ping_interval * 2 {
+                    warn!(
+                        "WebSocket silent for {} seconds, dropping connection",
+                        last_seen.elapsed().as_secs()
+                    );
+                    break;
+                }
+                if let Err(e) = sink.send(Message::Ping(Vec::new().into())).await {
+                    warn!("WebSocket ping failed: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+    // Dropping the channels tells the client service the transport closed
+}
@@ -130,6 +130,45 @@ pub struct McpServerConfig {
     /// for HTTP and WebSocket connections. Process connections typically
     /// don't require authentication tokens.
     pub bearer_token: Option<String>,
+    /// OAuth 2.1 authorization for remote servers
+    ///
+    /// When set, HTTP, SSE and WebSocket connections get their token from
+    /// the MCP authorization flow instead of `bearer_token`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth: Option<OAuthConfig>,
+    /// Whether the server may ask the host for LLM completions
+    pub sampling: ApprovalPolicy,
+    /// Whether the server may ask the user for input
+    pub elicitation: ApprovalPolicy,
+}
+
+/// How the client answers requests a server makes of the host
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalPolicy {
+    /// Refuse requests; the capability is not advertised
+    Deny,
+    /// Have the host's handler approve each request
+    #[default]
+    Ask,
+    /// Pass requests to the host's handler without asking
+    Allow,
+}
+
+/// OAuth settings for a server that requires authorization
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OAuthConfig {
+    /// Pre-registered client id; the client registers itself if unset
+    pub client_id: Option<String>,
+    /// Secret of a pre-registered confidential client
+    pub client_secret: Option<String>,
+    /// Name shown to the user when the client registers itself
+    pub client_name: Option<String>,
+    /// Scopes to request; defaults to those the server advertises
+    pub scopes: Vec<String>,
+    /// Loopback port for the redirect; any free port if unset
+    pub redirect_port: Option<u16>,
 }
 
 impl Default for McpServerConfig {
@@ -146,6 +185,9 @@ impl Default for McpServerConfig {
             tool_prefix: generate_uuid_prefix(),
             resources: None,
             bearer_token: None,
+            oauth: None,
+            sampling: ApprovalPolicy::default(),
+            elicitation: ApprovalPolicy::default(),
         }
     }
 }
@@ -209,6 +251,10 @@ pub enum McpServerSource {
         timeout_secs: Option<u64>,
         /// Optional headers for the WebSocket handshake
         headers: Option<HashMap<String, String>>,
+        /// Seconds between keepalive pings; the connection is dropped when
+        /// nothing arrives for two intervals. Defaults to 30.
+        #[serde(default)]
+        ping_interval_secs: Option<u64>,
     },
 }
 
@@ -221,6 +267,9 @@ pub struct McpToolInfo {
     pub description: Option<String>,
     /// JSON schema describing the tool's input parameters
     pub input_schema: serde_json::Value,
+    /// JSON schema the tool's structured output must match, if it declares one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<serde_json::Value>,
     /// ID of the server this tool comes from
     ///
     /// Used to route tool calls to the correct MCP server connection.
@@ -260,4 +309,50 @@ mod tests {
         assert!(json.contains("http"));
         assert!(json.contains("example.com"));
     }
+
+    #[test]
+    fn test_websocket_source_deserialization() {
+        let source: McpServerSource = serde_json::from_value(serde_json::json!({
+            "type": "websocket",
+            "url": "ws://localhost:3334",
+            "headers": {"X-Team": "core"}
+        }))
+        .unwrap();
+        match source {
+            McpServerSource::WebSocket {
+                url,
+                ping_interval_secs,
+                ..
+            } => {
+                assert_eq!(url, "ws://localhost:3334");
+                assert_eq!(ping_interval_secs, None);
+            }
+            other => panic!("unexpected source: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_oauth_config_deserialization() {
+        let server: McpServerConfig = serde_json::from_value(serde_json::json!({
+            "name": "linear",
+            "source": {"type": "http", "url": "https://mcp.linear.app/mcp"},
+            "oauth": {"scopes": ["read"]}
+        }))
+        .unwrap();
+        let oauth = server.oauth.unwrap();
+        assert_eq!(oauth.scopes, vec!["read"]);
+        assert!(oauth.client_id.is_none());
+    }
+
+    #[test]
+    fn test_approval_policy_deserialization() {
+        let server: McpServerConfig = serde_json::from_value(serde_json::json!({
+            "name": "writer",
+            "source": {"type": "http", "url": "http://localhost:3000/mcp"},
+            "sampling": "allow"
+        }))
+        .unwrap();
+        assert_eq!(server.sampling, ApprovalPolicy::Allow);
+        assert_eq!(server.elicitation, ApprovalPolicy::Ask);
+    }
 }
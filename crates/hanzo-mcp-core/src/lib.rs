@@ -39,6 +39,7 @@ mod config;
 mod error;
 mod traits;
 mod types;
+mod uri_template;
 
 pub use config::*;
 pub use error::*;
@@ -1,5 +1,6 @@
 //! Core types for MCP protocol
 
+use crate::{McpError, McpResult};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -50,6 +51,9 @@ pub struct ToolResult {
     /// Whether the tool execution errored
     #[serde(default)]
     pub is_error: bool,
+    /// Structured JSON output, for tools that declare an output schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<serde_json::Value>,
 }
 
 impl ToolResult {
@@ -60,6 +64,7 @@ impl ToolResult {
                 text: content.into(),
             }],
             is_error: false,
+            structured_content: None,
         }
     }
 
@@ -70,6 +75,7 @@ impl ToolResult {
                 text: message.into(),
             }],
             is_error: true,
+            structured_content: None,
         }
     }
 
@@ -78,6 +84,18 @@ impl ToolResult {
         Self {
             content: vec![ContentBlock::Image { data, mime_type }],
             is_error: false,
+            structured_content: None,
+        }
+    }
+
+    /// Create a structured result, with its JSON text as content
+    pub fn structured(value: serde_json::Value) -> Self {
+        Self {
+            content: vec![ContentBlock::Text {
+                text: value.to_string(),
+            }],
+            is_error: false,
+            structured_content: Some(value),
         }
     }
 }
@@ -90,8 +108,27 @@ pub enum ContentBlock {
     Text { text: String },
     /// Image content (base64 encoded)
     Image { data: String, mime_type: String },
-    /// Resource reference
-    Resource { uri: String, text: Option<String> },
+    /// Audio content (base64 encoded)
+    Audio { data: String, mime_type: String },
+    /// Embedded resource contents: `text`, or base64 `blob` for binary data
+    Resource {
+        uri: String,
+        text: Option<String>,
+        #[serde(default)]
+        blob: Option<String>,
+        #[serde(default)]
+        mime_type: Option<String>,
+    },
+    /// Link to a resource the client can read
+    #[serde(rename = "resource_link")]
+    ResourceLink {
+        uri: String,
+        name: String,
+        #[serde(default)]
+        description: Option<String>,
+        #[serde(default)]
+        mime_type: Option<String>,
+    },
 }
 
 impl ContentBlock {
@@ -117,6 +154,71 @@ pub struct ResourceDefinition {
     pub mime_type: Option<String>,
 }
 
+/// Resource template in MCP, for resources addressed by parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceTemplate {
+    /// RFC 6570 URI template, e.g. `tickets://{id}`
+    pub uri_template: String,
+    /// Human-readable name
+    pub name: String,
+    /// Description of the resources
+    pub description: Option<String>,
+    /// MIME type of the resources
+    pub mime_type: Option<String>,
+}
+
+impl ResourceTemplate {
+    /// Expand the template into a resource URI
+    pub fn expand(&self, variables: &HashMap<String, String>) -> McpResult<String> {
+        crate::uri_template::expand(&self.uri_template, variables)
+    }
+
+    /// Whether a URI could come from this template
+    pub fn matches(&self, uri: &str) -> bool {
+        crate::uri_template::matches(&self.uri_template, uri)
+    }
+}
+
+/// Contents of a resource as read: text, or base64 encoded binary data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ResourceContents {
+    Text {
+        uri: String,
+        mime_type: Option<String>,
+        text: String,
+    },
+    Blob {
+        uri: String,
+        mime_type: Option<String>,
+        blob: String,
+    },
+}
+
+impl ResourceContents {
+    pub fn uri(&self) -> &str {
+        match self {
+            ResourceContents::Text { uri, .. } | ResourceContents::Blob { uri, .. } => uri,
+        }
+    }
+
+    pub fn mime_type(&self) -> Option<&str> {
+        match self {
+            ResourceContents::Text { mime_type, .. } | ResourceContents::Blob { mime_type, .. } => {
+                mime_type.as_deref()
+            }
+        }
+    }
+
+    /// Get text content if this is text
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ResourceContents::Text { text, .. } => Some(text),
+            ResourceContents::Blob { .. } => None,
+        }
+    }
+}
+
 /// Prompt definition in MCP
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PromptDefinition {
@@ -141,6 +243,165 @@ pub struct PromptArgument {
     pub required: bool,
 }
 
+impl PromptDefinition {
+    /// Check arguments for `prompts/get`: every required argument must be
+    /// present and no unknown arguments may be passed
+    pub fn validate_arguments(&self, args: &HashMap<String, String>) -> McpResult<()> {
+        let missing: Vec<&str> = self
+            .arguments
+            .iter()
+            .filter(|a| a.required && !args.contains_key(&a.name))
+            .map(|a| a.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            return Err(McpError::InvalidParameters(format!(
+                "prompt {} is missing required arguments: {}",
+                self.name,
+                missing.join(", ")
+            )));
+        }
+        let mut unknown: Vec<&str> = args
+            .keys()
+            .filter(|k| !self.arguments.iter().any(|a| &a.name == *k))
+            .map(String::as_str)
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort_unstable();
+            return Err(McpError::InvalidParameters(format!(
+                "prompt {} has no arguments named: {}",
+                self.name,
+                unknown.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Speaker of a prompt message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptRole {
+    User,
+    Assistant,
+}
+
+/// Message of a prompt returned by `prompts/get`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptMessage {
+    pub role: PromptRole,
+    pub content: ContentBlock,
+}
+
+/// Prompt filled in with its arguments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptResult {
+    /// Description of the prompt, if the server gave one
+    pub description: Option<String>,
+    /// Messages to send to the model, in order
+    pub messages: Vec<PromptMessage>,
+}
+
+/// Model choice hints sent with a sampling request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelPreferences {
+    /// Model names or name fragments, most preferred first
+    pub hints: Vec<String>,
+    /// Priorities from 0 to 1
+    pub cost_priority: Option<f64>,
+    pub speed_priority: Option<f64>,
+    pub intelligence_priority: Option<f64>,
+}
+
+/// LLM completion a server asks the host for (`sampling/createMessage`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingRequest {
+    pub messages: Vec<PromptMessage>,
+    pub system_prompt: Option<String>,
+    pub model_preferences: Option<ModelPreferences>,
+    /// Context to add from MCP servers: `none`, `thisServer` or `allServers`
+    pub include_context: Option<String>,
+    pub temperature: Option<f64>,
+    pub max_tokens: u32,
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    /// Provider-specific parameters
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// Completion returned to the server for a sampling request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingResponse {
+    /// Generated text, image or audio
+    pub content: ContentBlock,
+    /// Name of the model that produced it
+    pub model: String,
+    /// Why generation stopped, e.g. `endTurn`, `stopSequence` or `maxTokens`
+    pub stop_reason: Option<String>,
+}
+
+impl SamplingResponse {
+    /// Text completion that ended its turn
+    pub fn text(model: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            content: ContentBlock::Text { text: text.into() },
+            model: model.into(),
+            stop_reason: Some("endTurn".to_string()),
+        }
+    }
+}
+
+/// Input a server asks the user for (`elicitation/create`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElicitationRequest {
+    /// What to show the user
+    pub message: String,
+    /// JSON schema of a flat object with primitive properties
+    pub requested_schema: serde_json::Value,
+}
+
+/// What the user did with an elicitation request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ElicitationAction {
+    /// Submitted the requested input
+    Accept,
+    /// Explicitly refused
+    Decline,
+    /// Dismissed without choosing
+    Cancel,
+}
+
+/// The user's answer to an elicitation request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElicitationResponse {
+    pub action: ElicitationAction,
+    /// The input, matching the requested schema; only with `accept`
+    pub content: Option<serde_json::Value>,
+}
+
+impl ElicitationResponse {
+    pub fn accept(content: serde_json::Value) -> Self {
+        Self {
+            action: ElicitationAction::Accept,
+            content: Some(content),
+        }
+    }
+
+    pub fn decline() -> Self {
+        Self {
+            action: ElicitationAction::Decline,
+            content: None,
+        }
+    }
+
+    pub fn cancel() -> Self {
+        Self {
+            action: ElicitationAction::Cancel,
+            content: None,
+        }
+    }
+}
+
 /// Server capabilities advertised during initialization
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ServerCapabilities {
@@ -242,3 +503,68 @@ pub enum McpTransport {
         env: HashMap<String, String>,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_argument_validation() {
+        let prompt = PromptDefinition {
+            name: "review".to_string(),
+            description: None,
+            arguments: vec![
+                PromptArgument {
+                    name: "code".to_string(),
+                    description: None,
+                    required: true,
+                },
+                PromptArgument {
+                    name: "style".to_string(),
+                    description: None,
+                    required: false,
+                },
+            ],
+        };
+        let args = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+
+        assert!(prompt
+            .validate_arguments(&args(&[("code", "fn main() {}")]))
+            .is_ok());
+        let missing = prompt.validate_arguments(&args(&[("style", "terse")]));
+        assert!(missing.unwrap_err().to_string().contains("code"));
+        let unknown = prompt.validate_arguments(&args(&[("code", "x"), ("lang", "rust")]));
+        assert!(unknown.unwrap_err().to_string().contains("lang"));
+    }
+
+    #[test]
+    fn test_content_block_serialization() {
+        let link = ContentBlock::ResourceLink {
+            uri: "file:///shot.png".to_string(),
+            name: "shot.png".to_string(),
+            description: None,
+            mime_type: Some("image/png".to_string()),
+        };
+        let json = serde_json::to_value(&link).unwrap();
+        assert_eq!(json["type"], "resource_link");
+
+        let audio: ContentBlock = serde_json::from_value(
+            serde_json::json!({"type": "audio", "data": "AA==", "mime_type": "audio/wav"}),
+        )
+        .unwrap();
+        assert!(matches!(audio, ContentBlock::Audio { .. }));
+        let resource: ContentBlock = serde_json::from_value(
+            serde_json::json!({"type": "resource", "uri": "mem://a", "text": "hi"}),
+        )
+        .unwrap();
+        assert!(matches!(
+            resource,
+            ContentBlock::Resource { blob: None, .. }
+        ));
+    }
+}
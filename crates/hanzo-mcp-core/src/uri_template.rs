@@ -0,0 +1,221 @@
+//! URI templates (RFC 6570, up to level 3 plus prefixes) for resource templates
+//!
+//! Variables are strings, so explode modifiers (`{var*}`) expand like plain
+//! variables. Undefined variables are left out, as the RFC specifies.
+
+use crate::{McpError, McpResult};
+use std::collections::HashMap;
+
+enum Part<'a> {
+    Literal(&'a str),
+    Expression {
+        operator: Option<char>,
+        variables: Vec<(&'a str, Option<usize>)>,
+    },
+}
+
+/// How an operator expands: prefix, separator, named, value for empty,
+/// whether reserved characters pass through
+fn rules(operator: Option<char>) -> (&'static str, char, bool, &'static str, bool) {
+    match operator {
+        Some('+') => ("", ',', false, "", true),
+        Some('#') => ("#", ',', false, "", true),
+        Some('.') => (".", '.', false, "", false),
+        Some('/') => ("/", '/', false, "", false),
+        Some(';') => (";", ';', true, "", false),
+        Some('?') => ("?", '&', true, "=", false),
+        Some('&') => ("&", '&', true, "=", false),
+        _ => ("", ',', false, "", false),
+    }
+}
+
+fn parse(template: &str) -> McpResult<Vec<Part<'_>>> {
+    let invalid = |reason: &str| {
+        McpError::InvalidParameters(format!("Invalid URI template {}: {}", template, reason))
+    };
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while !rest.is_empty() {
+        let Some(open) = rest.find('{') else {
+            if rest.contains('}') {
+                return Err(invalid("unmatched '}'"));
+            }
+            parts.push(Part::Literal(rest));
+            break;
+        };
+        if open > 0 {
+            if rest[..open].contains('}') {
+                return Err(invalid("unmatched '}'"));
+            }
+            parts.push(Part::Literal(&rest[..open]));
+        }
+        let close = rest[open..]
+            .find('}')
+            .map(|i| open + i)
+            .ok_or_else(|| invalid("unclosed '{'"))?;
+        let mut expression = &rest[open + 1..close];
+        let operator = expression.chars().next().filter(|c| "+#./;?&".contains(*c));
+        if operator.is_some() {
+            expression = &expression[1..];
+        }
+        let mut variables = Vec::new();
+        for spec in expression.split(',') {
+            let spec = spec.trim_end_matches('*');
+            let (name, prefix) = match spec.split_once(':') {
+                Some((name, length)) => (
+                    name,
+                    Some(length.parse().map_err(|_| invalid("bad prefix length"))?),
+                ),
+                None => (spec, None),
+            };
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '%');
+            if !valid {
+                return Err(invalid("bad variable name"));
+            }
+            variables.push((name, prefix));
+        }
+        parts.push(Part::Expression {
+            operator,
+            variables,
+        });
+        rest = &rest[close + 1..];
+    }
+    Ok(parts)
+}
+
+fn encode(value: &str, allow_reserved: bool, out: &mut String) {
+    let bytes = value.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        let keep = b.is_ascii_alphanumeric()
+            || b"-._~".contains(&b)
+            || (allow_reserved && b":/?#[]@!$&'()*+,;=".contains(&b));
+        let escaped = allow_reserved
+            && b == b'%'
+            && bytes
+                .get(i + 1..i + 3)
+                .is_some_and(|h| h.iter().all(u8::is_ascii_hexdigit));
+        if keep || escaped {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+}
+
+/// Expand a URI template with string variables
+pub(crate) fn expand(template: &str, variables: &HashMap<String, String>) -> McpResult<String> {
+    let mut uri = String::with_capacity(template.len());
+    for part in parse(template)? {
+        match part {
+            Part::Literal(literal) => uri.push_str(literal),
+            Part::Expression {
+                operator,
+                variables: specs,
+            } => {
+                let (first, separator, named, if_empty, allow_reserved) = rules(operator);
+                let mut expanded = 0;
+                for (name, prefix) in specs {
+                    let Some(value) = variables.get(name) else {
+                        continue;
+                    };
+                    if expanded == 0 {
+                        uri.push_str(first);
+                    } else {
+                        uri.push(separator);
+                    }
+                    expanded += 1;
+                    if named {
+                        uri.push_str(name);
+                        if value.is_empty() {
+                            uri.push_str(if_empty);
+                            continue;
+                        }
+                        uri.push('=');
+                    }
+                    let value = match prefix {
+                        Some(length) => value.chars().take(length).collect(),
+                        None => value.clone(),
+                    };
+                    encode(&value, allow_reserved, &mut uri);
+                }
+            }
+        }
+    }
+    Ok(uri)
+}
+
+/// Whether a URI could have been expanded from a template
+///
+/// Literal text must match exactly; a plain `{var}` can't span a `/`.
+pub(crate) fn matches(template: &str, uri: &str) -> bool {
+    fn matches_parts(parts: &[Part<'_>], uri: &str) -> bool {
+        match parts.split_first() {
+            None => uri.is_empty(),
+            Some((Part::Literal(literal), rest)) => uri
+                .strip_prefix(literal)
+                .is_some_and(|uri| matches_parts(rest, uri)),
+            Some((Part::Expression { operator, .. }, rest)) => {
+                let spans_slash = matches!(operator, Some('+' | '#' | '/'));
+                for (i, c) in uri.char_indices() {
+                    if matches_parts(rest, &uri[i..]) {
+                        return true;
+                    }
+                    if c == '/' && !spans_slash {
+                        return false;
+                    }
+                }
+                matches_parts(rest, "")
+            }
+        }
+    }
+    parse(template).is_ok_and(|parts| matches_parts(&parts, uri))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand() {
+        let v = vars(&[
+            ("path", "src/main.rs"),
+            ("id", "HAN-42"),
+            ("q", "a b"),
+            ("empty", ""),
+        ]);
+        let cases = [
+            ("file:///{+path}", "file:///src/main.rs"),
+            ("file:///{path}", "file:///src%2Fmain.rs"),
+            ("tickets://{id}", "tickets://HAN-42"),
+            ("search{?q,empty,missing}", "search?q=a%20b&empty="),
+            ("repo{/id,missing}", "repo/HAN-42"),
+            ("x{;id:3}", "x;id=HAN"),
+            ("doc{#path}", "doc#src/main.rs"),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(expand(template, &v).unwrap(), expected, "{}", template);
+        }
+        assert!(expand("file:///{path", &v).is_err());
+        assert!(expand("file:///{pa th}", &v).is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("tickets://{id}", "tickets://HAN-42"));
+        assert!(!matches("tickets://{id}", "tickets://HAN/42"));
+        assert!(matches("file:///{+path}", "file:///src/main.rs"));
+        assert!(matches("db://{table}/rows/{id}", "db://users/rows/7"));
+        assert!(!matches("db://{table}/rows/{id}", "db://users/cols/7"));
+        assert!(!matches("tickets://{id}", "file:///HAN-42"));
+    }
+}
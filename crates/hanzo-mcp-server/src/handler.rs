@@ -0,0 +1,277 @@
+//! MCP method dispatch
+//!
+//! Shared by every transport. A session must complete `initialize`, where the
+//! protocol version is negotiated and capabilities exchanged, before it can
+//! call anything but `ping`.
+
+use crate::protocol::{
+    negotiate_version, InitializeParams, InitializeResult, MCPError, MCPRequest, MCPResponse,
+    ServerCapabilities, ServerInfo, INTERNAL_ERROR, INVALID_PARAMS, INVALID_REQUEST,
+    METHOD_NOT_FOUND, PARSE_ERROR,
+};
+use crate::session::Session;
+use crate::ToolRegistry;
+use futures::future::join_all;
+use log::{debug, error, info};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+type MethodResult = std::result::Result<Value, MCPError>;
+
+pub(crate) struct Handler {
+    tools: Arc<RwLock<ToolRegistry>>,
+}
+
+impl Handler {
+    pub fn new(tools: Arc<RwLock<ToolRegistry>>) -> Self {
+        Self { tools }
+    }
+
+    /// Handle one serialized message or batch; `None` if nothing needs sending back
+    pub async fn handle_text(&self, session: &Session, text: &str) -> Option<String> {
+        let response = match serde_json::from_str(text) {
+            Ok(message) => self.handle(session, message).await?,
+            Err(e) => json!(MCPResponse::error(
+                None,
+                MCPError::new(PARSE_ERROR, format!("Parse error: {}", e))
+            )),
+        };
+        Some(response.to_string())
+    }
+
+    /// Handle a message or batch; `None` if it held no requests
+    pub async fn handle(&self, session: &Session, message: Value) -> Option<Value> {
+        match message {
+            Value::Array(batch) if batch.is_empty() => Some(json!(MCPResponse::error(
+                None,
+                MCPError::new(INVALID_REQUEST, "Empty batch")
+            ))),
+            Value::Array(batch) => {
+                let responses: Vec<MCPResponse> = join_all(
+                    batch
+                        .into_iter()
+                        .map(|message| self.handle_one(session, message)),
+                )
+                .await
+                .into_iter()
+                .flatten()
+                .collect();
+                (!responses.is_empty()).then(|| json!(responses))
+            }
+            message => self.handle_one(session, message).await.map(|r| json!(r)),
+        }
+    }
+
+    async fn handle_one(&self, session: &Session, message: Value) -> Option<MCPResponse> {
+        // Responses to server requests need no answer
+        if is_response(&message) {
+            return None;
+        }
+        let request: MCPRequest = match serde_json::from_value(message) {
+            Ok(request) => request,
+            Err(e) => {
+                return Some(MCPResponse::error(
+                    None,
+                    MCPError::new(INVALID_REQUEST, e.to_string()),
+                ))
+            }
+        };
+        let Some(id) = request.id.clone() else {
+            self.notification(session, &request.method);
+            return None;
+        };
+        let result = self
+            .call(
+                session,
+                &request.method,
+                request.params.unwrap_or(Value::Null),
+            )
+            .await;
+        Some(match result {
+            Ok(result) => MCPResponse::success(Some(id), result),
+            Err(error) => MCPResponse::error(Some(id), error),
+        })
+    }
+
+    fn notification(&self, session: &Session, method: &str) {
+        match method {
+            "notifications/initialized" => debug!("Session {} initialized", session.id()),
+            "notifications/cancelled" => {}
+            other => debug!("Ignoring notification {}", other),
+        }
+    }
+
+    async fn call(&self, session: &Session, method: &str, params: Value) -> MethodResult {
+        match method {
+            "initialize" => return initialize(session, params),
+            "ping" => return Ok(json!({})),
+            _ if session.protocol_version().is_none() => {
+                return Err(MCPError::new(INVALID_REQUEST, "Session is not initialized"))
+            }
+            _ => {}
+        }
+        match method {
+            "tools/list" => Ok(self.list_tools().await),
+            "tools/call" => self.call_tool(params).await,
+            "resources/list" => Ok(json!({ "resources": [] })),
+            "prompts/list" => Ok(json!({ "prompts": [] })),
+            other => Err(MCPError::new(
+                METHOD_NOT_FOUND,
+                format!("Method not found: {}", other),
+            )),
+        }
+    }
+
+    async fn list_tools(&self) -> Value {
+        let tools = self.tools.read().await;
+        let mut tool_list = Vec::new();
+
+        for name in tools.list() {
+            if let Some(tool) = tools.get(&name) {
+                tool_list.push(json!({
+                    "name": tool.name(),
+                    "description": tool.description(),
+                    "inputSchema": tool.parameters()
+                }));
+            }
+        }
+
+        json!({ "tools": tool_list })
+    }
+
+    async fn call_tool(&self, params: Value) -> MethodResult {
+        let tool_name = params["name"]
+            .as_str()
+            .ok_or_else(|| MCPError::new(INVALID_PARAMS, "Missing tool name"))?;
+
+        let tool_params = params.get("arguments").cloned().unwrap_or(json!({}));
+
+        let tools = self.tools.read().await;
+        let tool = tools
+            .get(tool_name)
+            .ok_or_else(|| MCPError::new(INVALID_PARAMS, format!("Unknown tool: {}", tool_name)))?;
+
+        match tool.execute(tool_params).await {
+            Ok(result) => Ok(json!({
+                "content": [{
+                    "type": "text",
+                    "text": serde_json::to_string(&result.content).unwrap_or_default()
+                }]
+            })),
+            Err(e) => {
+                error!("Tool execution failed: {}", e);
+                Ok(json!({
+                    "content": [{
+                        "type": "text",
+                        "text": format!("Error: {}", e)
+                    }],
+                    "isError": true
+                }))
+            }
+        }
+    }
+}
+
+/// Negotiate the protocol version and advertise the server's capabilities
+fn initialize(session: &Session, params: Value) -> MethodResult {
+    let params: InitializeParams = serde_json::from_value(params)
+        .map_err(|e| MCPError::new(INVALID_PARAMS, format!("Invalid initialize params: {}", e)))?;
+    let version = negotiate_version(&params.protocol_version);
+    if !session.initialize(version) {
+        return Err(MCPError::new(
+            INVALID_REQUEST,
+            "Session is already initialized",
+        ));
+    }
+    info!(
+        "Client {} {} initialized with protocol {} (requested {})",
+        params.client_info.name, params.client_info.version, version, params.protocol_version
+    );
+
+    let result = InitializeResult {
+        protocol_version: version.to_string(),
+        capabilities: ServerCapabilities {
+            tools: Some(json!({ "listChanged": false })),
+            resources: Some(json!({})),
+            prompts: Some(json!({})),
+        },
+        server_info: ServerInfo {
+            name: "hanzo-mcp".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        },
+        instructions: None,
+    };
+    serde_json::to_value(result).map_err(|e| MCPError::new(INTERNAL_ERROR, e.to_string()))
+}
+
+fn is_response(message: &Value) -> bool {
+    message.get("method").is_none()
+        && (message.get("result").is_some() || message.get("error").is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+    }
+
+    fn initialize_request(version: &str) -> Value {
+        request(
+            1,
+            "initialize",
+            json!({
+                "protocolVersion": version,
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "1.0"}
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_initialize_negotiation() {
+        let handler = Handler::new(Arc::new(RwLock::new(ToolRegistry::new())));
+        let session = Session::new();
+
+        let early = handler
+            .handle(&session, request(0, "tools/list", json!({})))
+            .await
+            .unwrap();
+        assert_eq!(early["error"]["code"], INVALID_REQUEST);
+        let pong = handler
+            .handle(&session, request(0, "ping", Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(pong["result"], json!({}));
+
+        let response = handler
+            .handle(&session, initialize_request("2025-03-26"))
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["protocolVersion"], "2025-03-26");
+        assert!(response["result"]["capabilities"]["tools"].is_object());
+        assert_eq!(session.protocol_version(), Some("2025-03-26"));
+
+        let again = handler
+            .handle(&session, initialize_request("2025-06-18"))
+            .await
+            .unwrap();
+        assert_eq!(again["error"]["code"], INVALID_REQUEST);
+
+        let initialized = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+        assert!(handler.handle(&session, initialized).await.is_none());
+
+        let batch = json!([
+            request(2, "tools/list", json!({})),
+            request(3, "unknown/method", json!({}))
+        ]);
+        let responses = handler.handle(&session, batch).await.unwrap();
+        assert_eq!(responses[0]["result"]["tools"], json!([]));
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+
+        let garbage = handler.handle_text(&session, "{not json").await.unwrap();
+        assert!(garbage.contains(&PARSE_ERROR.to_string()));
+    }
+}
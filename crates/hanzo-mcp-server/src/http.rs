@@ -0,0 +1,498 @@
+//! HTTP transports
+//!
+//! Streamable HTTP is served at `/mcp` (and `/`): clients POST each message
+//! and get the response as JSON, or as a one-event SSE stream if they only
+//! accept `text/event-stream`. `initialize` opens a session whose id comes
+//! back in the `Mcp-Session-Id` header; later requests must send it, and
+//! DELETE ends it. The server sends nothing unprompted, so GET is refused.
+//!
+//! The legacy HTTP+SSE transport (protocol 2024-11-05) is served at `/sse`:
+//! the stream's first `endpoint` event names the URL to POST messages to,
+//! and responses arrive on the stream as `message` events.
+//!
+//! When bound to a loopback address, requests from non-local browser origins
+//! are refused to guard against DNS rebinding.
+
+use crate::handler::Handler;
+use crate::protocol::{
+    MCPError, MCPResponse, INVALID_REQUEST, PARSE_ERROR, SUPPORTED_PROTOCOL_VERSIONS,
+};
+use crate::session::{Session, Sessions};
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::header::{ACCEPT, ORIGIN};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::StreamExt;
+use log::info;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Semaphore};
+
+const MCP_SESSION_ID: &str = "mcp-session-id";
+const MCP_PROTOCOL_VERSION: &str = "mcp-protocol-version";
+
+/// Responses buffered per legacy SSE stream before handlers wait
+const OUTGOING_CAPACITY: usize = 64;
+
+pub(crate) struct HttpState {
+    handler: Arc<Handler>,
+    connections: Arc<Semaphore>,
+    /// Streamable HTTP sessions
+    sessions: Sessions,
+    /// Legacy SSE sessions, with the stream their responses go to
+    streams: Mutex<HashMap<String, (Arc<Session>, mpsc::Sender<String>)>>,
+    /// Refuse non-local origins
+    local_only: bool,
+}
+
+impl HttpState {
+    /// `host` is the address the server is bound to
+    pub fn new(handler: Arc<Handler>, connections: Arc<Semaphore>, host: &str) -> Self {
+        let local_only = host == "localhost"
+            || host
+                .parse::<IpAddr>()
+                .is_ok_and(|address| address.is_loopback());
+        Self {
+            handler,
+            connections,
+            sessions: Sessions::default(),
+            streams: Mutex::new(HashMap::new()),
+            local_only,
+        }
+    }
+}
+
+/// Serve both HTTP transports until the listener fails
+pub(crate) async fn serve(listener: TcpListener, state: Arc<HttpState>) -> Result<()> {
+    let address = listener.local_addr()?;
+    info!("MCP Streamable HTTP on http://{}/mcp", address);
+    info!("MCP legacy SSE on http://{}/sse", address);
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+fn router(state: Arc<HttpState>) -> Router {
+    Router::new()
+        .route("/", post(post_message).delete(delete_session))
+        .route("/mcp", post(post_message).delete(delete_session))
+        .route("/sse", get(open_stream))
+        .route("/message", post(post_stream_message))
+        .with_state(state)
+}
+
+/// JSON-RPC error response without an id, for requests refused before dispatch
+fn rejection(status: StatusCode, code: i64, message: &str) -> Response {
+    let error = MCPResponse::error(None, MCPError::new(code, message));
+    (status, Json(error)).into_response()
+}
+
+/// Whether a request may be served: browser requests from other sites are
+/// refused when serving locally
+fn allowed_origin(state: &HttpState, headers: &HeaderMap) -> bool {
+    match headers.get(ORIGIN) {
+        Some(origin) => !state.local_only || origin.to_str().is_ok_and(is_local_origin),
+        None => true,
+    }
+}
+
+fn forbidden_origin() -> Response {
+    rejection(StatusCode::FORBIDDEN, INVALID_REQUEST, "Origin not allowed")
+}
+
+fn is_local_origin(origin: &str) -> bool {
+    let Some((_, authority)) = origin.split_once("://") else {
+        return false;
+    };
+    let host = match authority.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    host == "localhost"
+        || host
+            .parse::<IpAddr>()
+            .is_ok_and(|address| address.is_loopback())
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Handle one Streamable HTTP message
+async fn post_message(
+    State(state): State<Arc<HttpState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if !allowed_origin(&state, &headers) {
+        return forbidden_origin();
+    }
+    if let Some(version) = header(&headers, MCP_PROTOCOL_VERSION) {
+        if !SUPPORTED_PROTOCOL_VERSIONS.contains(&version) {
+            return rejection(
+                StatusCode::BAD_REQUEST,
+                INVALID_REQUEST,
+                &format!("Unsupported protocol version: {}", version),
+            );
+        }
+    }
+    let message: Value = match serde_json::from_slice(&body) {
+        Ok(message) => message,
+        Err(e) => {
+            return rejection(
+                StatusCode::BAD_REQUEST,
+                PARSE_ERROR,
+                &format!("Parse error: {}", e),
+            )
+        }
+    };
+
+    let session = match header(&headers, MCP_SESSION_ID) {
+        Some(id) => match state.sessions.get(id) {
+            Some(session) => session,
+            None => return rejection(StatusCode::NOT_FOUND, INVALID_REQUEST, "Session not found"),
+        },
+        None if message["method"] == "initialize" => {
+            match state.sessions.open(&state.connections) {
+                Some(session) => session,
+                None => {
+                    return rejection(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        INVALID_REQUEST,
+                        "Too many connections",
+                    )
+                }
+            }
+        }
+        None => {
+            return rejection(
+                StatusCode::BAD_REQUEST,
+                INVALID_REQUEST,
+                "Missing Mcp-Session-Id header",
+            )
+        }
+    };
+
+    let response = state.handler.handle(&session, message).await;
+    // A failed initialize leaves no session behind
+    if session.protocol_version().is_none() {
+        state.sessions.close(session.id());
+    }
+    let Some(response) = response else {
+        return StatusCode::ACCEPTED.into_response();
+    };
+
+    let accept = header(&headers, ACCEPT.as_str()).unwrap_or_default();
+    let mut response =
+        if accept.contains("text/event-stream") && !accept.contains("application/json") {
+            let event = Event::default().event("message").data(response.to_string());
+            Sse::new(futures::stream::once(
+                async move { Ok::<_, Infallible>(event) },
+            ))
+            .into_response()
+        } else {
+            Json(response).into_response()
+        };
+    if session.protocol_version().is_some() {
+        if let Ok(id) = HeaderValue::from_str(session.id()) {
+            response.headers_mut().insert(MCP_SESSION_ID, id);
+        }
+    }
+    response
+}
+
+/// End a Streamable HTTP session
+async fn delete_session(State(state): State<Arc<HttpState>>, headers: HeaderMap) -> Response {
+    if !allowed_origin(&state, &headers) {
+        return forbidden_origin();
+    }
+    match header(&headers, MCP_SESSION_ID) {
+        Some(id) if state.sessions.close(id) => StatusCode::NO_CONTENT.into_response(),
+        Some(_) => rejection(StatusCode::NOT_FOUND, INVALID_REQUEST, "Session not found"),
+        None => rejection(
+            StatusCode::BAD_REQUEST,
+            INVALID_REQUEST,
+            "Missing Mcp-Session-Id header",
+        ),
+    }
+}
+
+/// Removes a legacy SSE session when its stream goes away
+struct StreamGuard {
+    state: Arc<HttpState>,
+    id: String,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.state.streams.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Open a legacy SSE stream, which is one session
+async fn open_stream(State(state): State<Arc<HttpState>>, headers: HeaderMap) -> Response {
+    if !allowed_origin(&state, &headers) {
+        return forbidden_origin();
+    }
+    let Some(session) = Session::open(&state.connections) else {
+        return rejection(
+            StatusCode::SERVICE_UNAVAILABLE,
+            INVALID_REQUEST,
+            "Too many connections",
+        );
+    };
+    let id = session.id().to_string();
+    let (sender, receiver) = mpsc::channel(OUTGOING_CAPACITY);
+    state
+        .streams
+        .lock()
+        .unwrap()
+        .insert(id.clone(), (Arc::new(session), sender));
+
+    let endpoint = Event::default()
+        .event("endpoint")
+        .data(format!("/message?sessionId={}", id));
+    let guard = StreamGuard { state, id };
+    let messages = futures::stream::unfold((receiver, guard), |(mut receiver, guard)| async move {
+        let message = receiver.recv().await?;
+        let event = Event::default().event("message").data(message);
+        Some((Ok(event), (receiver, guard)))
+    });
+    let events =
+        futures::stream::once(async move { Ok::<_, Infallible>(endpoint) }).chain(messages);
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+}
+
+/// Take a message for a legacy SSE session; its response goes to the stream
+///
+/// Waits to accept it while the session is handling as many messages as it
+/// may at once.
+async fn post_stream_message(
+    State(state): State<Arc<HttpState>>,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if !allowed_origin(&state, &headers) {
+        return forbidden_origin();
+    }
+    let stream = state
+        .streams
+        .lock()
+        .unwrap()
+        .get(&query.session_id)
+        .cloned();
+    let Some((session, sender)) = stream else {
+        return rejection(StatusCode::NOT_FOUND, INVALID_REQUEST, "Session not found");
+    };
+    let slot = session.handling().await;
+    let handler = state.handler.clone();
+    tokio::spawn(async move {
+        let message = String::from_utf8_lossy(&body);
+        if let Some(response) = handler.handle_text(&session, &message).await {
+            let _ = sender.send(response).await;
+        }
+        drop(slot);
+    });
+    StatusCode::ACCEPTED.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ToolRegistry;
+    use serde_json::json;
+    use tokio::sync::RwLock;
+
+    async fn start(max_connections: usize) -> String {
+        let handler = Arc::new(Handler::new(Arc::new(RwLock::new(ToolRegistry::new()))));
+        let connections = Arc::new(Semaphore::new(max_connections));
+        let state = Arc::new(HttpState::new(handler, connections, "127.0.0.1"));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve(listener, state));
+        url
+    }
+
+    fn initialize() -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-06-18",
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "1.0"}
+            }
+        })
+    }
+
+    fn tools_list() -> Value {
+        json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"})
+    }
+
+    #[tokio::test]
+    async fn test_streamable_http_sessions() {
+        let url = format!("{}/mcp", start(10).await);
+        let http = reqwest::Client::new();
+
+        let response = http.post(&url).json(&initialize()).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let session_id = response.headers()[MCP_SESSION_ID]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["result"]["protocolVersion"], "2025-06-18");
+
+        let missing = http.post(&url).json(&tools_list()).send().await.unwrap();
+        assert_eq!(missing.status(), StatusCode::BAD_REQUEST);
+        let unknown = http
+            .post(&url)
+            .header(MCP_SESSION_ID, "nope")
+            .json(&tools_list())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+        let old_version = http
+            .post(&url)
+            .header(MCP_SESSION_ID, &session_id)
+            .header(MCP_PROTOCOL_VERSION, "2023-01-01")
+            .json(&tools_list())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(old_version.status(), StatusCode::BAD_REQUEST);
+        let foreign = http
+            .post(&url)
+            .header(ORIGIN, "https://evil.example")
+            .header(MCP_SESSION_ID, &session_id)
+            .json(&tools_list())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(foreign.status(), StatusCode::FORBIDDEN);
+
+        let notification = http
+            .post(&url)
+            .header(MCP_SESSION_ID, &session_id)
+            .json(&json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(notification.status(), StatusCode::ACCEPTED);
+
+        let listed = http
+            .post(&url)
+            .header(MCP_SESSION_ID, &session_id)
+            .header(MCP_PROTOCOL_VERSION, "2025-06-18")
+            .header(ORIGIN, "http://localhost:5173")
+            .json(&tools_list())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(listed.status(), StatusCode::OK);
+        let body: Value = listed.json().await.unwrap();
+        assert!(body["result"]["tools"].is_array());
+
+        assert_eq!(
+            http.get(&url).send().await.unwrap().status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        let deleted = http
+            .delete(&url)
+            .header(MCP_SESSION_ID, &session_id)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(deleted.status(), StatusCode::NO_CONTENT);
+        let ended = http
+            .post(&url)
+            .header(MCP_SESSION_ID, &session_id)
+            .json(&tools_list())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(ended.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_max_connections() {
+        let url = format!("{}/mcp", start(1).await);
+        let http = reqwest::Client::new();
+
+        let first = http.post(&url).json(&initialize()).send().await.unwrap();
+        let session_id = first.headers()[MCP_SESSION_ID].clone();
+        let second = http.post(&url).json(&initialize()).send().await.unwrap();
+        assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        http.delete(&url)
+            .header(MCP_SESSION_ID, session_id)
+            .send()
+            .await
+            .unwrap();
+        let third = http.post(&url).json(&initialize()).send().await.unwrap();
+        assert_eq!(third.status(), StatusCode::OK);
+    }
+
+    /// Read a stream until `needle` appears, returning everything read
+    async fn read_until(stream: &mut reqwest::Response, needle: &str, seen: &mut String) {
+        while !seen.contains(needle) {
+            let chunk = stream.chunk().await.unwrap().expect("stream ended");
+            seen.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_legacy_sse() {
+        let base = start(10).await;
+        let http = reqwest::Client::new();
+
+        let mut stream = http.get(format!("{}/sse", base)).send().await.unwrap();
+        assert_eq!(stream.status(), StatusCode::OK);
+        let mut seen = String::new();
+        read_until(&mut stream, "sessionId=", &mut seen).await;
+        read_until(&mut stream, "\n\n", &mut seen).await;
+        let endpoint = seen
+            .lines()
+            .find_map(|line| line.strip_prefix("data: "))
+            .unwrap()
+            .to_string();
+
+        let posted = http
+            .post(format!("{}{}", base, endpoint))
+            .json(&initialize())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(posted.status(), StatusCode::ACCEPTED);
+        read_until(&mut stream, "protocolVersion", &mut seen).await;
+        assert!(seen.contains("event: message"));
+
+        let unknown = http
+            .post(format!("{}/message?sessionId=nope", base))
+            .json(&tools_list())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+    }
+}
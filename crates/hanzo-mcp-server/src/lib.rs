@@ -1,7 +1,12 @@
 pub mod config;
+mod handler;
+mod http;
 pub mod protocol;
 pub mod server;
+mod session;
+mod stdio;
 pub mod tools;
+mod websocket;
 
 pub use config::Config;
 pub use server::MCPServer;
@@ -20,9 +20,17 @@ struct Args {
     #[clap(short, long)]
     debug: bool,
 
-    /// Port to listen on
-    #[clap(short, long, default_value = "3333")]
-    port: u16,
+    /// Port to listen on; defaults to the config's `server.port`
+    #[clap(short, long)]
+    port: Option<u16>,
+
+    /// Serve a single client over stdin/stdout instead of HTTP
+    #[clap(long)]
+    stdio: bool,
+
+    /// Also accept WebSocket connections on this port
+    #[clap(long)]
+    ws_port: Option<u16>,
 
     /// Connect to existing hanzo-node if running
     #[clap(long)]
@@ -59,9 +67,12 @@ async fn main() -> Result<()> {
     }
 
     // Create and start MCP server
-    let server = MCPServer::new(config, args.port)?;
+    let port = args.port.unwrap_or(config.server.port);
+    let mut server = MCPServer::new(config, port)?;
+    if let Some(ws_port) = args.ws_port {
+        server = server.with_websocket(ws_port);
+    }
 
-    info!("MCP Server listening on port {}", args.port);
     info!("Available tools:");
     info!("  - computer_control: Screenshot, mouse, keyboard control");
     info!("  - blockchain: Ethereum/Web3 operations and payments");
@@ -70,7 +81,11 @@ async fn main() -> Result<()> {
     info!("  - web_search: Web scraping and search");
     info!("  - code_execution: Safe code execution sandbox");
 
-    server.run().await?;
+    if args.stdio {
+        server.run_stdio().await?;
+    } else {
+        server.run().await?;
+    }
 
     Ok(())
 }
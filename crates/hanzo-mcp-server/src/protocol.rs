@@ -1,8 +1,30 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-/// MCP Protocol version
-pub const PROTOCOL_VERSION: &str = "2024-11-05";
+/// Latest MCP protocol version
+pub const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Protocol versions the server speaks, newest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &[PROTOCOL_VERSION, "2025-03-26", "2024-11-05"];
+
+/// JSON-RPC error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// Version to use with a client that requested `requested`
+///
+/// The client's version if the server supports it, else the latest one; the
+/// client disconnects if it can't speak that.
+pub fn negotiate_version(requested: &str) -> &'static str {
+    SUPPORTED_PROTOCOL_VERSIONS
+        .iter()
+        .find(|version| **version == requested)
+        .copied()
+        .unwrap_or(PROTOCOL_VERSION)
+}
 
 /// MCP Request structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +46,26 @@ pub struct MCPResponse {
     pub error: Option<MCPError>,
 }
 
+impl MCPResponse {
+    pub fn success(id: Option<Value>, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn error(id: Option<Value>, error: MCPError) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
 /// MCP Error structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MCPError {
@@ -33,8 +75,19 @@ pub struct MCPError {
     pub data: Option<Value>,
 }
 
+impl MCPError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
 /// Initialize request params
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct InitializeParams {
     pub protocol_version: String,
     pub capabilities: ClientCapabilities,
@@ -42,12 +95,16 @@ pub struct InitializeParams {
 }
 
 /// Client capabilities
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ClientCapabilities {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub experimental: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub roots: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub sampling: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elicitation: Option<Value>,
 }
 
 /// Client information
@@ -57,6 +114,24 @@ pub struct ClientInfo {
     pub version: String,
 }
 
+/// Initialize response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeResult {
+    pub protocol_version: String,
+    pub capabilities: ServerCapabilities,
+    pub server_info: ServerInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+}
+
+/// Server information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub name: String,
+    pub version: String,
+}
+
 /// Server capabilities
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerCapabilities {
@@ -105,3 +180,24 @@ pub enum Content {
         text: Option<String>,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_version() {
+        assert_eq!(negotiate_version("2025-03-26"), "2025-03-26");
+        assert_eq!(negotiate_version("2024-11-05"), "2024-11-05");
+        assert_eq!(negotiate_version("2099-01-01"), PROTOCOL_VERSION);
+
+        let params: InitializeParams = serde_json::from_value(serde_json::json!({
+            "protocolVersion": "2025-03-26",
+            "capabilities": {"sampling": {}},
+            "clientInfo": {"name": "desktop", "version": "1.0"}
+        }))
+        .unwrap();
+        assert_eq!(params.client_info.name, "desktop");
+        assert!(params.capabilities.sampling.is_some());
+    }
+}
@@ -1,158 +1,88 @@
+use crate::handler::Handler;
+use crate::http::{self, HttpState};
 use crate::{Config, ToolRegistry};
 use anyhow::Result;
-use jsonrpc_core::{IoHandler, Params};
-use jsonrpc_http_server::ServerBuilder;
-use log::{debug, error, info};
-use serde_json::json;
+use log::{error, info};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::net::TcpListener;
+use tokio::sync::{RwLock, Semaphore};
 
 pub struct MCPServer {
-    #[allow(dead_code)] // Reserved for future configuration-based behavior
     config: Config,
     port: u16,
+    /// Also accept WebSocket connections on this port
+    ws_port: Option<u16>,
     tools: Arc<RwLock<ToolRegistry>>,
-    handler: IoHandler,
+    handler: Arc<Handler>,
+    /// One slot per session, `config.server.max_connections` in all
+    connections: Arc<Semaphore>,
 }
 
 impl MCPServer {
     pub fn new(config: Config, port: u16) -> Result<Self> {
         let tools = Arc::new(RwLock::new(ToolRegistry::with_defaults()));
-        let mut handler = IoHandler::new();
-
-        // Clone for move into closures
-        let tools_clone = tools.clone();
-
-        // Initialize method
-        handler.add_method("initialize", move |params: Params| {
-            let tools = tools_clone.clone();
-            Box::pin(async move {
-                debug!("Received initialize request: {:?}", params);
-
-                let _tools = tools.read().await;
-
-                Ok(json!({
-                    "protocolVersion": "2024-11-05",
-                    "serverInfo": {
-                        "name": "hanzo-mcp",
-                        "version": env!("CARGO_PKG_VERSION")
-                    },
-                    "capabilities": {
-                        "tools": {},
-                        "resources": {},
-                        "prompts": {}
-                    }
-                }))
-            })
-        });
-
-        // List tools method
-        let tools_clone = tools.clone();
-        handler.add_method("tools/list", move |_params: Params| {
-            let tools = tools_clone.clone();
-            Box::pin(async move {
-                let tools = tools.read().await;
-                let mut tool_list = Vec::new();
-
-                for name in tools.list() {
-                    if let Some(tool) = tools.get(&name) {
-                        tool_list.push(json!({
-                            "name": tool.name(),
-                            "description": tool.description(),
-                            "inputSchema": tool.parameters()
-                        }));
-                    }
-                }
-
-                Ok(json!({
-                    "tools": tool_list
-                }))
-            })
-        });
-
-        // Call tool method
-        let tools_clone = tools.clone();
-        handler.add_method("tools/call", move |params: Params| {
-            let tools = tools_clone.clone();
-            Box::pin(async move {
-                let params = params
-                    .parse::<serde_json::Value>()
-                    .map_err(|e| jsonrpc_core::Error::invalid_params(e.to_string()))?;
-
-                let tool_name = params["name"]
-                    .as_str()
-                    .ok_or_else(|| jsonrpc_core::Error::invalid_params("Missing tool name"))?;
-
-                let tool_params = params.get("arguments").cloned().unwrap_or(json!({}));
-
-                let tools = tools.read().await;
-                let tool = tools.get(tool_name).ok_or_else(|| {
-                    jsonrpc_core::Error::invalid_params(format!("Unknown tool: {}", tool_name))
-                })?;
-
-                match tool.execute(tool_params).await {
-                    Ok(result) => Ok(json!({
-                        "content": [{
-                            "type": "text",
-                            "text": serde_json::to_string(&result.content).unwrap_or_default()
-                        }]
-                    })),
-                    Err(e) => {
-                        error!("Tool execution failed: {}", e);
-                        Ok(json!({
-                            "content": [{
-                                "type": "text",
-                                "text": format!("Error: {}", e)
-                            }],
-                            "isError": true
-                        }))
-                    }
-                }
-            })
-        });
-
-        // List resources method
-        handler.add_method("resources/list", |_params: Params| {
-            Box::pin(async move {
-                Ok(json!({
-                    "resources": []
-                }))
-            })
-        });
-
-        // List prompts method
-        handler.add_method("prompts/list", |_params: Params| {
-            Box::pin(async move {
-                Ok(json!({
-                    "prompts": []
-                }))
-            })
-        });
-
-        // Ping method for health checks
-        handler.add_method("ping", |_params: Params| {
-            Box::pin(async move { Ok(json!("pong")) })
-        });
+        let handler = Arc::new(Handler::new(tools.clone()));
+        let connections = Arc::new(Semaphore::new(config.server.max_connections));
 
         Ok(Self {
             config,
             port,
+            ws_port: None,
             tools,
             handler,
+            connections,
         })
     }
 
+    /// Serve WebSocket clients on `port` alongside HTTP
+    pub fn with_websocket(mut self, port: u16) -> Self {
+        self.ws_port = Some(port);
+        self
+    }
+
+    /// Serve Streamable HTTP and legacy SSE (and WebSocket, if enabled) on
+    /// `config.server.host`
     pub async fn run(self) -> Result<()> {
-        let server = ServerBuilder::new(self.handler)
-            .start_http(&format!("127.0.0.1:{}", self.port).parse()?)
-            .map_err(|e| anyhow::anyhow!("Failed to start server: {}", e))?;
+        let host = self.config.server.host.as_str();
+        if let Some(ws_port) = self.ws_port {
+            let listener = TcpListener::bind((host, ws_port)).await?;
+            let (handler, connections) = (self.handler.clone(), self.connections.clone());
+            tokio::spawn(async move {
+                if let Err(e) = crate::websocket::serve(listener, handler, connections).await {
+                    error!("WebSocket server stopped: {}", e);
+                }
+            });
+        }
+
+        let listener = TcpListener::bind((host, self.port)).await?;
+        info!(
+            "MCP Server running on http://{} (max {} connections)",
+            listener.local_addr()?,
+            self.config.server.max_connections
+        );
+        self.serve_http(listener).await
+    }
 
-        info!("MCP Server running on http://127.0.0.1:{}", self.port);
+    /// Serve a single client over stdin and stdout, for clients that launch
+    /// the server as a subprocess
+    pub async fn run_stdio(self) -> Result<()> {
+        info!("MCP Server running on stdio");
+        crate::stdio::serve(self.handler.clone()).await
+    }
 
-        // Keep server running
-        server.wait();
+    /// Serve HTTP clients on an already bound listener
+    pub async fn serve_http(&self, listener: TcpListener) -> Result<()> {
+        let state = HttpState::new(
+            self.handler.clone(),
+            self.connections.clone(),
+            &self.config.server.host,
+        );
+        http::serve(listener, Arc::new(state)).await
+    }
 
-        Ok(())
+    /// Serve WebSocket clients on an already bound listener
+    pub async fn serve_websocket(&self, listener: TcpListener) -> Result<()> {
+        crate::websocket::serve(listener, self.handler.clone(), self.connections.clone()).await
     }
 
     pub async fn add_tool(&self, tool: Box<dyn crate::MCPTool>) {
@@ -160,3 +90,123 @@ impl MCPServer {
         tools.register(tool);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MCPTool, ToolResult};
+    use hanzo_mcp_client::{McpClient, McpClientConfig, McpServerConfig, McpServerSource};
+    use hanzo_mcp_core::ContentBlock;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    struct Echo;
+
+    #[async_trait::async_trait]
+    impl MCPTool for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn description(&self) -> &str {
+            "Echo the given text"
+        }
+
+        fn parameters(&self) -> serde_json::Value {
+            json!({
+                "type": "object",
+                "properties": {"text": {"type": "string"}},
+                "required": ["text"]
+            })
+        }
+
+        async fn execute(&self, params: serde_json::Value) -> Result<ToolResult> {
+            Ok(ToolResult {
+                success: true,
+                content: params["text"].clone(),
+                error: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket_end_to_end() {
+        let server = Arc::new(MCPServer::new(Config::default(), 0).unwrap());
+        server.add_tool(Box::new(Echo)).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let serving = server.clone();
+        tokio::spawn(async move { serving.serve_websocket(listener).await });
+
+        let mut client = McpClient::new(McpClientConfig {
+            servers: vec![McpServerConfig {
+                id: "local".to_string(),
+                name: "local".to_string(),
+                source: McpServerSource::WebSocket {
+                    url,
+                    timeout_secs: Some(5),
+                    headers: Some(HashMap::from([("X-Test".to_string(), "1".to_string())])),
+                    ping_interval_secs: Some(1),
+                },
+                tool_prefix: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        client.initialize().await.unwrap();
+        assert!(client.is_server_connected("local").await);
+        assert!(client.list_tools().await.iter().any(|t| t.name == "echo"));
+
+        // Pings keep an idle connection alive past two intervals
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let result = client
+            .call_tool("echo", json!({"text": "hello over websocket"}))
+            .await
+            .unwrap();
+        assert!(!result.is_error);
+        match &result.content[0] {
+            ContentBlock::Text { text } => assert!(text.contains("hello over websocket")),
+            other => panic!("unexpected content: {:?}", other),
+        }
+        assert_eq!(client.server_status("local").await.unwrap().reconnects, 0);
+
+        client.close().await;
+        assert!(!client.is_server_connected("local").await);
+    }
+
+    #[tokio::test]
+    async fn test_streamable_http_end_to_end() {
+        let server = Arc::new(MCPServer::new(Config::default(), 0).unwrap());
+        server.add_tool(Box::new(Echo)).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        let serving = server.clone();
+        tokio::spawn(async move { serving.serve_http(listener).await });
+
+        let mut client = McpClient::new(McpClientConfig {
+            servers: vec![McpServerConfig {
+                id: "local".to_string(),
+                name: "local".to_string(),
+                source: McpServerSource::Http {
+                    url,
+                    timeout_secs: Some(5),
+                    headers: None,
+                },
+                tool_prefix: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        client.initialize().await.unwrap();
+        let result = client
+            .call_tool("echo", json!({"text": "hello over http"}))
+            .await
+            .unwrap();
+        match &result.content[0] {
+            ContentBlock::Text { text } => assert!(text.contains("hello over http")),
+            other => panic!("unexpected content: {:?}", other),
+        }
+        client.close().await;
+    }
+}
@@ -0,0 +1,145 @@
+//! Client sessions
+//!
+//! Every connection (stdio, a WebSocket, a legacy SSE stream) is one session,
+//! and Streamable HTTP sessions span requests under their `Mcp-Session-Id`.
+//! Sessions other than stdio hold one of the server's `max_connections`
+//! slots until they end; HTTP sessions left idle give theirs up.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// How long an HTTP session may go without requests before it is dropped
+pub(crate) const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Messages a session handles at once; reading further ones waits
+pub(crate) const SESSION_CONCURRENCY: usize = 16;
+
+/// One client's connection state
+pub(crate) struct Session {
+    id: String,
+    /// Negotiated by `initialize`
+    protocol_version: OnceLock<&'static str>,
+    last_used: Mutex<Instant>,
+    /// Handler slots, up to `SESSION_CONCURRENCY`
+    handling: Arc<Semaphore>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Session {
+    /// Session outside the connection limit, for stdio
+    pub fn new() -> Self {
+        Self::with_permit(None)
+    }
+
+    /// Session holding a connection slot, or `None` if all are taken
+    pub fn open(connections: &Arc<Semaphore>) -> Option<Self> {
+        let permit = connections.clone().try_acquire_owned().ok()?;
+        Some(Self::with_permit(Some(permit)))
+    }
+
+    fn with_permit(permit: Option<OwnedSemaphorePermit>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            protocol_version: OnceLock::new(),
+            last_used: Mutex::new(Instant::now()),
+            handling: Arc::new(Semaphore::new(SESSION_CONCURRENCY)),
+            _permit: permit,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The negotiated protocol version, once initialized
+    pub fn protocol_version(&self) -> Option<&'static str> {
+        self.protocol_version.get().copied()
+    }
+
+    /// Record the negotiated version; false if already initialized
+    pub fn initialize(&self, version: &'static str) -> bool {
+        self.protocol_version.set(version).is_ok()
+    }
+
+    /// Wait for a handler slot, to hold while handling one message
+    pub async fn handling(&self) -> OwnedSemaphorePermit {
+        self.handling
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("session semaphore is never closed")
+    }
+
+    pub fn touch(&self) {
+        *self.last_used.lock().unwrap() = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used.lock().unwrap().elapsed()
+    }
+}
+
+/// Sessions reachable by id
+#[derive(Default)]
+pub(crate) struct Sessions {
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+}
+
+impl Sessions {
+    /// Open a session, first dropping idle ones so they free their slots
+    pub fn open(&self, connections: &Arc<Semaphore>) -> Option<Arc<Session>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.idle_for() < SESSION_IDLE_TIMEOUT);
+        let session = Arc::new(Session::open(connections)?);
+        sessions.insert(session.id().to_string(), session.clone());
+        Some(session)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Session>> {
+        let session = self.sessions.lock().unwrap().get(id).cloned()?;
+        session.touch();
+        Some(session)
+    }
+
+    /// End a session, returning whether it existed
+    pub fn close(&self, id: &str) -> bool {
+        self.sessions.lock().unwrap().remove(id).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[test]
+    fn test_connection_limit() {
+        let connections = Arc::new(Semaphore::new(1));
+        let sessions = Sessions::default();
+        let first = sessions.open(&connections).unwrap();
+        assert!(sessions.open(&connections).is_none());
+        assert!(first.initialize("2025-06-18"));
+        assert!(!first.initialize("2024-11-05"));
+
+        let id = first.id().to_string();
+        drop(first);
+        assert!(sessions.get(&id).is_some());
+        assert!(sessions.close(&id));
+        assert!(sessions.get(&id).is_none());
+        assert!(sessions.open(&connections).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_handler_slots() {
+        let session = Session::new();
+        let mut slots = Vec::new();
+        for _ in 0..SESSION_CONCURRENCY {
+            slots.push(session.handling().await);
+        }
+        assert!(session.handling().now_or_never().is_none());
+        slots.pop();
+        assert!(session.handling().now_or_never().is_some());
+    }
+}
@@ -0,0 +1,113 @@
+//! stdio transport
+//!
+//! For clients that launch the server as a subprocess: one JSON-RPC message
+//! per line on stdin, responses as lines on stdout. Logs go to stderr, so
+//! stdout carries nothing but protocol messages. The process is one session,
+//! ending when stdin closes.
+
+use crate::handler::Handler;
+use crate::session::Session;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+/// Responses buffered before handlers wait
+const OUTGOING_CAPACITY: usize = 64;
+
+/// Serve one client over stdin and stdout
+pub(crate) async fn serve(handler: Arc<Handler>) -> Result<()> {
+    serve_io(tokio::io::stdin(), tokio::io::stdout(), handler).await
+}
+
+/// Serve one client over newline-delimited streams until `input` ends
+async fn serve_io<R, W>(input: R, mut output: W, handler: Arc<Handler>) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let session = Arc::new(Session::new());
+    let (outgoing, mut responses) = mpsc::channel::<String>(OUTGOING_CAPACITY);
+    let writer = tokio::spawn(async move {
+        while let Some(response) = responses.recv().await {
+            output.write_all(response.as_bytes()).await?;
+            output.write_all(b"\n").await?;
+            output.flush().await?;
+        }
+        anyhow::Ok(())
+    });
+
+    let mut lines = BufReader::new(input).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Handled concurrently, so a long tool call doesn't hold up others
+        let slot = session.handling().await;
+        let (handler, session, outgoing) = (handler.clone(), session.clone(), outgoing.clone());
+        tokio::spawn(async move {
+            if let Some(response) = handler.handle_text(&session, &line).await {
+                let _ = outgoing.send(response).await;
+            }
+            drop(slot);
+        });
+    }
+
+    // Responses still in flight are written before returning
+    drop(outgoing);
+    writer.await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ToolRegistry;
+    use serde_json::{json, Value};
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_stdio_session() {
+        let handler = Arc::new(Handler::new(Arc::new(RwLock::new(ToolRegistry::new()))));
+        let (client, server) = tokio::io::duplex(4096);
+        let (server_in, server_out) = tokio::io::split(server);
+        let serving = tokio::spawn(serve_io(server_in, server_out, handler));
+
+        let (client_in, mut client_out) = tokio::io::split(client);
+        let mut responses = BufReader::new(client_in).lines();
+        let initialize = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-06-18",
+                "capabilities": {},
+                "clientInfo": {"name": "desktop", "version": "1.0"}
+            }
+        });
+        let messages = format!(
+            "{}\n{}\n",
+            initialize,
+            json!({"jsonrpc": "2.0", "method": "notifications/initialized"})
+        );
+        client_out.write_all(messages.as_bytes()).await.unwrap();
+
+        let line = responses.next_line().await.unwrap().unwrap();
+        let response: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["protocolVersion"], "2025-06-18");
+
+        let list = json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"});
+        client_out
+            .write_all(format!("{}\n", list).as_bytes())
+            .await
+            .unwrap();
+        let line = responses.next_line().await.unwrap().unwrap();
+        let response: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(response["id"], 2);
+        assert!(response["result"]["tools"].is_array());
+
+        // Closing stdin ends the session
+        client_out.shutdown().await.unwrap();
+        serving.await.unwrap().unwrap();
+    }
+}
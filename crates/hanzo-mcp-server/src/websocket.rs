@@ -0,0 +1,82 @@
+//! WebSocket transport
+//!
+//! Clients send one JSON-RPC message per text frame and get each response
+//! back as a text frame. Each connection is a session. Requests on a
+//! connection are handled concurrently, so a long tool call doesn't hold up
+//! others (or keepalive pongs); past the session's limit, frames are not
+//! read until a request finishes.
+
+use crate::handler::Handler;
+use crate::session::Session;
+use anyhow::Result;
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Semaphore};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Responses buffered per connection before handlers wait
+const OUTGOING_CAPACITY: usize = 64;
+
+/// Accept WebSocket connections until the listener fails
+pub(crate) async fn serve(
+    listener: TcpListener,
+    handler: Arc<Handler>,
+    connections: Arc<Semaphore>,
+) -> Result<()> {
+    info!("MCP WebSocket server on ws://{}", listener.local_addr()?);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let Some(session) = Session::open(&connections) else {
+            warn!(
+                "Refusing WebSocket connection from {}: too many connections",
+                peer
+            );
+            continue;
+        };
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connection(stream, handler, Arc::new(session)).await {
+                debug!("WebSocket connection from {} ended: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn connection(stream: TcpStream, handler: Arc<Handler>, session: Arc<Session>) -> Result<()> {
+    let socket = tokio_tungstenite::accept_async(stream).await?;
+    let (mut sink, mut frames) = socket.split();
+    let (outgoing, mut responses) = mpsc::channel::<Message>(OUTGOING_CAPACITY);
+    let writer = tokio::spawn(async move {
+        while let Some(message) = responses.recv().await {
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+        let _ = sink.close().await;
+    });
+
+    while let Some(frame) = frames.next().await {
+        let request = match frame? {
+            Message::Text(text) => text.to_string(),
+            Message::Binary(data) => String::from_utf8_lossy(&data).into_owned(),
+            Message::Close(_) => break,
+            // Pings are answered by tungstenite itself
+            _ => continue,
+        };
+        let slot = session.handling().await;
+        let (handler, session, outgoing) = (handler.clone(), session.clone(), outgoing.clone());
+        tokio::spawn(async move {
+            // Notifications have no response
+            if let Some(response) = handler.handle_text(&session, &request).await {
+                let _ = outgoing.send(Message::Text(response.into())).await;
+            }
+            drop(slot);
+        });
+    }
+
+    drop(outgoing);
+    let _ = writer.await;
+    Ok(())
+}